@@ -2,7 +2,10 @@
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
 use rustlb::backend::BackendRouter;
-use rustlb::config::{Algorithm, BackendConfig, FrontendConfig, Protocol, ServerConfig};
+use rustlb::config::{
+    Algorithm, AllUnhealthyPolicy, BackendConfig, BackendProtocol, ConnectionPoolConfig,
+    FrontendConfig, PassiveHealthConfig, Protocol, ServerConfig,
+};
 use rustlb::health::{HealthConfig, HealthState};
 use rustlb::metrics::MetricsCollector;
 use rustlb::util::{generate_request_id, generate_short_request_id};
@@ -14,6 +17,7 @@ fn create_router(algorithm: Algorithm, num_servers: usize) -> BackendRouter {
         .map(|i| ServerConfig {
             address: format!("127.0.0.1:{}", 9000 + i).parse().unwrap(),
             weight: 1,
+            metadata: Default::default(),
         })
         .collect();
 
@@ -21,6 +25,14 @@ fn create_router(algorithm: Algorithm, num_servers: usize) -> BackendRouter {
         name: "test".to_string(),
         servers,
         health_check: None,
+        on_all_unhealthy: AllUnhealthyPolicy::Reject,
+        connection_pool: ConnectionPoolConfig::default(),
+        tls: None,
+        protocol: BackendProtocol::Http1,
+        passive_health: PassiveHealthConfig::default(),
+        outlier_detection: None,
+        drain_timeout: Duration::from_secs(30),
+        discovery: None,
     }];
 
     let frontends = vec![FrontendConfig {
@@ -28,9 +40,11 @@ fn create_router(algorithm: Algorithm, num_servers: usize) -> BackendRouter {
         listen: "127.0.0.1:0".parse().unwrap(),
         protocol: Protocol::Http,
         backend: "test".to_string(),
+        routes: Vec::new(),
         algorithm,
         http: None,
         tcp: None,
+        tls: None,
     }];
 
     BackendRouter::new(&backends, &frontends)
@@ -51,6 +65,7 @@ fn benchmark_weighted(c: &mut Criterion) {
         .map(|i| ServerConfig {
             address: format!("127.0.0.1:{}", 9000 + i).parse().unwrap(),
             weight: (i + 1) as u32,
+            metadata: Default::default(),
         })
         .collect();
 
@@ -58,6 +73,14 @@ fn benchmark_weighted(c: &mut Criterion) {
         name: "test".to_string(),
         servers,
         health_check: None,
+        on_all_unhealthy: AllUnhealthyPolicy::Reject,
+        connection_pool: ConnectionPoolConfig::default(),
+        tls: None,
+        protocol: BackendProtocol::Http1,
+        passive_health: PassiveHealthConfig::default(),
+        outlier_detection: None,
+        drain_timeout: Duration::from_secs(30),
+        discovery: None,
     }];
 
     let frontends = vec![FrontendConfig {
@@ -65,9 +88,11 @@ fn benchmark_weighted(c: &mut Criterion) {
         listen: "127.0.0.1:0".parse().unwrap(),
         protocol: Protocol::Http,
         backend: "test".to_string(),
+        routes: Vec::new(),
         algorithm: Algorithm::Weighted,
         http: None,
         tcp: None,
+        tls: None,
     }];
 
     let router = BackendRouter::new(&backends, &frontends);
@@ -158,6 +183,7 @@ fn benchmark_metrics(c: &mut Criterion) {
             collector.record_request(
                 black_box("web"),
                 black_box("api"),
+                black_box(None),
                 black_box("GET"),
                 black_box(200),
                 black_box(Duration::from_millis(10)),
@@ -0,0 +1,740 @@
+//! Admin REST API for runtime inspection and control.
+//!
+//! Served by the metrics server under `/admin/` while `global.admin.enabled`
+//! is set. Read endpoints list frontends, backends and the live state of
+//! their servers. Endpoints that change state require the bearer token from
+//! `global.admin.token`, and are refused while no token is configured.
+//!
+//! Changes made here last until the next configuration reload, except for
+//! disabled servers, which stay disabled while they remain in their pool, and
+//! servers added to or removed from a pool. Those survive reloads, and
+//! restarts too when `global.admin.state_file` is set.
+
+use crate::backend::{DrainReason, PoolError};
+use crate::config::{ReloadRequest, ServerConfig};
+use crate::health::{check_server, ServerStatus};
+use crate::AppState;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Body;
+use hyper::{header, Method, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+/// Largest request body accepted, enough for any action's parameters.
+const MAX_BODY_SIZE: usize = 4096;
+
+/// Admin API error, answered with a matching status code.
+#[derive(Debug, thiserror::Error)]
+pub enum AdminError {
+    #[error("not found")]
+    NotFound,
+
+    #[error("method not allowed")]
+    MethodNotAllowed,
+
+    #[error("missing or invalid bearer token")]
+    Unauthorized,
+
+    #[error("admin token not configured, changes are disabled")]
+    TokenNotConfigured,
+
+    #[error("backend '{0}' not found")]
+    BackendNotFound(String),
+
+    #[error("server {1} not found in backend '{0}'")]
+    ServerNotFound(String, SocketAddr),
+
+    #[error("invalid server address '{0}'")]
+    InvalidServer(String),
+
+    #[error("invalid request: {0}")]
+    BadRequest(String),
+
+    #[error("backend '{0}' has no active health check")]
+    NoHealthCheck(String),
+
+    #[error("configuration reload failed: {0}")]
+    Reload(String),
+
+    #[error("configuration reload is not available")]
+    ReloadUnavailable,
+
+    #[error(transparent)]
+    Pool(#[from] PoolError),
+}
+
+impl AdminError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AdminError::NotFound
+            | AdminError::BackendNotFound(_)
+            | AdminError::ServerNotFound(..) => StatusCode::NOT_FOUND,
+            AdminError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AdminError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AdminError::TokenNotConfigured => StatusCode::FORBIDDEN,
+            AdminError::InvalidServer(_) | AdminError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AdminError::NoHealthCheck(_) => StatusCode::CONFLICT,
+            AdminError::Reload(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AdminError::ReloadUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            AdminError::Pool(e) => match e {
+                PoolError::BackendNotFound(_) | PoolError::ServerNotFound(..) => {
+                    StatusCode::NOT_FOUND
+                }
+                PoolError::ServerExists(..) | PoolError::LastServer(_) => StatusCode::CONFLICT,
+                PoolError::Overlay(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        }
+    }
+}
+
+/// A frontend as listed by the admin API.
+#[derive(Debug, Serialize)]
+struct FrontendView {
+    name: String,
+    listen: SocketAddr,
+    protocol: crate::config::Protocol,
+    backend: String,
+    routes: Vec<String>,
+    algorithm: crate::config::Algorithm,
+    tls: bool,
+}
+
+/// A backend pool as listed by the admin API.
+#[derive(Debug, Serialize)]
+struct BackendView {
+    name: String,
+    algorithm: Option<crate::config::Algorithm>,
+    protocol: crate::config::BackendProtocol,
+    health_check: bool,
+    servers: Vec<ServerView>,
+}
+
+/// Live state of a server in a backend pool.
+#[derive(Debug, Serialize)]
+struct ServerView {
+    address: SocketAddr,
+    weight: u32,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+    /// Whether the router may select the server for new traffic.
+    available: bool,
+    healthy: bool,
+    in_cooldown: bool,
+    ejected: bool,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    passive_failures: u32,
+    active_connections: u32,
+    /// Unix timestamp (seconds) of the last health check.
+    last_check: Option<u64>,
+    drain: Option<DrainView>,
+}
+
+/// Drain state of a server.
+#[derive(Debug, Serialize)]
+struct DrainView {
+    backend: String,
+    server: SocketAddr,
+    reason: &'static str,
+    active_connections: u32,
+    /// Milliseconds until the remaining connections are closed.
+    remaining_ms: u64,
+}
+
+/// Result of a forced health check.
+#[derive(Debug, Serialize)]
+struct CheckView {
+    passed: bool,
+    error: Option<String>,
+    server: ServerView,
+}
+
+/// A server removed from its pool.
+#[derive(Debug, Serialize)]
+struct RemovedView {
+    backend: String,
+    server: SocketAddr,
+    drain: Option<DrainView>,
+}
+
+/// Body of a weight change.
+#[derive(Debug, Deserialize)]
+struct WeightRequest {
+    weight: u32,
+}
+
+/// Admin REST API handler.
+pub struct AdminApi {
+    /// Shared state to inspect and control.
+    state: AppState,
+    /// Channel to request configuration reloads on.
+    reload: Option<mpsc::UnboundedSender<ReloadRequest>>,
+}
+
+impl AdminApi {
+    /// Create an admin API for the given state.
+    pub fn new(state: AppState) -> Self {
+        Self {
+            state,
+            reload: None,
+        }
+    }
+
+    /// Serve reload requests by sending them on this channel, usually to the
+    /// [`ConfigWatcher`](crate::config::ConfigWatcher).
+    pub fn with_reload_requests(mut self, reload: mpsc::UnboundedSender<ReloadRequest>) -> Self {
+        self.reload = Some(reload);
+        self
+    }
+
+    /// Whether the admin API is enabled by the current configuration.
+    pub fn enabled(&self) -> bool {
+        self.state.config().global.admin.enabled
+    }
+
+    /// Handle a request to a path under `/admin/`.
+    pub async fn handle<B>(&self, req: Request<B>) -> Response<Full<Bytes>>
+    where
+        B: Body,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        if !self.enabled() {
+            return error_response(&AdminError::NotFound);
+        }
+
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        let result = self.route(req).await;
+        match result {
+            Ok(response) => response,
+            Err(e) => {
+                if matches!(e, AdminError::Unauthorized) {
+                    warn!(
+                        method = %method,
+                        path = %path,
+                        "rejected admin request without valid token"
+                    );
+                }
+                error_response(&e)
+            }
+        }
+    }
+
+    async fn route<B>(&self, req: Request<B>) -> Result<Response<Full<Bytes>>, AdminError>
+    where
+        B: Body,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let path = req.uri().path().trim_end_matches('/').to_string();
+        let segments: Vec<&str> = path.split('/').skip(2).collect();
+        let method = req.method().clone();
+
+        match (&method, segments.as_slice()) {
+            (&Method::GET, ["frontends"]) => Ok(json_response(StatusCode::OK, &self.frontends())),
+            (&Method::GET, ["backends"]) => Ok(json_response(StatusCode::OK, &self.backends())),
+            (&Method::GET, ["backends", backend]) => {
+                let view = self
+                    .backends()
+                    .into_iter()
+                    .find(|b| b.name == *backend)
+                    .ok_or_else(|| AdminError::BackendNotFound(backend.to_string()))?;
+                Ok(json_response(StatusCode::OK, &view))
+            }
+            (&Method::GET, ["backends", backend, "servers", server]) => {
+                let server = self.find_server(backend, server)?;
+                Ok(json_response(StatusCode::OK, &self.server(backend, server)))
+            }
+            (&Method::GET, ["draining"]) => Ok(json_response(StatusCode::OK, &self.draining())),
+
+            (&Method::POST, ["reload"]) => {
+                self.authorize(&req)?;
+                self.reload().await?;
+                Ok(json_response(
+                    StatusCode::OK,
+                    &serde_json::json!({ "status": "reloaded" }),
+                ))
+            }
+            (&Method::POST, ["backends", backend, "servers"]) => {
+                self.authorize(&req)?;
+                let body = read_body(req).await?;
+                let server: ServerConfig = serde_json::from_slice(&body)
+                    .map_err(|e| AdminError::BadRequest(e.to_string()))?;
+                if server.weight == 0 {
+                    return Err(AdminError::BadRequest("weight must be >= 1".to_string()));
+                }
+                let address = server.address;
+                self.state.router().add_server(backend, server)?;
+                Ok(json_response(
+                    StatusCode::CREATED,
+                    &self.server(backend, address),
+                ))
+            }
+            (&Method::DELETE, ["backends", backend, "servers", server]) => {
+                self.authorize(&req)?;
+                let server = self.find_server(backend, server)?;
+                self.state.router().remove_server(backend, server)?;
+                let view = RemovedView {
+                    backend: backend.to_string(),
+                    server,
+                    drain: self
+                        .draining()
+                        .into_iter()
+                        .find(|d| d.backend == *backend && d.server == server),
+                };
+                Ok(json_response(StatusCode::OK, &view))
+            }
+            (&Method::POST, ["backends", backend, "servers", server, action]) => {
+                self.authorize(&req)?;
+                let server = self.find_server(backend, server)?;
+                let query = req.uri().query().unwrap_or_default().to_string();
+                self.server_action(backend, server, action, &query).await
+            }
+            (&Method::PUT, ["backends", backend, "servers", server, "weight"]) => {
+                self.authorize(&req)?;
+                let server = self.find_server(backend, server)?;
+                let body = read_body(req).await?;
+                let request: WeightRequest = serde_json::from_slice(&body)
+                    .map_err(|e| AdminError::BadRequest(e.to_string()))?;
+                if request.weight == 0 {
+                    return Err(AdminError::BadRequest("weight must be >= 1".to_string()));
+                }
+                self.state
+                    .router()
+                    .set_weight(backend, server, request.weight);
+                Ok(json_response(StatusCode::OK, &self.server(backend, server)))
+            }
+
+            (_, ["frontends" | "backends" | "draining" | "reload", ..]) => {
+                Err(AdminError::MethodNotAllowed)
+            }
+            _ => Err(AdminError::NotFound),
+        }
+    }
+
+    /// Run an action on a server: enable, disable, drain or check.
+    async fn server_action(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        action: &str,
+        query: &str,
+    ) -> Result<Response<Full<Bytes>>, AdminError> {
+        let router = self.state.router();
+        match action {
+            "enable" => {
+                router.enable_server(backend, server);
+            }
+            "disable" => {
+                router.disable_server(backend, server);
+            }
+            "drain" => match query_param(query, "timeout") {
+                Some(timeout) => {
+                    let timeout = humantime::parse_duration(timeout)
+                        .map_err(|e| AdminError::BadRequest(format!("timeout: {}", e)))?;
+                    router.drain_server(backend, server, timeout);
+                }
+                None => {
+                    router.disable_server(backend, server);
+                }
+            },
+            "check" => {
+                let default_timeout = self.state.config().health_check_defaults.timeout;
+                let result = check_server(router, backend, server, default_timeout)
+                    .await
+                    .ok_or_else(|| AdminError::NoHealthCheck(backend.to_string()))?;
+                let view = CheckView {
+                    passed: result.is_ok(),
+                    error: result.err(),
+                    server: self.server(backend, server),
+                };
+                return Ok(json_response(StatusCode::OK, &view));
+            }
+            _ => return Err(AdminError::NotFound),
+        }
+        info!(backend = backend, server = %server, action = action, "admin action applied");
+        Ok(json_response(StatusCode::OK, &self.server(backend, server)))
+    }
+
+    /// Check the request's bearer token against the configured one.
+    fn authorize<B>(&self, req: &Request<B>) -> Result<(), AdminError> {
+        let config = self.state.config();
+        let Some(expected) = config.global.admin.token.as_deref() else {
+            return Err(AdminError::TokenNotConfigured);
+        };
+        let given = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AdminError::Unauthorized)?;
+        if constant_time_eq(expected.as_bytes(), given.trim().as_bytes()) {
+            Ok(())
+        } else {
+            Err(AdminError::Unauthorized)
+        }
+    }
+
+    /// Ask for a configuration reload and wait for its outcome.
+    async fn reload(&self) -> Result<(), AdminError> {
+        let reload = self.reload.as_ref().ok_or(AdminError::ReloadUnavailable)?;
+        let (reply, outcome) = oneshot::channel();
+        reload
+            .send(reply)
+            .map_err(|_| AdminError::ReloadUnavailable)?;
+        match outcome.await {
+            Ok(result) => result.map_err(AdminError::Reload),
+            Err(_) => Err(AdminError::ReloadUnavailable),
+        }
+    }
+
+    /// Resolve a server of a backend from its address in the path.
+    fn find_server(&self, backend: &str, server: &str) -> Result<SocketAddr, AdminError> {
+        let servers = self
+            .state
+            .router()
+            .get_servers(backend)
+            .ok_or_else(|| AdminError::BackendNotFound(backend.to_string()))?;
+        let decoded = percent_decode(server);
+        let address: SocketAddr = decoded
+            .parse()
+            .map_err(|_| AdminError::InvalidServer(decoded.clone()))?;
+        if servers.contains(&address) {
+            Ok(address)
+        } else {
+            Err(AdminError::ServerNotFound(backend.to_string(), address))
+        }
+    }
+
+    fn frontends(&self) -> Vec<FrontendView> {
+        self.state
+            .config()
+            .frontends
+            .iter()
+            .map(|f| FrontendView {
+                name: f.name.clone(),
+                listen: f.listen,
+                protocol: f.protocol.clone(),
+                backend: f.backend.clone(),
+                routes: f.routes.iter().map(|r| r.backend.clone()).collect(),
+                algorithm: f.algorithm.clone(),
+                tls: f.tls.is_some(),
+            })
+            .collect()
+    }
+
+    fn backends(&self) -> Vec<BackendView> {
+        let router = self.state.router();
+        let mut backends: Vec<BackendView> = router
+            .backend_configs()
+            .into_iter()
+            .map(|b| BackendView {
+                algorithm: router.algorithm(&b.name),
+                protocol: b.protocol,
+                health_check: b.health_check.is_some(),
+                servers: b
+                    .servers
+                    .iter()
+                    .map(|s| self.server_view(&b.name, s))
+                    .collect(),
+                name: b.name,
+            })
+            .collect();
+        backends.sort_by(|a, b| a.name.cmp(&b.name));
+        backends
+    }
+
+    fn server(&self, backend: &str, server: SocketAddr) -> ServerView {
+        let config = self
+            .state
+            .router()
+            .backend_config(backend)
+            .and_then(|b| b.servers.iter().find(|s| s.address == server).cloned())
+            .unwrap_or(ServerConfig {
+                address: server,
+                weight: 0,
+                metadata: BTreeMap::new(),
+            });
+        self.server_view(backend, &config)
+    }
+
+    fn server_view(&self, backend: &str, config: &ServerConfig) -> ServerView {
+        let server = config.address;
+        let router = self.state.router();
+        let status = router.health_state().status(server).unwrap_or(UNTRACKED);
+        let drain = self
+            .draining()
+            .into_iter()
+            .find(|d| d.backend == backend && d.server == server);
+        ServerView {
+            address: server,
+            weight: config.weight,
+            metadata: config.metadata.clone(),
+            available: drain.is_none() && router.health_state().is_available(server),
+            healthy: status.healthy,
+            in_cooldown: status.in_cooldown,
+            ejected: status.ejected,
+            consecutive_failures: status.consecutive_failures,
+            consecutive_successes: status.consecutive_successes,
+            passive_failures: status.passive_failures,
+            active_connections: router.active_connections(backend, server),
+            last_check: status.last_check,
+            drain,
+        }
+    }
+
+    fn draining(&self) -> Vec<DrainView> {
+        self.state
+            .router()
+            .draining_servers()
+            .into_iter()
+            .map(|d| DrainView {
+                backend: d.backend,
+                server: d.server,
+                reason: match d.reason {
+                    DrainReason::Removed => "removed",
+                    DrainReason::Disabled => "disabled",
+                },
+                active_connections: d.active,
+                remaining_ms: d.remaining.as_millis() as u64,
+            })
+            .collect()
+    }
+}
+
+/// Health of a server the health state does not track, which is assumed
+/// healthy.
+const UNTRACKED: ServerStatus = ServerStatus {
+    healthy: true,
+    in_cooldown: false,
+    ejected: false,
+    consecutive_failures: 0,
+    consecutive_successes: 0,
+    passive_failures: 0,
+    last_check: None,
+};
+
+/// Read a request body of at most [`MAX_BODY_SIZE`] bytes.
+async fn read_body<B>(req: Request<B>) -> Result<Bytes, AdminError>
+where
+    B: Body,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    Limited::new(req.into_body(), MAX_BODY_SIZE)
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .map_err(|e| AdminError::BadRequest(e.to_string()))
+}
+
+/// Find a parameter in a query string.
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Decode `%XX` escapes, such as the brackets of an IPv6 server address.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Compare two tokens without leaking where they differ through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Full<Bytes>> {
+    let mut body = serde_json::to_vec_pretty(body).unwrap_or_default();
+    body.push(b'\n');
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+fn error_response(error: &AdminError) -> Response<Full<Bytes>> {
+    let mut response = json_response(
+        error.status(),
+        &serde_json::json!({ "error": error.to_string() }),
+    );
+    if matches!(error, AdminError::Unauthorized) {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Bearer"),
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn admin(token: Option<&str>) -> AdminApi {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:8080"
+    protocol: http
+    backend: api
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+      - address: "127.0.0.1:9002"
+"#,
+        )
+        .unwrap();
+        config.global.admin.enabled = true;
+        config.global.admin.token = token.map(str::to_string);
+        AdminApi::new(AppState::new(config))
+    }
+
+    fn request(method: Method, uri: &str, token: Option<&str>, body: &str) -> Request<Full<Bytes>> {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder
+            .body(Full::new(Bytes::from(body.to_string())))
+            .unwrap()
+    }
+
+    async fn call(admin: &AdminApi, req: Request<Full<Bytes>>) -> (StatusCode, serde_json::Value) {
+        let response = admin.handle(req).await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_list_backends() {
+        let admin = admin(None);
+        let (status, body) = call(&admin, request(Method::GET, "/admin/backends", None, "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["name"], "api");
+        assert_eq!(body[0]["algorithm"], "round_robin");
+        assert_eq!(body[0]["servers"][1]["address"], "127.0.0.1:9002");
+        assert_eq!(body[0]["servers"][1]["available"], true);
+
+        let (status, _) = call(
+            &admin,
+            request(Method::GET, "/admin/backends/nope", None, ""),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_actions_require_token() {
+        let uri = "/admin/backends/api/servers/127.0.0.1:9001/disable";
+
+        // Without a configured token, changes are refused
+        let admin_without_token = admin(None);
+        let (status, _) = call(&admin_without_token, request(Method::POST, uri, None, "")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let admin = admin(Some("secret"));
+        let (status, _) = call(&admin, request(Method::POST, uri, Some("wrong"), "")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = call(&admin, request(Method::POST, uri, Some("secret"), "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["available"], false);
+        assert_eq!(body["drain"]["reason"], "disabled");
+        // Disabling drains for the backend's drain_timeout
+        assert!(body["drain"]["remaining_ms"].as_u64().unwrap() > 1000);
+
+        let uri = "/admin/backends/api/servers/127.0.0.1:9001/enable";
+        let (_, body) = call(&admin, request(Method::POST, uri, Some("secret"), "")).await;
+        assert_eq!(body["available"], true);
+    }
+
+    #[tokio::test]
+    async fn test_set_weight() {
+        let admin = admin(Some("secret"));
+        let uri = "/admin/backends/api/servers/127.0.0.1:9002/weight";
+
+        let (status, body) = call(
+            &admin,
+            request(Method::PUT, uri, Some("secret"), r#"{"weight": 5}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["weight"], 5);
+
+        let (status, _) = call(
+            &admin,
+            request(Method::PUT, uri, Some("secret"), r#"{"weight": 0}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let uri = "/admin/backends/api/servers/127.0.0.1:9009/weight";
+        let (status, _) = call(
+            &admin,
+            request(Method::PUT, uri, Some("secret"), r#"{"weight": 2}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_server() {
+        let admin = admin(Some("secret"));
+        let uri = "/admin/backends/api/servers";
+        let body = r#"{"address": "127.0.0.1:9003", "weight": 2}"#;
+
+        let (status, body) = call(&admin, request(Method::POST, uri, Some("secret"), body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["address"], "127.0.0.1:9003");
+        assert_eq!(body["weight"], 2);
+
+        let body = r#"{"address": "127.0.0.1:9003"}"#;
+        let (status, _) = call(&admin, request(Method::POST, uri, Some("secret"), body)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let uri = "/admin/backends/api/servers/127.0.0.1:9001";
+        let (status, body) = call(&admin, request(Method::DELETE, uri, Some("secret"), "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["drain"]["reason"], "removed");
+
+        let (status, _) = call(&admin, request(Method::GET, uri, None, "")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("%5B%3A%3A1%5D:80"), "[::1]:80");
+        assert_eq!(percent_decode("127.0.0.1:80"), "127.0.0.1:80");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
@@ -0,0 +1,208 @@
+//! Connection draining.
+//!
+//! A draining server receives no new connections or requests. Its existing
+//! TCP sessions, requests and backend connections may finish until the drain
+//! timeout expires, after which they are closed.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Why a server is draining.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainReason {
+    /// The server was removed from its pool by a configuration reload.
+    Removed,
+    /// The server was disabled by an operator.
+    Disabled,
+}
+
+/// Drain state of a server.
+#[derive(Debug, Clone, Copy)]
+pub struct Drain {
+    /// Why the server is draining.
+    pub reason: DrainReason,
+    /// When the remaining connections are closed.
+    pub deadline: Instant,
+}
+
+/// Active connections and drain state of a server in a backend pool.
+///
+/// Shared by the router and every [`ConnectionGuard`](super::ConnectionGuard)
+/// of the server, so counts survive hot reloads and removal from the pool.
+#[derive(Debug)]
+pub(crate) struct ServerSlot {
+    /// Active connections and requests.
+    active: AtomicU32,
+    /// Drain state, `None` while the server takes traffic.
+    drain: watch::Sender<Option<Drain>>,
+}
+
+impl ServerSlot {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: AtomicU32::new(0),
+            drain: watch::Sender::new(None),
+        }
+    }
+
+    pub(crate) fn acquire(&self) {
+        self.active.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn release(&self) {
+        self.active.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// Number of active connections and requests.
+    pub(crate) fn active(&self) -> u32 {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Current drain state.
+    pub(crate) fn drain(&self) -> Option<Drain> {
+        *self.drain.borrow()
+    }
+
+    pub(crate) fn is_draining(&self) -> bool {
+        self.drain.borrow().is_some()
+    }
+
+    /// Start draining, closing remaining connections after `timeout`.
+    ///
+    /// Returns false if the server was already draining, in which case its
+    /// original deadline is kept.
+    pub(crate) fn start_drain(&self, reason: DrainReason, timeout: Duration) -> bool {
+        self.drain.send_if_modified(|drain| {
+            if drain.is_some() {
+                return false;
+            }
+            *drain = Some(Drain {
+                reason,
+                deadline: Instant::now() + timeout,
+            });
+            true
+        })
+    }
+
+    /// Put the server back into rotation if it is draining for `reason`.
+    ///
+    /// Returns false if it was not draining for that reason.
+    pub(crate) fn cancel_drain(&self, reason: DrainReason) -> bool {
+        self.drain.send_if_modified(|drain| {
+            if drain.is_some_and(|drain| drain.reason == reason) {
+                *drain = None;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Signal that fires once the server's drain timeout expires.
+    pub(crate) fn signal(&self) -> DrainSignal {
+        DrainSignal {
+            drain: self.drain.subscribe(),
+        }
+    }
+}
+
+/// Fires once the drain timeout of a server expires.
+///
+/// Held by everything that keeps a connection to the server open, so the
+/// connection can be closed when draining ends.
+#[derive(Debug, Clone)]
+pub struct DrainSignal {
+    drain: watch::Receiver<Option<Drain>>,
+}
+
+impl DrainSignal {
+    /// A signal that never fires, for connections outside any pool.
+    pub fn never() -> Self {
+        let (_, drain) = watch::channel(None);
+        Self { drain }
+    }
+
+    /// Wait until the server is draining and its drain timeout has expired.
+    ///
+    /// Waits forever if the server never drains. A cancelled drain resets the
+    /// wait.
+    pub async fn expired(mut self) {
+        loop {
+            let deadline = self.drain.borrow_and_update().map(|drain| drain.deadline);
+            let changed = async {
+                if self.drain.changed().await.is_err() {
+                    // The server is gone, so its state can no longer change
+                    std::future::pending::<()>().await;
+                }
+            };
+
+            match deadline {
+                Some(deadline) => tokio::select! {
+                    _ = tokio::time::sleep_until(deadline.into()) => return,
+                    _ = changed => {}
+                },
+                None => changed.await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_and_cancel_drain() {
+        let slot = ServerSlot::new();
+        assert!(!slot.is_draining());
+
+        assert!(slot.start_drain(DrainReason::Disabled, Duration::from_secs(30)));
+        let deadline = slot.drain().unwrap().deadline;
+
+        // Draining again keeps the original deadline
+        assert!(!slot.start_drain(DrainReason::Removed, Duration::from_secs(60)));
+        assert_eq!(slot.drain().unwrap().deadline, deadline);
+
+        // Only a drain for the same reason is cancelled
+        assert!(!slot.cancel_drain(DrainReason::Removed));
+        assert!(slot.cancel_drain(DrainReason::Disabled));
+        assert!(!slot.is_draining());
+    }
+
+    #[tokio::test]
+    async fn test_signal_fires_at_deadline() {
+        let slot = ServerSlot::new();
+        let signal = slot.signal();
+        let expired = tokio::spawn(signal.expired());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!expired.is_finished());
+
+        slot.start_drain(DrainReason::Removed, Duration::from_millis(50));
+        tokio::time::timeout(Duration::from_secs(1), expired)
+            .await
+            .expect("signal did not fire")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_drain_does_not_fire() {
+        let slot = ServerSlot::new();
+        let expired = tokio::spawn(slot.signal().expired());
+
+        slot.start_drain(DrainReason::Disabled, Duration::from_millis(50));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        slot.cancel_drain(DrainReason::Disabled);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!expired.is_finished());
+        expired.abort();
+
+        // A signal for a server outside any pool never fires either
+        let never = tokio::spawn(DrainSignal::never().expired());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!never.is_finished());
+        never.abort();
+    }
+}
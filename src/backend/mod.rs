@@ -1,6 +1,10 @@
 //! Backend pool management and load balancing algorithms.
 
 pub mod algorithms;
+mod drain;
+mod overlay;
 mod router;
 
-pub use router::BackendRouter;
+pub use drain::{DrainReason, DrainSignal};
+pub use overlay::{OverlayError, ServerOverlay};
+pub use router::{BackendRouter, ConnectionGuard, DrainingServer, PoolError};
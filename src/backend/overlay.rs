@@ -0,0 +1,318 @@
+//! Runtime changes to backend pools.
+//!
+//! Servers added to or removed from a pool at runtime are recorded in a
+//! [`ServerOverlay`], which is applied on top of the configured pools on
+//! every reload so the changes are not lost. With a state file, the overlay
+//! is also written to disk and survives restarts.
+
+use crate::config::{BackendConfig, ServerConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Error reading or writing the overlay state file.
+#[derive(Debug, thiserror::Error)]
+pub enum OverlayError {
+    #[error("failed to read state file '{0}': {1}")]
+    Read(PathBuf, io::Error),
+
+    #[error("invalid state file '{0}': {1}")]
+    Parse(PathBuf, serde_json::Error),
+
+    #[error("failed to write state file '{0}': {1}")]
+    Write(PathBuf, io::Error),
+}
+
+/// Runtime changes to a single pool.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct PoolChanges {
+    /// Servers added to the pool, or configured servers with a new weight.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    added: Vec<ServerConfig>,
+    /// Configured servers removed from the pool.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    removed: Vec<SocketAddr>,
+}
+
+impl PoolChanges {
+    fn len(&self) -> usize {
+        self.added.len() + self.removed.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Servers added to and removed from backend pools at runtime.
+///
+/// Changes are kept relative to the configured pools: a server that is
+/// added to the configuration file stops being an addition, and removing a
+/// server that is no longer configured is forgotten.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ServerOverlay {
+    /// Changes per backend name.
+    #[serde(default)]
+    backends: BTreeMap<String, PoolChanges>,
+    /// Servers of the configured pools, as of the last [`apply`](Self::apply).
+    #[serde(skip)]
+    configured: HashMap<String, Vec<ServerConfig>>,
+    /// File the overlay is persisted to.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl ServerOverlay {
+    /// Create an empty overlay that is only kept in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the overlay persisted at `path`, or start an empty one if the
+    /// file does not exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, OverlayError> {
+        let path = path.into();
+        let mut overlay = match fs::read(&path) {
+            Ok(data) => {
+                serde_json::from_slice(&data).map_err(|e| OverlayError::Parse(path.clone(), e))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::new(),
+            Err(e) => return Err(OverlayError::Read(path, e)),
+        };
+        info!(path = %path.display(), changes = overlay.len(), "loaded backend state file");
+        overlay.path = Some(path);
+        Ok(overlay)
+    }
+
+    /// File the overlay is persisted to, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Number of servers added or removed.
+    pub fn len(&self) -> usize {
+        self.backends.values().map(PoolChanges::len).sum()
+    }
+
+    /// Whether the overlay records no changes.
+    pub fn is_empty(&self) -> bool {
+        self.backends.is_empty()
+    }
+
+    /// Apply the overlay to the configured pools.
+    ///
+    /// Changes that no longer make a difference to the configuration are
+    /// dropped first. A pool never loses all its servers: if every configured
+    /// server was removed and none was added, the removals are ignored.
+    pub(crate) fn apply(&mut self, backends: &[BackendConfig]) -> Vec<BackendConfig> {
+        self.configured = backends
+            .iter()
+            .map(|b| (b.name.clone(), b.servers.clone()))
+            .collect();
+        if self.prune() {
+            self.persist();
+        }
+
+        backends
+            .iter()
+            .map(|backend| {
+                let mut backend = backend.clone();
+                let Some(changes) = self.backends.get(&backend.name) else {
+                    return backend;
+                };
+                let mut servers: Vec<ServerConfig> = backend
+                    .servers
+                    .iter()
+                    .filter(|s| !changes.removed.contains(&s.address))
+                    .filter(|s| !changes.added.iter().any(|a| a.address == s.address))
+                    .cloned()
+                    .collect();
+                servers.extend(changes.added.iter().cloned());
+                if servers.is_empty() {
+                    warn!(
+                        backend = %backend.name,
+                        "ignoring runtime removals that would leave the backend without servers"
+                    );
+                } else {
+                    backend.servers = servers;
+                }
+                backend
+            })
+            .collect()
+    }
+
+    /// Record that `server` was added to a pool.
+    pub(crate) fn add(&mut self, backend: &str, server: ServerConfig) {
+        let changes = self.backends.entry(backend.to_string()).or_default();
+        changes.removed.retain(|s| *s != server.address);
+        changes.added.retain(|s| s.address != server.address);
+        let configured = self.configured.get(backend).is_some_and(|servers| {
+            servers
+                .iter()
+                .any(|s| s.address == server.address && s.weight == server.weight)
+        });
+        if !configured {
+            changes.added.push(server);
+        }
+        self.prune();
+    }
+
+    /// Record that `server` was removed from a pool.
+    pub(crate) fn remove(&mut self, backend: &str, server: SocketAddr) {
+        let changes = self.backends.entry(backend.to_string()).or_default();
+        changes.added.retain(|s| s.address != server);
+        let configured = self
+            .configured
+            .get(backend)
+            .is_some_and(|servers| servers.iter().any(|s| s.address == server));
+        if configured && !changes.removed.contains(&server) {
+            changes.removed.push(server);
+        }
+        self.prune();
+    }
+
+    /// Write the overlay to its state file, if it has one.
+    ///
+    /// The file is replaced atomically, so a crash never leaves it half
+    /// written.
+    pub(crate) fn save(&self) -> Result<(), OverlayError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut data = serde_json::to_vec_pretty(self).expect("overlay serializes to JSON");
+        data.push(b'\n');
+
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, &data)
+            .and_then(|()| fs::rename(&temp, path))
+            .map_err(|e| OverlayError::Write(path.clone(), e))
+    }
+
+    /// Save the overlay, logging failures.
+    fn persist(&self) {
+        if let Err(e) = self.save() {
+            warn!(error = %e, "failed to update backend state file");
+        }
+    }
+
+    /// Drop changes that make no difference to the configured pools.
+    ///
+    /// Returns whether anything was dropped.
+    fn prune(&mut self) -> bool {
+        let configured = &self.configured;
+        let before = self.len();
+        self.backends.retain(|name, changes| {
+            let Some(servers) = configured.get(name) else {
+                return false;
+            };
+            changes.added.retain(|added| {
+                !servers
+                    .iter()
+                    .any(|s| s.address == added.address && s.weight == added.weight)
+            });
+            changes
+                .removed
+                .retain(|removed| servers.iter().any(|s| s.address == *removed));
+            !changes.is_empty()
+        });
+        self.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(address: &str, weight: u32) -> ServerConfig {
+        ServerConfig {
+            address: address.parse().unwrap(),
+            weight,
+            metadata: Default::default(),
+        }
+    }
+
+    fn backends(servers: &[&str]) -> Vec<BackendConfig> {
+        vec![BackendConfig {
+            name: "api".to_string(),
+            servers: servers.iter().map(|s| server(s, 1)).collect(),
+            ..Default::default()
+        }]
+    }
+
+    fn addresses(backends: &[BackendConfig]) -> Vec<String> {
+        backends[0]
+            .servers
+            .iter()
+            .map(|s| s.address.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let configured = backends(&["127.0.0.1:9001", "127.0.0.1:9002"]);
+        let mut overlay = ServerOverlay::new();
+        overlay.apply(&configured);
+
+        overlay.add("api", server("127.0.0.1:9003", 2));
+        overlay.remove("api", "127.0.0.1:9001".parse().unwrap());
+        let applied = overlay.apply(&configured);
+        assert_eq!(addresses(&applied), ["127.0.0.1:9002", "127.0.0.1:9003"]);
+        assert_eq!(applied[0].servers[1].weight, 2);
+
+        // Removing an added server forgets it entirely
+        overlay.remove("api", "127.0.0.1:9003".parse().unwrap());
+        assert_eq!(overlay.backends["api"].added.len(), 0);
+
+        // Adding a configured server back undoes its removal
+        overlay.add("api", server("127.0.0.1:9001", 1));
+        assert!(overlay.is_empty());
+    }
+
+    #[test]
+    fn test_apply_prunes_against_configuration() {
+        let mut overlay = ServerOverlay::new();
+        overlay.apply(&backends(&["127.0.0.1:9001", "127.0.0.1:9002"]));
+        overlay.add("api", server("127.0.0.1:9003", 1));
+        overlay.remove("api", "127.0.0.1:9002".parse().unwrap());
+
+        // The added server is now configured and the removed one is gone
+        let applied = overlay.apply(&backends(&["127.0.0.1:9001", "127.0.0.1:9003"]));
+        assert_eq!(addresses(&applied), ["127.0.0.1:9001", "127.0.0.1:9003"]);
+        assert!(overlay.is_empty());
+
+        // Removals never empty a pool
+        overlay.remove("api", "127.0.0.1:9001".parse().unwrap());
+        overlay.remove("api", "127.0.0.1:9003".parse().unwrap());
+        let applied = overlay.apply(&backends(&["127.0.0.1:9001", "127.0.0.1:9003"]));
+        assert_eq!(applied[0].servers.len(), 2);
+    }
+
+    #[test]
+    fn test_state_file_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let configured = backends(&["127.0.0.1:9001"]);
+
+        let mut overlay = ServerOverlay::load(&path).unwrap();
+        assert!(overlay.is_empty());
+        overlay.apply(&configured);
+        overlay.add("api", server("127.0.0.1:9002", 3));
+        overlay.save().unwrap();
+
+        let mut restored = ServerOverlay::load(&path).unwrap();
+        let applied = restored.apply(&configured);
+        assert_eq!(addresses(&applied), ["127.0.0.1:9001", "127.0.0.1:9002"]);
+        assert_eq!(applied[0].servers[1].weight, 3);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            ServerOverlay::load(&path),
+            Err(OverlayError::Parse(..))
+        ));
+    }
+}
@@ -1,72 +1,305 @@
 //! Backend router for selecting upstream servers.
 
 use crate::backend::algorithms::{IpHash, LeastConnections, LoadBalancer, RoundRobin, ServerInfo, Weighted};
-use crate::config::{Algorithm, BackendConfig, FrontendConfig};
-use std::collections::HashMap;
+use crate::backend::drain::{DrainReason, DrainSignal, ServerSlot};
+use crate::backend::overlay::{OverlayError, ServerOverlay};
+use crate::config::{AllUnhealthyPolicy, Algorithm, BackendConfig, FrontendConfig, ServerConfig};
+use crate::health::HealthState;
+use crate::tls::ClientTls;
+use arc_swap::ArcSwap;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tracing::{debug, warn};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
 
 /// Routes requests to backend servers based on configured algorithm.
+///
+/// Only servers that the shared [`HealthState`] considers healthy (and not in
+/// cooldown) are offered to the load balancing algorithm. Draining servers,
+/// removed by a hot reload or disabled by an operator, are never offered.
+///
+/// The backend pools live behind an [`ArcSwap`] so they can be replaced
+/// atomically on hot reload, or when servers are added and removed at
+/// runtime, without blocking selection.
 pub struct BackendRouter {
     /// Map of backend name to backend info.
-    backends: HashMap<String, BackendInfo>,
+    backends: ArcSwap<HashMap<String, Arc<BackendInfo>>>,
+    /// Servers removed from their pool whose connections are draining.
+    retired: Mutex<Vec<RetiredServer>>,
+    /// Configured and discovered servers, and runtime changes to them.
+    sources: Mutex<Sources>,
+    /// Shared health state used to filter out unhealthy servers.
+    health_state: Arc<HealthState>,
+    /// Generation counter, bumped every time the backend pools change.
+    changes: watch::Sender<u64>,
 }
 
 /// Information about a backend pool.
+#[derive(Clone)]
 struct BackendInfo {
+    /// Configuration the pool was built from.
+    config: Arc<BackendConfig>,
     /// List of servers with their weights.
     servers: Vec<ServerInfo>,
+    /// Algorithm kind, used to decide whether state can be carried over.
+    algorithm_kind: Algorithm,
     /// The load balancer algorithm.
     algorithm: Arc<dyn LoadBalancer>,
+    /// Behaviour when every server in the pool is unhealthy.
+    on_all_unhealthy: AllUnhealthyPolicy,
+    /// TLS settings for connections to the servers, if they speak TLS.
+    tls: Option<Arc<ClientTls>>,
+    /// Active connections and drain state per server.
+    slots: HashMap<SocketAddr, Arc<ServerSlot>>,
+}
+
+impl BackendInfo {
+    /// Check whether a server may be selected regardless of its health.
+    fn accepts(&self, server: SocketAddr) -> bool {
+        !self
+            .slots
+            .get(&server)
+            .is_some_and(|slot| slot.is_draining())
+    }
+}
+
+/// Everything the servers of the pools come from.
+struct Sources {
+    /// Backend configurations of the last reload.
+    backends: Vec<BackendConfig>,
+    /// Servers found by discovery, by backend and discovery source.
+    discovered: BTreeMap<(String, String), Vec<ServerConfig>>,
+    /// Servers added and removed at runtime.
+    overlay: ServerOverlay,
+    /// Weights changed at runtime, by backend and server, until the next
+    /// reload.
+    weights: BTreeMap<(String, SocketAddr), u32>,
+}
+
+impl Sources {
+    /// Backend configurations with their discovered servers and the runtime
+    /// changes applied.
+    fn pools(&mut self) -> Vec<BackendConfig> {
+        let mut backends = self.backends.clone();
+        for backend in &mut backends {
+            let discovered = self
+                .discovered
+                .iter()
+                .filter(|((name, _), _)| *name == backend.name)
+                .flat_map(|(_, servers)| servers);
+            for server in discovered {
+                if !backend.servers.iter().any(|s| s.address == server.address) {
+                    backend.servers.push(server.clone());
+                }
+            }
+        }
+        let mut backends = self.overlay.apply(&backends);
+        for backend in &mut backends {
+            for server in &mut backend.servers {
+                if let Some(weight) = self.weights.get(&(backend.name.clone(), server.address)) {
+                    server.weight = *weight;
+                }
+            }
+        }
+        backends
+    }
+}
+
+/// A server removed from its pool, kept until its connections are drained.
+struct RetiredServer {
+    backend: String,
+    server: SocketAddr,
+    slot: Arc<ServerSlot>,
+}
+
+/// A draining server and its remaining connections.
+#[derive(Debug, Clone)]
+pub struct DrainingServer {
+    /// Backend pool the server belongs, or belonged, to.
+    pub backend: String,
+    /// Server address.
+    pub server: SocketAddr,
+    /// Why the server is draining.
+    pub reason: DrainReason,
+    /// Active connections and requests.
+    pub active: u32,
+    /// Time left until the remaining connections are closed.
+    pub remaining: Duration,
+}
+
+/// Error adding or removing a server at runtime.
+#[derive(Debug, thiserror::Error)]
+pub enum PoolError {
+    #[error("backend '{0}' not found")]
+    BackendNotFound(String),
+
+    #[error("server {1} not found in backend '{0}'")]
+    ServerNotFound(String, SocketAddr),
+
+    #[error("server {1} is already in backend '{0}'")]
+    ServerExists(String, SocketAddr),
+
+    #[error("cannot remove the last server of backend '{0}'")]
+    LastServer(String),
+
+    #[error(transparent)]
+    Overlay(#[from] OverlayError),
 }
 
 impl BackendRouter {
     /// Create a new backend router from configuration.
+    ///
+    /// The router gets its own private health state, so every server is
+    /// treated as healthy. Use [`BackendRouter::with_health_state`] to share
+    /// the state maintained by the health checker.
     pub fn new(backends: &[BackendConfig], frontends: &[FrontendConfig]) -> Self {
-        let mut backend_map = HashMap::new();
+        Self::with_health_state(backends, frontends, Arc::new(HealthState::new()))
+    }
 
-        // Build a map of frontend -> algorithm
-        let frontend_algorithms: HashMap<&str, Algorithm> = frontends
-            .iter()
-            .map(|f| (f.backend.as_str(), f.algorithm.clone()))
-            .collect();
+    /// Create a new backend router that consults the given health state.
+    pub fn with_health_state(
+        backends: &[BackendConfig],
+        frontends: &[FrontendConfig],
+        health_state: Arc<HealthState>,
+    ) -> Self {
+        Self::with_overlay(backends, frontends, health_state, ServerOverlay::new())
+    }
 
-        for backend in backends {
-            let servers: Vec<ServerInfo> = backend
-                .servers
-                .iter()
-                .map(|s| ServerInfo {
-                    address: s.address,
-                    weight: s.weight,
-                })
-                .collect();
+    /// Create a new backend router whose pools include the servers added
+    /// and removed at runtime that `overlay` records, for example in a state
+    /// file from an earlier run.
+    pub fn with_overlay(
+        backends: &[BackendConfig],
+        frontends: &[FrontendConfig],
+        health_state: Arc<HealthState>,
+        overlay: ServerOverlay,
+    ) -> Self {
+        let mut sources = Sources {
+            backends: backends.to_vec(),
+            discovered: BTreeMap::new(),
+            overlay,
+            weights: BTreeMap::new(),
+        };
+        let backend_map =
+            build_backends(&sources.pools(), frontends, &HashMap::new(), &mut Vec::new());
+        let (changes, _) = watch::channel(0);
 
-            // Get the algorithm for this backend (from the frontend that uses it)
-            let algorithm = frontend_algorithms
-                .get(backend.name.as_str())
-                .cloned()
-                .unwrap_or(Algorithm::RoundRobin);
+        Self {
+            backends: ArcSwap::from_pointee(backend_map),
+            retired: Mutex::new(Vec::new()),
+            sources: Mutex::new(sources),
+            health_state,
+            changes,
+        }
+    }
 
-            let lb: Arc<dyn LoadBalancer> = match algorithm {
-                Algorithm::RoundRobin => Arc::new(RoundRobin::new()),
-                Algorithm::Weighted => Arc::new(Weighted::new()),
-                Algorithm::LeastConnections => Arc::new(LeastConnections::new()),
-                Algorithm::IpHash => Arc::new(IpHash::new()),
-            };
+    /// Atomically replace the backend pools with a new configuration.
+    ///
+    /// Pools that keep their algorithm reuse the existing load balancer, so
+    /// round-robin positions and least-connections counts survive the reload.
+    /// Servers that left their pool start draining: their established
+    /// connections may finish until the old pool's `drain_timeout`.
+    ///
+    /// Discovered servers stay in their pool, and servers added and removed
+    /// at runtime stay added and removed. Weights changed at runtime go back
+    /// to the configured ones.
+    pub fn reload(&self, backends: &[BackendConfig], frontends: &[FrontendConfig]) {
+        let mut sources = self.sources.lock();
+        sources.backends = backends.to_vec();
+        sources.weights.clear();
+        sources
+            .discovered
+            .retain(|(name, _), _| backends.iter().any(|b| &b.name == name));
+        let backends = sources.pools();
+        let current = self.backends.load();
+        let mut retired = self.retired.lock();
+        let backend_map = build_backends(&backends, frontends, &current, &mut retired);
 
-            backend_map.insert(
-                backend.name.clone(),
-                BackendInfo {
-                    servers,
-                    algorithm: lb,
-                },
-            );
+        for name in current.keys().filter(|n| !backend_map.contains_key(*n)) {
+            info!(backend = %name, "backend removed");
+        }
+        for name in backend_map.keys().filter(|n| !current.contains_key(*n)) {
+            info!(backend = %name, "backend added");
         }
 
-        Self {
-            backends: backend_map,
+        for (name, old) in current.iter() {
+            let new = backend_map.get(name);
+            for (server, slot) in &old.slots {
+                if new.is_some_and(|new| new.slots.contains_key(server)) {
+                    continue;
+                }
+                slot.start_drain(DrainReason::Removed, old.config.drain_timeout);
+                info!(
+                    backend = %name,
+                    server = %server,
+                    active = slot.active(),
+                    drain_timeout = ?old.config.drain_timeout,
+                    "server removed, draining connections"
+                );
+                retired.push(RetiredServer {
+                    backend: name.clone(),
+                    server: *server,
+                    slot: Arc::clone(slot),
+                });
+            }
         }
+        prune_retired(&mut retired);
+        drop(retired);
+
+        self.backends.store(Arc::new(backend_map));
+        drop(sources);
+        self.changes.send_modify(|generation| *generation += 1);
+    }
+
+    /// Subscribe to backend pool changes.
+    ///
+    /// The received value is a generation counter that increases on every
+    /// reload, and whenever servers are added, removed or reweighted.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    /// Get the current configuration of every backend pool.
+    pub fn backend_configs(&self) -> Vec<BackendConfig> {
+        self.backends
+            .load()
+            .values()
+            .map(|b| b.config.as_ref().clone())
+            .collect()
+    }
+
+    /// Get the current configuration of a single backend pool.
+    pub fn backend_config(&self, backend_name: &str) -> Option<Arc<BackendConfig>> {
+        self.backends
+            .load()
+            .get(backend_name)
+            .map(|b| Arc::clone(&b.config))
+    }
+
+    /// Get the TLS settings for connecting to the servers of a backend pool.
+    ///
+    /// Returns `None` for plaintext backends and unknown names.
+    pub fn backend_tls(&self, backend_name: &str) -> Option<Arc<ClientTls>> {
+        self.backends
+            .load()
+            .get(backend_name)
+            .and_then(|b| b.tls.clone())
+    }
+
+    /// Get the load balancing algorithm of a backend pool.
+    pub fn algorithm(&self, backend_name: &str) -> Option<Algorithm> {
+        self.backends
+            .load()
+            .get(backend_name)
+            .map(|b| b.algorithm_kind.clone())
+    }
+
+    /// Get the health state consulted during selection.
+    pub fn health_state(&self) -> &Arc<HealthState> {
+        &self.health_state
     }
 
     /// Select a backend server for the given backend name.
@@ -78,20 +311,81 @@ impl BackendRouter {
     ///
     /// # Returns
     ///
-    /// The selected server address, or None if no servers available.
+    /// The selected server address, or None if no servers available. When
+    /// every server is unhealthy, the backend's [`AllUnhealthyPolicy`] decides
+    /// between failing open to all servers and returning None.
     pub fn select(
         &self,
         backend_name: &str,
         client_addr: Option<SocketAddr>,
     ) -> Option<SocketAddr> {
-        let backend = self.backends.get(backend_name)?;
+        self.select_excluding(backend_name, client_addr, &[])
+    }
+
+    /// Select a backend server other than the ones in `exclude`.
+    ///
+    /// Used to retry a request on a different server. Behaves like
+    /// [`BackendRouter::select`] over the remaining servers, and returns None
+    /// once every server has been excluded.
+    pub fn select_excluding(
+        &self,
+        backend_name: &str,
+        client_addr: Option<SocketAddr>,
+        exclude: &[SocketAddr],
+    ) -> Option<SocketAddr> {
+        let backends = self.backends.load();
+        let backend = backends.get(backend_name)?;
 
         if backend.servers.is_empty() {
             warn!(backend = backend_name, "no servers configured for backend");
             return None;
         }
 
-        let selected = backend.algorithm.select(&backend.servers, client_addr);
+        // Fast path: avoid allocating when the whole pool is healthy
+        let all_available = exclude.is_empty()
+            && backend
+                .servers
+                .iter()
+                .all(|s| backend.accepts(s.address) && self.health_state.is_available(s.address));
+
+        let selected = if all_available {
+            backend.algorithm.select(&backend.servers, client_addr)
+        } else {
+            let remaining: Vec<ServerInfo> = backend
+                .servers
+                .iter()
+                .filter(|s| !exclude.contains(&s.address) && backend.accepts(s.address))
+                .copied()
+                .collect();
+            if remaining.is_empty() {
+                debug!(
+                    backend = backend_name,
+                    "every server has been tried or is draining"
+                );
+                return None;
+            }
+
+            let healthy: Vec<ServerInfo> = remaining
+                .iter()
+                .filter(|s| self.health_state.is_available(s.address))
+                .copied()
+                .collect();
+
+            if !healthy.is_empty() {
+                backend.algorithm.select(&healthy, client_addr)
+            } else {
+                match backend.on_all_unhealthy {
+                    AllUnhealthyPolicy::FailOpen => {
+                        warn!(
+                            backend = backend_name,
+                            "all servers unhealthy, failing open to all servers"
+                        );
+                        backend.algorithm.select(&remaining, client_addr)
+                    }
+                    AllUnhealthyPolicy::Reject => None,
+                }
+            }
+        };
 
         if let Some(addr) = selected {
             debug!(backend = backend_name, server = %addr, "selected backend server");
@@ -105,33 +399,593 @@ impl BackendRouter {
     /// Get all servers for a backend.
     pub fn get_servers(&self, backend_name: &str) -> Option<Vec<SocketAddr>> {
         self.backends
+            .load()
             .get(backend_name)
             .map(|b| b.servers.iter().map(|s| s.address).collect())
     }
 
     /// Notify that a connection was established to a server.
     pub fn on_connect(&self, backend_name: &str, server: SocketAddr) {
-        if let Some(backend) = self.backends.get(backend_name) {
+        if let Some(backend) = self.backends.load().get(backend_name) {
             backend.algorithm.on_connect(server);
         }
+        self.health_state.increment_connections(server);
     }
 
     /// Notify that a connection was closed to a server.
     pub fn on_disconnect(&self, backend_name: &str, server: SocketAddr) {
-        if let Some(backend) = self.backends.get(backend_name) {
+        if let Some(backend) = self.backends.load().get(backend_name) {
             backend.algorithm.on_disconnect(server);
         }
+        self.health_state.decrement_connections(server);
+    }
+
+    /// Track an active connection or request to a server.
+    ///
+    /// Calls [`BackendRouter::on_connect`] now and
+    /// [`BackendRouter::on_disconnect`] when the returned guard is dropped.
+    /// The guard also counts towards the server's active connections and
+    /// tells when the server's drain timeout expires.
+    pub fn track(self: &Arc<Self>, backend_name: &str, server: SocketAddr) -> ConnectionGuard {
+        self.on_connect(backend_name, server);
+        let slot = self
+            .slot(backend_name, server)
+            .unwrap_or_else(|| Arc::new(ServerSlot::new()));
+        slot.acquire();
+        ConnectionGuard {
+            router: Arc::clone(self),
+            backend_name: backend_name.to_string(),
+            server,
+            slot,
+        }
+    }
+
+    /// Find the slot of a server in a pool, or of a retired server.
+    fn slot(&self, backend_name: &str, server: SocketAddr) -> Option<Arc<ServerSlot>> {
+        if let Some(slot) = self
+            .backends
+            .load()
+            .get(backend_name)
+            .and_then(|b| b.slots.get(&server))
+        {
+            return Some(Arc::clone(slot));
+        }
+        self.retired
+            .lock()
+            .iter()
+            .find(|r| r.backend == backend_name && r.server == server)
+            .map(|r| Arc::clone(&r.slot))
+    }
+
+    /// Disable a server: it gets no new connections or requests, and its
+    /// existing ones are closed after the backend's `drain_timeout`.
+    ///
+    /// Returns false if the server is not in the pool.
+    pub fn disable_server(&self, backend_name: &str, server: SocketAddr) -> bool {
+        let Some(drain_timeout) = self
+            .backend_config(backend_name)
+            .map(|config| config.drain_timeout)
+        else {
+            return false;
+        };
+        self.drain_server(backend_name, server, drain_timeout)
+    }
+
+    /// Disable a server, closing its existing connections after `timeout`
+    /// instead of the backend's `drain_timeout`.
+    ///
+    /// A server that is already draining keeps its deadline. Returns false if
+    /// the server is not in the pool.
+    pub fn drain_server(&self, backend_name: &str, server: SocketAddr, timeout: Duration) -> bool {
+        let backends = self.backends.load();
+        let Some(slot) = backends
+            .get(backend_name)
+            .and_then(|b| b.slots.get(&server))
+        else {
+            return false;
+        };
+
+        if slot.start_drain(DrainReason::Disabled, timeout) {
+            info!(
+                backend = backend_name,
+                server = %server,
+                active = slot.active(),
+                drain_timeout = ?timeout,
+                "server disabled, draining connections"
+            );
+        }
+        true
+    }
+
+    /// Change the weight of a server until the next reload.
+    ///
+    /// The new weight survives servers being added, removed and discovered
+    /// in the meantime. Returns false if the server is not in the pool.
+    pub fn set_weight(&self, backend_name: &str, server: SocketAddr, weight: u32) -> bool {
+        let mut sources = self.sources.lock();
+        let found = self
+            .backends
+            .load()
+            .get(backend_name)
+            .is_some_and(|backend| backend.slots.contains_key(&server));
+        if !found {
+            return false;
+        }
+        sources
+            .weights
+            .insert((backend_name.to_string(), server), weight);
+        let changed = self.sync_pool(&mut sources, backend_name);
+        drop(sources);
+
+        info!(
+            backend = backend_name,
+            server = %server,
+            weight = weight,
+            "server weight changed"
+        );
+        if changed {
+            self.changes.send_modify(|generation| *generation += 1);
+        }
+        true
+    }
+
+    /// Add a server to a pool.
+    ///
+    /// The server gets traffic right away, and health checks start for it if
+    /// the backend has them. The addition survives reloads, and restarts if
+    /// the router has a state file. A server that was removed and is still
+    /// draining gets its connections back.
+    pub fn add_server(&self, backend_name: &str, server: ServerConfig) -> Result<(), PoolError> {
+        let mut sources = self.sources.lock();
+        let backends = self.backends.load();
+        let backend = backends
+            .get(backend_name)
+            .ok_or_else(|| PoolError::BackendNotFound(backend_name.to_string()))?;
+        if backend.slots.contains_key(&server.address) {
+            return Err(PoolError::ServerExists(
+                backend_name.to_string(),
+                server.address,
+            ));
+        }
+
+        let key = (backend_name.to_string(), server.address);
+        let mut overlay = sources.overlay.clone();
+        overlay.add(backend_name, server);
+        overlay.save()?;
+        sources.overlay = overlay;
+        sources.weights.remove(&key);
+
+        self.sync_pool(&mut sources, backend_name);
+        drop(sources);
+        self.changes.send_modify(|generation| *generation += 1);
+        Ok(())
+    }
+
+    /// Remove a server from a pool.
+    ///
+    /// The server gets no new connections or requests, and its existing ones
+    /// are closed after the backend's `drain_timeout`. The removal survives
+    /// reloads, and restarts if the router has a state file. The last server
+    /// of a pool cannot be removed.
+    pub fn remove_server(&self, backend_name: &str, server: SocketAddr) -> Result<(), PoolError> {
+        let mut sources = self.sources.lock();
+        let backends = self.backends.load();
+        let backend = backends
+            .get(backend_name)
+            .ok_or_else(|| PoolError::BackendNotFound(backend_name.to_string()))?;
+        if !backend.slots.contains_key(&server) {
+            return Err(PoolError::ServerNotFound(backend_name.to_string(), server));
+        }
+        if backend.servers.len() == 1 {
+            return Err(PoolError::LastServer(backend_name.to_string()));
+        }
+
+        let mut overlay = sources.overlay.clone();
+        overlay.remove(backend_name, server);
+        overlay.save()?;
+        sources.overlay = overlay;
+        sources.weights.remove(&(backend_name.to_string(), server));
+
+        self.sync_pool(&mut sources, backend_name);
+        drop(sources);
+        self.changes.send_modify(|generation| *generation += 1);
+        Ok(())
+    }
+
+    /// Replace the servers a discovery source found for a pool.
+    ///
+    /// Discovered servers join the configured ones. Servers that are no
+    /// longer discovered start draining like servers removed by a reload.
+    /// Nothing changes if the source found the same servers as before.
+    pub fn set_discovered(&self, backend_name: &str, source: &str, servers: Vec<ServerConfig>) {
+        let mut sources = self.sources.lock();
+        let key = (backend_name.to_string(), source.to_string());
+        if sources.discovered.get(&key).map_or(&[][..], Vec::as_slice) == servers.as_slice() {
+            return;
+        }
+        if servers.is_empty() {
+            sources.discovered.remove(&key);
+        } else {
+            sources.discovered.insert(key, servers);
+        }
+
+        if self.sync_pool(&mut sources, backend_name) {
+            drop(sources);
+            self.changes.send_modify(|generation| *generation += 1);
+        }
+    }
+
+    /// Bring the servers of a pool in line with its sources, without
+    /// rebuilding it.
+    ///
+    /// Returns whether the pool changed.
+    fn sync_pool(&self, sources: &mut Sources, backend_name: &str) -> bool {
+        let Some(pool) = sources
+            .pools()
+            .into_iter()
+            .find(|pool| pool.name == backend_name)
+        else {
+            return false;
+        };
+        let servers = pool.servers;
+
+        let backends = self.backends.load();
+        let Some(backend) = backends.get(backend_name) else {
+            return false;
+        };
+        let mut retired = self.retired.lock();
+        let removed: Vec<(SocketAddr, Arc<ServerSlot>)> = backend
+            .slots
+            .iter()
+            .filter(|(address, _)| !servers.iter().any(|s| s.address == **address))
+            .map(|(address, slot)| (*address, Arc::clone(slot)))
+            .collect();
+        let added: Vec<(&ServerConfig, Arc<ServerSlot>)> = servers
+            .iter()
+            .filter(|s| !backend.slots.contains_key(&s.address))
+            .map(|s| {
+                let slot = reclaim(&mut retired, backend_name, s.address)
+                    .unwrap_or_else(|| Arc::new(ServerSlot::new()));
+                (s, slot)
+            })
+            .collect();
+        // Weights and metadata may change without servers coming or going
+        if removed.is_empty() && added.is_empty() && backend.config.servers == servers {
+            return false;
+        }
+
+        self.update_backend(backend_name, |backend, config| {
+            backend.servers = servers
+                .iter()
+                .map(|s| ServerInfo {
+                    address: s.address,
+                    weight: s.weight,
+                })
+                .collect();
+            backend
+                .slots
+                .retain(|address, _| servers.iter().any(|s| s.address == *address));
+            for (server, slot) in &added {
+                backend.slots.insert(server.address, Arc::clone(slot));
+            }
+            config.servers = servers.clone();
+            true
+        });
+
+        for (server, _) in &added {
+            info!(
+                backend = backend_name,
+                server = %server.address,
+                weight = server.weight,
+                "server added"
+            );
+        }
+        let drain_timeout = backend.config.drain_timeout;
+        for (server, slot) in removed {
+            slot.start_drain(DrainReason::Removed, drain_timeout);
+            info!(
+                backend = backend_name,
+                server = %server,
+                active = slot.active(),
+                drain_timeout = ?drain_timeout,
+                "server removed, draining connections"
+            );
+            retired.push(RetiredServer {
+                backend: backend_name.to_string(),
+                server,
+                slot,
+            });
+        }
+        prune_retired(&mut retired);
+        true
+    }
+
+    /// Replace a pool with a copy changed by `update`, leaving the other
+    /// pools as they are.
+    ///
+    /// `update` gets the pool and its configuration, and may run more than
+    /// once if the pools change concurrently. Returns false, without changing
+    /// anything, if the pool does not exist or `update` returns false.
+    fn update_backend(
+        &self,
+        backend_name: &str,
+        update: impl Fn(&mut BackendInfo, &mut BackendConfig) -> bool,
+    ) -> bool {
+        let mut found = false;
+        self.backends.rcu(|current| {
+            let mut backends = HashMap::clone(current);
+            found = false;
+            if let Some(backend) = backends.get_mut(backend_name) {
+                let mut updated = BackendInfo::clone(backend);
+                let mut config = BackendConfig::clone(&updated.config);
+                if update(&mut updated, &mut config) {
+                    updated.config = Arc::new(config);
+                    *backend = Arc::new(updated);
+                    found = true;
+                }
+            }
+            backends
+        });
+        found
+    }
+
+    /// Put a disabled server back into rotation.
+    ///
+    /// Returns false if the server is not in the pool.
+    pub fn enable_server(&self, backend_name: &str, server: SocketAddr) -> bool {
+        let backends = self.backends.load();
+        let Some(slot) = backends
+            .get(backend_name)
+            .and_then(|b| b.slots.get(&server))
+        else {
+            return false;
+        };
+
+        if slot.cancel_drain(DrainReason::Disabled) {
+            info!(backend = backend_name, server = %server, "server enabled");
+        }
+        true
+    }
+
+    /// Check whether a server is draining.
+    pub fn is_draining(&self, backend_name: &str, server: SocketAddr) -> bool {
+        self.slot(backend_name, server)
+            .is_some_and(|slot| slot.is_draining())
+    }
+
+    /// Check whether a server may be selected: it is not draining and the
+    /// health state neither marks it unhealthy nor ejected.
+    pub fn is_available(&self, backend_name: &str, server: SocketAddr) -> bool {
+        !self.is_draining(backend_name, server) && self.health_state.is_available(server)
+    }
+
+    /// Number of active connections and requests to a server.
+    pub fn active_connections(&self, backend_name: &str, server: SocketAddr) -> u32 {
+        self.slot(backend_name, server)
+            .map(|slot| slot.active())
+            .unwrap_or(0)
+    }
+
+    /// List the draining servers, in pools and removed ones, with their
+    /// remaining connections.
+    pub fn draining_servers(&self) -> Vec<DrainingServer> {
+        let now = Instant::now();
+        let status = |backend: &str, server: SocketAddr, slot: &ServerSlot| {
+            slot.drain().map(|drain| DrainingServer {
+                backend: backend.to_string(),
+                server,
+                reason: drain.reason,
+                active: slot.active(),
+                remaining: drain.deadline.saturating_duration_since(now),
+            })
+        };
+
+        let mut draining: Vec<DrainingServer> = self
+            .backends
+            .load()
+            .iter()
+            .flat_map(|(name, backend)| {
+                backend
+                    .slots
+                    .iter()
+                    .filter_map(|(server, slot)| status(name, *server, slot))
+            })
+            .collect();
+
+        let mut retired = self.retired.lock();
+        prune_retired(&mut retired);
+        draining.extend(
+            retired
+                .iter()
+                .filter_map(|r| status(&r.backend, r.server, &r.slot)),
+        );
+
+        draining.sort_by(|a, b| (&a.backend, a.server).cmp(&(&b.backend, b.server)));
+        draining
     }
 
     /// Get connection count for a server (for metrics/debugging).
     pub fn connection_count(&self, backend_name: &str, server: SocketAddr) -> u32 {
         self.backends
+            .load()
             .get(backend_name)
             .map(|b| b.algorithm.connection_count(server))
             .unwrap_or(0)
     }
 }
 
+/// Guard for an active connection or request to a backend server.
+///
+/// Created by [`BackendRouter::track`]; notifies the router when dropped so
+/// least-connections counts reflect real concurrency.
+pub struct ConnectionGuard {
+    router: Arc<BackendRouter>,
+    backend_name: String,
+    server: SocketAddr,
+    slot: Arc<ServerSlot>,
+}
+
+impl ConnectionGuard {
+    /// The server this guard tracks.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Signal that fires once the server's drain timeout expires.
+    pub fn drain_signal(&self) -> DrainSignal {
+        self.slot.signal()
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.slot.release();
+        self.router.on_disconnect(&self.backend_name, self.server);
+    }
+}
+
+/// Forget retired servers whose drain timeout expired and whose connections
+/// are all closed.
+fn prune_retired(retired: &mut Vec<RetiredServer>) {
+    let now = Instant::now();
+    retired.retain(|r| {
+        r.slot.active() > 0 || r.slot.drain().is_some_and(|drain| drain.deadline > now)
+    });
+}
+
+/// Take back the slot of a retired server that returns to its pool, which
+/// stops its drain.
+fn reclaim(
+    retired: &mut Vec<RetiredServer>,
+    backend_name: &str,
+    server: SocketAddr,
+) -> Option<Arc<ServerSlot>> {
+    let index = retired
+        .iter()
+        .position(|r| r.backend == backend_name && r.server == server)?;
+    let slot = retired.swap_remove(index).slot;
+    slot.cancel_drain(DrainReason::Removed);
+    info!(
+        backend = backend_name,
+        server = %server,
+        "server returned to pool, drain stopped"
+    );
+    Some(slot)
+}
+
+/// Build the backend map, reusing load balancers from `previous` where the
+/// algorithm is unchanged.
+///
+/// Server slots are carried over from `previous`, or taken back from
+/// `retired` for servers that return to their pool, which stops their drain.
+fn build_backends(
+    backends: &[BackendConfig],
+    frontends: &[FrontendConfig],
+    previous: &HashMap<String, Arc<BackendInfo>>,
+    retired: &mut Vec<RetiredServer>,
+) -> HashMap<String, Arc<BackendInfo>> {
+    let mut backend_map = HashMap::new();
+
+    // Build a map of backend -> algorithm of the frontend that uses it,
+    // either as its default backend or as the target of a route
+    let frontend_algorithms: HashMap<&str, Algorithm> = frontends
+        .iter()
+        .flat_map(|f| {
+            std::iter::once(f.backend.as_str())
+                .chain(f.routes.iter().map(|r| r.backend.as_str()))
+                .map(move |backend| (backend, f.algorithm.clone()))
+        })
+        .collect();
+
+    for backend in backends {
+        // Configurations whose backend TLS settings fail to load are refused
+        // before they get here, but the files may change in the meantime
+        let tls = match backend
+            .tls
+            .as_ref()
+            .map(|tls| ClientTls::new(tls, backend.protocol))
+            .transpose()
+        {
+            Ok(tls) => tls.map(Arc::new),
+            Err(e) => match previous.get(&backend.name) {
+                // Keep the certificates that were loaded before
+                Some(old) if old.tls.is_some() => {
+                    error!(
+                        backend = %backend.name,
+                        error = %e,
+                        "failed to load backend TLS settings, keeping previous certificates"
+                    );
+                    old.tls.clone()
+                }
+                // Never fall back to plaintext
+                _ => {
+                    error!(
+                        backend = %backend.name,
+                        error = %e,
+                        "failed to load backend TLS settings, backend disabled"
+                    );
+                    continue;
+                }
+            },
+        };
+
+        let servers: Vec<ServerInfo> = backend
+            .servers
+            .iter()
+            .map(|s| ServerInfo {
+                address: s.address,
+                weight: s.weight,
+            })
+            .collect();
+
+        // Get the algorithm for this backend (from the frontend that uses it)
+        let algorithm = frontend_algorithms
+            .get(backend.name.as_str())
+            .cloned()
+            .unwrap_or(Algorithm::RoundRobin);
+
+        let lb: Arc<dyn LoadBalancer> = match previous.get(&backend.name) {
+            Some(old) if old.algorithm_kind == algorithm => Arc::clone(&old.algorithm),
+            _ => match algorithm {
+                Algorithm::RoundRobin => Arc::new(RoundRobin::new()),
+                Algorithm::Weighted => Arc::new(Weighted::new()),
+                Algorithm::LeastConnections => Arc::new(LeastConnections::new()),
+                Algorithm::IpHash => Arc::new(IpHash::new()),
+            },
+        };
+
+        let slots = servers
+            .iter()
+            .map(|s| {
+                let previous_slot = previous
+                    .get(&backend.name)
+                    .and_then(|old| old.slots.get(&s.address))
+                    .cloned();
+                let slot = previous_slot.unwrap_or_else(|| {
+                    reclaim(retired, &backend.name, s.address)
+                        .unwrap_or_else(|| Arc::new(ServerSlot::new()))
+                });
+                (s.address, slot)
+            })
+            .collect();
+
+        backend_map.insert(
+            backend.name.clone(),
+            Arc::new(BackendInfo {
+                config: Arc::new(backend.clone()),
+                servers,
+                algorithm_kind: algorithm,
+                algorithm: lb,
+                on_all_unhealthy: backend.on_all_unhealthy,
+                tls,
+                slots,
+            }),
+        );
+    }
+
+    backend_map
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,13 +998,15 @@ mod tests {
                 ServerConfig {
                     address: "127.0.0.1:9001".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 },
                 ServerConfig {
                     address: "127.0.0.1:9002".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 },
             ],
-            health_check: None,
+            ..Default::default()
         }]
     }
 
@@ -160,9 +1016,11 @@ mod tests {
             listen: "127.0.0.1:8080".parse().unwrap(),
             protocol: crate::config::Protocol::Tcp,
             backend: "test-backend".to_string(),
+            routes: Vec::new(),
             algorithm: Algorithm::RoundRobin,
             http: None,
             tcp: None,
+            tls: None,
         }]
     }
 
@@ -193,13 +1051,15 @@ mod tests {
                 ServerConfig {
                     address: "127.0.0.1:9001".parse().unwrap(),
                     weight: 3,
+                    metadata: Default::default(),
                 },
                 ServerConfig {
                     address: "127.0.0.1:9002".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 },
             ],
-            health_check: None,
+            ..Default::default()
         }];
 
         let frontends = vec![FrontendConfig {
@@ -207,9 +1067,11 @@ mod tests {
             listen: "127.0.0.1:8080".parse().unwrap(),
             protocol: crate::config::Protocol::Tcp,
             backend: "weighted-backend".to_string(),
+            routes: Vec::new(),
             algorithm: Algorithm::Weighted,
             http: None,
             tcp: None,
+            tls: None,
         }];
 
         let router = BackendRouter::new(&backends, &frontends);
@@ -239,13 +1101,15 @@ mod tests {
                 ServerConfig {
                     address: "127.0.0.1:9001".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 },
                 ServerConfig {
                     address: "127.0.0.1:9002".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 },
             ],
-            health_check: None,
+            ..Default::default()
         }];
 
         let frontends = vec![FrontendConfig {
@@ -253,9 +1117,11 @@ mod tests {
             listen: "127.0.0.1:8080".parse().unwrap(),
             protocol: crate::config::Protocol::Tcp,
             backend: "lc-backend".to_string(),
+            routes: Vec::new(),
             algorithm: Algorithm::LeastConnections,
             http: None,
             tcp: None,
+            tls: None,
         }];
 
         let router = BackendRouter::new(&backends, &frontends);
@@ -270,6 +1136,402 @@ mod tests {
         assert_eq!(selected, "127.0.0.1:9002".parse::<SocketAddr>().unwrap());
     }
 
+    fn unhealthy_state() -> Arc<HealthState> {
+        Arc::new(HealthState::with_config(crate::health::HealthConfig {
+            unhealthy_threshold: 1,
+            healthy_threshold: 1,
+            cooldown: std::time::Duration::from_secs(60),
+        }))
+    }
+
+    #[test]
+    fn test_skips_unhealthy_servers() {
+        let health_state = unhealthy_state();
+        let router = BackendRouter::with_health_state(
+            &test_backends(),
+            &test_frontends(),
+            Arc::clone(&health_state),
+        );
+
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let s2: SocketAddr = "127.0.0.1:9002".parse().unwrap();
+        health_state.record_failure(s1);
+
+        for _ in 0..4 {
+            assert_eq!(router.select("test-backend", None), Some(s2));
+        }
+    }
+
+    #[test]
+    fn test_all_unhealthy_reject() {
+        let health_state = unhealthy_state();
+        let router = BackendRouter::with_health_state(
+            &test_backends(),
+            &test_frontends(),
+            Arc::clone(&health_state),
+        );
+
+        health_state.record_failure("127.0.0.1:9001".parse().unwrap());
+        health_state.record_failure("127.0.0.1:9002".parse().unwrap());
+
+        assert!(router.select("test-backend", None).is_none());
+    }
+
+    #[test]
+    fn test_all_unhealthy_fail_open() {
+        let health_state = unhealthy_state();
+        let mut backends = test_backends();
+        backends[0].on_all_unhealthy = AllUnhealthyPolicy::FailOpen;
+        let router = BackendRouter::with_health_state(
+            &backends,
+            &test_frontends(),
+            Arc::clone(&health_state),
+        );
+
+        health_state.record_failure("127.0.0.1:9001".parse().unwrap());
+        health_state.record_failure("127.0.0.1:9002".parse().unwrap());
+
+        assert!(router.select("test-backend", None).is_some());
+    }
+
+    #[test]
+    fn test_select_excluding() {
+        let router = BackendRouter::new(&test_backends(), &test_frontends());
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let s2: SocketAddr = "127.0.0.1:9002".parse().unwrap();
+
+        for _ in 0..4 {
+            assert_eq!(
+                router.select_excluding("test-backend", None, &[s1]),
+                Some(s2)
+            );
+        }
+        assert!(
+            router
+                .select_excluding("test-backend", None, &[s1, s2])
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_reload_swaps_servers() {
+        let router = BackendRouter::new(&test_backends(), &test_frontends());
+        let changes = router.subscribe();
+
+        let mut backends = test_backends();
+        backends[0].servers.truncate(1);
+        router.reload(&backends, &test_frontends());
+
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(
+            router.get_servers("test-backend").unwrap(),
+            vec!["127.0.0.1:9001".parse::<SocketAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_reload_keeps_algorithm_state() {
+        let mut frontends = test_frontends();
+        frontends[0].algorithm = Algorithm::LeastConnections;
+        let router = BackendRouter::new(&test_backends(), &frontends);
+
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        router.on_connect("test-backend", s1);
+        router.reload(&test_backends(), &frontends);
+        assert_eq!(router.connection_count("test-backend", s1), 1);
+
+        // Switching algorithms starts from a fresh balancer
+        frontends[0].algorithm = Algorithm::RoundRobin;
+        router.reload(&test_backends(), &frontends);
+        assert_eq!(router.connection_count("test-backend", s1), 0);
+    }
+
+    #[test]
+    fn test_reload_removes_backend() {
+        let router = BackendRouter::new(&test_backends(), &test_frontends());
+        router.reload(&[], &[]);
+        assert!(router.select("test-backend", None).is_none());
+        assert!(router.backend_configs().is_empty());
+    }
+
+    #[test]
+    fn test_backend_tls_load_failure() {
+        use std::io::Write;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["api.internal".to_string()]).unwrap();
+        let mut ca = tempfile::NamedTempFile::new().unwrap();
+        ca.write_all(cert.cert.pem().as_bytes()).unwrap();
+
+        let with_ca = |path: &std::path::Path| {
+            let mut backends = test_backends();
+            backends[0].tls = Some(crate::config::BackendTlsConfig {
+                ca: Some(path.to_path_buf()),
+                cert: None,
+                key: None,
+                server_name: None,
+                verify: true,
+            });
+            backends
+        };
+        let missing = std::path::Path::new("/nonexistent/ca.pem");
+
+        // A backend whose TLS settings never loaded is left out
+        let router = BackendRouter::new(&with_ca(missing), &test_frontends());
+        assert!(router.select("test-backend", None).is_none());
+
+        router.reload(&with_ca(ca.path()), &test_frontends());
+        assert!(router.backend_tls("test-backend").is_some());
+        assert!(router.select("test-backend", None).is_some());
+
+        // Once loaded, a failed reload keeps the previous certificates
+        router.reload(&with_ca(missing), &test_frontends());
+        assert!(router.backend_tls("test-backend").is_some());
+        assert!(router.select("test-backend", None).is_some());
+    }
+
+    #[test]
+    fn test_route_backend_uses_frontend_algorithm() {
+        let mut backends = test_backends();
+        let mut api = backends[0].clone();
+        api.name = "api".to_string();
+        backends.push(api);
+
+        let mut frontends = test_frontends();
+        frontends[0].algorithm = Algorithm::LeastConnections;
+        frontends[0].routes.push(crate::config::RouteConfig {
+            backend: "api".to_string(),
+            ..Default::default()
+        });
+        let router = BackendRouter::new(&backends, &frontends);
+
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        router.on_connect("api", s1);
+        assert_eq!(router.connection_count("api", s1), 1);
+    }
+
+    #[test]
+    fn test_connection_guard() {
+        let mut frontends = test_frontends();
+        frontends[0].algorithm = Algorithm::LeastConnections;
+        let router = Arc::new(BackendRouter::new(&test_backends(), &frontends));
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+
+        let guard = router.track("test-backend", s1);
+        assert_eq!(guard.server(), s1);
+        assert_eq!(router.connection_count("test-backend", s1), 1);
+
+        drop(guard);
+        assert_eq!(router.connection_count("test-backend", s1), 0);
+    }
+
+    #[test]
+    fn test_reload_drains_removed_servers() {
+        let router = Arc::new(BackendRouter::new(&test_backends(), &test_frontends()));
+        let s2: SocketAddr = "127.0.0.1:9002".parse().unwrap();
+        let guard = router.track("test-backend", s2);
+
+        let mut backends = test_backends();
+        backends[0].servers.truncate(1);
+        router.reload(&backends, &test_frontends());
+
+        // The open connection is still counted while the server drains
+        let draining = router.draining_servers();
+        assert_eq!(draining.len(), 1);
+        assert_eq!(draining[0].server, s2);
+        assert_eq!(draining[0].reason, DrainReason::Removed);
+        assert_eq!(draining[0].active, 1);
+        assert!(draining[0].remaining <= Duration::from_secs(30));
+        assert_eq!(router.active_connections("test-backend", s2), 1);
+
+        // Returning to the pool stops the drain
+        router.reload(&test_backends(), &test_frontends());
+        assert!(!router.is_draining("test-backend", s2));
+        assert!(router.draining_servers().is_empty());
+        assert_eq!(router.active_connections("test-backend", s2), 1);
+
+        drop(guard);
+        assert_eq!(router.active_connections("test-backend", s2), 0);
+    }
+
+    #[test]
+    fn test_disable_server() {
+        let mut backends = test_backends();
+        backends[0].on_all_unhealthy = AllUnhealthyPolicy::FailOpen;
+        let health_state = unhealthy_state();
+        let router = BackendRouter::with_health_state(
+            &backends,
+            &test_frontends(),
+            Arc::clone(&health_state),
+        );
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let s2: SocketAddr = "127.0.0.1:9002".parse().unwrap();
+
+        assert!(router.disable_server("test-backend", s1));
+        assert!(router.is_draining("test-backend", s1));
+        for _ in 0..4 {
+            assert_eq!(router.select("test-backend", None), Some(s2));
+        }
+
+        // Failing open never falls back to a draining server
+        health_state.record_failure(s2);
+        for _ in 0..4 {
+            assert_eq!(router.select("test-backend", None), Some(s2));
+        }
+
+        assert!(router.enable_server("test-backend", s1));
+        assert_eq!(router.select("test-backend", None), Some(s1));
+
+        // Unknown servers cannot be disabled
+        let unknown: SocketAddr = "127.0.0.1:9003".parse().unwrap();
+        assert!(!router.disable_server("test-backend", unknown));
+        assert!(!router.enable_server("other-backend", s1));
+    }
+
+    #[test]
+    fn test_set_weight() {
+        let mut frontends = test_frontends();
+        frontends[0].algorithm = Algorithm::Weighted;
+        let router = BackendRouter::new(&test_backends(), &frontends);
+        let changes = router.subscribe();
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+
+        assert!(router.set_weight("test-backend", s1, 3));
+        assert!(changes.has_changed().unwrap());
+        let config = router.backend_config("test-backend").unwrap();
+        assert_eq!(config.servers[0].weight, 3);
+
+        let picks = (0..8)
+            .filter(|_| router.select("test-backend", None) == Some(s1))
+            .count();
+        assert_eq!(picks, 6);
+
+        let unknown: SocketAddr = "127.0.0.1:9003".parse().unwrap();
+        assert!(!router.set_weight("test-backend", unknown, 3));
+    }
+
+    #[test]
+    fn test_set_weight_survives_pool_changes() {
+        let router = BackendRouter::new(&test_backends(), &test_frontends());
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let s3: SocketAddr = "127.0.0.1:9003".parse().unwrap();
+        let weight = |router: &BackendRouter| {
+            let config = router.backend_config("test-backend").unwrap();
+            config
+                .servers
+                .iter()
+                .find(|s| s.address == s1)
+                .unwrap()
+                .weight
+        };
+        assert!(router.set_weight("test-backend", s1, 3));
+
+        let server = ServerConfig {
+            address: s3,
+            weight: 1,
+            metadata: Default::default(),
+        };
+        router.add_server("test-backend", server.clone()).unwrap();
+        assert_eq!(weight(&router), 3);
+        router.set_discovered("test-backend", "dns", vec![server]);
+        router.remove_server("test-backend", s3).unwrap();
+        assert_eq!(weight(&router), 3);
+
+        router.reload(&test_backends(), &test_frontends());
+        assert_eq!(weight(&router), 1);
+    }
+
+    #[test]
+    fn test_add_and_remove_server() {
+        let router = Arc::new(BackendRouter::new(&test_backends(), &test_frontends()));
+        let changes = router.subscribe();
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let s3: SocketAddr = "127.0.0.1:9003".parse().unwrap();
+        let added = ServerConfig {
+            address: s3,
+            weight: 1,
+            metadata: Default::default(),
+        };
+
+        router.add_server("test-backend", added.clone()).unwrap();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(router.get_servers("test-backend").unwrap().len(), 3);
+        assert_eq!(
+            router.backend_config("test-backend").unwrap().servers.len(),
+            3
+        );
+        assert!(matches!(
+            router.add_server("test-backend", added.clone()),
+            Err(PoolError::ServerExists(..))
+        ));
+        assert!(matches!(
+            router.add_server("other-backend", added.clone()),
+            Err(PoolError::BackendNotFound(_))
+        ));
+
+        // A removed server drains its connections
+        let guard = router.track("test-backend", s1);
+        router.remove_server("test-backend", s1).unwrap();
+        assert!(!router.get_servers("test-backend").unwrap().contains(&s1));
+        assert!(router.is_draining("test-backend", s1));
+        assert_eq!(router.active_connections("test-backend", s1), 1);
+        for _ in 0..4 {
+            assert_ne!(router.select("test-backend", None), Some(s1));
+        }
+
+        // Adding it back stops the drain
+        router
+            .add_server(
+                "test-backend",
+                ServerConfig {
+                    address: s1,
+                    weight: 1,
+                    metadata: Default::default(),
+                },
+            )
+            .unwrap();
+        assert!(!router.is_draining("test-backend", s1));
+        assert_eq!(router.active_connections("test-backend", s1), 1);
+        drop(guard);
+
+        // Pools keep at least one server
+        router.remove_server("test-backend", s1).unwrap();
+        router
+            .remove_server("test-backend", "127.0.0.1:9002".parse().unwrap())
+            .unwrap();
+        assert!(matches!(
+            router.remove_server("test-backend", s3),
+            Err(PoolError::LastServer(_))
+        ));
+        assert!(matches!(
+            router.remove_server("test-backend", s1),
+            Err(PoolError::ServerNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn test_runtime_changes_survive_reload() {
+        let router = BackendRouter::new(&test_backends(), &test_frontends());
+        let s1: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let s3: SocketAddr = "127.0.0.1:9003".parse().unwrap();
+        router
+            .add_server(
+                "test-backend",
+                ServerConfig {
+                    address: s3,
+                    weight: 2,
+                    metadata: Default::default(),
+                },
+            )
+            .unwrap();
+        router.remove_server("test-backend", s1).unwrap();
+
+        router.reload(&test_backends(), &test_frontends());
+        let servers = router.get_servers("test-backend").unwrap();
+        assert_eq!(servers, vec!["127.0.0.1:9002".parse().unwrap(), s3]);
+        let config = router.backend_config("test-backend").unwrap();
+        assert_eq!(config.servers[1].weight, 2);
+    }
+
     #[test]
     fn test_ip_hash_consistency() {
         let backends = vec![BackendConfig {
@@ -278,13 +1540,15 @@ mod tests {
                 ServerConfig {
                     address: "127.0.0.1:9001".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 },
                 ServerConfig {
                     address: "127.0.0.1:9002".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 },
             ],
-            health_check: None,
+            ..Default::default()
         }];
 
         let frontends = vec![FrontendConfig {
@@ -292,9 +1556,11 @@ mod tests {
             listen: "127.0.0.1:8080".parse().unwrap(),
             protocol: crate::config::Protocol::Tcp,
             backend: "ip-backend".to_string(),
+            routes: Vec::new(),
             algorithm: Algorithm::IpHash,
             http: None,
             tcp: None,
+            tls: None,
         }];
 
         let router = BackendRouter::new(&backends, &frontends);
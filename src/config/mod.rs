@@ -8,4 +8,5 @@ mod watcher;
 pub use loader::load_config;
 pub use types::*;
 pub use validation::validate_config;
-pub use watcher::{ConfigWatcher, ReloadCallback};
+pub use watcher::{ConfigWatcher, ReloadCallback, ReloadRequest};
+pub(crate) use watcher::{absolute, changed_paths, FileWatcher};
@@ -1,7 +1,9 @@
 //! Configuration data types.
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::time::Duration;
 
 /// Root configuration structure.
@@ -24,6 +26,32 @@ pub struct Config {
     pub backends: Vec<BackendConfig>,
 }
 
+impl Config {
+    /// Files referenced by the configuration whose changes should trigger a
+    /// reload, such as TLS certificates and keys.
+    pub fn watched_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for frontend in &self.frontends {
+            if let Some(ref tls) = frontend.tls {
+                files.push(tls.cert.clone());
+                files.push(tls.key.clone());
+                for cert in &tls.certificates {
+                    files.push(cert.cert.clone());
+                    files.push(cert.key.clone());
+                }
+            }
+        }
+        for backend in &self.backends {
+            if let Some(ref tls) = backend.tls {
+                for file in [&tls.ca, &tls.cert, &tls.key].into_iter().flatten() {
+                    files.push(file.clone());
+                }
+            }
+        }
+        files
+    }
+}
+
 /// Global configuration settings.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GlobalConfig {
@@ -38,6 +66,19 @@ pub struct GlobalConfig {
     /// Metrics configuration
     #[serde(default)]
     pub metrics: MetricsConfig,
+
+    /// Admin API configuration
+    #[serde(default)]
+    pub admin: AdminConfig,
+
+    /// Readiness probe configuration
+    #[serde(default)]
+    pub readiness: ReadinessConfig,
+
+    /// How long open connections may finish on shutdown before they are
+    /// closed
+    #[serde(default = "default_shutdown_timeout", with = "humantime_serde")]
+    pub shutdown_timeout: Duration,
 }
 
 impl Default for GlobalConfig {
@@ -46,6 +87,9 @@ impl Default for GlobalConfig {
             log_level: default_log_level(),
             log_format: LogFormat::Json,
             metrics: MetricsConfig::default(),
+            admin: AdminConfig::default(),
+            readiness: ReadinessConfig::default(),
+            shutdown_timeout: default_shutdown_timeout(),
         }
     }
 }
@@ -85,6 +129,37 @@ impl Default for MetricsConfig {
     }
 }
 
+/// Admin API configuration.
+///
+/// The admin API is served by the metrics server under `/admin/`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AdminConfig {
+    /// Whether the admin API is enabled
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Bearer token required by endpoints that change state (unset: those
+    /// endpoints are refused)
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// File that servers added and removed through the admin API are saved
+    /// to, so they survive restarts (unset: changes last until restart)
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+}
+
+/// Readiness probe configuration.
+///
+/// The probe is served by the metrics server on `/readyz`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ReadinessConfig {
+    /// Backends that must have at least one healthy server for rustlb to
+    /// report ready
+    #[serde(default)]
+    pub require_healthy: Vec<String>,
+}
+
 /// Default health check settings.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HealthCheckDefaults {
@@ -134,9 +209,13 @@ pub struct FrontendConfig {
     #[serde(default)]
     pub protocol: Protocol,
 
-    /// Name of the backend pool to use
+    /// Name of the backend pool to use (default for connections and requests that match no route)
     pub backend: String,
 
+    /// Ordered routing rules; the first matching route wins
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+
     /// Load balancing algorithm
     #[serde(default)]
     pub algorithm: Algorithm,
@@ -148,6 +227,209 @@ pub struct FrontendConfig {
     /// TCP-specific settings
     #[serde(default)]
     pub tcp: Option<TcpConfig>,
+
+    /// TLS termination settings
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// TLS termination settings for a frontend.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// Path to the PEM-encoded certificate chain of the default certificate
+    pub cert: PathBuf,
+
+    /// Path to the PEM-encoded private key of the default certificate
+    pub key: PathBuf,
+
+    /// Additional certificates, selected by the client's SNI server name
+    #[serde(default)]
+    pub certificates: Vec<SniCertificate>,
+
+    /// Minimum TLS protocol version: "1.2" or "1.3"
+    #[serde(default)]
+    pub min_version: TlsVersion,
+
+    /// Allowed cipher suites, by IANA name (empty: library defaults)
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+
+    /// ALPN protocols to offer, in order of preference
+    #[serde(default)]
+    pub alpn: Vec<String>,
+}
+
+/// Certificate served to clients that request one of its server names.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SniCertificate {
+    /// Server names to serve this certificate for: exact (`api.example.com`)
+    /// or wildcard (`*.example.com`)
+    pub server_names: Vec<String>,
+
+    /// Path to the PEM-encoded certificate chain
+    pub cert: PathBuf,
+
+    /// Path to the PEM-encoded private key
+    pub key: PathBuf,
+}
+
+/// TLS protocol version.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// Routing rule.
+///
+/// Every condition that is set must match for the route to apply; a route
+/// with no conditions matches every request. TCP frontends only support the
+/// `sni` condition.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RouteConfig {
+    /// Optional name used in logs and validation errors
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Host to match: exact (`api.example.com`) or wildcard (`*.example.com`)
+    #[serde(default)]
+    pub host: Option<String>,
+
+    /// TLS server name (SNI) to match: exact or wildcard, like `host`
+    #[serde(default)]
+    pub sni: Option<String>,
+
+    /// Path to match
+    #[serde(default)]
+    pub path: Option<PathMatch>,
+
+    /// HTTP methods to match (any of)
+    #[serde(default)]
+    pub methods: Vec<String>,
+
+    /// Request headers that must be present (and optionally equal a value)
+    #[serde(default)]
+    pub headers: Vec<KeyValueMatch>,
+
+    /// Query parameters that must be present (and optionally equal a value)
+    #[serde(default)]
+    pub query: Vec<KeyValueMatch>,
+
+    /// Name of the backend pool that handles matching requests
+    pub backend: String,
+}
+
+impl RouteConfig {
+    /// Name of the route for logs, falling back to its position.
+    pub fn display_name(&self, index: usize) -> String {
+        match self.name {
+            Some(ref name) => name.clone(),
+            None => format!("#{}", index + 1),
+        }
+    }
+}
+
+/// Path condition of a route.
+///
+/// Written in YAML as a map with exactly one of `prefix`, `exact` or `regex`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "PathMatchRepr", into = "PathMatchRepr")]
+pub enum PathMatch {
+    /// Path starts with the given prefix
+    Prefix(String),
+    /// Path equals the given string
+    Exact(String),
+    /// Path matches the given regular expression
+    Regex(RegexPattern),
+}
+
+/// Serialized form of [`PathMatch`].
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct PathMatchRepr {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exact: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    regex: Option<RegexPattern>,
+}
+
+impl TryFrom<PathMatchRepr> for PathMatch {
+    type Error = String;
+
+    fn try_from(repr: PathMatchRepr) -> Result<Self, Self::Error> {
+        match (repr.prefix, repr.exact, repr.regex) {
+            (Some(prefix), None, None) => Ok(PathMatch::Prefix(prefix)),
+            (None, Some(exact), None) => Ok(PathMatch::Exact(exact)),
+            (None, None, Some(regex)) => Ok(PathMatch::Regex(regex)),
+            _ => Err("path must set exactly one of 'prefix', 'exact' or 'regex'".to_string()),
+        }
+    }
+}
+
+impl From<PathMatch> for PathMatchRepr {
+    fn from(path: PathMatch) -> Self {
+        let mut repr = PathMatchRepr {
+            prefix: None,
+            exact: None,
+            regex: None,
+        };
+        match path {
+            PathMatch::Prefix(prefix) => repr.prefix = Some(prefix),
+            PathMatch::Exact(exact) => repr.exact = Some(exact),
+            PathMatch::Regex(regex) => repr.regex = Some(regex),
+        }
+        repr
+    }
+}
+
+/// Header or query parameter condition of a route.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyValueMatch {
+    /// Header or parameter name (header names are case-insensitive)
+    pub name: String,
+
+    /// Required value; if omitted, presence is enough
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+/// Regular expression compiled when the configuration is loaded.
+#[derive(Debug, Clone)]
+pub struct RegexPattern(regex::Regex);
+
+impl RegexPattern {
+    /// Compile a regular expression.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        regex::Regex::new(pattern).map(Self)
+    }
+
+    /// Check whether the expression matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    /// The source pattern.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Serialize for RegexPattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RegexPattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
 }
 
 /// Protocol type.
@@ -171,7 +453,7 @@ pub enum Algorithm {
 }
 
 /// HTTP-specific configuration.
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HttpConfig {
     /// Headers to add to requests going to backend
     #[serde(default)]
@@ -180,6 +462,87 @@ pub struct HttpConfig {
     /// Headers to add to responses going to client
     #[serde(default)]
     pub response_headers: std::collections::HashMap<String, String>,
+
+    /// gRPC mode: answer failed calls with gRPC status codes and record
+    /// per-method metrics (backends must use HTTP/2)
+    #[serde(default)]
+    pub grpc: bool,
+
+    /// Close upgraded connections (such as WebSockets) after this long
+    /// without data in either direction
+    #[serde(default = "default_upgrade_idle_timeout", with = "humantime_serde")]
+    pub upgrade_idle_timeout: Duration,
+
+    /// Retry failed requests on other servers of the backend (no retries if
+    /// omitted)
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            request_headers: std::collections::HashMap::new(),
+            response_headers: std::collections::HashMap::new(),
+            grpc: false,
+            upgrade_idle_timeout: default_upgrade_idle_timeout(),
+            retry: None,
+        }
+    }
+}
+
+/// Retry policy for HTTP requests.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RetryConfig {
+    /// Total number of attempts, including the first one
+    #[serde(default = "default_retry_attempts")]
+    pub max_attempts: u32,
+
+    /// Failures that are retried
+    #[serde(default = "default_retry_on")]
+    pub retry_on: Vec<RetryCondition>,
+
+    /// Backend response statuses (5xx) that are retried
+    #[serde(default)]
+    pub retry_on_status: Vec<u16>,
+
+    /// Also retry methods that are not idempotent, such as POST, after the
+    /// request has been sent
+    #[serde(default)]
+    pub retry_non_idempotent: bool,
+
+    /// Time limit of each attempt, until the response headers arrive
+    #[serde(default, with = "option_humantime_serde")]
+    pub per_try_timeout: Option<Duration>,
+
+    /// Time limit of all attempts together; no new attempt starts after it
+    #[serde(default, with = "option_humantime_serde")]
+    pub budget: Option<Duration>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_attempts(),
+            retry_on: default_retry_on(),
+            retry_on_status: Vec::new(),
+            retry_non_idempotent: false,
+            per_try_timeout: None,
+            budget: None,
+        }
+    }
+}
+
+/// Failure that can trigger a retry.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryCondition {
+    /// Connecting to the server failed (TCP, TLS or HTTP handshake)
+    ConnectFailure,
+    /// The connection failed before a response arrived
+    Reset,
+    /// The attempt exceeded `per_try_timeout`
+    Timeout,
 }
 
 /// TCP-specific configuration.
@@ -188,32 +551,302 @@ pub struct TcpConfig {
     /// Connection timeout
     #[serde(default = "default_connect_timeout", with = "humantime_serde")]
     pub connect_timeout: Duration,
+
+    /// Route TLS connections by the SNI in their ClientHello without
+    /// terminating TLS; the encrypted bytes are forwarded untouched
+    #[serde(default)]
+    pub tls_passthrough: bool,
+
+    /// What to do with connections whose SNI matches no route
+    #[serde(default)]
+    pub unmatched_sni: UnmatchedSniPolicy,
+
+    /// Try other servers when connecting to a backend server fails (a single
+    /// attempt if omitted)
+    #[serde(default)]
+    pub connect_retry: Option<ConnectRetryConfig>,
 }
 
 impl Default for TcpConfig {
     fn default() -> Self {
         Self {
             connect_timeout: default_connect_timeout(),
+            tls_passthrough: false,
+            unmatched_sni: UnmatchedSniPolicy::default(),
+            connect_retry: None,
         }
     }
 }
 
+/// Connect retry policy for TCP frontends.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConnectRetryConfig {
+    /// Total connection attempts, each to a different server
+    #[serde(default = "default_retry_attempts")]
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled for every further retry
+    #[serde(default = "default_connect_backoff", with = "humantime_serde")]
+    pub backoff: Duration,
+
+    /// Upper bound of the delay between retries
+    #[serde(default = "default_connect_max_backoff", with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+impl Default for ConnectRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_attempts(),
+            backoff: default_connect_backoff(),
+            max_backoff: default_connect_max_backoff(),
+        }
+    }
+}
+
+/// Behaviour for TCP connections with a missing or unknown SNI server name.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnmatchedSniPolicy {
+    /// Send the connection to the frontend's default backend
+    #[default]
+    Default,
+    /// Close the connection
+    Reject,
+}
+
 /// Backend pool configuration.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BackendConfig {
     /// Unique name for this backend pool
     pub name: String,
 
-    /// List of upstream servers
+    /// List of upstream servers (may be empty with `discovery`)
+    #[serde(default)]
     pub servers: Vec<ServerConfig>,
 
     /// Health check configuration for this backend
     #[serde(default)]
     pub health_check: Option<HealthCheckConfig>,
+
+    /// What to do when every server in the pool is unhealthy
+    #[serde(default)]
+    pub on_all_unhealthy: AllUnhealthyPolicy,
+
+    /// Keep-alive connection pool settings (HTTP only)
+    #[serde(default)]
+    pub connection_pool: ConnectionPoolConfig,
+
+    /// TLS settings for connections to the servers (plaintext if omitted)
+    #[serde(default)]
+    pub tls: Option<BackendTlsConfig>,
+
+    /// HTTP version spoken to the servers (HTTP frontends only)
+    #[serde(default)]
+    pub protocol: BackendProtocol,
+
+    /// Ejection of servers that fail proxied requests or connections
+    #[serde(default)]
+    pub passive_health: PassiveHealthConfig,
+
+    /// Ejection of servers whose error rate or latency stands out from the
+    /// rest of the pool (HTTP only, disabled if omitted)
+    #[serde(default)]
+    pub outlier_detection: Option<OutlierDetectionConfig>,
+
+    /// How long connections to a removed or disabled server may finish
+    /// before they are closed
+    #[serde(default = "default_drain_timeout", with = "humantime_serde")]
+    pub drain_timeout: Duration,
+
+    /// Servers discovered at runtime, in addition to `servers`
+    #[serde(default)]
+    pub discovery: Option<DiscoveryConfig>,
 }
 
-/// Individual server configuration.
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            servers: Vec::new(),
+            health_check: None,
+            on_all_unhealthy: AllUnhealthyPolicy::default(),
+            connection_pool: ConnectionPoolConfig::default(),
+            tls: None,
+            protocol: BackendProtocol::default(),
+            passive_health: PassiveHealthConfig::default(),
+            outlier_detection: None,
+            drain_timeout: default_drain_timeout(),
+            discovery: None,
+        }
+    }
+}
+
+/// Passive health checking, driven by the outcome of proxied traffic.
+///
+/// Independent of the active `health_check` and its thresholds.
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PassiveHealthConfig {
+    /// Whether failures of proxied traffic eject servers
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Consecutive failed requests or connections before a server is ejected
+    #[serde(default = "default_passive_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+
+    /// How long an ejected server receives no traffic
+    #[serde(default = "default_cooldown", with = "humantime_serde")]
+    pub ejection_time: Duration,
+
+    /// Backend response statuses counted as failures (HTTP only)
+    #[serde(default)]
+    pub failure_statuses: Vec<u16>,
+}
+
+impl Default for PassiveHealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            unhealthy_threshold: default_passive_unhealthy_threshold(),
+            ejection_time: default_cooldown(),
+            failure_statuses: Vec::new(),
+        }
+    }
+}
+
+/// Outlier detection, comparing the request outcomes of each server with
+/// the rest of its pool.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutlierDetectionConfig {
+    /// Time between analyses, each covering the requests since the last one
+    #[serde(default = "default_outlier_interval", with = "humantime_serde")]
+    pub interval: Duration,
+
+    /// Requests a server needs within an interval to be analysed
+    #[serde(default = "default_outlier_min_requests")]
+    pub min_requests: u64,
+
+    /// Servers with enough requests needed for an analysis to run
+    #[serde(default = "default_outlier_min_servers")]
+    pub min_servers: usize,
+
+    /// Standard deviations above the rest of the pool that make a server an
+    /// outlier
+    #[serde(default = "default_outlier_stdev_factor")]
+    pub stdev_factor: f64,
+
+    /// Ejection time of a first ejection, multiplied by the number of
+    /// recent ejections of the server
+    #[serde(default = "default_cooldown", with = "humantime_serde")]
+    pub base_ejection_time: Duration,
+
+    /// Upper bound of the ejection time
+    #[serde(
+        default = "default_outlier_max_ejection_time",
+        with = "humantime_serde"
+    )]
+    pub max_ejection_time: Duration,
+
+    /// Largest share of the pool that may be ejected at once, in percent
+    #[serde(default = "default_outlier_max_ejection_percent")]
+    pub max_ejection_percent: u32,
+}
+
+impl Default for OutlierDetectionConfig {
+    fn default() -> Self {
+        Self {
+            interval: default_outlier_interval(),
+            min_requests: default_outlier_min_requests(),
+            min_servers: default_outlier_min_servers(),
+            stdev_factor: default_outlier_stdev_factor(),
+            base_ejection_time: default_cooldown(),
+            max_ejection_time: default_outlier_max_ejection_time(),
+            max_ejection_percent: default_outlier_max_ejection_percent(),
+        }
+    }
+}
+
+/// HTTP version used for connections to backend servers.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendProtocol {
+    /// HTTP/1.1, one request at a time per connection
+    #[default]
+    Http1,
+    /// HTTP/2, multiplexing requests over a shared connection per server
+    /// (h2 over TLS, or cleartext h2c with prior knowledge)
+    Http2,
+}
+
+/// TLS settings for connections from the load balancer to backend servers.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BackendTlsConfig {
+    /// Path to the PEM-encoded CA bundle that server certificates must chain to
+    #[serde(default)]
+    pub ca: Option<PathBuf>,
+
+    /// Path to the PEM-encoded client certificate chain, for mutual TLS
+    #[serde(default)]
+    pub cert: Option<PathBuf>,
+
+    /// Path to the PEM-encoded client private key
+    #[serde(default)]
+    pub key: Option<PathBuf>,
+
+    /// Server name to send as SNI and verify (default: the server's IP address)
+    #[serde(default)]
+    pub server_name: Option<String>,
+
+    /// Verify server certificates against `ca`
+    #[serde(default = "default_true")]
+    pub verify: bool,
+}
+
+/// Behaviour when no healthy servers remain in a backend pool.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AllUnhealthyPolicy {
+    /// Refuse to select a server (HTTP clients receive 503)
+    #[default]
+    Reject,
+    /// Fail open and balance across all servers regardless of health
+    FailOpen,
+}
+
+/// Keep-alive connection pool settings for HTTP backends.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConnectionPoolConfig {
+    /// Maximum idle connections kept per server (0 disables pooling)
+    #[serde(default = "default_pool_max_idle")]
+    pub max_idle: usize,
+
+    /// How long an idle connection is kept before being closed
+    #[serde(default = "default_pool_idle_timeout", with = "humantime_serde")]
+    pub idle_timeout: Duration,
+
+    /// Maximum age of a connection before it is retired
+    #[serde(default, with = "option_humantime_serde")]
+    pub max_lifetime: Option<Duration>,
+
+    /// Maximum number of requests sent over a single connection
+    #[serde(default)]
+    pub max_requests: Option<u32>,
+}
+
+impl Default for ConnectionPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle: default_pool_max_idle(),
+            idle_timeout: default_pool_idle_timeout(),
+            max_lifetime: None,
+            max_requests: None,
+        }
+    }
+}
+
+/// Individual server configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct ServerConfig {
     /// Server address and port
     pub address: SocketAddr,
@@ -221,12 +854,67 @@ pub struct ServerConfig {
     /// Weight for weighted load balancing (default: 1)
     #[serde(default = "default_weight")]
     pub weight: u32,
+
+    /// Free-form labels describing the server, shown by the admin API
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Runtime discovery of the servers of a backend pool.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiscoveryConfig {
+    /// DNS names whose records expand into servers
+    #[serde(default)]
+    pub dns: Vec<DnsTarget>,
+
+    /// Longest time between two resolutions; records with a shorter TTL are
+    /// resolved again when it expires
+    #[serde(default = "default_dns_refresh", with = "humantime_serde")]
+    pub dns_refresh: Duration,
+
+    /// JSON or YAML file listing servers, applied whenever it changes
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+}
+
+/// A DNS name resolved into servers.
+///
+/// Exactly one of `address` and `srv` is set.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DnsTarget {
+    /// `host:port` whose A and AAAA records each become a server
+    #[serde(default)]
+    pub address: Option<String>,
+
+    /// SRV name like `_http._tcp.example.com` whose records of the best
+    /// priority each become a server, with the record's port and weight
+    #[serde(default)]
+    pub srv: Option<String>,
+
+    /// Weight of each server resolved from `address` (default: 1)
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+impl DnsTarget {
+    /// Split `address` into its host and port.
+    ///
+    /// Returns `None` if `address` is unset or not a `host:port` pair.
+    pub fn host_port(&self) -> Option<(&str, u16)> {
+        let (host, port) = self.address.as_deref()?.rsplit_once(':')?;
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        let port = port.parse().ok()?;
+        (!host.is_empty()).then_some((host, port))
+    }
 }
 
 /// Health check configuration.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HealthCheckConfig {
-    /// Type of health check: tcp or http
+    /// Type of health check: tcp, http or grpc
     #[serde(default, rename = "type")]
     pub check_type: HealthCheckType,
 
@@ -234,6 +922,11 @@ pub struct HealthCheckConfig {
     #[serde(default)]
     pub path: Option<String>,
 
+    /// Service name to check (for gRPC health checks; empty checks the
+    /// server as a whole)
+    #[serde(default)]
+    pub service: Option<String>,
+
     /// Expected HTTP status code (for HTTP health checks)
     #[serde(default = "default_expected_status")]
     pub expected_status: u16,
@@ -252,6 +945,7 @@ impl Default for HealthCheckConfig {
         Self {
             check_type: HealthCheckType::Tcp,
             path: None,
+            service: None,
             expected_status: default_expected_status(),
             interval: None,
             timeout: None,
@@ -266,6 +960,8 @@ pub enum HealthCheckType {
     #[default]
     Tcp,
     Http,
+    /// Standard `grpc.health.v1.Health/Check` call over HTTP/2
+    Grpc,
 }
 
 // Default value functions
@@ -305,18 +1001,90 @@ fn default_healthy_threshold() -> u32 {
     2
 }
 
+fn default_passive_unhealthy_threshold() -> u32 {
+    5
+}
+
 fn default_cooldown() -> Duration {
     Duration::from_secs(30)
 }
 
+fn default_outlier_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_outlier_min_requests() -> u64 {
+    20
+}
+
+fn default_outlier_min_servers() -> usize {
+    3
+}
+
+fn default_outlier_stdev_factor() -> f64 {
+    2.0
+}
+
+fn default_outlier_max_ejection_time() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_outlier_max_ejection_percent() -> u32 {
+    10
+}
+
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_drain_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
 fn default_connect_timeout() -> Duration {
     Duration::from_secs(10)
 }
 
+fn default_upgrade_idle_timeout() -> Duration {
+    Duration::from_secs(300)
+}
+
+fn default_retry_attempts() -> u32 {
+    3
+}
+
+fn default_retry_on() -> Vec<RetryCondition> {
+    vec![
+        RetryCondition::ConnectFailure,
+        RetryCondition::Reset,
+        RetryCondition::Timeout,
+    ]
+}
+
+fn default_connect_backoff() -> Duration {
+    Duration::from_millis(50)
+}
+
+fn default_connect_max_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_pool_max_idle() -> usize {
+    32
+}
+
+fn default_pool_idle_timeout() -> Duration {
+    Duration::from_secs(90)
+}
+
 fn default_weight() -> u32 {
     1
 }
 
+fn default_dns_refresh() -> Duration {
+    Duration::from_secs(30)
+}
+
 fn default_expected_status() -> u16 {
     200
 }
@@ -399,4 +1167,17 @@ mod tests {
         let algo: Algorithm = serde_yaml::from_str("least_connections").unwrap();
         assert_eq!(algo, Algorithm::LeastConnections);
     }
+
+    #[test]
+    fn test_default_backend_config() {
+        let parsed: BackendConfig = serde_yaml::from_str("name: api").unwrap();
+        let default = BackendConfig {
+            name: "api".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_yaml::to_string(&default).unwrap(),
+            serde_yaml::to_string(&parsed).unwrap()
+        );
+    }
 }
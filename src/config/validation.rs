@@ -1,16 +1,29 @@
 //! Configuration validation.
 
-use crate::config::{Config, HealthCheckType, Protocol};
+use crate::config::{
+    BackendConfig, BackendProtocol, Config, FrontendConfig, HealthCheckType, KeyValueMatch, PathMatch, Protocol,
+    RouteConfig, UnmatchedSniPolicy,
+};
+use rustls::pki_types::ServerName;
 use std::collections::HashSet;
+use std::time::Duration;
 
 /// Validate the configuration.
 ///
 /// Checks for:
 /// - At least one frontend and one backend
 /// - Unique frontend and backend names
-/// - Frontend backend references exist
-/// - HTTP health checks have paths
+/// - Frontend and route backend references exist
+/// - Routes on TCP frontends only match on SNI, with no duplicate or unreachable routes
+/// - SNI routes and certificates are only used on TLS frontends, with valid names
+/// - TLS passthrough is only used on TCP frontends that do not terminate TLS
+/// - Backend TLS has a CA bundle when verifying, and a complete client certificate
+/// - gRPC mode is only used on HTTP frontends whose backends speak HTTP/2
+/// - HTTP health checks have paths, and gRPC health checks use HTTP/2
 /// - No duplicate listen addresses
+/// - The admin API runs on an enabled metrics server, with a non-empty token
+/// - Backends required by the readiness probe exist
+/// - DNS discovery entries are `host:port` pairs or SRV names
 ///
 /// # Returns
 ///
@@ -71,6 +84,12 @@ pub fn validate_config(config: &Config) -> Result<(), String> {
         if frontend.protocol == Protocol::Http && frontend.http.is_none() {
             // HTTP config is optional, but we could warn here if needed
         }
+
+        validate_routes(frontend, &backend_names, &mut errors);
+        validate_tls(frontend, &mut errors);
+        validate_grpc(frontend, &config.backends, &mut errors);
+        validate_retry(frontend, &mut errors);
+        validate_connect_retry(frontend, &mut errors);
     }
 
     // Validate backends
@@ -80,8 +99,12 @@ pub fn validate_config(config: &Config) -> Result<(), String> {
             errors.push("backend name cannot be empty".to_string());
         }
 
-        // Check for at least one server
-        if backend.servers.is_empty() {
+        // Check for at least one server, unless servers are discovered
+        let discovers = backend
+            .discovery
+            .as_ref()
+            .is_some_and(|discovery| !discovery.dns.is_empty() || discovery.file.is_some());
+        if backend.servers.is_empty() && !discovers {
             errors.push(format!(
                 "backend '{}' must have at least one server",
                 backend.name
@@ -99,14 +122,31 @@ pub fn validate_config(config: &Config) -> Result<(), String> {
         }
 
         // Check HTTP health check has path
-        if let Some(ref hc) = backend.health_check {
-            if hc.check_type == HealthCheckType::Http && hc.path.is_none() {
-                errors.push(format!(
-                    "backend '{}' has HTTP health check but no path specified",
-                    backend.name
-                ));
-            }
+        if let Some(ref hc) = backend.health_check
+            && hc.check_type == HealthCheckType::Http
+            && hc.path.is_none()
+        {
+            errors.push(format!(
+                "backend '{}' has HTTP health check but no path specified",
+                backend.name
+            ));
         }
+
+        // gRPC health checks need an HTTP/2 connection (and ALPN h2 over TLS)
+        if let Some(ref hc) = backend.health_check
+            && hc.check_type == HealthCheckType::Grpc
+            && backend.protocol != BackendProtocol::Http2
+        {
+            errors.push(format!(
+                "backend '{}' has gRPC health check but does not use 'protocol: http2'",
+                backend.name
+            ));
+        }
+
+        validate_backend_tls(backend, &mut errors);
+        validate_passive_health(backend, &mut errors);
+        validate_outlier_detection(backend, &mut errors);
+        validate_discovery(backend, &mut errors);
     }
 
     // Validate log level
@@ -119,6 +159,20 @@ pub fn validate_config(config: &Config) -> Result<(), String> {
         ));
     }
 
+    // The admin API is served by the metrics server
+    if config.global.admin.enabled && !config.global.metrics.enabled {
+        errors.push("admin API requires the metrics server to be enabled".to_string());
+    }
+    if config.global.admin.token.as_deref() == Some("") {
+        errors.push("admin token cannot be empty".to_string());
+    }
+
+    for name in &config.global.readiness.require_healthy {
+        if !config.backends.iter().any(|b| &b.name == name) {
+            errors.push(format!("readiness requires unknown backend '{}'", name));
+        }
+    }
+
     if errors.is_empty() {
         Ok(())
     } else {
@@ -126,10 +180,477 @@ pub fn validate_config(config: &Config) -> Result<(), String> {
     }
 }
 
+/// Validate the routing rules of a frontend.
+fn validate_routes(
+    frontend: &FrontendConfig,
+    backend_names: &HashSet<&str>,
+    errors: &mut Vec<String>,
+) {
+    if frontend.routes.is_empty() {
+        return;
+    }
+
+    for (index, route) in frontend.routes.iter().enumerate() {
+        let name = route.display_name(index);
+
+        if frontend.protocol != Protocol::Http && has_request_conditions(route) {
+            errors.push(format!(
+                "frontend '{}' route '{}' matches on HTTP request fields but routes on TCP frontends may only match on 'sni'",
+                frontend.name, name
+            ));
+        }
+
+        if let Some(ref sni) = route.sni {
+            if !has_sni(frontend) {
+                errors.push(format!(
+                    "frontend '{}' route '{}' matches on 'sni' but the frontend neither terminates nor passes through TLS",
+                    frontend.name, name
+                ));
+            }
+            if !is_valid_host_pattern(sni) {
+                errors.push(format!(
+                    "frontend '{}' route '{}' has invalid sni '{}' (wildcards must be a leading '*.')",
+                    frontend.name, name, sni
+                ));
+            }
+        }
+
+        if !backend_names.contains(route.backend.as_str()) {
+            errors.push(format!(
+                "frontend '{}' route '{}' references non-existent backend '{}'",
+                frontend.name, name, route.backend
+            ));
+        }
+
+        if let Some(ref host) = route.host
+            && !is_valid_host_pattern(host)
+        {
+            errors.push(format!(
+                "frontend '{}' route '{}' has invalid host '{}' (wildcards must be a leading '*.')",
+                frontend.name, name, host
+            ));
+        }
+
+        for method in &route.methods {
+            if hyper::Method::from_bytes(method.as_bytes()).is_err() {
+                errors.push(format!(
+                    "frontend '{}' route '{}' has invalid method '{}'",
+                    frontend.name, name, method
+                ));
+            }
+        }
+
+        // An earlier route that matches everything this one matches makes it dead
+        for (earlier_index, earlier) in frontend.routes[..index].iter().enumerate() {
+            if !route_covers(earlier, route) {
+                continue;
+            }
+            let earlier_name = earlier.display_name(earlier_index);
+            if route_covers(route, earlier) {
+                errors.push(format!(
+                    "frontend '{}' route '{}' duplicates route '{}'",
+                    frontend.name, name, earlier_name
+                ));
+            } else {
+                errors.push(format!(
+                    "frontend '{}' route '{}' is unreachable (shadowed by route '{}')",
+                    frontend.name, name, earlier_name
+                ));
+            }
+            break;
+        }
+    }
+}
+
+/// Validate the TLS settings of a frontend.
+fn validate_tls(frontend: &FrontendConfig, errors: &mut Vec<String>) {
+    if let Some(ref tcp) = frontend.tcp {
+        if tcp.tls_passthrough && frontend.protocol != Protocol::Tcp {
+            errors.push(format!(
+                "frontend '{}' enables TLS passthrough, which is only supported on TCP frontends",
+                frontend.name
+            ));
+        }
+        if tcp.tls_passthrough && frontend.tls.is_some() {
+            errors.push(format!(
+                "frontend '{}' cannot both terminate TLS and pass it through",
+                frontend.name
+            ));
+        }
+        if tcp.unmatched_sni == UnmatchedSniPolicy::Reject && !has_sni(frontend) {
+            errors.push(format!(
+                "frontend '{}' rejects unmatched SNI but neither terminates nor passes through TLS",
+                frontend.name
+            ));
+        }
+    }
+
+    let Some(ref tls) = frontend.tls else {
+        return;
+    };
+
+    let mut server_names = HashSet::new();
+    for cert in &tls.certificates {
+        if cert.server_names.is_empty() {
+            errors.push(format!(
+                "frontend '{}' certificate '{}' has no server names",
+                frontend.name,
+                cert.cert.display()
+            ));
+        }
+
+        for name in &cert.server_names {
+            if !is_valid_host_pattern(name) {
+                errors.push(format!(
+                    "frontend '{}' certificate '{}' has invalid server name '{}' (wildcards must be a leading '*.')",
+                    frontend.name,
+                    cert.cert.display(),
+                    name
+                ));
+            }
+            if !server_names.insert(name.to_ascii_lowercase()) {
+                errors.push(format!(
+                    "frontend '{}' has more than one certificate for server name '{}'",
+                    frontend.name, name
+                ));
+            }
+        }
+    }
+}
+
+/// Validate the gRPC mode of a frontend.
+fn validate_grpc(frontend: &FrontendConfig, backends: &[BackendConfig], errors: &mut Vec<String>) {
+    if !frontend.http.as_ref().is_some_and(|http| http.grpc) {
+        return;
+    }
+
+    if frontend.protocol != Protocol::Http {
+        errors.push(format!(
+            "frontend '{}' enables gRPC mode but is not an HTTP frontend",
+            frontend.name
+        ));
+        return;
+    }
+
+    let mut names: Vec<&str> = vec![frontend.backend.as_str()];
+    for route in &frontend.routes {
+        if !names.contains(&route.backend.as_str()) {
+            names.push(route.backend.as_str());
+        }
+    }
+    for backend in backends.iter().filter(|b| names.contains(&b.name.as_str())) {
+        if backend.protocol != BackendProtocol::Http2 {
+            errors.push(format!(
+                "frontend '{}' is in gRPC mode but backend '{}' does not use 'protocol: http2'",
+                frontend.name, backend.name
+            ));
+        }
+    }
+}
+
+/// Validate the retry policy of a frontend.
+fn validate_retry(frontend: &FrontendConfig, errors: &mut Vec<String>) {
+    let Some(retry) = frontend.http.as_ref().and_then(|http| http.retry.as_ref()) else {
+        return;
+    };
+
+    if retry.max_attempts == 0 {
+        errors.push(format!(
+            "frontend '{}' retry max_attempts must be at least 1",
+            frontend.name
+        ));
+    }
+    for status in &retry.retry_on_status {
+        if !(500..=599).contains(status) {
+            errors.push(format!(
+                "frontend '{}' retries on status {}, only 5xx statuses can be retried",
+                frontend.name, status
+            ));
+        }
+    }
+    if retry.per_try_timeout.is_some_and(|t| t.is_zero()) {
+        errors.push(format!(
+            "frontend '{}' retry per_try_timeout must be greater than zero",
+            frontend.name
+        ));
+    }
+    if retry.budget.is_some_and(|t| t.is_zero()) {
+        errors.push(format!(
+            "frontend '{}' retry budget must be greater than zero",
+            frontend.name
+        ));
+    }
+}
+
+/// Validate the TCP connect retry policy of a frontend.
+fn validate_connect_retry(frontend: &FrontendConfig, errors: &mut Vec<String>) {
+    let Some(retry) = frontend
+        .tcp
+        .as_ref()
+        .and_then(|tcp| tcp.connect_retry.as_ref())
+    else {
+        return;
+    };
+
+    if retry.max_attempts == 0 {
+        errors.push(format!(
+            "frontend '{}' connect_retry max_attempts must be at least 1",
+            frontend.name
+        ));
+    }
+    if retry.backoff > retry.max_backoff {
+        errors.push(format!(
+            "frontend '{}' connect_retry backoff must not exceed max_backoff",
+            frontend.name
+        ));
+    }
+}
+
+/// Validate the upstream TLS settings of a backend.
+fn validate_backend_tls(backend: &BackendConfig, errors: &mut Vec<String>) {
+    let Some(ref tls) = backend.tls else {
+        return;
+    };
+
+    if tls.verify && tls.ca.is_none() {
+        errors.push(format!(
+            "backend '{}' verifies server certificates but has no 'ca' bundle (set 'verify: false' to skip verification)",
+            backend.name
+        ));
+    }
+    if tls.cert.is_some() != tls.key.is_some() {
+        errors.push(format!(
+            "backend '{}' client certificate needs both 'cert' and 'key'",
+            backend.name
+        ));
+    }
+    if let Some(ref name) = tls.server_name
+        && ServerName::try_from(name.as_str()).is_err()
+    {
+        errors.push(format!(
+            "backend '{}' has invalid TLS server name '{}'",
+            backend.name, name
+        ));
+    }
+}
+
+/// Validate the passive health settings of a backend.
+fn validate_passive_health(backend: &BackendConfig, errors: &mut Vec<String>) {
+    let passive = &backend.passive_health;
+    if !passive.enabled {
+        return;
+    }
+
+    if passive.unhealthy_threshold == 0 {
+        errors.push(format!(
+            "backend '{}' passive_health unhealthy_threshold must be at least 1",
+            backend.name
+        ));
+    }
+    if passive.ejection_time.is_zero() {
+        errors.push(format!(
+            "backend '{}' passive_health ejection_time must be greater than zero",
+            backend.name
+        ));
+    }
+    for status in &passive.failure_statuses {
+        if !(500..=599).contains(status) {
+            errors.push(format!(
+                "backend '{}' passive_health counts status {} as a failure, only 5xx statuses can be",
+                backend.name, status
+            ));
+        }
+    }
+}
+
+/// Validate the discovery settings of a backend.
+fn validate_discovery(backend: &BackendConfig, errors: &mut Vec<String>) {
+    let Some(ref discovery) = backend.discovery else {
+        return;
+    };
+
+    if discovery.dns_refresh < Duration::from_secs(1) {
+        errors.push(format!(
+            "backend '{}' discovery dns_refresh must be at least 1s",
+            backend.name
+        ));
+    }
+    for target in &discovery.dns {
+        match (&target.address, &target.srv) {
+            (Some(address), None) => {
+                if target.host_port().is_none() {
+                    errors.push(format!(
+                        "backend '{}' DNS address '{}' must be host:port",
+                        backend.name, address
+                    ));
+                }
+                if target.weight == 0 {
+                    errors.push(format!(
+                        "backend '{}' DNS address '{}' has weight 0 (must be >= 1)",
+                        backend.name, address
+                    ));
+                }
+            }
+            (None, Some(srv)) => {
+                if srv.is_empty() {
+                    errors.push(format!(
+                        "backend '{}' has an empty DNS SRV name",
+                        backend.name
+                    ));
+                }
+            }
+            _ => errors.push(format!(
+                "backend '{}' DNS entries need exactly one of 'address' and 'srv'",
+                backend.name
+            )),
+        }
+    }
+    if discovery.file.as_ref().is_some_and(|file| file.as_os_str().is_empty()) {
+        errors.push(format!(
+            "backend '{}' discovery file cannot be empty",
+            backend.name
+        ));
+    }
+}
+
+/// Validate the outlier detection settings of a backend.
+fn validate_outlier_detection(backend: &BackendConfig, errors: &mut Vec<String>) {
+    let Some(ref outlier) = backend.outlier_detection else {
+        return;
+    };
+
+    if outlier.interval.is_zero() {
+        errors.push(format!(
+            "backend '{}' outlier_detection interval must be greater than zero",
+            backend.name
+        ));
+    }
+    if outlier.min_requests == 0 {
+        errors.push(format!(
+            "backend '{}' outlier_detection min_requests must be at least 1",
+            backend.name
+        ));
+    }
+    if outlier.min_servers < 2 {
+        errors.push(format!(
+            "backend '{}' outlier_detection min_servers must be at least 2",
+            backend.name
+        ));
+    }
+    if !outlier.stdev_factor.is_finite() || outlier.stdev_factor < 0.0 {
+        errors.push(format!(
+            "backend '{}' outlier_detection stdev_factor must be a non-negative number",
+            backend.name
+        ));
+    }
+    if outlier.base_ejection_time.is_zero() {
+        errors.push(format!(
+            "backend '{}' outlier_detection base_ejection_time must be greater than zero",
+            backend.name
+        ));
+    }
+    if outlier.base_ejection_time > outlier.max_ejection_time {
+        errors.push(format!(
+            "backend '{}' outlier_detection base_ejection_time must not exceed max_ejection_time",
+            backend.name
+        ));
+    }
+    if outlier.max_ejection_percent > 100 {
+        errors.push(format!(
+            "backend '{}' outlier_detection max_ejection_percent must be at most 100",
+            backend.name
+        ));
+    }
+}
+
+/// Check whether connections to a frontend have an SNI server name to route on.
+fn has_sni(frontend: &FrontendConfig) -> bool {
+    frontend.tls.is_some() || frontend.tcp.as_ref().is_some_and(|tcp| tcp.tls_passthrough)
+}
+
+/// Check that a host pattern is a name with at most a leading `*.` wildcard.
+fn is_valid_host_pattern(pattern: &str) -> bool {
+    let name = pattern.strip_prefix("*.").unwrap_or(pattern);
+    !name.is_empty() && !name.contains('*')
+}
+
+/// Check whether a route has conditions that need an HTTP request.
+fn has_request_conditions(route: &RouteConfig) -> bool {
+    route.host.is_some()
+        || route.path.is_some()
+        || !route.methods.is_empty()
+        || !route.headers.is_empty()
+        || !route.query.is_empty()
+}
+
+/// Check whether `earlier` matches every request that `later` matches.
+fn route_covers(earlier: &RouteConfig, later: &RouteConfig) -> bool {
+    host_covers(earlier.host.as_deref(), later.host.as_deref())
+        && host_covers(earlier.sni.as_deref(), later.sni.as_deref())
+        && path_covers(earlier.path.as_ref(), later.path.as_ref())
+        && methods_cover(&earlier.methods, &later.methods)
+        && conditions_cover(&earlier.headers, &later.headers, true)
+        && conditions_cover(&earlier.query, &later.query, false)
+}
+
+fn host_covers(earlier: Option<&str>, later: Option<&str>) -> bool {
+    let (earlier, later) = match (earlier, later) {
+        (None, _) => return true,
+        (Some(_), None) => return false,
+        (Some(e), Some(l)) => (e.to_ascii_lowercase(), l.to_ascii_lowercase()),
+    };
+    if earlier == later {
+        return true;
+    }
+    // `*.example.com` covers `api.example.com`, but not `*.api.example.com`
+    // since a wildcard stands for a single label
+    match earlier.strip_prefix("*.") {
+        Some(suffix) => later
+            .split_once('.')
+            .is_some_and(|(label, parent)| !label.is_empty() && label != "*" && parent == suffix),
+        None => false,
+    }
+}
+
+fn path_covers(earlier: Option<&PathMatch>, later: Option<&PathMatch>) -> bool {
+    match (earlier, later) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(PathMatch::Prefix(p)), Some(PathMatch::Prefix(q) | PathMatch::Exact(q))) => {
+            q.starts_with(p.as_str())
+        }
+        (Some(PathMatch::Exact(a)), Some(PathMatch::Exact(b))) => a == b,
+        (Some(PathMatch::Regex(a)), Some(PathMatch::Regex(b))) => a.as_str() == b.as_str(),
+        _ => false,
+    }
+}
+
+fn methods_cover(earlier: &[String], later: &[String]) -> bool {
+    earlier.is_empty()
+        || (!later.is_empty()
+            && later
+                .iter()
+                .all(|l| earlier.iter().any(|e| e.eq_ignore_ascii_case(l))))
+}
+
+fn conditions_cover(earlier: &[KeyValueMatch], later: &[KeyValueMatch], ignore_case: bool) -> bool {
+    earlier.iter().all(|e| {
+        later.iter().any(|l| {
+            let same_name = if ignore_case {
+                e.name.eq_ignore_ascii_case(&l.name)
+            } else {
+                e.name == l.name
+            };
+            same_name && (e.value.is_none() || e.value == l.value)
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::*;
+    use std::time::Duration;
 
     fn minimal_config() -> Config {
         Config {
@@ -140,17 +661,20 @@ mod tests {
                 listen: "127.0.0.1:8080".parse().unwrap(),
                 protocol: Protocol::Http,
                 backend: "test-backend".to_string(),
+                routes: Vec::new(),
                 algorithm: Algorithm::RoundRobin,
                 http: None,
                 tcp: None,
+                tls: None,
             }],
             backends: vec![BackendConfig {
                 name: "test-backend".to_string(),
                 servers: vec![ServerConfig {
                     address: "127.0.0.1:9000".parse().unwrap(),
                     weight: 1,
+                    metadata: Default::default(),
                 }],
-                health_check: None,
+                ..Default::default()
             }],
         }
     }
@@ -196,9 +720,11 @@ mod tests {
             listen: "127.0.0.1:8081".parse().unwrap(),
             protocol: Protocol::Http,
             backend: "test-backend".to_string(),
+            routes: Vec::new(),
             algorithm: Algorithm::RoundRobin,
             http: None,
             tcp: None,
+            tls: None,
         });
         let result = validate_config(&config);
         assert!(result.is_err());
@@ -213,9 +739,11 @@ mod tests {
             listen: "127.0.0.1:8080".parse().unwrap(), // Same as first
             protocol: Protocol::Http,
             backend: "test-backend".to_string(),
+            routes: Vec::new(),
             algorithm: Algorithm::RoundRobin,
             http: None,
             tcp: None,
+            tls: None,
         });
         let result = validate_config(&config);
         assert!(result.is_err());
@@ -228,6 +756,7 @@ mod tests {
         config.backends[0].health_check = Some(HealthCheckConfig {
             check_type: HealthCheckType::Http,
             path: None, // Missing path for HTTP check
+            service: None,
             expected_status: 200,
             interval: None,
             timeout: None,
@@ -245,4 +774,352 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("weight 0"));
     }
+
+    #[test]
+    fn test_admin_requires_metrics() {
+        let mut config = minimal_config();
+        config.global.admin.enabled = true;
+        config.global.admin.token = Some("secret".to_string());
+        assert!(validate_config(&config).is_ok());
+
+        config.global.metrics.enabled = false;
+        let result = validate_config(&config);
+        assert!(result.unwrap_err().contains("requires the metrics server"));
+
+        config.global.metrics.enabled = true;
+        config.global.admin.token = Some(String::new());
+        let result = validate_config(&config);
+        assert!(result.unwrap_err().contains("admin token cannot be empty"));
+    }
+
+    #[test]
+    fn test_readiness_requires_known_backends() {
+        let mut config = minimal_config();
+        config.global.readiness.require_healthy = vec!["test-backend".to_string()];
+        assert!(validate_config(&config).is_ok());
+
+        config.global.readiness.require_healthy.push("missing".to_string());
+        let result = validate_config(&config);
+        assert!(result.unwrap_err().contains("readiness requires unknown backend 'missing'"));
+    }
+
+    fn route(backend: &str) -> RouteConfig {
+        RouteConfig {
+            backend: backend.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn tls_config() -> TlsConfig {
+        TlsConfig {
+            cert: "/certs/default.pem".into(),
+            key: "/certs/default.key".into(),
+            certificates: Vec::new(),
+            min_version: TlsVersion::Tls12,
+            cipher_suites: Vec::new(),
+            alpn: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_valid_routes() {
+        let mut config = minimal_config();
+        config.frontends[0].routes = vec![
+            RouteConfig {
+                host: Some("api.example.com".to_string()),
+                path: Some(PathMatch::Prefix("/v2".to_string())),
+                ..route("test-backend")
+            },
+            RouteConfig {
+                host: Some("*.example.com".to_string()),
+                ..route("test-backend")
+            },
+        ];
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_route_unknown_backend() {
+        let mut config = minimal_config();
+        config.frontends[0].routes = vec![route("nonexistent")];
+        let result = validate_config(&config);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("route '#1' references non-existent backend")
+        );
+    }
+
+    #[test]
+    fn test_routes_on_tcp_frontend() {
+        let mut config = minimal_config();
+        config.frontends[0].protocol = Protocol::Tcp;
+        config.frontends[0].routes = vec![RouteConfig {
+            path: Some(PathMatch::Prefix("/api".to_string())),
+            ..route("test-backend")
+        }];
+        let result = validate_config(&config);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("routes on TCP frontends may only match on 'sni'")
+        );
+    }
+
+    #[test]
+    fn test_sni_routes() {
+        let mut config = minimal_config();
+        config.frontends[0].protocol = Protocol::Tcp;
+        config.frontends[0].routes = vec![RouteConfig {
+            sni: Some("*.example.com".to_string()),
+            ..route("test-backend")
+        }];
+        let result = validate_config(&config);
+        assert!(result.unwrap_err().contains(
+            "matches on 'sni' but the frontend neither terminates nor passes through TLS"
+        ));
+
+        config.frontends[0].tls = Some(tls_config());
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_tls_passthrough() {
+        let mut config = minimal_config();
+        config.frontends[0].protocol = Protocol::Tcp;
+        config.frontends[0].tcp = Some(TcpConfig {
+            tls_passthrough: true,
+            unmatched_sni: UnmatchedSniPolicy::Reject,
+            ..Default::default()
+        });
+        config.frontends[0].routes = vec![RouteConfig {
+            sni: Some("db.example.com".to_string()),
+            ..route("test-backend")
+        }];
+        assert!(validate_config(&config).is_ok());
+
+        config.frontends[0].tls = Some(tls_config());
+        let result = validate_config(&config);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("cannot both terminate TLS and pass it through")
+        );
+
+        config.frontends[0].tls = None;
+        config.frontends[0].protocol = Protocol::Http;
+        let result = validate_config(&config);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("only supported on TCP frontends")
+        );
+    }
+
+    #[test]
+    fn test_sni_certificates() {
+        let mut config = minimal_config();
+        let mut tls = tls_config();
+        tls.certificates = vec![
+            SniCertificate {
+                server_names: vec!["*.example.com".to_string(), "example.com".to_string()],
+                cert: "/certs/example.pem".into(),
+                key: "/certs/example.key".into(),
+            },
+            SniCertificate {
+                server_names: vec!["Example.com".to_string(), "api.*.com".to_string()],
+                cert: "/certs/other.pem".into(),
+                key: "/certs/other.key".into(),
+            },
+        ];
+        config.frontends[0].tls = Some(tls);
+
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("more than one certificate for server name 'Example.com'"));
+        assert!(err.contains("invalid server name 'api.*.com'"));
+    }
+
+    #[test]
+    fn test_backend_tls() {
+        let mut config = minimal_config();
+        config.backends[0].tls = Some(BackendTlsConfig {
+            ca: Some("/certs/ca.pem".into()),
+            cert: Some("/certs/client.pem".into()),
+            key: Some("/certs/client.key".into()),
+            server_name: Some("api.internal".to_string()),
+            verify: true,
+        });
+        assert!(validate_config(&config).is_ok());
+
+        config.backends[0].tls = Some(BackendTlsConfig {
+            ca: None,
+            cert: Some("/certs/client.pem".into()),
+            key: None,
+            server_name: Some("not a name".to_string()),
+            verify: true,
+        });
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("has no 'ca' bundle"));
+        assert!(err.contains("needs both 'cert' and 'key'"));
+        assert!(err.contains("invalid TLS server name 'not a name'"));
+
+        // Without verification no CA bundle is needed
+        config.backends[0].tls = Some(BackendTlsConfig {
+            ca: None,
+            cert: None,
+            key: None,
+            server_name: None,
+            verify: false,
+        });
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_grpc_mode() {
+        let mut config = minimal_config();
+        config.frontends[0].http = Some(HttpConfig {
+            grpc: true,
+            ..Default::default()
+        });
+        config.backends[0].health_check = Some(HealthCheckConfig {
+            check_type: HealthCheckType::Grpc,
+            ..Default::default()
+        });
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("backend 'test-backend' does not use 'protocol: http2'"));
+        assert!(err.contains("has gRPC health check but does not use 'protocol: http2'"));
+
+        config.backends[0].protocol = BackendProtocol::Http2;
+        assert!(validate_config(&config).is_ok());
+
+        config.frontends[0].protocol = Protocol::Tcp;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("enables gRPC mode but is not an HTTP frontend"));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let mut config = minimal_config();
+        config.frontends[0].http = Some(HttpConfig {
+            retry: Some(RetryConfig {
+                retry_on_status: vec![502, 503],
+                per_try_timeout: Some(Duration::from_secs(2)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert!(validate_config(&config).is_ok());
+
+        let http = config.frontends[0].http.as_mut().unwrap();
+        let retry = http.retry.as_mut().unwrap();
+        retry.max_attempts = 0;
+        retry.retry_on_status.push(404);
+        retry.budget = Some(Duration::ZERO);
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("max_attempts must be at least 1"));
+        assert!(err.contains("retries on status 404"));
+        assert!(err.contains("retry budget must be greater than zero"));
+    }
+
+    #[test]
+    fn test_connect_retry_policy() {
+        let mut config = minimal_config();
+        config.frontends[0].protocol = Protocol::Tcp;
+        config.frontends[0].tcp = Some(TcpConfig {
+            connect_retry: Some(ConnectRetryConfig::default()),
+            ..Default::default()
+        });
+        assert!(validate_config(&config).is_ok());
+
+        let tcp = config.frontends[0].tcp.as_mut().unwrap();
+        let retry = tcp.connect_retry.as_mut().unwrap();
+        retry.max_attempts = 0;
+        retry.backoff = Duration::from_secs(5);
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("connect_retry max_attempts must be at least 1"));
+        assert!(err.contains("backoff must not exceed max_backoff"));
+    }
+
+    #[test]
+    fn test_passive_health() {
+        let mut config = minimal_config();
+        config.backends[0].passive_health.failure_statuses = vec![502, 503];
+        assert!(validate_config(&config).is_ok());
+
+        let passive = &mut config.backends[0].passive_health;
+        passive.unhealthy_threshold = 0;
+        passive.ejection_time = Duration::ZERO;
+        passive.failure_statuses = vec![404];
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("unhealthy_threshold must be at least 1"));
+        assert!(err.contains("ejection_time must be greater than zero"));
+        assert!(err.contains("counts status 404 as a failure"));
+
+        // Settings of a disabled tracker are not checked
+        config.backends[0].passive_health.enabled = false;
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_outlier_detection() {
+        let mut config = minimal_config();
+        config.backends[0].outlier_detection = Some(OutlierDetectionConfig::default());
+        assert!(validate_config(&config).is_ok());
+
+        let outlier = config.backends[0].outlier_detection.as_mut().unwrap();
+        outlier.min_servers = 1;
+        outlier.stdev_factor = -1.0;
+        outlier.base_ejection_time = Duration::from_secs(600);
+        outlier.max_ejection_percent = 150;
+        let err = validate_config(&config).unwrap_err();
+        assert!(err.contains("min_servers must be at least 2"));
+        assert!(err.contains("stdev_factor must be a non-negative number"));
+        assert!(err.contains("base_ejection_time must not exceed max_ejection_time"));
+        assert!(err.contains("max_ejection_percent must be at most 100"));
+    }
+
+    #[test]
+    fn test_duplicate_route() {
+        let mut config = minimal_config();
+        let api = RouteConfig {
+            path: Some(PathMatch::Prefix("/api".to_string())),
+            ..route("test-backend")
+        };
+        config.frontends[0].routes = vec![api.clone(), api];
+        let result = validate_config(&config);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("route '#2' duplicates route '#1'")
+        );
+    }
+
+    #[test]
+    fn test_unreachable_route() {
+        let mut config = minimal_config();
+        config.frontends[0].routes = vec![
+            RouteConfig {
+                name: Some("wildcard".to_string()),
+                host: Some("*.example.com".to_string()),
+                ..route("test-backend")
+            },
+            RouteConfig {
+                name: Some("api".to_string()),
+                host: Some("api.example.com".to_string()),
+                path: Some(PathMatch::Prefix("/v1".to_string())),
+                methods: vec!["GET".to_string()],
+                ..route("test-backend")
+            },
+        ];
+        let result = validate_config(&config);
+        assert!(
+            result
+                .unwrap_err()
+                .contains("route 'api' is unreachable (shadowed by route 'wildcard')")
+        );
+
+        // A wildcard stands for a single label
+        config.frontends[0].routes[1].host = Some("*.api.example.com".to_string());
+        assert!(validate_config(&config).is_ok());
+    }
 }
@@ -1,24 +1,120 @@
 //! Configuration file watcher for hot reload.
 //!
-//! Watches the configuration file for changes and triggers reload.
+//! Watches the configuration file, and files it references such as TLS
+//! certificates, for changes and triggers reload. Reloads can also be
+//! requested over a channel, for example by the admin API.
 
 use crate::config::{load_config, validate_config, Config};
-use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::PathBuf;
+use crate::tls::check_backend_tls;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::time::Duration;
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc as tokio_mpsc, oneshot};
 use tracing::{error, info, warn};
 
 /// Callback type for config reload.
 pub type ReloadCallback = Box<dyn Fn(Config) + Send + Sync>;
 
+/// A requested reload, answered with the error if the new configuration
+/// could not be loaded or failed validation.
+pub type ReloadRequest = oneshot::Sender<Result<(), String>>;
+
+/// Watches files for changes through their directories.
+///
+/// Directories are watched rather than the files themselves, so files that
+/// are replaced, for example by an editor's atomic save, stay watched. Used
+/// by the [`ConfigWatcher`] and file discovery.
+pub(crate) struct FileWatcher {
+    /// The notify watcher, delivering events on its own thread.
+    watcher: RecommendedWatcher,
+    /// Events from the notify watcher.
+    events: mpsc::Receiver<Event>,
+    /// Directories being watched.
+    watched_dirs: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Create a file watcher that watches nothing yet.
+    pub(crate) fn new() -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let watcher = Watcher::new(
+            move |res: Result<Event, notify::Error>| {
+                if let Ok(event) = res {
+                    let _ = tx.send(event);
+                }
+            },
+            notify::Config::default().with_poll_interval(Duration::from_secs(2)),
+        )?;
+        Ok(Self {
+            watcher,
+            events,
+            watched_dirs: HashSet::new(),
+        })
+    }
+
+    /// Watch a directory, unless it is watched already.
+    pub(crate) fn watch_dir(&mut self, dir: &Path) -> notify::Result<()> {
+        if !self.watched_dirs.contains(dir) {
+            self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            self.watched_dirs.insert(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Watch the directory of a file, logging a failure.
+    pub(crate) fn watch_file(&mut self, file: &Path) {
+        if let Some(parent) = file.parent()
+            && let Err(e) = self.watch_dir(parent)
+        {
+            warn!(path = %parent.display(), error = %e, "failed to watch directory");
+        }
+    }
+
+    /// Wait for file events.
+    ///
+    /// Events are collected every 100ms, so the steps of one write come in
+    /// one batch. Cancel safe.
+    pub(crate) async fn events(&mut self) -> Vec<Event> {
+        loop {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let events: Vec<Event> = self.events.try_iter().collect();
+            if !events.is_empty() {
+                return events;
+            }
+        }
+    }
+}
+
+/// Paths whose content an event changed.
+///
+/// Files written or created count. Files removed or renamed count if a file
+/// is at their path afterwards: an atomic save renames a new file over the
+/// old one, which some platforms report as the removal of the old file only.
+pub(crate) fn changed_paths(event: &Event) -> impl Iterator<Item = &PathBuf> {
+    let replaced = matches!(
+        event.kind,
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    );
+    let written = !replaced && matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_));
+    event
+        .paths
+        .iter()
+        .filter(move |path| written || (replaced && path.exists()))
+}
+
 /// Configuration file watcher.
 pub struct ConfigWatcher {
     /// Path to the config file.
     config_path: PathBuf,
+    /// Files referenced by the current configuration (e.g. TLS certificates).
+    watched_files: Vec<PathBuf>,
     /// Callback to invoke when config is reloaded.
     reload_callback: ReloadCallback,
+    /// Reloads requested by other tasks.
+    requests: Option<tokio_mpsc::UnboundedReceiver<ReloadRequest>>,
 }
 
 impl ConfigWatcher {
@@ -26,10 +122,30 @@ impl ConfigWatcher {
     pub fn new(config_path: PathBuf, reload_callback: ReloadCallback) -> Self {
         Self {
             config_path,
+            watched_files: Vec::new(),
             reload_callback,
+            requests: None,
         }
     }
 
+    /// Also reload when a request arrives on this channel.
+    pub fn with_reload_requests(
+        mut self,
+        requests: tokio_mpsc::UnboundedReceiver<ReloadRequest>,
+    ) -> Self {
+        self.requests = Some(requests);
+        self
+    }
+
+    /// Also reload when one of these files changes.
+    ///
+    /// The list is replaced by [`Config::watched_files`] after every
+    /// successful reload.
+    pub fn watch_files(mut self, files: Vec<PathBuf>) -> Self {
+        self.watched_files = files.iter().map(|f| absolute(f)).collect();
+        self
+    }
+
     /// Get the path being watched.
     pub fn path(&self) -> &PathBuf {
         &self.config_path
@@ -39,24 +155,14 @@ impl ConfigWatcher {
     ///
     /// This watches for:
     /// - File changes to the config file
+    /// - File changes to referenced files such as TLS certificates
     /// - SIGHUP signal for manual reload
-    pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
+    /// - Reload requests, if a request channel was given
+    pub async fn run(mut self, mut shutdown: broadcast::Receiver<()>) {
         info!(path = %self.config_path.display(), "config watcher starting");
 
-        // Create file watcher channel
-        let (tx, rx) = mpsc::channel();
-
         // Create file watcher
-        let watcher_result: Result<RecommendedWatcher, _> = Watcher::new(
-            move |res: Result<Event, notify::Error>| {
-                if let Ok(event) = res {
-                    let _ = tx.send(event);
-                }
-            },
-            notify::Config::default().with_poll_interval(Duration::from_secs(2)),
-        );
-
-        let mut watcher = match watcher_result {
+        let mut watcher = match FileWatcher::new() {
             Ok(w) => w,
             Err(e) => {
                 error!(error = %e, "failed to create file watcher");
@@ -67,14 +173,17 @@ impl ConfigWatcher {
         };
 
         // Watch the config file's parent directory
-        if let Some(parent) = self.config_path.parent() {
-            if let Err(e) = watcher.watch(parent, RecursiveMode::NonRecursive) {
-                error!(error = %e, "failed to watch config directory");
-                let _ = shutdown.recv().await;
-                return;
-            }
+        if let Some(parent) = self.config_path.parent()
+            && let Err(e) = watcher.watch_dir(parent)
+        {
+            error!(error = %e, "failed to watch config directory");
+            let _ = shutdown.recv().await;
+            return;
         }
 
+        // Watch the directories of referenced files
+        self.watch_file_dirs(&mut watcher);
+
         // Setup SIGHUP handler (Unix only)
         #[cfg(unix)]
         let mut sighup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
@@ -86,17 +195,17 @@ impl ConfigWatcher {
             }
         };
 
+        let mut requests = self.requests.take();
+
         info!("config watcher ready, watching for changes");
 
         loop {
             tokio::select! {
-                // Check for file changes (polling)
-                _ = tokio::time::sleep(Duration::from_millis(100)) => {
-                    // Process any pending file events
-                    while let Ok(event) = rx.try_recv() {
-                        if self.should_reload(&event) {
-                            self.try_reload();
-                        }
+                // Handle file changes
+                events = watcher.events() => {
+                    if events.iter().any(|event| self.should_reload(event)) {
+                        let _ = self.try_reload();
+                        self.watch_file_dirs(&mut watcher);
                     }
                 }
 
@@ -116,7 +225,21 @@ impl ConfigWatcher {
                     }
                 } => {
                     info!("received SIGHUP, reloading configuration");
-                    self.try_reload();
+                    let _ = self.try_reload();
+                    self.watch_file_dirs(&mut watcher);
+                }
+
+                // Handle reload requests
+                Some(reply) = async {
+                    match requests.as_mut() {
+                        Some(requests) => requests.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    info!("reload requested, reloading configuration");
+                    let result = self.try_reload();
+                    self.watch_file_dirs(&mut watcher);
+                    let _ = reply.send(result);
                 }
 
                 // Handle shutdown
@@ -128,38 +251,57 @@ impl ConfigWatcher {
         }
     }
 
+    /// Start watching the directories of referenced files not yet watched.
+    fn watch_file_dirs(&self, watcher: &mut FileWatcher) {
+        for file in &self.watched_files {
+            watcher.watch_file(file);
+        }
+    }
+
     /// Check if this event should trigger a reload.
     fn should_reload(&self, event: &Event) -> bool {
-        // Check if the event is for our config file
-        let is_our_file = event.paths.iter().any(|p| {
-            p.file_name() == self.config_path.file_name()
-        });
-
-        let is_modify_or_create = matches!(
-            event.kind,
-            notify::EventKind::Modify(_) | notify::EventKind::Create(_)
-        );
-
-        is_our_file && is_modify_or_create
+        // Check if the event changed our config file or a referenced file
+        changed_paths(event).any(|p| {
+            p.file_name() == self.config_path.file_name() || self.watched_files.contains(p)
+        })
     }
 
     /// Try to reload the configuration.
-    fn try_reload(&self) {
+    ///
+    /// systemd is told a reload started. The receiver of the new
+    /// configuration reports when it is applied; a failed reload is reported
+    /// here. Returns the error if the configuration was kept.
+    fn try_reload(&mut self) -> Result<(), String> {
         info!(path = %self.config_path.display(), "attempting config reload");
+        #[cfg(unix)]
+        crate::systemd::notify_reloading();
 
         // Load the new config
         let new_config = match load_config(&self.config_path) {
             Ok(config) => config,
             Err(e) => {
                 error!(error = %e, "failed to load new config, keeping current");
-                return;
+                #[cfg(unix)]
+                crate::systemd::notify(crate::systemd::READY);
+                return Err(e.to_string());
             }
         };
 
         // Validate the new config
         if let Err(e) = validate_config(&new_config) {
             error!(error = %e, "new config validation failed, keeping current");
-            return;
+            #[cfg(unix)]
+            crate::systemd::notify(crate::systemd::READY);
+            return Err(e);
+        }
+
+        // Backends whose TLS settings fail to load would be left out of the
+        // router, so keep the current configuration instead
+        if let Err(e) = check_backend_tls(&new_config.backends) {
+            error!(error = %e, "new config validation failed, keeping current");
+            #[cfg(unix)]
+            crate::systemd::notify(crate::systemd::READY);
+            return Err(e);
         }
 
         // Apply the new config via callback
@@ -168,10 +310,21 @@ impl ConfigWatcher {
             backends = new_config.backends.len(),
             "configuration reloaded successfully"
         );
+        self.watched_files = new_config
+            .watched_files()
+            .iter()
+            .map(|f| absolute(f))
+            .collect();
         (self.reload_callback)(new_config);
+        Ok(())
     }
 }
 
+/// Make a path absolute so it can be compared with watcher event paths.
+pub(crate) fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,6 +375,28 @@ mod tests {
         assert!(!watcher.should_reload(&event));
     }
 
+    #[test]
+    fn test_should_reload_watched_file() {
+        let callback: ReloadCallback = Box::new(|_| {});
+        let watcher = ConfigWatcher::new(PathBuf::from("/test/config.yaml"), callback)
+            .watch_files(vec![PathBuf::from("/certs/site.pem")]);
+
+        let event = Event {
+            kind: notify::EventKind::Modify(notify::event::ModifyKind::Data(
+                notify::event::DataChange::Content,
+            )),
+            paths: vec![PathBuf::from("/certs/site.pem")],
+            attrs: Default::default(),
+        };
+        assert!(watcher.should_reload(&event));
+
+        let event = Event {
+            paths: vec![PathBuf::from("/certs/other.pem")],
+            ..event
+        };
+        assert!(!watcher.should_reload(&event));
+    }
+
     #[test]
     fn test_should_reload_delete_ignored() {
         let callback: ReloadCallback = Box::new(|_| {});
@@ -235,4 +410,67 @@ mod tests {
 
         assert!(!watcher.should_reload(&event));
     }
+
+    #[test]
+    fn test_should_reload_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        let callback: ReloadCallback = Box::new(|_| {});
+        let watcher = ConfigWatcher::new(config_path.clone(), callback);
+
+        // An atomic save reported as the removal of the old file
+        let event = Event {
+            kind: notify::EventKind::Remove(notify::event::RemoveKind::File),
+            paths: vec![config_path.clone()],
+            attrs: Default::default(),
+        };
+        assert!(!watcher.should_reload(&event));
+        std::fs::write(&config_path, "").unwrap();
+        assert!(watcher.should_reload(&event));
+
+        // Renaming the file away leaves nothing to reload
+        std::fs::remove_file(&config_path).unwrap();
+        let event = Event {
+            kind: notify::EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::From)),
+            ..event
+        };
+        assert!(!watcher.should_reload(&event));
+    }
+
+    #[test]
+    fn test_reload_rejects_backend_tls_failure() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        std::fs::write(
+            &config_path,
+            r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:8080"
+    backend: api
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+    tls:
+      ca: /nonexistent/ca.pem
+"#,
+        )
+        .unwrap();
+
+        let reloaded = Arc::new(AtomicBool::new(false));
+        let callback: ReloadCallback = {
+            let reloaded = Arc::clone(&reloaded);
+            Box::new(move |_| reloaded.store(true, Ordering::SeqCst))
+        };
+        let mut watcher = ConfigWatcher::new(config_path, callback);
+
+        // The pool would be left out of the router, so the reload is refused
+        let err = watcher.try_reload().unwrap_err();
+        assert!(err.contains("failed to load TLS settings of backend 'api'"));
+        assert!(!reloaded.load(Ordering::SeqCst));
+    }
 }
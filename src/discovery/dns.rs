@@ -0,0 +1,417 @@
+//! DNS-based server discovery.
+//!
+//! Resolves the `discovery.dns` names of every backend into servers: each A
+//! and AAAA record of an `address`, or each SRV record of the best priority.
+//! Names are resolved again when their records expire, and at least every
+//! `dns_refresh`. A name that fails to resolve keeps the servers it resolved
+//! to last.
+
+use crate::backend::BackendRouter;
+use crate::config::{DnsTarget, ServerConfig};
+use crate::metrics::{DiscoverySource, MetricsCollector};
+use futures::future::join_all;
+use hickory_resolver::config::{LookupIpStrategy, ResolverConfig};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::rr::rdata::SRV;
+use hickory_resolver::{ResolveError, ResolverBuilder, TokioResolver};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Discovery source name passed to the router.
+const SOURCE: &str = "dns";
+
+/// Shortest time between two resolutions of a name, whatever its TTL.
+const MIN_REFRESH: Duration = Duration::from_secs(1);
+
+/// Time to wait while no backend uses DNS discovery.
+const IDLE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The DNS names of a backend pool.
+struct Pool {
+    /// Names to resolve.
+    targets: Vec<DnsTarget>,
+    /// Longest time between two resolutions.
+    refresh: Duration,
+    /// Servers each target resolved to last, `None` until it resolved once.
+    resolved: Vec<Option<Vec<ServerConfig>>>,
+    /// When the next resolution is due.
+    next_run: Instant,
+}
+
+/// DNS discovery that keeps the discovered servers of every pool current.
+///
+/// Follows the backend pools in the [`BackendRouter`] across hot reloads,
+/// like the [`HealthChecker`](crate::health::HealthChecker).
+pub struct DnsDiscovery {
+    /// Router that owns the backend pools.
+    router: Arc<BackendRouter>,
+    /// Resolver with its own cache.
+    resolver: TokioResolver,
+    /// Metrics collector counting failed resolutions.
+    metrics: MetricsCollector,
+}
+
+impl DnsDiscovery {
+    /// Create DNS discovery using the system's resolver configuration.
+    pub fn new(
+        router: Arc<BackendRouter>,
+        metrics: MetricsCollector,
+    ) -> Result<Self, ResolveError> {
+        let builder = TokioResolver::builder_tokio()?;
+        Ok(Self::with_resolver(router, metrics, builder))
+    }
+
+    /// Create DNS discovery querying the name servers of `config`.
+    pub fn with_config(
+        router: Arc<BackendRouter>,
+        metrics: MetricsCollector,
+        config: ResolverConfig,
+    ) -> Self {
+        let builder =
+            TokioResolver::builder_with_config(config, TokioConnectionProvider::default());
+        Self::with_resolver(router, metrics, builder)
+    }
+
+    fn with_resolver(
+        router: Arc<BackendRouter>,
+        metrics: MetricsCollector,
+        mut builder: ResolverBuilder<TokioConnectionProvider>,
+    ) -> Self {
+        // Every address record becomes a server
+        builder.options_mut().ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+        Self {
+            router,
+            resolver: builder.build(),
+            metrics,
+        }
+    }
+
+    /// Start the DNS discovery background task.
+    pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
+        info!("DNS discovery starting");
+
+        let mut changes = self.router.subscribe();
+        let mut pools = HashMap::new();
+        self.refresh_pools(&mut pools);
+
+        loop {
+            let next_run = pools
+                .values()
+                .map(|pool| pool.next_run)
+                .min()
+                .unwrap_or_else(|| Instant::now() + IDLE_INTERVAL);
+
+            tokio::select! {
+                _ = tokio::time::sleep_until(next_run) => {
+                    let now = Instant::now();
+                    let due = pools.iter_mut().filter(|(_, pool)| pool.next_run <= now);
+                    join_all(due.map(|(name, pool)| self.resolve_pool(name, pool))).await;
+                }
+
+                Ok(()) = changes.changed() => {
+                    self.refresh_pools(&mut pools);
+                }
+
+                _ = shutdown.recv() => {
+                    info!("DNS discovery shutting down");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Update the pools from the router's current backend configurations.
+    ///
+    /// Pools whose names are unchanged keep their resolved servers and
+    /// schedule. Pools that no longer use DNS discovery lose their
+    /// discovered servers.
+    fn refresh_pools(&self, pools: &mut HashMap<String, Pool>) {
+        let mut configured: HashMap<String, (Vec<DnsTarget>, Duration)> = self
+            .router
+            .backend_configs()
+            .into_iter()
+            .filter_map(|backend| {
+                let discovery = backend.discovery?;
+                (!discovery.dns.is_empty())
+                    .then_some((backend.name, (discovery.dns, discovery.dns_refresh)))
+            })
+            .collect();
+
+        pools.retain(|name, pool| match configured.remove(name) {
+            Some((targets, refresh)) if targets == pool.targets => {
+                pool.refresh = refresh;
+                true
+            }
+            Some((targets, refresh)) => {
+                debug!(backend = %name, "DNS names changed");
+                *pool = Pool::new(targets, refresh);
+                true
+            }
+            None => {
+                debug!(backend = %name, "stopping DNS discovery");
+                self.router.set_discovered(name, SOURCE, Vec::new());
+                false
+            }
+        });
+        for (name, (targets, refresh)) in configured {
+            debug!(backend = %name, names = targets.len(), "starting DNS discovery");
+            pools.insert(name, Pool::new(targets, refresh));
+        }
+    }
+
+    /// Resolve the names of a pool and hand the servers to the router.
+    async fn resolve_pool(&self, backend_name: &str, pool: &mut Pool) {
+        let results = join_all(pool.targets.iter().map(|target| self.resolve(target))).await;
+
+        let mut valid_for = pool.refresh;
+        for ((target, resolved), result) in pool.targets.iter().zip(&mut pool.resolved).zip(results)
+        {
+            match result {
+                Ok((servers, ttl)) => {
+                    debug!(
+                        backend = backend_name,
+                        name = %target_name(target),
+                        servers = servers.len(),
+                        ttl = ?ttl,
+                        "resolved DNS name"
+                    );
+                    valid_for = valid_for.min(ttl);
+                    *resolved = Some(servers);
+                }
+                Err(e) => {
+                    warn!(
+                        backend = backend_name,
+                        name = %target_name(target),
+                        error = %e,
+                        "DNS resolution failed, keeping last known servers"
+                    );
+                    self.metrics
+                        .record_discovery_error(backend_name, DiscoverySource::Dns);
+                }
+            }
+        }
+        pool.next_run = Instant::now() + valid_for.max(MIN_REFRESH);
+
+        // Leave the pool alone until something resolved at all
+        if pool.resolved.iter().all(Option::is_none) {
+            return;
+        }
+        let mut servers: Vec<ServerConfig> = Vec::new();
+        for server in pool.resolved.iter().flatten().flatten() {
+            if !servers.iter().any(|s| s.address == server.address) {
+                servers.push(server.clone());
+            }
+        }
+        self.router.set_discovered(backend_name, SOURCE, servers);
+    }
+
+    /// Resolve a name into servers, and how long the records stay valid.
+    async fn resolve(
+        &self,
+        target: &DnsTarget,
+    ) -> Result<(Vec<ServerConfig>, Duration), ResolveError> {
+        if let Some(srv) = &target.srv {
+            return self.resolve_srv(srv).await;
+        }
+        let Some((host, port)) = target.host_port() else {
+            return Err(ResolveError::from(format!(
+                "invalid DNS address '{}'",
+                target_name(target)
+            )));
+        };
+
+        let lookup = self.resolver.lookup_ip(host).await?;
+        let servers = lookup
+            .iter()
+            .map(|ip| ServerConfig {
+                address: SocketAddr::new(ip, port),
+                weight: target.weight,
+                metadata: Default::default(),
+            })
+            .collect();
+        Ok((servers, until(lookup.valid_until())))
+    }
+
+    /// Resolve an SRV name and the targets of its best records.
+    ///
+    /// Targets that fail to resolve are left out, unless all of them do.
+    async fn resolve_srv(&self, name: &str) -> Result<(Vec<ServerConfig>, Duration), ResolveError> {
+        let lookup = self.resolver.srv_lookup(name).await?;
+        let records = best_priority(lookup.iter());
+
+        let lookups = join_all(
+            records
+                .iter()
+                .map(|record| self.resolver.lookup_ip(record.target().clone())),
+        )
+        .await;
+
+        let mut servers = Vec::new();
+        let mut valid_for = until(lookup.as_lookup().valid_until());
+        let mut error = None;
+        for (record, result) in records.iter().zip(lookups) {
+            match result {
+                Ok(ips) => {
+                    valid_for = valid_for.min(until(ips.valid_until()));
+                    servers.extend(ips.iter().map(|ip| ServerConfig {
+                        address: SocketAddr::new(ip, record.port()),
+                        weight: u32::from(record.weight()).max(1),
+                        metadata: Default::default(),
+                    }));
+                }
+                Err(e) => {
+                    warn!(
+                        name = %name,
+                        target = %record.target(),
+                        error = %e,
+                        "SRV target failed to resolve"
+                    );
+                    error = Some(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if servers.is_empty() => Err(e),
+            _ => Ok((servers, valid_for)),
+        }
+    }
+}
+
+impl Pool {
+    fn new(targets: Vec<DnsTarget>, refresh: Duration) -> Self {
+        Self {
+            resolved: vec![None; targets.len()],
+            targets,
+            refresh,
+            next_run: Instant::now(),
+        }
+    }
+}
+
+/// The SRV records with the best (lowest) priority; the others are only
+/// meant as fallbacks.
+fn best_priority<'a>(records: impl Iterator<Item = &'a SRV>) -> Vec<&'a SRV> {
+    let records: Vec<&SRV> = records.collect();
+    let best = records.iter().map(|record| record.priority()).min();
+    records
+        .into_iter()
+        .filter(|record| Some(record.priority()) == best)
+        .collect()
+}
+
+/// Time left until `deadline`, the expiry of a DNS answer.
+fn until(deadline: std::time::Instant) -> Duration {
+    deadline.saturating_duration_since(std::time::Instant::now())
+}
+
+/// Name of a DNS target for logs.
+fn target_name(target: &DnsTarget) -> &str {
+    target
+        .srv
+        .as_deref()
+        .or(target.address.as_deref())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use hickory_resolver::config::NameServerConfigGroup;
+    use hickory_resolver::Name;
+
+    fn target(address: &str) -> DnsTarget {
+        DnsTarget {
+            address: Some(address.to_string()),
+            srv: None,
+            weight: 2,
+        }
+    }
+
+    #[test]
+    fn test_best_priority() {
+        let name = Name::from_ascii("api.example.com.").unwrap();
+        let records = [
+            SRV::new(20, 10, 8080, name.clone()),
+            SRV::new(10, 30, 8081, name.clone()),
+            SRV::new(10, 70, 8082, name),
+        ];
+        let best = best_priority(records.iter());
+        assert_eq!(best.len(), 2);
+        assert!(best.iter().all(|record| record.priority() == 10));
+    }
+
+    #[tokio::test]
+    async fn test_discovered_servers_join_pool() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:8080"
+    backend: api
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+    discovery:
+      dns:
+        - address: "127.0.0.2:9002"
+"#,
+        )
+        .unwrap();
+        let router = Arc::new(BackendRouter::new(&config.backends, &config.frontends));
+        // Nothing below queries the name server, so the test runs offline
+        let resolver_config = ResolverConfig::from_parts(
+            None,
+            Vec::new(),
+            NameServerConfigGroup::from_ips_clear(&["127.0.0.1".parse().unwrap()], 53, true),
+        );
+        let discovery = DnsDiscovery::with_config(
+            Arc::clone(&router),
+            MetricsCollector::new(),
+            resolver_config,
+        );
+
+        // IP addresses resolve without a query
+        let mut pools = HashMap::new();
+        discovery.refresh_pools(&mut pools);
+        let pool = pools.get_mut("api").unwrap();
+        discovery.resolve_pool("api", pool).await;
+        let servers = router.get_servers("api").unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[1], "127.0.0.2:9002".parse().unwrap());
+
+        // A failed name keeps its last servers; `.invalid` names never exist
+        pool.targets[0] = target("name.invalid:80");
+        discovery.resolve_pool("api", pool).await;
+        assert_eq!(router.get_servers("api").unwrap().len(), 2);
+
+        // Removing discovery drains the discovered servers
+        router.reload(
+            &[config.backends[0].clone()].map(|mut b| {
+                b.discovery = None;
+                b
+            }),
+            &config.frontends,
+        );
+        discovery.refresh_pools(&mut pools);
+        assert!(pools.is_empty());
+        assert_eq!(router.get_servers("api").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_host_port() {
+        assert_eq!(
+            target("api.internal:8080").host_port(),
+            Some(("api.internal", 8080))
+        );
+        assert_eq!(target("[::1]:80").host_port(), Some(("::1", 80)));
+        assert_eq!(target("api.internal").host_port(), None);
+        assert_eq!(target(":80").host_port(), None);
+    }
+}
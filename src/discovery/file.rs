@@ -0,0 +1,347 @@
+//! File-based server discovery.
+//!
+//! Reads the `discovery.file` server list of every backend and applies it
+//! whenever the file changes, without reloading the configuration. A list
+//! that cannot be read or is invalid is rejected, and the pool keeps the
+//! servers of the last valid one.
+
+use crate::backend::BackendRouter;
+use crate::config::{absolute, changed_paths, FileWatcher, ServerConfig};
+use crate::metrics::{DiscoverySource, MetricsCollector};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::{debug, error, info};
+
+/// Discovery source name passed to the router.
+const SOURCE: &str = "file";
+
+/// Error loading a server list file.
+#[derive(Debug, thiserror::Error)]
+pub enum ServerListError {
+    #[error("failed to read server list '{0}': {1}")]
+    Read(PathBuf, io::Error),
+
+    #[error("invalid JSON in server list '{0}': {1}")]
+    Json(PathBuf, serde_json::Error),
+
+    #[error("invalid YAML in server list '{0}': {1}")]
+    Yaml(PathBuf, serde_yaml::Error),
+
+    #[error("server list '{0}' is empty")]
+    Empty(PathBuf),
+
+    #[error("server list '{0}' lists {1} more than once")]
+    Duplicate(PathBuf, SocketAddr),
+
+    #[error("server {1} in server list '{0}' has weight 0 (must be >= 1)")]
+    ZeroWeight(PathBuf, SocketAddr),
+}
+
+/// Read a server list file.
+///
+/// The file holds a list of servers like a backend's `servers`, each with an
+/// `address` and optional `weight` and `metadata`. Files ending in `.json`
+/// are parsed as JSON, others as YAML. An empty list is rejected, since it
+/// more likely comes from a truncated write than from an intent to remove
+/// every server.
+pub fn load_server_list(path: &Path) -> Result<Vec<ServerConfig>, ServerListError> {
+    let data = fs::read(path).map_err(|e| ServerListError::Read(path.to_path_buf(), e))?;
+    let servers: Vec<ServerConfig> = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_slice(&data).map_err(|e| ServerListError::Json(path.to_path_buf(), e))?
+    } else if data.iter().all(u8::is_ascii_whitespace) {
+        Vec::new()
+    } else {
+        serde_yaml::from_slice(&data).map_err(|e| ServerListError::Yaml(path.to_path_buf(), e))?
+    };
+
+    if servers.is_empty() {
+        return Err(ServerListError::Empty(path.to_path_buf()));
+    }
+    let mut addresses = HashSet::new();
+    for server in &servers {
+        if server.weight == 0 {
+            return Err(ServerListError::ZeroWeight(
+                path.to_path_buf(),
+                server.address,
+            ));
+        }
+        if !addresses.insert(server.address) {
+            return Err(ServerListError::Duplicate(
+                path.to_path_buf(),
+                server.address,
+            ));
+        }
+    }
+    Ok(servers)
+}
+
+/// File discovery that applies the server list files of every pool.
+///
+/// Follows the backend pools in the [`BackendRouter`] across hot reloads,
+/// and watches the files with the same mechanism as the
+/// [`ConfigWatcher`](crate::config::ConfigWatcher).
+pub struct FileDiscovery {
+    /// Router that owns the backend pools.
+    router: Arc<BackendRouter>,
+    /// Metrics collector counting rejected files.
+    metrics: MetricsCollector,
+}
+
+impl FileDiscovery {
+    /// Create file discovery for the pools of `router`.
+    pub fn new(router: Arc<BackendRouter>, metrics: MetricsCollector) -> Self {
+        Self { router, metrics }
+    }
+
+    /// Start the file discovery background task.
+    pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
+        info!("file discovery starting");
+
+        let mut watcher = match FileWatcher::new() {
+            Ok(w) => w,
+            Err(e) => {
+                error!(error = %e, "failed to create file watcher for server lists");
+                let _ = shutdown.recv().await;
+                return;
+            }
+        };
+
+        let mut changes = self.router.subscribe();
+        let mut files = HashMap::new();
+        self.refresh_files(&mut files, &mut watcher);
+
+        loop {
+            tokio::select! {
+                events = watcher.events() => {
+                    let changed: HashSet<&PathBuf> = events.iter().flat_map(changed_paths).collect();
+                    for (backend, path) in &files {
+                        if changed.contains(path) {
+                            self.load(backend, path);
+                        }
+                    }
+                }
+
+                Ok(()) = changes.changed() => {
+                    self.refresh_files(&mut files, &mut watcher);
+                }
+
+                _ = shutdown.recv() => {
+                    info!("file discovery shutting down");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Update the watched files from the router's current backend
+    /// configurations.
+    ///
+    /// New and changed files are loaded right away. Pools that no longer use
+    /// a file lose the servers it listed.
+    fn refresh_files(&self, files: &mut HashMap<String, PathBuf>, watcher: &mut FileWatcher) {
+        let mut configured: HashMap<String, PathBuf> = self
+            .router
+            .backend_configs()
+            .into_iter()
+            .filter_map(|backend| {
+                let file = backend.discovery?.file?;
+                Some((backend.name, absolute(&file)))
+            })
+            .collect();
+
+        files.retain(|name, path| {
+            if configured.get(name) == Some(path) {
+                configured.remove(name);
+                return true;
+            }
+            if !configured.contains_key(name) {
+                debug!(backend = %name, "stopping file discovery");
+                self.router.set_discovered(name, SOURCE, Vec::new());
+            }
+            false
+        });
+        for (name, path) in configured {
+            debug!(backend = %name, path = %path.display(), "starting file discovery");
+            watcher.watch_file(&path);
+            self.load(&name, &path);
+            files.insert(name, path);
+        }
+    }
+
+    /// Load a server list and hand its servers to the router, or keep the
+    /// previous ones if it is invalid.
+    fn load(&self, backend_name: &str, path: &Path) {
+        match load_server_list(path) {
+            Ok(servers) => {
+                debug!(
+                    backend = backend_name,
+                    path = %path.display(),
+                    servers = servers.len(),
+                    "loaded server list"
+                );
+                self.router.set_discovered(backend_name, SOURCE, servers);
+            }
+            Err(e) => {
+                error!(
+                    backend = backend_name,
+                    error = %e,
+                    "rejected server list, keeping previous servers"
+                );
+                self.metrics
+                    .record_discovery_error(backend_name, DiscoverySource::File);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_load_server_list() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let json = dir.path().join("servers.json");
+        fs::write(
+            &json,
+            r#"[
+                {"address": "127.0.0.1:9001", "weight": 3, "metadata": {"zone": "a"}},
+                {"address": "127.0.0.1:9002"}
+            ]"#,
+        )
+        .unwrap();
+        let servers = load_server_list(&json).unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].weight, 3);
+        assert_eq!(servers[0].metadata["zone"], "a");
+        assert_eq!(servers[1].weight, 1);
+
+        let yaml = dir.path().join("servers.yaml");
+        fs::write(&yaml, "- address: \"127.0.0.1:9001\"\n  weight: 2\n").unwrap();
+        assert_eq!(load_server_list(&yaml).unwrap()[0].weight, 2);
+
+        let invalid = [
+            ("", "Empty"),
+            ("[]", "Empty"),
+            ("- address: not-an-address", "Yaml"),
+            ("- address: \"127.0.0.1:9001\"\n  weight: 0", "ZeroWeight"),
+            (
+                "- address: \"127.0.0.1:9001\"\n- address: \"127.0.0.1:9001\"",
+                "Duplicate",
+            ),
+        ];
+        for (content, kind) in invalid {
+            fs::write(&yaml, content).unwrap();
+            let err = load_server_list(&yaml).unwrap_err();
+            assert!(
+                format!("{:?}", err).starts_with(kind),
+                "{}: {:?}",
+                content,
+                err
+            );
+        }
+        assert!(matches!(
+            load_server_list(&dir.path().join("missing.yaml")),
+            Err(ServerListError::Read(..))
+        ));
+    }
+
+    async fn wait_for_servers(router: &BackendRouter, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while router.get_servers("api").unwrap().len() != count {
+            assert!(
+                Instant::now() < deadline,
+                "pool never had {} servers",
+                count
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_changes_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("servers.yaml");
+        fs::write(&path, "- address: \"127.0.0.1:9002\"\n").unwrap();
+
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:8080"
+    backend: api
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+    discovery:
+      file: "{}"
+"#,
+            path.display()
+        ))
+        .unwrap();
+        let router = Arc::new(BackendRouter::new(&config.backends, &config.frontends));
+        let metrics = MetricsCollector::new();
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let discovery = FileDiscovery::new(Arc::clone(&router), metrics.clone());
+        let handle = tokio::spawn(discovery.run(shutdown_rx));
+
+        // The file is loaded at startup
+        wait_for_servers(&router, 2).await;
+
+        fs::write(
+            &path,
+            "- address: \"127.0.0.1:9002\"\n- address: \"127.0.0.1:9003\"\n",
+        )
+        .unwrap();
+        wait_for_servers(&router, 3).await;
+
+        // An invalid list is counted and the servers are kept
+        fs::write(&path, "- address: \"127.0.0.1:9002\"\n  weight: 0\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let mut buffer = String::new();
+            prometheus_client::encoding::text::encode(&mut buffer, metrics.registry()).unwrap();
+            if buffer.contains(r#"rustlb_discovery_errors_total{backend="api",source="File"}"#) {
+                break;
+            }
+            assert!(Instant::now() < deadline, "rejected list was not counted");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(router.get_servers("api").unwrap().len(), 3);
+
+        // An atomic save renames a new list over the old one
+        let temp = dir.path().join("servers.yaml.tmp");
+        fs::write(&temp, "- address: \"127.0.0.1:9003\"\n").unwrap();
+        fs::rename(&temp, &path).unwrap();
+        wait_for_servers(&router, 2).await;
+        let servers = router.get_servers("api").unwrap();
+        assert!(servers.contains(&"127.0.0.1:9003".parse().unwrap()));
+
+        // A removed list keeps its servers until it comes back
+        fs::remove_file(&path).unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(router.get_servers("api").unwrap().len(), 2);
+        fs::write(&path, "- address: \"127.0.0.1:9004\"\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !router
+            .get_servers("api")
+            .unwrap()
+            .contains(&"127.0.0.1:9004".parse().unwrap())
+        {
+            assert!(Instant::now() < deadline, "recreated list was not loaded");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let _ = shutdown_tx.send(());
+        handle.await.unwrap();
+    }
+}
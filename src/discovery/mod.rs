@@ -0,0 +1,12 @@
+//! Runtime discovery of backend servers.
+//!
+//! Discovered servers join the configured servers of their pool through
+//! [`BackendRouter::set_discovered`](crate::backend::BackendRouter::set_discovered),
+//! so the health checker and outlier detector follow them like servers added
+//! by a reload.
+
+mod dns;
+mod file;
+
+pub use dns::DnsDiscovery;
+pub use file::{load_server_list, FileDiscovery, ServerListError};
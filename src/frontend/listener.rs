@@ -3,33 +3,61 @@
 //! Accepts incoming connections and dispatches them to the appropriate handler.
 
 use crate::backend::BackendRouter;
-use crate::config::{FrontendConfig, HttpConfig, Protocol, TcpConfig};
-use crate::metrics::MetricsCollector;
-use crate::proxy::{handle_tcp_proxy, proxy_request, HttpProxyConfig, ProxyContext, TcpProxyError};
-use crate::util::RequestId;
-use hyper::server::conn::http1;
+use crate::config::{
+    BackendProtocol, ConnectionPoolConfig, FrontendConfig, HttpConfig, PassiveHealthConfig,
+    Protocol, TcpConfig, UnmatchedSniPolicy,
+};
+use crate::frontend::{route_connection, route_request};
+use crate::health::PassiveHealthTracker;
+use crate::metrics::{FailureReason, MetricsCollector};
+use crate::proxy::{
+    connect_backend, grpc_method, handle_tcp_proxy, is_upgrade_request, proxy_request,
+    proxy_upgrade, reject_request, ConnectionPool, HttpProxyConfig, ProxyContext, Rewind,
+    TcpProxyError,
+};
+use crate::tls::{read_client_hello, ClientHelloError, ServerTls, DEFAULT_CERTIFICATE};
+use crate::util::{ConnectionTracker, RequestId};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use hyper::body::Incoming;
 use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
+use hyper::{Request, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::broadcast;
+use tokio::time::timeout;
+use tokio_rustls::server::TlsStream;
 use tracing::{debug, error, info, instrument, warn};
 
 /// Default connect timeout if not specified in config.
 const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Maximum time allowed for a client to complete the TLS handshake, or to
+/// send its ClientHello on passthrough frontends.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Frontend listener that accepts and handles connections.
 pub struct FrontendListener {
-    /// Frontend configuration.
-    config: FrontendConfig,
+    /// Frontend configuration (swapped on hot reload).
+    config: Arc<ArcSwap<FrontendConfig>>,
     /// Backend router for selecting upstream servers.
     router: Arc<BackendRouter>,
-    /// TCP listener.
-    listener: TcpListener,
+    /// TCP listener, shared with the manager to hand it over on upgrade.
+    listener: Arc<TcpListener>,
     /// Metrics collector.
     metrics: MetricsCollector,
+    /// Keep-alive pool for HTTP backend connections.
+    pool: ConnectionPool,
+    /// TLS termination settings (swapped on hot reload), if the frontend
+    /// terminates TLS.
+    tls: Arc<ArcSwapOption<ServerTls>>,
+    /// Accepted client connections, drained on shutdown.
+    connections: ConnectionTracker,
 }
 
 impl FrontendListener {
@@ -39,26 +67,96 @@ impl FrontendListener {
         router: Arc<BackendRouter>,
         metrics: MetricsCollector,
     ) -> std::io::Result<Self> {
+        let tls = load_tls(&config)?;
         let listener = TcpListener::bind(config.listen).await?;
+        Ok(Self::new(config, listener, tls, router, metrics))
+    }
+
+    /// Create a frontend listener on an already bound socket, such as one
+    /// inherited from the previous process on a binary upgrade.
+    pub fn from_std(
+        config: FrontendConfig,
+        listener: std::net::TcpListener,
+        router: Arc<BackendRouter>,
+        metrics: MetricsCollector,
+    ) -> std::io::Result<Self> {
+        let tls = load_tls(&config)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        Ok(Self::new(config, listener, tls, router, metrics))
+    }
 
+    fn new(
+        config: FrontendConfig,
+        listener: TcpListener,
+        tls: Option<ServerTls>,
+        router: Arc<BackendRouter>,
+        metrics: MetricsCollector,
+    ) -> Self {
         info!(
             name = %config.name,
             listen = %config.listen,
             protocol = ?config.protocol,
             backend = %config.backend,
+            tls = tls.is_some(),
             "frontend listener bound"
         );
 
-        Ok(Self {
-            config,
+        Self {
+            config: Arc::new(ArcSwap::from_pointee(config)),
             router,
-            listener,
+            listener: Arc::new(listener),
+            pool: ConnectionPool::new(metrics.clone()),
             metrics,
-        })
+            tls: Arc::new(ArcSwapOption::new(tls.map(Arc::new))),
+            connections: ConnectionTracker::new(),
+        }
+    }
+
+    /// Use a shared backend connection pool instead of a private one.
+    pub fn with_connection_pool(mut self, pool: ConnectionPool) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Track connections in a shared tracker instead of a private one.
+    pub fn with_connection_tracker(mut self, connections: ConnectionTracker) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    /// Get the address the listener is bound to.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Get a handle to the listener's configuration.
+    ///
+    /// Storing a new configuration through the handle affects connections
+    /// accepted afterwards; established connections keep the snapshot they
+    /// started with.
+    pub fn config_handle(&self) -> Arc<ArcSwap<FrontendConfig>> {
+        Arc::clone(&self.config)
+    }
+
+    /// Get a handle to the listening socket.
+    ///
+    /// The socket stays open while the handle is held, even after the
+    /// listener stops, which lets a binary upgrade pass it on.
+    pub fn socket_handle(&self) -> Arc<TcpListener> {
+        Arc::clone(&self.listener)
+    }
+
+    /// Get a handle to the listener's TLS settings.
+    ///
+    /// Storing new settings (for example reloaded certificates) affects
+    /// handshakes started afterwards; storing `None` disables TLS.
+    pub fn tls_handle(&self) -> Arc<ArcSwapOption<ServerTls>> {
+        Arc::clone(&self.tls)
     }
 
     /// Run the listener, accepting connections until shutdown.
-    #[instrument(skip_all, fields(frontend = %self.config.name))]
+    #[instrument(skip_all, fields(frontend = %self.config.load().name))]
     pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
         info!("frontend listener starting");
 
@@ -92,54 +190,80 @@ impl FrontendListener {
             warn!(error = %e, "failed to set TCP_NODELAY on client connection");
         }
 
-        let frontend_name = self.config.name.clone();
-        let backend_name = self.config.backend.clone();
-        let protocol = self.config.protocol.clone();
+        let config = self.config.load_full();
+        let frontend_name = config.name.clone();
         let router = Arc::clone(&self.router);
-        let tcp_config = self.config.tcp.clone();
-        let http_config = self.config.http.clone();
+        let tls = self.tls.load_full();
+        let passthrough = config.protocol == Protocol::Tcp
+            && config.tcp.as_ref().is_some_and(|tcp| tcp.tls_passthrough);
         let metrics = self.metrics.clone();
+        let pool = self.pool.clone();
+        let connections = self.connections.clone();
+        let token = connections.track();
         let request_id = RequestId::short();
 
-        // Track connection opened
-        metrics.connection_opened(&frontend_name, &backend_name);
-
         // Spawn a task to handle this connection
         tokio::spawn(async move {
+            let _token = token;
             let start_time = Instant::now();
 
-            let result: Result<(), Box<dyn std::error::Error + Send + Sync>> = match protocol {
-                Protocol::Tcp => {
-                    handle_tcp_connection(
-                        stream,
-                        client_addr,
-                        &frontend_name,
-                        &backend_name,
-                        &router,
-                        tcp_config,
-                        &metrics,
-                        &request_id,
-                    )
-                    .await
-                    .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
-                }
-                Protocol::Http => {
-                    handle_http_connection(
-                        stream,
-                        client_addr,
-                        &frontend_name,
-                        &backend_name,
-                        &router,
-                        http_config,
-                        &metrics,
-                        &request_id,
-                    )
-                    .await
+            let serve = async {
+                match tls {
+                    Some(tls) => match accept_tls(&tls, stream, &frontend_name, &metrics).await {
+                        Ok(stream) => {
+                            let sni = stream.get_ref().1.server_name().map(str::to_string);
+                            serve_connection(
+                                stream,
+                                client_addr,
+                                config,
+                                sni,
+                                &router,
+                                &metrics,
+                                pool,
+                                &connections,
+                                &request_id,
+                            )
+                            .await
+                        }
+                        Err(e) => Err(e),
+                    },
+                    None if passthrough => match read_sni(stream).await {
+                        Ok((sni, stream)) => {
+                            serve_connection(
+                                stream,
+                                client_addr,
+                                config,
+                                sni,
+                                &router,
+                                &metrics,
+                                pool,
+                                &connections,
+                                &request_id,
+                            )
+                            .await
+                        }
+                        Err(e) => Err(e),
+                    },
+                    None => {
+                        serve_connection(
+                            stream,
+                            client_addr,
+                            config,
+                            None,
+                            &router,
+                            &metrics,
+                            pool,
+                            &connections,
+                            &request_id,
+                        )
+                        .await
+                    }
                 }
             };
-
-            // Track connection closed
-            metrics.connection_closed(&frontend_name, &backend_name);
+            let result = tokio::select! {
+                result = serve => result,
+                _ = connections.closed() => Err("connection closed at shutdown".into()),
+            };
 
             let duration = start_time.elapsed();
 
@@ -165,46 +289,262 @@ impl FrontendListener {
     }
 }
 
+/// Load the TLS termination settings of a frontend, if it terminates TLS.
+fn load_tls(config: &FrontendConfig) -> std::io::Result<Option<ServerTls>> {
+    config
+        .tls
+        .as_ref()
+        .map(|tls| ServerTls::new(tls, &config.protocol))
+        .transpose()
+        .map_err(std::io::Error::other)
+}
+
+/// Complete the TLS handshake on a client connection.
+///
+/// Handshakes are counted per certificate server name, since the raw SNI
+/// value is chosen by the client.
+async fn accept_tls(
+    tls: &ServerTls,
+    stream: TcpStream,
+    frontend_name: &str,
+    metrics: &MetricsCollector,
+) -> Result<TlsStream<TcpStream>, Box<dyn std::error::Error + Send + Sync>> {
+    match timeout(TLS_HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+        Ok(Ok(stream)) => {
+            let (_, session) = stream.get_ref();
+            let certificate = tls.certificate_name(session.server_name());
+            metrics.record_tls_handshake(frontend_name, certificate, true);
+            debug!(
+                version = ?session.protocol_version(),
+                alpn = ?session.alpn_protocol().map(String::from_utf8_lossy),
+                sni = ?session.server_name(),
+                certificate = certificate,
+                "TLS handshake completed"
+            );
+            Ok(stream)
+        }
+        Ok(Err(e)) => {
+            let certificate = tls.certificate_name(e.server_name.as_deref());
+            metrics.record_tls_handshake(frontend_name, certificate, false);
+            Err(match e.server_name {
+                Some(ref sni) => format!("TLS handshake failed (sni {}): {}", sni, e),
+                None => format!("TLS handshake failed: {}", e),
+            }
+            .into())
+        }
+        Err(_) => {
+            metrics.record_tls_handshake(frontend_name, DEFAULT_CERTIFICATE, false);
+            Err("TLS handshake timed out".into())
+        }
+    }
+}
+
+/// Read the ClientHello of a passthrough connection to learn its SNI.
+///
+/// Connections that are not TLS, or whose ClientHello cannot be parsed, are
+/// treated as having no SNI. Everything read is replayed to the backend.
+async fn read_sni(
+    mut stream: TcpStream,
+) -> Result<(Option<String>, Rewind<TcpStream>), Box<dyn std::error::Error + Send + Sync>> {
+    let mut buf = Vec::new();
+    let sni = match timeout(
+        TLS_HANDSHAKE_TIMEOUT,
+        read_client_hello(&mut stream, &mut buf),
+    )
+    .await
+    {
+        Ok(Ok(sni)) => sni,
+        Ok(Err(ClientHelloError::Io(e))) => {
+            return Err(format!("failed to read ClientHello: {}", e).into());
+        }
+        Ok(Err(e)) => {
+            debug!(error = %e, "no SNI available for passthrough routing");
+            None
+        }
+        Err(_) => return Err("timed out waiting for ClientHello".into()),
+    };
+
+    debug!(sni = ?sni, "ClientHello inspected for passthrough routing");
+    Ok((sni, Rewind::new(buf, stream)))
+}
+
+/// Dispatch a (possibly decrypted) client connection to its protocol handler.
+///
+/// `sni` is the server name the client sent in its TLS handshake, if any.
+/// The connection counts as active for the pool it is routed to, or for the
+/// frontend's default backend if requests choose their pool.
+#[allow(clippy::too_many_arguments)]
+async fn serve_connection<S>(
+    stream: S,
+    client_addr: SocketAddr,
+    config: Arc<FrontendConfig>,
+    sni: Option<String>,
+    router: &Arc<BackendRouter>,
+    metrics: &MetricsCollector,
+    pool: ConnectionPool,
+    connections: &ConnectionTracker,
+    request_id: &RequestId,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    match config.protocol {
+        Protocol::Tcp => {
+            let unmatched_sni = config
+                .tcp
+                .as_ref()
+                .map(|tcp| tcp.unmatched_sni)
+                .unwrap_or_default();
+            let backend_name = match route_connection(&config, sni.as_deref()) {
+                Some(backend) => backend,
+                None if unmatched_sni == UnmatchedSniPolicy::Reject => {
+                    return Err(format!(
+                        "no route for SNI '{}', rejecting connection",
+                        sni.as_deref().unwrap_or("")
+                    )
+                    .into());
+                }
+                None => &config.backend,
+            };
+            let _active = metrics.track_connection(&config.name, backend_name);
+
+            handle_tcp_connection(
+                stream,
+                client_addr,
+                &config.name,
+                backend_name,
+                router,
+                config.tcp.clone(),
+                metrics,
+                request_id,
+            )
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+        }
+        Protocol::Http => {
+            let _active = metrics.track_connection(&config.name, &config.backend);
+            handle_http_connection(
+                stream,
+                client_addr,
+                config,
+                sni,
+                router,
+                metrics,
+                pool,
+                connections,
+                request_id,
+            )
+            .await
+        }
+    }
+}
+
 /// Handle a TCP connection.
+///
+/// No client data is forwarded before the backend connection is up, so with
+/// a connect retry policy a failed connection attempt is retried on another
+/// server after a backoff. Every attempt is reported to passive health
+/// tracking.
 #[allow(clippy::too_many_arguments)]
-async fn handle_tcp_connection(
-    client_stream: TcpStream,
+async fn handle_tcp_connection<S>(
+    client_stream: S,
     client_addr: SocketAddr,
     frontend_name: &str,
     backend_name: &str,
-    router: &BackendRouter,
+    router: &Arc<BackendRouter>,
     tcp_config: Option<TcpConfig>,
     metrics: &MetricsCollector,
     request_id: &RequestId,
-) -> Result<(), TcpProxyError> {
-    // Select a backend server
-    let backend_addr = router
-        .select(backend_name, Some(client_addr))
-        .ok_or_else(|| {
-            TcpProxyError::BackendConnectError(
-                "0.0.0.0:0".parse().unwrap(),
-                std::io::Error::new(std::io::ErrorKind::NotFound, "no backend servers available"),
-            )
-        })?;
+) -> Result<(), TcpProxyError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Get connect timeout and retry policy
+    let connect_timeout = tcp_config
+        .as_ref()
+        .map(|c| c.connect_timeout)
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+    let retry = tcp_config.and_then(|c| c.connect_retry);
+    let max_attempts = retry.as_ref().map_or(1, |r| r.max_attempts);
+    let mut backoff = retry.as_ref().map(|r| r.backoff).unwrap_or_default();
+
+    let tls = router.backend_tls(backend_name);
+    let passive_health =
+        PassiveHealthTracker::new(Arc::clone(router.health_state()), metrics.clone());
+    let passive_health_config = router
+        .backend_config(backend_name)
+        .map(|backend| backend.passive_health.clone())
+        .unwrap_or_default();
+    let mut tried = Vec::new();
+    let mut last_error = None;
+
+    // Select a backend server and connect, moving on to another server
+    // while attempts are left
+    let (backend_addr, backend_stream) = loop {
+        let Some(backend_addr) = router.select_excluding(backend_name, Some(client_addr), &tried)
+        else {
+            // Report why the last server failed if any was tried
+            return Err(last_error.unwrap_or(TcpProxyError::NoBackendAvailable));
+        };
+
+        match connect_backend(backend_addr, connect_timeout, tls.as_deref()).await {
+            Ok(stream) => {
+                passive_health.record_success(backend_addr);
+                break (backend_addr, stream);
+            }
+            Err(e) => {
+                let reason = match e {
+                    TcpProxyError::BackendTimeout(_) => FailureReason::Timeout,
+                    _ => FailureReason::ConnectFailure,
+                };
+                passive_health.record_failure(
+                    backend_name,
+                    backend_addr,
+                    &passive_health_config,
+                    reason,
+                );
+                tried.push(backend_addr);
+                if tried.len() as u32 >= max_attempts {
+                    return Err(e);
+                }
+
+                warn!(
+                    request_id = %request_id,
+                    client = %client_addr,
+                    backend = %backend_addr,
+                    error = %e,
+                    backoff_ms = backoff.as_millis(),
+                    "backend connection failed, retrying on another server"
+                );
+                metrics.record_retry(frontend_name, backend_name, backend_addr, reason);
+                last_error = Some(e);
+
+                tokio::time::sleep(backoff).await;
+                if let Some(ref retry) = retry {
+                    backoff = (backoff * 2).min(retry.max_backoff);
+                }
+            }
+        }
+    };
 
     info!(
         request_id = %request_id,
         client = %client_addr,
         backend = %backend_addr,
+        attempts = tried.len() + 1,
         "TCP proxy session starting"
     );
 
-    // Get connect timeout
-    let connect_timeout = tcp_config
-        .map(|c| c.connect_timeout)
-        .unwrap_or(DEFAULT_CONNECT_TIMEOUT);
-
-    // Notify router of connection start
-    router.on_connect(backend_name, backend_addr);
+    // Count the session as active until it ends
+    let guard = router.track(backend_name, backend_addr);
 
-    // Handle the proxy
+    // Handle the proxy, closing the session if the server finishes draining
     let start = Instant::now();
-    let result = handle_tcp_proxy(client_stream, client_addr, backend_addr, connect_timeout).await;
+    let proxy = handle_tcp_proxy(client_stream, client_addr, backend_addr, backend_stream);
+    let result = tokio::select! {
+        result = proxy => result,
+        _ = guard.drain_signal().expired() => Err(TcpProxyError::Drained(backend_addr)),
+    };
     let duration = start.elapsed();
 
     // Record metrics
@@ -228,82 +568,149 @@ async fn handle_tcp_connection(
         );
     }
 
-    // Notify router of connection end
-    router.on_disconnect(backend_name, backend_addr);
-
     result.map(|_| ())
 }
 
 /// Handle an HTTP connection.
+///
+/// Clients may speak HTTP/1.1 or HTTP/2: h2 negotiated by ALPN, or h2c with
+/// prior knowledge on plaintext connections. The protocol is detected from
+/// the connection preface.
+///
+/// Every request is routed to a backend pool using the frontend's routes, and
+/// a server is selected from that pool per request, so keep-alive clients are
+/// balanced across servers and stop using a server once it turns unhealthy.
+/// On HTTP/2 this happens per stream, so the calls of a single gRPC channel
+/// are spread across all servers. Upgrade requests to HTTP/1.1 backends are
+/// tunnelled once the backend switches protocols.
+///
+/// On shutdown the connection is closed gracefully: HTTP/1.1 clients get
+/// `Connection: close` on the responses to their current requests, HTTP/2
+/// clients a GOAWAY.
 #[allow(clippy::too_many_arguments)]
-async fn handle_http_connection(
-    client_stream: TcpStream,
+async fn handle_http_connection<S>(
+    client_stream: S,
     client_addr: SocketAddr,
-    frontend_name: &str,
-    backend_name: &str,
-    router: &BackendRouter,
-    http_config: Option<HttpConfig>,
+    frontend: Arc<FrontendConfig>,
+    sni: Option<String>,
+    router: &Arc<BackendRouter>,
     metrics: &MetricsCollector,
+    pool: ConnectionPool,
+    connections: &ConnectionTracker,
     request_id: &RequestId,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Select a backend server
-    let backend_addr = router
-        .select(backend_name, Some(client_addr))
-        .ok_or("no backend servers available")?;
-
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
     info!(
         request_id = %request_id,
         client = %client_addr,
-        backend = %backend_addr,
+        backend = %frontend.backend,
+        routes = frontend.routes.len(),
         "HTTP connection started"
     );
 
     // Build the proxy config from frontend HTTP config
+    let http_config = frontend.http.as_ref();
     let proxy_config = HttpProxyConfig {
         request_headers: http_config
-            .as_ref()
             .map(|c| c.request_headers.clone())
             .unwrap_or_default(),
         response_headers: http_config
-            .as_ref()
             .map(|c| c.response_headers.clone())
             .unwrap_or_default(),
         connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        upgrade_idle_timeout: http_config
+            .map(|c| c.upgrade_idle_timeout)
+            .unwrap_or_else(|| HttpConfig::default().upgrade_idle_timeout),
+        retry: http_config.and_then(|c| c.retry.clone()),
     };
 
-    // Create the proxy context with metrics
-    let ctx = ProxyContext {
+    // Template for per-request proxy contexts; the backend pool and server
+    // are filled in once the request has been routed.
+    let template = ProxyContext {
         client_addr,
-        backend_addr,
-        frontend_name: frontend_name.to_string(),
-        backend_name: backend_name.to_string(),
+        backend_addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+        frontend_name: frontend.name.clone(),
+        backend_name: frontend.backend.clone(),
         config: proxy_config,
         metrics: metrics.clone(),
         connection_request_id: request_id.as_str().to_string(),
+        pool,
+        pool_config: ConnectionPoolConfig::default(),
+        tls: None,
+        protocol: BackendProtocol::Http1,
+        grpc: http_config.is_some_and(|c| c.grpc),
+        passive_health: PassiveHealthTracker::new(
+            Arc::clone(router.health_state()),
+            metrics.clone(),
+        ),
+        passive_health_config: PassiveHealthConfig::default(),
+        connections: connections.clone(),
     };
-
-    // Notify router of connection start
-    router.on_connect(backend_name, backend_addr);
+    let router = Arc::clone(router);
 
     // Wrap the TCP stream for hyper
     let io = TokioIo::new(client_stream);
 
-    // Create the HTTP service that proxies requests
-    let service = service_fn(move |req| {
-        let ctx = ctx.clone();
-        async move { proxy_request(req, ctx).await }
-    });
+    // Create the HTTP service that routes, selects a server and proxies each request
+    let service = service_fn(move |req: Request<Incoming>| {
+        let mut ctx = template.clone();
+        ctx.backend_name = route_request(&frontend, sni.as_deref(), &req).to_string();
+        let router = Arc::clone(&router);
+        async move {
+            let Some(backend_addr) = router.select(&ctx.backend_name, Some(ctx.client_addr)) else {
+                warn!(
+                    request_id = %ctx.connection_request_id,
+                    client = %ctx.client_addr,
+                    backend = %ctx.backend_name,
+                    "no healthy backend servers, responding with 503"
+                );
+                let grpc_method = if ctx.grpc { grpc_method(&req) } else { None };
+                return Ok::<_, Infallible>(reject_request(
+                    &ctx,
+                    None,
+                    req.method().as_str(),
+                    grpc_method.as_deref(),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "No healthy backend servers available",
+                    Duration::ZERO,
+                ));
+            };
 
-    // Serve HTTP/1.1 with keep-alive support
-    let result = http1::Builder::new()
-        .keep_alive(true)
-        .serve_connection(io, service)
-        .await;
+            // Count the request as active until its response body is done
+            let guard = router.track(&ctx.backend_name, backend_addr);
+            ctx.backend_addr = backend_addr;
+            if let Some(backend) = router.backend_config(&ctx.backend_name) {
+                ctx.pool_config = backend.connection_pool.clone();
+                ctx.protocol = backend.protocol;
+                ctx.passive_health_config = backend.passive_health.clone();
+            }
+            ctx.tls = router.backend_tls(&ctx.backend_name);
+
+            // WebSockets and other upgrades hold the server until they close
+            if ctx.protocol == BackendProtocol::Http1 && is_upgrade_request(&req) {
+                return proxy_upgrade(req, ctx, guard).await;
+            }
 
-    // Notify router of connection end
-    router.on_disconnect(backend_name, backend_addr);
+            proxy_request(req, ctx, guard, &router).await
+        }
+    });
+
+    // Serve HTTP/1.1 with keep-alive and upgrade support, or HTTP/2
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder.http1().keep_alive(true);
+    let conn = builder.serve_connection_with_upgrades(io, service);
+    tokio::pin!(conn);
 
-    result.map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    // Stop keep-alive once shutdown starts, letting current requests finish
+    tokio::select! {
+        result = conn.as_mut() => return result,
+        _ = connections.draining() => {}
+    }
+    debug!(request_id = %request_id, "shutting down HTTP connection");
+    conn.as_mut().graceful_shutdown();
+    conn.await
 }
 
 #[cfg(test)]
@@ -318,9 +725,11 @@ mod tests {
             listen: "127.0.0.1:0".parse().unwrap(),
             protocol: Protocol::Tcp,
             backend: "test-backend".to_string(),
+            routes: Vec::new(),
             algorithm: Algorithm::RoundRobin,
             http: None,
             tcp: None,
+            tls: None,
         };
 
         let backends = vec![BackendConfig {
@@ -328,8 +737,9 @@ mod tests {
             servers: vec![ServerConfig {
                 address: "127.0.0.1:9000".parse().unwrap(),
                 weight: 1,
+                metadata: Default::default(),
             }],
-            health_check: None,
+            ..Default::default()
         }];
 
         let frontends = vec![config.clone()];
@@ -339,4 +749,303 @@ mod tests {
         let listener = FrontendListener::bind(config, router, metrics).await;
         assert!(listener.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_tcp_connect_failover() {
+        use crate::config::ConnectRetryConfig;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // One server is down, the other echoes
+        let dead_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = echo.accept().await.unwrap();
+            let (mut read, mut write) = stream.split();
+            let _ = tokio::io::copy(&mut read, &mut write).await;
+        });
+
+        let backends = vec![BackendConfig {
+            name: "test-backend".to_string(),
+            servers: [dead_addr, echo_addr]
+                .into_iter()
+                .map(|address| ServerConfig {
+                    address,
+                    weight: 1,
+                    metadata: Default::default(),
+                })
+                .collect(),
+            ..Default::default()
+        }];
+        let router = Arc::new(BackendRouter::new(&backends, &[]));
+        let metrics = MetricsCollector::new();
+        let tcp_config = TcpConfig {
+            connect_retry: Some(ConnectRetryConfig {
+                backoff: Duration::from_millis(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        // Round robin starts with the server that is down
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let session = {
+            let router = Arc::clone(&router);
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                handle_tcp_connection(
+                    client,
+                    "127.0.0.1:50000".parse().unwrap(),
+                    "test",
+                    "test-backend",
+                    &router,
+                    Some(tcp_config),
+                    &metrics,
+                    &RequestId::new(),
+                )
+                .await
+            })
+        };
+
+        client_peer.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        client_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        drop(client_peer);
+        assert!(session.await.unwrap().is_ok());
+
+        // The failed attempt counts against the server's passive health
+        assert_eq!(router.health_state().get_passive_failures(dead_addr), 1);
+        assert_eq!(router.health_state().get_failures(dead_addr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connect_reports_last_error() {
+        use crate::config::ConnectRetryConfig;
+
+        // Both servers are down, so the attempts run out of servers
+        let mut dead = Vec::new();
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            dead.push(listener.local_addr().unwrap());
+        }
+        let backends = vec![BackendConfig {
+            name: "test-backend".to_string(),
+            servers: dead
+                .iter()
+                .map(|&address| ServerConfig {
+                    address,
+                    weight: 1,
+                    metadata: Default::default(),
+                })
+                .collect(),
+            ..Default::default()
+        }];
+        let router = Arc::new(BackendRouter::new(&backends, &[]));
+        let metrics = MetricsCollector::new();
+        let tcp_config = TcpConfig {
+            connect_retry: Some(ConnectRetryConfig {
+                max_attempts: 3,
+                backoff: Duration::from_millis(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let connect = |backend_name: &'static str| {
+            let (client, _client_peer) = tokio::io::duplex(1024);
+            let router = Arc::clone(&router);
+            let metrics = metrics.clone();
+            let tcp_config = tcp_config.clone();
+            async move {
+                handle_tcp_connection(
+                    client,
+                    "127.0.0.1:50000".parse().unwrap(),
+                    "test",
+                    backend_name,
+                    &router,
+                    Some(tcp_config),
+                    &metrics,
+                    &RequestId::new(),
+                )
+                .await
+            }
+        };
+
+        // The error of the last server tried is reported
+        match connect("test-backend").await {
+            Err(TcpProxyError::BackendConnectError(addr, _)) => assert_eq!(addr, dead[1]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Without any server to try there is no connect error to report
+        assert!(matches!(
+            connect("other-backend").await,
+            Err(TcpProxyError::NoBackendAvailable)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // HTTP server that answers slowly, and an echo server
+        let http = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_addr = http.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = http.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            while stream.read(&mut buf).await.unwrap_or(0) > 0 {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .await;
+            }
+        });
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = echo.accept().await.unwrap();
+            let (mut read, mut write) = stream.split();
+            let _ = tokio::io::copy(&mut read, &mut write).await;
+        });
+
+        let backend = |name: &str, address| BackendConfig {
+            name: name.to_string(),
+            servers: vec![ServerConfig {
+                address,
+                weight: 1,
+                metadata: Default::default(),
+            }],
+            ..Default::default()
+        };
+        let frontend = |name: &str, protocol| FrontendConfig {
+            name: name.to_string(),
+            listen: "127.0.0.1:0".parse().unwrap(),
+            protocol,
+            backend: name.to_string(),
+            routes: Vec::new(),
+            algorithm: Algorithm::RoundRobin,
+            http: None,
+            tcp: None,
+            tls: None,
+        };
+        let backends = vec![backend("web", http_addr), backend("tcp", echo_addr)];
+        let router = Arc::new(BackendRouter::new(&backends, &[]));
+        let connections = ConnectionTracker::new();
+        let (stop, _) = broadcast::channel(1);
+
+        let mut addrs = Vec::new();
+        for config in [
+            frontend("web", Protocol::Http),
+            frontend("tcp", Protocol::Tcp),
+        ] {
+            let listener =
+                FrontendListener::bind(config, Arc::clone(&router), MetricsCollector::new())
+                    .await
+                    .unwrap()
+                    .with_connection_tracker(connections.clone());
+            addrs.push(listener.local_addr().unwrap());
+            tokio::spawn(listener.run(stop.subscribe()));
+        }
+
+        let mut tcp_client = TcpStream::connect(addrs[1]).await.unwrap();
+        tcp_client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        tcp_client.read_exact(&mut buf).await.unwrap();
+
+        let mut http_client = TcpStream::connect(addrs[0]).await.unwrap();
+        http_client
+            .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(connections.active(), 2);
+
+        // The request in flight completes, then its connection is closed
+        let drain = {
+            let connections = connections.clone();
+            tokio::spawn(async move { connections.drain(Duration::from_millis(500)).await })
+        };
+        let mut response = String::new();
+        http_client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.to_ascii_lowercase().contains("connection: close"));
+
+        // The TCP session lasts until the drain timeout
+        let report = drain.await.unwrap();
+        assert_eq!(report.drained, 1);
+        assert_eq!(report.closed, 1);
+        let n = tokio::time::timeout(Duration::from_secs(1), tcp_client.read(&mut buf))
+            .await
+            .expect("TCP session was not closed")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+    }
+
+    #[tokio::test]
+    async fn test_connection_metrics_use_routed_backend() {
+        use crate::config::RouteConfig;
+
+        let dead_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let backend = |name: &str| BackendConfig {
+            name: name.to_string(),
+            servers: vec![ServerConfig {
+                address: dead_addr,
+                weight: 1,
+                metadata: Default::default(),
+            }],
+            ..Default::default()
+        };
+        let config = FrontendConfig {
+            name: "tls".to_string(),
+            listen: "127.0.0.1:0".parse().unwrap(),
+            protocol: Protocol::Tcp,
+            backend: "default".to_string(),
+            routes: vec![RouteConfig {
+                sni: Some("api.example.com".to_string()),
+                backend: "api".to_string(),
+                ..Default::default()
+            }],
+            algorithm: Algorithm::RoundRobin,
+            http: None,
+            tcp: None,
+            tls: None,
+        };
+        let router = Arc::new(BackendRouter::new(
+            &[backend("default"), backend("api")],
+            &[],
+        ));
+        let metrics = MetricsCollector::new();
+
+        let (client, _client_peer) = tokio::io::duplex(1024);
+        let result = serve_connection(
+            client,
+            "127.0.0.1:50000".parse().unwrap(),
+            Arc::new(config),
+            Some("api.example.com".to_string()),
+            &router,
+            &metrics,
+            ConnectionPool::new(metrics.clone()),
+            &ConnectionTracker::new(),
+            &RequestId::new(),
+        )
+        .await;
+        assert!(result.is_err());
+
+        // The connection is charged to the pool the SNI route picked
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, metrics.registry()).unwrap();
+        assert!(buffer.contains(r#"rustlb_connections_total{frontend="tls",backend="api"} 1"#));
+        assert!(buffer.contains(r#"rustlb_active_connections{frontend="tls",backend="api"} 0"#));
+        assert!(!buffer.contains(r#"backend="default""#));
+    }
 }
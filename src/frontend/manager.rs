@@ -0,0 +1,482 @@
+//! Frontend lifecycle management for hot reload.
+//!
+//! Keeps track of running listeners and reconciles them against a new
+//! configuration: new frontends are bound, removed ones stop accepting and
+//! drain their connections, and existing ones have their configuration
+//! swapped in place. Frontends can also adopt sockets inherited from a
+//! previous process on binary upgrade or passed by systemd socket activation.
+
+use crate::backend::BackendRouter;
+use crate::config::FrontendConfig;
+use crate::frontend::FrontendListener;
+use crate::metrics::MetricsCollector;
+use crate::proxy::ConnectionPool;
+use crate::tls::ServerTls;
+use crate::util::ConnectionTracker;
+use arc_swap::{ArcSwap, ArcSwapOption};
+use socket2::SockRef;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// A listener task managed by [`FrontendManager`].
+struct RunningFrontend {
+    /// Address the frontend was configured to listen on.
+    listen: SocketAddr,
+    /// Handle used to swap the frontend configuration.
+    config: Arc<ArcSwap<FrontendConfig>>,
+    /// Handle used to swap the TLS settings.
+    tls: Arc<ArcSwapOption<ServerTls>>,
+    /// The listening socket.
+    socket: Arc<TcpListener>,
+    /// Connections accepted by this listener.
+    connections: ConnectionTracker,
+    /// Stops this listener only.
+    stop: broadcast::Sender<()>,
+    /// The listener task.
+    handle: JoinHandle<()>,
+}
+
+/// Owns the set of running frontend listeners.
+pub struct FrontendManager {
+    /// Backend router shared by all listeners.
+    router: Arc<BackendRouter>,
+    /// Metrics collector shared by all listeners.
+    metrics: MetricsCollector,
+    /// Backend connection pool shared by all listeners.
+    pool: ConnectionPool,
+    /// Client connections of all listeners, drained on shutdown.
+    connections: ConnectionTracker,
+    /// Running listeners keyed by frontend name.
+    running: HashMap<String, RunningFrontend>,
+    /// Tasks of listeners that were stopped, and of the drains of removed
+    /// listeners, that may still be finishing.
+    stopped: Vec<JoinHandle<()>>,
+    /// Sockets inherited from a previous process, by listen address.
+    inherited: HashMap<SocketAddr, std::net::TcpListener>,
+    /// Sockets passed by socket activation, by frontend name.
+    activated: HashMap<String, std::net::TcpListener>,
+}
+
+impl FrontendManager {
+    /// Create a manager with no running listeners.
+    pub fn new(
+        router: Arc<BackendRouter>,
+        metrics: MetricsCollector,
+        pool: ConnectionPool,
+        connections: ConnectionTracker,
+    ) -> Self {
+        Self {
+            router,
+            metrics,
+            pool,
+            connections,
+            running: HashMap::new(),
+            stopped: Vec::new(),
+            inherited: HashMap::new(),
+            activated: HashMap::new(),
+        }
+    }
+
+    /// Start frontends on the given sockets instead of binding new ones
+    /// where their listen address matches.
+    pub fn with_inherited_sockets(
+        mut self,
+        inherited: HashMap<SocketAddr, std::net::TcpListener>,
+    ) -> Self {
+        self.inherited = inherited;
+        self
+    }
+
+    /// Start frontends on the given sockets instead of binding new ones
+    /// where their name matches.
+    ///
+    /// Takes precedence over inherited sockets.
+    pub fn with_activated_sockets(
+        mut self,
+        activated: HashMap<String, std::net::TcpListener>,
+    ) -> Self {
+        self.activated = activated;
+        self
+    }
+
+    /// Bind and start a listener for a frontend.
+    ///
+    /// An activated socket named after the frontend, or else an inherited
+    /// socket for its listen address, is used instead of binding a new one.
+    pub async fn start(&mut self, config: FrontendConfig) -> std::io::Result<()> {
+        let name = config.name.clone();
+        let listen = config.listen;
+
+        let router = Arc::clone(&self.router);
+        let socket = self
+            .activated
+            .remove(&name)
+            .inspect(|socket| {
+                let address = socket.local_addr().ok();
+                if address != Some(listen) {
+                    warn!(
+                        name = %name,
+                        listen = %listen,
+                        address = ?address,
+                        "activated socket is not bound to the configured listen address"
+                    );
+                }
+            })
+            .or_else(|| self.inherited.remove(&listen));
+        let listener = match socket {
+            Some(socket) => {
+                FrontendListener::from_std(config, socket, router, self.metrics.clone())?
+            }
+            None => FrontendListener::bind(config, router, self.metrics.clone()).await?,
+        };
+        let connections = self.connections.child();
+        let listener = listener
+            .with_connection_pool(self.pool.clone())
+            .with_connection_tracker(connections.clone());
+        let config_handle = listener.config_handle();
+        let tls_handle = listener.tls_handle();
+        let socket = listener.socket_handle();
+        let (stop, stop_rx) = broadcast::channel(1);
+
+        let handle = tokio::spawn(async move {
+            listener.run(stop_rx).await;
+        });
+
+        self.running.insert(
+            name,
+            RunningFrontend {
+                listen,
+                config: config_handle,
+                tls: tls_handle,
+                socket,
+                connections,
+                stop,
+                handle,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Reconcile running listeners with a new set of frontends.
+    ///
+    /// Frontends whose listen address is unchanged keep their socket and only
+    /// have their configuration swapped; TLS certificates are re-read from
+    /// disk. Removed frontends stop accepting and drain their connections
+    /// like shutdown does, closing those still open after `drain_timeout`.
+    /// A new frontend on the listen address of a removed one, such as a
+    /// renamed frontend, takes over its socket instead of binding it again.
+    pub async fn apply(&mut self, frontends: &[FrontendConfig], drain_timeout: Duration) {
+        // Forget listeners and drains that finished since the last reload
+        self.stopped.retain(|handle| !handle.is_finished());
+
+        // Stop frontends that were removed or moved to a different address
+        let to_stop: Vec<String> = self
+            .running
+            .iter()
+            .filter(|(name, running)| {
+                !frontends
+                    .iter()
+                    .any(|f| &f.name == *name && f.listen == running.listen)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let mut released = Vec::new();
+        for name in to_stop {
+            released.extend(self.retire(&name, drain_timeout));
+        }
+
+        // The stopped listener may still hold its socket, so binding the
+        // address again could fail
+        let mut handed_over = Vec::new();
+        for (listen, socket) in released {
+            let wanted = frontends
+                .iter()
+                .any(|f| f.listen == listen && !self.running.contains_key(&f.name));
+            if !wanted {
+                continue;
+            }
+            match SockRef::from(socket.as_ref()).try_clone() {
+                Ok(socket) => {
+                    self.inherited.insert(listen, socket.into());
+                    handed_over.push(listen);
+                }
+                Err(e) => {
+                    warn!(
+                        listen = %listen,
+                        error = %e,
+                        "failed to reuse socket of removed frontend"
+                    );
+                }
+            }
+        }
+
+        for frontend in frontends {
+            match self.running.get(&frontend.name) {
+                Some(running) => {
+                    let tls = match frontend
+                        .tls
+                        .as_ref()
+                        .map(|tls| ServerTls::new(tls, &frontend.protocol))
+                        .transpose()
+                    {
+                        Ok(tls) => tls,
+                        Err(e) => {
+                            error!(
+                                name = %frontend.name,
+                                error = %e,
+                                "failed to load TLS settings, keeping previous frontend configuration"
+                            );
+                            continue;
+                        }
+                    };
+                    running.tls.store(tls.map(Arc::new));
+                    running.config.store(Arc::new(frontend.clone()));
+                    info!(name = %frontend.name, "frontend configuration updated");
+                }
+                None => {
+                    if let Err(e) = self.start(frontend.clone()).await {
+                        error!(
+                            name = %frontend.name,
+                            listen = %frontend.listen,
+                            error = %e,
+                            "failed to bind new frontend"
+                        );
+                    }
+                }
+            }
+        }
+
+        for listen in handed_over {
+            if self.inherited.remove(&listen).is_some() {
+                debug!(listen = %listen, "closing unused socket of removed frontend");
+            }
+        }
+    }
+
+    /// Stop a removed listener and drain its connections in the background.
+    ///
+    /// Returns the listen address and socket of the listener.
+    fn retire(
+        &mut self,
+        name: &str,
+        drain_timeout: Duration,
+    ) -> Option<(SocketAddr, Arc<TcpListener>)> {
+        let running = self.running.remove(name)?;
+        info!(
+            name = %name,
+            listen = %running.listen,
+            connections = running.connections.active(),
+            drain_timeout = ?drain_timeout,
+            "stopping frontend listener, draining connections"
+        );
+        let _ = running.stop.send(());
+
+        let released = (running.listen, Arc::clone(&running.socket));
+        let name = name.to_string();
+        self.stopped.push(tokio::spawn(async move {
+            let _ = running.handle.await;
+            let report = running.connections.drain(drain_timeout).await;
+            if report.closed > 0 {
+                warn!(
+                    name = %name,
+                    drained = report.drained,
+                    closed = report.closed,
+                    "drain timeout reached, closed remaining connections of removed frontend"
+                );
+            } else {
+                info!(name = %name, drained = report.drained, "removed frontend drained");
+            }
+        }));
+        Some(released)
+    }
+
+    /// Stop a single listener.
+    fn stop(&mut self, name: &str) {
+        if let Some(running) = self.running.remove(name) {
+            info!(name = %name, listen = %running.listen, "stopping frontend listener");
+            let _ = running.stop.send(());
+            self.stopped.push(running.handle);
+        }
+    }
+
+    /// Close the inherited and activated sockets that no frontend took over.
+    pub fn close_inherited(&mut self) {
+        for (listen, _) in self.inherited.drain() {
+            info!(listen = %listen, "closing inherited socket of a removed frontend");
+        }
+        for (name, _) in self.activated.drain() {
+            warn!(name = %name, "closing activated socket that matches no frontend");
+        }
+    }
+
+    /// Listening sockets of the running frontends, by listen address.
+    pub fn sockets(&self) -> Vec<(SocketAddr, Arc<TcpListener>)> {
+        self.running
+            .values()
+            .map(|running| (running.listen, Arc::clone(&running.socket)))
+            .collect()
+    }
+
+    /// Names of the frontends that are currently listening.
+    pub fn names(&self) -> Vec<String> {
+        self.running.keys().cloned().collect()
+    }
+
+    /// Stop every listener and return their task handles.
+    ///
+    /// Connections the listeners accepted are left open; drain them with
+    /// the connection tracker.
+    pub fn shutdown(mut self) -> Vec<JoinHandle<()>> {
+        let names: Vec<String> = self.running.keys().cloned().collect();
+        for name in names {
+            self.stop(&name);
+        }
+        self.stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Algorithm, Protocol};
+
+    fn frontend(name: &str) -> FrontendConfig {
+        FrontendConfig {
+            name: name.to_string(),
+            listen: "127.0.0.1:0".parse().unwrap(),
+            protocol: Protocol::Tcp,
+            backend: "test-backend".to_string(),
+            routes: Vec::new(),
+            algorithm: Algorithm::RoundRobin,
+            http: None,
+            tcp: None,
+            tls: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_adds_and_removes_frontends() {
+        let router = Arc::new(BackendRouter::new(&[], &[]));
+        let metrics = MetricsCollector::new();
+        let pool = ConnectionPool::new(metrics.clone());
+        let mut manager = FrontendManager::new(router, metrics, pool, ConnectionTracker::new());
+
+        manager.start(frontend("a")).await.unwrap();
+        manager
+            .apply(&[frontend("b")], Duration::from_secs(1))
+            .await;
+
+        assert_eq!(manager.names(), vec!["b".to_string()]);
+
+        for handle in manager.shutdown() {
+            handle.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_apply_drains_removed_frontends() {
+        use crate::config::{BackendConfig, ServerConfig};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = echo.accept().await.unwrap();
+            let (mut read, mut write) = stream.split();
+            let _ = tokio::io::copy(&mut read, &mut write).await;
+        });
+        let backends = vec![BackendConfig {
+            name: "test-backend".to_string(),
+            servers: vec![ServerConfig {
+                address: echo_addr,
+                weight: 1,
+                metadata: Default::default(),
+            }],
+            ..Default::default()
+        }];
+        let router = Arc::new(BackendRouter::new(&backends, &[]));
+        let metrics = MetricsCollector::new();
+        let pool = ConnectionPool::new(metrics.clone());
+        let connections = ConnectionTracker::new();
+        let mut manager = FrontendManager::new(router, metrics, pool, connections.clone());
+
+        manager.start(frontend("a")).await.unwrap();
+        let addr = manager.sockets()[0].1.local_addr().unwrap();
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(connections.active(), 1);
+
+        // The session of the removed frontend is closed at its drain timeout
+        manager.apply(&[], Duration::from_millis(100)).await;
+        let n = tokio::time::timeout(Duration::from_secs(2), client.read(&mut buf))
+            .await
+            .expect("TCP session was not closed")
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+        for handle in manager.shutdown() {
+            handle.await.unwrap();
+        }
+        assert_eq!(connections.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_renamed_frontend_keeps_socket() {
+        let router = Arc::new(BackendRouter::new(&[], &[]));
+        let metrics = MetricsCollector::new();
+        let pool = ConnectionPool::new(metrics.clone());
+        let mut manager = FrontendManager::new(router, metrics, pool, ConnectionTracker::new());
+
+        manager.start(frontend("a")).await.unwrap();
+        let addr = manager.sockets()[0].1.local_addr().unwrap();
+
+        // Binding port 0 again would pick another port
+        manager
+            .apply(&[frontend("renamed")], Duration::from_secs(1))
+            .await;
+        assert_eq!(manager.names(), vec!["renamed".to_string()]);
+        assert_eq!(manager.stopped.len(), 1);
+        assert_eq!(manager.sockets()[0].1.local_addr().unwrap(), addr);
+        assert!(manager.inherited.is_empty());
+
+        // Finished drains are pruned on the next reload
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while !manager.stopped[0].is_finished() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("drain did not finish");
+        manager
+            .apply(&[frontend("renamed")], Duration::from_secs(1))
+            .await;
+        assert!(manager.stopped.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_updates_config_in_place() {
+        let router = Arc::new(BackendRouter::new(&[], &[]));
+        let metrics = MetricsCollector::new();
+        let pool = ConnectionPool::new(metrics.clone());
+        let mut manager = FrontendManager::new(router, metrics, pool, ConnectionTracker::new());
+
+        manager.start(frontend("a")).await.unwrap();
+
+        let mut updated = frontend("a");
+        updated.backend = "other-backend".to_string();
+        manager.apply(&[updated], Duration::from_secs(1)).await;
+
+        let running = manager.running.get("a").unwrap();
+        assert_eq!(running.config.load().backend, "other-backend");
+    }
+}
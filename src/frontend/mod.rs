@@ -5,6 +5,10 @@
 
 mod http;
 mod listener;
+mod manager;
+mod routes;
 mod tcp;
 
 pub use listener::FrontendListener;
+pub use manager::FrontendManager;
+pub use routes::{route_connection, route_request};
@@ -0,0 +1,338 @@
+//! Request and connection routing.
+//!
+//! Picks the backend pool for an HTTP request, or for a TCP connection by its
+//! TLS server name, by evaluating the frontend's ordered route list; anything
+//! that matches no route goes to the frontend's default backend.
+
+use crate::config::{FrontendConfig, KeyValueMatch, PathMatch, RouteConfig};
+use hyper::Request;
+use hyper::header::HOST;
+
+/// Select the backend pool for a request.
+///
+/// `sni` is the TLS server name of the connection the request arrived on.
+pub fn route_request<'a, B>(
+    frontend: &'a FrontendConfig,
+    sni: Option<&str>,
+    req: &Request<B>,
+) -> &'a str {
+    frontend
+        .routes
+        .iter()
+        .find(|route| route_matches(route, sni, req))
+        .map(|route| route.backend.as_str())
+        .unwrap_or(&frontend.backend)
+}
+
+/// Select the backend pool for a TCP connection from its TLS server name.
+///
+/// Only `sni` conditions are considered; TCP frontends cannot have others.
+/// Returns `None` if no route matches, leaving the fallback to the caller.
+pub fn route_connection<'a>(frontend: &'a FrontendConfig, sni: Option<&str>) -> Option<&'a str> {
+    frontend
+        .routes
+        .iter()
+        .find(|route| sni_matches(route, sni))
+        .map(|route| route.backend.as_str())
+}
+
+/// Check whether every condition of a route matches the request.
+pub fn route_matches<B>(route: &RouteConfig, sni: Option<&str>, req: &Request<B>) -> bool {
+    if !sni_matches(route, sni) {
+        return false;
+    }
+
+    if let Some(ref pattern) = route.host {
+        match request_host(req) {
+            Some(host) if host_matches(pattern, host) => {}
+            _ => return false,
+        }
+    }
+
+    if let Some(ref path) = route.path
+        && !path_matches(path, req.uri().path())
+    {
+        return false;
+    }
+
+    if !route.methods.is_empty()
+        && !route
+            .methods
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(req.method().as_str()))
+    {
+        return false;
+    }
+
+    let headers_match = route.headers.iter().all(|condition| {
+        req.headers()
+            .get_all(condition.name.as_str())
+            .iter()
+            .any(|value| value_matches(condition, value.to_str().ok()))
+    });
+    if !headers_match {
+        return false;
+    }
+
+    route.query.iter().all(|condition| {
+        query_pairs(req.uri().query().unwrap_or(""))
+            .any(|(name, value)| name == condition.name && value_matches(condition, Some(value)))
+    })
+}
+
+/// Check a route's `sni` condition against a connection's server name.
+fn sni_matches(route: &RouteConfig, sni: Option<&str>) -> bool {
+    match (&route.sni, sni) {
+        (None, _) => true,
+        (Some(pattern), Some(sni)) => host_matches(pattern, sni),
+        (Some(_), None) => false,
+    }
+}
+
+/// Host the request is addressed to, without port.
+fn request_host<B>(req: &Request<B>) -> Option<&str> {
+    let host = match req.uri().host() {
+        Some(host) => host,
+        None => req.headers().get(HOST)?.to_str().ok()?,
+    };
+    Some(strip_port(host))
+}
+
+/// Remove a trailing `:port` from a host, leaving IPv6 literals intact.
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host
+            .split_once(']')
+            .map(|(h, _)| &host[..=h.len()])
+            .unwrap_or(host);
+    }
+    host.split_once(':').map(|(h, _)| h).unwrap_or(host)
+}
+
+/// Match a host against an exact or `*.`-wildcard pattern, ignoring case.
+///
+/// A wildcard covers a single label, as in the choice of TLS certificates:
+/// `*.example.com` matches `api.example.com` but neither `example.com` nor
+/// `a.b.example.com`.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host.split_once('.').is_some_and(|(label, parent)| {
+            !label.is_empty() && parent.eq_ignore_ascii_case(suffix)
+        }),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+/// Match a request path against a path condition.
+fn path_matches(condition: &PathMatch, path: &str) -> bool {
+    match condition {
+        PathMatch::Prefix(prefix) => path.starts_with(prefix.as_str()),
+        PathMatch::Exact(exact) => path == exact,
+        PathMatch::Regex(regex) => regex.is_match(path),
+    }
+}
+
+/// Match a header or query value against a condition.
+fn value_matches(condition: &KeyValueMatch, value: Option<&str>) -> bool {
+    match condition.value {
+        Some(ref expected) => value == Some(expected.as_str()),
+        None => true,
+    }
+}
+
+/// Split a query string into name/value pairs.
+fn query_pairs(query: &str) -> impl Iterator<Item = (&str, &str)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Algorithm, Protocol, RegexPattern};
+
+    fn frontend(routes: Vec<RouteConfig>) -> FrontendConfig {
+        FrontendConfig {
+            name: "web".to_string(),
+            listen: "127.0.0.1:0".parse().unwrap(),
+            protocol: Protocol::Http,
+            backend: "default".to_string(),
+            routes,
+            algorithm: Algorithm::RoundRobin,
+            http: None,
+            tcp: None,
+            tls: None,
+        }
+    }
+
+    fn route(backend: &str) -> RouteConfig {
+        RouteConfig {
+            backend: backend.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn request(method: &str, uri: &str, host: &str) -> Request<()> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(HOST, host)
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_backend() {
+        let config = frontend(vec![]);
+        let req = request("GET", "/", "example.com");
+        assert_eq!(route_request(&config, None, &req), "default");
+    }
+
+    #[test]
+    fn test_host_matching() {
+        assert!(host_matches("api.example.com", "API.example.com"));
+        assert!(!host_matches("api.example.com", "www.example.com"));
+        assert!(host_matches("*.example.com", "api.example.com"));
+        assert!(!host_matches("*.example.com", "a.b.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+
+        let config = frontend(vec![RouteConfig {
+            host: Some("*.example.com".to_string()),
+            ..route("wild")
+        }]);
+        let req = request("GET", "/", "shop.example.com:8080");
+        assert_eq!(route_request(&config, None, &req), "wild");
+    }
+
+    #[test]
+    fn test_path_matching() {
+        let config = frontend(vec![
+            RouteConfig {
+                path: Some(PathMatch::Exact("/health".to_string())),
+                ..route("exact")
+            },
+            RouteConfig {
+                path: Some(PathMatch::Prefix("/api/".to_string())),
+                ..route("prefix")
+            },
+            RouteConfig {
+                path: Some(PathMatch::Regex(
+                    RegexPattern::new(r"^/users/\d+$").unwrap(),
+                )),
+                ..route("regex")
+            },
+        ]);
+
+        let cases = [
+            ("/health", "exact"),
+            ("/health/deep", "default"),
+            ("/api/v1/items", "prefix"),
+            ("/users/42", "regex"),
+            ("/users/bob", "default"),
+        ];
+        for (path, expected) in cases {
+            let req = request("GET", path, "example.com");
+            assert_eq!(
+                route_request(&config, None, &req),
+                expected,
+                "path {}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_method_header_and_query_matching() {
+        let config = frontend(vec![
+            RouteConfig {
+                methods: vec!["POST".to_string(), "PUT".to_string()],
+                ..route("writes")
+            },
+            RouteConfig {
+                headers: vec![KeyValueMatch {
+                    name: "X-Canary".to_string(),
+                    value: Some("1".to_string()),
+                }],
+                ..route("canary")
+            },
+            RouteConfig {
+                query: vec![KeyValueMatch {
+                    name: "debug".to_string(),
+                    value: None,
+                }],
+                ..route("debug")
+            },
+        ]);
+
+        let req = request("post", "/", "example.com");
+        assert_eq!(route_request(&config, None, &req), "writes");
+
+        let mut req = request("GET", "/", "example.com");
+        req.headers_mut().insert("x-canary", "1".parse().unwrap());
+        assert_eq!(route_request(&config, None, &req), "canary");
+
+        let mut req = request("GET", "/", "example.com");
+        req.headers_mut().insert("x-canary", "0".parse().unwrap());
+        assert_eq!(route_request(&config, None, &req), "default");
+
+        let req = request("GET", "/?a=1&debug", "example.com");
+        assert_eq!(route_request(&config, None, &req), "debug");
+    }
+
+    #[test]
+    fn test_first_matching_route_wins() {
+        let config = frontend(vec![
+            RouteConfig {
+                host: Some("api.example.com".to_string()),
+                path: Some(PathMatch::Prefix("/v2".to_string())),
+                ..route("v2")
+            },
+            RouteConfig {
+                host: Some("api.example.com".to_string()),
+                ..route("api")
+            },
+        ]);
+
+        let req = request("GET", "/v2/items", "api.example.com");
+        assert_eq!(route_request(&config, None, &req), "v2");
+
+        let req = request("GET", "/v1/items", "api.example.com");
+        assert_eq!(route_request(&config, None, &req), "api");
+    }
+
+    #[test]
+    fn test_sni_matching() {
+        let config = frontend(vec![
+            RouteConfig {
+                sni: Some("api.example.com".to_string()),
+                ..route("api")
+            },
+            RouteConfig {
+                sni: Some("*.example.com".to_string()),
+                ..route("tenants")
+            },
+        ]);
+
+        assert_eq!(
+            route_connection(&config, Some("api.example.com")),
+            Some("api")
+        );
+        assert_eq!(
+            route_connection(&config, Some("acme.example.com")),
+            Some("tenants")
+        );
+        assert_eq!(route_connection(&config, Some("other.test")), None);
+        assert_eq!(route_connection(&config, None), None);
+
+        // The SNI name is matched separately from the Host header
+        let req = request("GET", "/", "api.example.com");
+        assert_eq!(
+            route_request(&config, Some("acme.example.com"), &req),
+            "tenants"
+        );
+        assert_eq!(route_request(&config, None, &req), "default");
+    }
+}
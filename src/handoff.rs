@@ -0,0 +1,290 @@
+//! Listening socket handoff for zero-downtime binary upgrades.
+//!
+//! On upgrade the running process starts its binary again with the same
+//! arguments, passing its listening sockets as inherited file descriptors
+//! listed in `RUSTLB_LISTEN_FDS`. The new process adopts the sockets whose
+//! address is still configured and reports readiness over the socket in
+//! `RUSTLB_READY_FD`. The old process then stops accepting, drains its
+//! connections and exits. Both processes accept on the same sockets in the
+//! meantime, so no connection is refused.
+//!
+//! Under systemd the old process sends `MAINPID=` with the new process's pid
+//! once it is ready. The new process sends `READY=1` and watchdog keepalives
+//! before that, as a process other than the main one, so the unit needs
+//! `NotifyAccess=all` for systemd to accept them.
+
+use socket2::SockRef;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, UnixStream};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Environment variable listing the inherited sockets as `address=fd` pairs.
+pub const LISTEN_FDS_ENV: &str = "RUSTLB_LISTEN_FDS";
+
+/// Environment variable holding the socket the new process reports
+/// readiness on.
+pub const READY_FD_ENV: &str = "RUSTLB_READY_FD";
+
+/// Binary upgrade error.
+#[derive(Debug, thiserror::Error)]
+pub enum HandoffError {
+    #[error("failed to pass listening socket {0}: {1}")]
+    Socket(SocketAddr, io::Error),
+
+    #[error("failed to start new process: {0}")]
+    Spawn(io::Error),
+
+    #[error("new process exited before it was ready")]
+    Exited,
+
+    #[error("new process was not ready within {0:?}")]
+    Timeout(Duration),
+
+    #[error("failed to wait for new process: {0}")]
+    Ready(io::Error),
+}
+
+/// Take the listening sockets inherited from the previous process, keyed by
+/// the address they are bound to.
+///
+/// Returns no sockets if the process was not started by an upgrade.
+pub fn inherited_sockets() -> HashMap<SocketAddr, StdTcpListener> {
+    match env::var(LISTEN_FDS_ENV) {
+        Ok(value) => parse_inherited(&value),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Adopt the sockets listed in a `RUSTLB_LISTEN_FDS` value.
+fn parse_inherited(value: &str) -> HashMap<SocketAddr, StdTcpListener> {
+    let mut sockets = HashMap::new();
+    for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+        let parsed = entry.rsplit_once('=').and_then(|(addr, fd)| {
+            Some((addr.parse::<SocketAddr>().ok()?, fd.parse::<RawFd>().ok()?))
+        });
+        let Some((addr, fd)) = parsed else {
+            warn!(entry = entry, "ignoring malformed inherited socket");
+            continue;
+        };
+
+        // SAFETY: the previous process passed this descriptor for our
+        // exclusive use, and nothing else in this process refers to it.
+        let listener = unsafe { StdTcpListener::from_raw_fd(fd) };
+        match listener.local_addr() {
+            Ok(local) if local == addr => {}
+            result => {
+                warn!(
+                    address = %addr,
+                    fd = fd,
+                    local = ?result.ok(),
+                    "ignoring inherited socket bound elsewhere"
+                );
+                continue;
+            }
+        }
+        // Do not leak the socket into processes started later on
+        if let Err(e) = SockRef::from(&listener).set_cloexec(true) {
+            warn!(
+                address = %addr,
+                error = %e,
+                "failed to set close-on-exec on inherited socket"
+            );
+        }
+        info!(address = %addr, fd = fd, "inherited listening socket");
+        sockets.insert(addr, listener);
+    }
+    sockets
+}
+
+/// Tell the previous process that this one accepts connections, so it can
+/// drain and exit.
+///
+/// Does nothing if the process was not started by an upgrade.
+pub fn notify_ready() -> io::Result<()> {
+    match env::var(READY_FD_ENV) {
+        Ok(value) => send_ready(&value),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Report readiness over the socket in a `RUSTLB_READY_FD` value.
+fn send_ready(value: &str) -> io::Result<()> {
+    let fd: RawFd = value
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid ready socket"))?;
+
+    // SAFETY: the previous process passed this descriptor for our exclusive
+    // use, and it is only taken here, once.
+    let mut ready = unsafe { StdUnixStream::from_raw_fd(fd) };
+    ready.write_all(b"1")
+}
+
+/// Start a new process of the current binary that takes over the given
+/// listening sockets, and wait until it is ready.
+///
+/// The new process gets the arguments this one was started with, and its
+/// binary is looked up again, so an upgraded binary at the same path is
+/// picked up. It is stopped if it does not become ready within
+/// `ready_timeout`. Returns its process ID.
+pub async fn spawn_successor(
+    sockets: &[(SocketAddr, Arc<TcpListener>)],
+    ready_timeout: Duration,
+) -> Result<u32, HandoffError> {
+    let mut args = env::args_os();
+    let program = args
+        .next()
+        .ok_or_else(|| HandoffError::Spawn(io::Error::other("unknown program path")))?;
+    let mut command = Command::new(program);
+    command.args(args);
+    spawn_ready(command, sockets, ready_timeout).await
+}
+
+/// Start `command` with the given listening sockets, and wait until it is
+/// ready.
+async fn spawn_ready(
+    mut command: Command,
+    sockets: &[(SocketAddr, Arc<TcpListener>)],
+    ready_timeout: Duration,
+) -> Result<u32, HandoffError> {
+    // Only duplicates without close-on-exec reach the new process
+    let mut inherited = Vec::with_capacity(sockets.len());
+    let mut fds = Vec::with_capacity(sockets.len());
+    for (addr, listener) in sockets {
+        let fd = listener
+            .as_fd()
+            .try_clone_to_owned()
+            .and_then(|fd| SockRef::from(&fd).set_cloexec(false).map(|_| fd))
+            .map_err(|e| HandoffError::Socket(*addr, e))?;
+        fds.push(format!("{}={}", addr, fd.as_raw_fd()));
+        inherited.push(fd);
+    }
+
+    let (ready, successor_ready) = StdUnixStream::pair().map_err(HandoffError::Spawn)?;
+    SockRef::from(&successor_ready)
+        .set_cloexec(false)
+        .map_err(HandoffError::Spawn)?;
+
+    let mut child = command
+        .env(LISTEN_FDS_ENV, fds.join(","))
+        .env(READY_FD_ENV, successor_ready.as_raw_fd().to_string())
+        // Sockets activated by systemd are passed above, and the watchdog
+        // is the new process's to keep once it becomes the main process
+        .env_remove("LISTEN_PID")
+        .env_remove("LISTEN_FDS")
+        .env_remove("LISTEN_FDNAMES")
+        .env_remove("WATCHDOG_PID")
+        .spawn()
+        .map_err(HandoffError::Spawn)?;
+    let pid = child.id().unwrap_or_default();
+    info!(pid = pid, sockets = sockets.len(), "started new process");
+
+    // Close our copies, so the ready socket reports the new process exiting
+    drop(inherited);
+    drop(successor_ready);
+
+    let result = async {
+        ready.set_nonblocking(true)?;
+        let mut ready = UnixStream::from_std(ready)?;
+        let mut buf = [0u8; 1];
+        ready.read(&mut buf).await
+    };
+    match tokio::time::timeout(ready_timeout, result).await {
+        Ok(Ok(1)) => Ok(pid),
+        Ok(result) => {
+            let _ = child.kill().await;
+            Err(match result {
+                Ok(_) => HandoffError::Exited,
+                Err(e) => HandoffError::Ready(e),
+            })
+        }
+        Err(_) => {
+            let _ = child.kill().await;
+            Err(HandoffError::Timeout(ready_timeout))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::fd::IntoRawFd;
+
+    #[test]
+    fn test_parse_inherited() {
+        let listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let fd = listener.into_raw_fd();
+
+        // Malformed entries are skipped
+        let sockets = parse_inherited(&format!("{}={},garbage", addr, fd));
+        assert_eq!(sockets.len(), 1);
+        assert_eq!(sockets[&addr].local_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn test_send_ready() {
+        let (ready, successor_ready) = StdUnixStream::pair().unwrap();
+        send_ready(&successor_ready.into_raw_fd().to_string()).unwrap();
+        let mut buf = [0u8; 1];
+        (&ready).read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"1");
+
+        assert!(send_ready("stdin").is_err());
+    }
+
+    /// Start a shell running `script` as the new process.
+    ///
+    /// bash rather than sh, since the descriptors passed may be above 9.
+    async fn spawn_shell(
+        script: &str,
+        sockets: &[(SocketAddr, Arc<TcpListener>)],
+        ready_timeout: Duration,
+    ) -> Result<u32, HandoffError> {
+        let mut command = Command::new("bash");
+        command.args(["-c", script]);
+        spawn_ready(command, sockets, ready_timeout).await
+    }
+
+    #[tokio::test]
+    async fn test_spawn_ready() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sockets = [(listener.local_addr().unwrap(), Arc::new(listener))];
+
+        // The listening socket is open in the new process, which reports
+        // readiness over the ready socket
+        let script = r#"
+            fd=${RUSTLB_LISTEN_FDS##*=}
+            ( : <&"$fd" ) 2>/dev/null || exit 1
+            printf 1 >&"$RUSTLB_READY_FD"
+        "#;
+        let pid = spawn_shell(script, &sockets, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(pid > 0);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_exits_before_ready() {
+        let result = spawn_shell("exit 0", &[], Duration::from_secs(5)).await;
+        assert!(matches!(result, Err(HandoffError::Exited)));
+    }
+
+    #[tokio::test]
+    async fn test_spawn_ready_timeout() {
+        let start = std::time::Instant::now();
+        let result = spawn_shell("sleep 10", &[], Duration::from_millis(200)).await;
+        assert!(matches!(result, Err(HandoffError::Timeout(_))));
+        // The new process is killed rather than waited for
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,104 +1,103 @@
 //! Active health checker.
 //!
-//! Periodically probes backend servers to verify they are healthy.
+//! Periodically probes backend servers to verify they are healthy. Probes
+//! use TLS for backends that have upstream TLS configured.
 
+use crate::backend::BackendRouter;
 use crate::config::{BackendConfig, HealthCheckConfig, HealthCheckType};
-use crate::health::HealthState;
+use crate::health::grpc::grpc_probe;
+use crate::health::{HealthState, Readiness};
+use crate::tls::ClientTls;
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::sync::broadcast;
-use tokio::time::{interval, timeout};
+use tokio::time::{interval, timeout, Interval};
 use tracing::{debug, info, warn};
 
+/// A single server probe derived from the backend configuration.
+struct Check {
+    /// Server to probe.
+    server: SocketAddr,
+    /// Probe settings of the server's backend.
+    config: HealthCheckConfig,
+    /// Time between probes.
+    interval: Duration,
+    /// TLS settings of the server's backend, if it speaks TLS.
+    tls: Option<Arc<ClientTls>>,
+}
+
 /// Active health checker that probes backend servers.
+///
+/// The set of probed servers follows the backend pools in the
+/// [`BackendRouter`], so servers added or removed by a hot reload are picked
+/// up without restarting the checker.
 pub struct HealthChecker {
     /// Health state to update.
     health_state: Arc<HealthState>,
-    /// Backend configurations.
-    backends: Vec<BackendConfig>,
+    /// Router that owns the current backend pools.
+    router: Arc<BackendRouter>,
     /// Default check interval.
     default_interval: Duration,
     /// Default check timeout.
     default_timeout: Duration,
+    /// Readiness to report the end of the first round of checks to.
+    readiness: Option<Arc<Readiness>>,
 }
 
 impl HealthChecker {
     /// Create a new health checker.
     pub fn new(
         health_state: Arc<HealthState>,
-        backends: Vec<BackendConfig>,
+        router: Arc<BackendRouter>,
         default_interval: Duration,
         default_timeout: Duration,
     ) -> Self {
         Self {
             health_state,
-            backends,
+            router,
             default_interval,
             default_timeout,
+            readiness: None,
         }
     }
 
+    /// Report to `readiness` once the first round of checks finished.
+    pub fn with_readiness(mut self, readiness: Arc<Readiness>) -> Self {
+        self.readiness = Some(readiness);
+        self
+    }
+
     /// Start the health checker background task.
     pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
         info!("health checker starting");
 
-        // Collect all servers that need checking
-        let checks: Vec<(SocketAddr, HealthCheckConfig, Duration)> = self
-            .backends
-            .iter()
-            .filter_map(|backend| {
-                backend.health_check.as_ref().map(|check| {
-                    backend
-                        .servers
-                        .iter()
-                        .map(|s| {
-                            let interval = check.interval.unwrap_or(self.default_interval);
-                            (s.address, check.clone(), interval)
-                        })
-                        .collect::<Vec<_>>()
-                })
-            })
-            .flatten()
-            .collect();
-
-        if checks.is_empty() {
-            info!("no health checks configured, health checker idle");
-            // Just wait for shutdown
-            let _ = shutdown.recv().await;
-            return;
-        }
-
-        // Register all servers
-        for (server, _, _) in &checks {
-            self.health_state.register_server(*server);
-        }
-
-        // Use the smallest interval as the tick rate
-        let min_interval = checks
-            .iter()
-            .map(|(_, _, i)| *i)
-            .min()
-            .unwrap_or(self.default_interval);
-
-        let mut check_interval = interval(min_interval);
-        check_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut changes = self.router.subscribe();
+        let mut registered = HashSet::new();
+        let mut checks = self.refresh_checks(&mut registered);
+        let mut check_interval = self.tick_interval(&checks);
+        let mut readiness = self.readiness.clone();
 
         loop {
             tokio::select! {
                 _ = check_interval.tick() => {
                     // Perform health checks
-                    for (server, config, _) in &checks {
-                        let server = *server;
-                        let config = config.clone();
+                    let mut round = Vec::with_capacity(checks.len());
+                    for check in &checks {
+                        let server = check.server;
+                        let config = check.config.clone();
+                        let tls = check.tls.clone();
                         let health_state = Arc::clone(&self.health_state);
                         let check_timeout = config.timeout.unwrap_or(self.default_timeout);
 
                         // Spawn check in background to not block other checks
-                        tokio::spawn(async move {
-                            let result = perform_health_check(server, &config, check_timeout).await;
+                        round.push(tokio::spawn(async move {
+                            let result =
+                                perform_health_check(server, &config, tls.as_deref(), check_timeout)
+                                    .await;
                             match result {
                                 Ok(()) => {
                                     debug!(server = %server, "health check passed");
@@ -109,10 +108,26 @@ impl HealthChecker {
                                     health_state.record_failure(server);
                                 }
                             }
+                        }));
+                    }
+
+                    // The first tick probes every server
+                    if let Some(readiness) = readiness.take() {
+                        tokio::spawn(async move {
+                            for check in round {
+                                let _ = check.await;
+                            }
+                            readiness.initial_checks_finished();
                         });
                     }
                 }
 
+                Ok(()) = changes.changed() => {
+                    info!("backend pools changed, refreshing health checks");
+                    checks = self.refresh_checks(&mut registered);
+                    check_interval = self.tick_interval(&checks);
+                }
+
                 _ = shutdown.recv() => {
                     info!("health checker shutting down");
                     break;
@@ -120,48 +135,220 @@ impl HealthChecker {
             }
         }
     }
+
+    /// Rebuild the list of checks from the router's current backend pools.
+    ///
+    /// Newly seen servers are registered with the health state and servers
+    /// that left every pool are forgotten.
+    fn refresh_checks(&self, registered: &mut HashSet<SocketAddr>) -> Vec<Check> {
+        let backends = self.router.backend_configs();
+        let checks = collect_checks(&backends, self.default_interval, |name| {
+            self.router.backend_tls(name)
+        });
+
+        let current: HashSet<SocketAddr> = checks.iter().map(|c| c.server).collect();
+        for server in registered.difference(&current) {
+            debug!(server = %server, "stopping health checks for server");
+            self.health_state.remove_server(*server);
+        }
+        for server in current.difference(registered) {
+            debug!(server = %server, "starting health checks for server");
+            self.health_state.register_server(*server);
+        }
+        *registered = current;
+
+        if checks.is_empty() {
+            info!("no health checks configured, health checker idle");
+        }
+
+        checks
+    }
+
+    /// Build the tick interval, using the smallest configured interval.
+    fn tick_interval(&self, checks: &[Check]) -> Interval {
+        let min_interval = checks
+            .iter()
+            .map(|c| c.interval)
+            .min()
+            .unwrap_or(self.default_interval);
+
+        let mut check_interval = interval(min_interval);
+        check_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        check_interval
+    }
+}
+
+/// Probe a server of a backend right away and record the result.
+///
+/// Used to force a check outside the regular interval. Returns `None` if the
+/// server is not in the backend or the backend has no active health check.
+pub async fn check_server(
+    router: &BackendRouter,
+    backend_name: &str,
+    server: SocketAddr,
+    default_timeout: Duration,
+) -> Option<Result<(), String>> {
+    let backend = router.backend_config(backend_name)?;
+    let config = backend.health_check.as_ref()?;
+    if !backend.servers.iter().any(|s| s.address == server) {
+        return None;
+    }
+
+    let tls = router.backend_tls(backend_name);
+    let check_timeout = config.timeout.unwrap_or(default_timeout);
+    let result = perform_health_check(server, config, tls.as_deref(), check_timeout).await;
+    let health_state = router.health_state();
+    match &result {
+        Ok(()) => {
+            info!(server = %server, "forced health check passed");
+            health_state.record_success(server);
+        }
+        Err(e) => {
+            warn!(server = %server, error = %e, "forced health check failed");
+            health_state.record_failure(server);
+        }
+    }
+    Some(result)
+}
+
+/// Collect all servers that need checking.
+///
+/// `backend_tls` looks up the loaded TLS settings of a backend by name.
+fn collect_checks(
+    backends: &[BackendConfig],
+    default_interval: Duration,
+    backend_tls: impl Fn(&str) -> Option<Arc<ClientTls>>,
+) -> Vec<Check> {
+    backends
+        .iter()
+        .filter_map(|backend| {
+            backend.health_check.as_ref().map(|check| {
+                let tls = backend_tls(&backend.name);
+                backend
+                    .servers
+                    .iter()
+                    .map(|s| Check {
+                        server: s.address,
+                        config: check.clone(),
+                        interval: check.interval.unwrap_or(default_interval),
+                        tls: tls.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .flatten()
+        .collect()
 }
 
 /// Perform a single health check on a server.
 async fn perform_health_check(
     server: SocketAddr,
     config: &HealthCheckConfig,
+    tls: Option<&ClientTls>,
     check_timeout: Duration,
 ) -> Result<(), String> {
     match config.check_type {
-        HealthCheckType::Tcp => tcp_health_check(server, check_timeout).await,
+        HealthCheckType::Tcp => tcp_health_check(server, tls, check_timeout).await,
         HealthCheckType::Http => {
             let path = config.path.as_deref().unwrap_or("/");
-            http_health_check(server, path, config.expected_status, check_timeout).await
+            http_health_check(server, path, config.expected_status, tls, check_timeout).await
+        }
+        HealthCheckType::Grpc => {
+            let service = config.service.as_deref().unwrap_or("");
+            grpc_health_check(server, service, tls, check_timeout).await
         }
     }
 }
 
-/// Perform a TCP health check (just connect).
-async fn tcp_health_check(server: SocketAddr, check_timeout: Duration) -> Result<(), String> {
+/// Open a probe connection to a server.
+async fn connect(server: SocketAddr, check_timeout: Duration) -> Result<TcpStream, String> {
     match timeout(check_timeout, TcpStream::connect(server)).await {
-        Ok(Ok(_stream)) => Ok(()),
+        Ok(Ok(stream)) => Ok(stream),
         Ok(Err(e)) => Err(format!("connection failed: {}", e)),
         Err(_) => Err("connection timeout".to_string()),
     }
 }
 
-/// Perform an HTTP health check.
+/// Perform a TLS handshake on a probe connection.
+async fn tls_handshake(
+    tls: &ClientTls,
+    server: SocketAddr,
+    stream: TcpStream,
+    check_timeout: Duration,
+) -> Result<tokio_rustls::client::TlsStream<TcpStream>, String> {
+    match timeout(check_timeout, tls.connect(server, stream)).await {
+        Ok(Ok(stream)) => Ok(stream),
+        Ok(Err(e)) => Err(format!("TLS handshake failed: {}", e)),
+        Err(_) => Err("TLS handshake timeout".to_string()),
+    }
+}
+
+/// Perform a TCP health check (just connect, plus the TLS handshake for TLS
+/// backends).
+async fn tcp_health_check(
+    server: SocketAddr,
+    tls: Option<&ClientTls>,
+    check_timeout: Duration,
+) -> Result<(), String> {
+    let stream = connect(server, check_timeout).await?;
+    if let Some(tls) = tls {
+        tls_handshake(tls, server, stream, check_timeout).await?;
+    }
+    Ok(())
+}
+
+/// Perform an HTTP health check, over HTTPS for TLS backends.
 async fn http_health_check(
     server: SocketAddr,
     path: &str,
     expected_status: u16,
+    tls: Option<&ClientTls>,
     check_timeout: Duration,
 ) -> Result<(), String> {
-    // Connect
-    let stream = match timeout(check_timeout, TcpStream::connect(server)).await {
-        Ok(Ok(s)) => s,
-        Ok(Err(e)) => return Err(format!("connection failed: {}", e)),
-        Err(_) => return Err("connection timeout".to_string()),
-    };
+    let stream = connect(server, check_timeout).await?;
+    match tls {
+        Some(tls) => {
+            let stream = tls_handshake(tls, server, stream, check_timeout).await?;
+            http_probe(stream, server, path, expected_status, check_timeout).await
+        }
+        None => http_probe(stream, server, path, expected_status, check_timeout).await,
+    }
+}
 
-    let mut stream = stream;
+/// Perform a gRPC health check, over TLS for TLS backends.
+async fn grpc_health_check(
+    server: SocketAddr,
+    service: &str,
+    tls: Option<&ClientTls>,
+    check_timeout: Duration,
+) -> Result<(), String> {
+    let stream = connect(server, check_timeout).await?;
+    let probe = async {
+        match tls {
+            Some(tls) => {
+                let stream = tls_handshake(tls, server, stream, check_timeout).await?;
+                grpc_probe(stream, server, service, true).await
+            }
+            None => grpc_probe(stream, server, service, false).await,
+        }
+    };
+    match timeout(check_timeout, probe).await {
+        Ok(result) => result,
+        Err(_) => Err("health check timeout".to_string()),
+    }
+}
 
+/// Send an HTTP health check request on a connected stream.
+async fn http_probe<S>(
+    mut stream: S,
+    server: SocketAddr,
+    path: &str,
+    expected_status: u16,
+    check_timeout: Duration,
+) -> Result<(), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     // Build simple HTTP request
     let request = format!(
         "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
@@ -226,6 +413,37 @@ mod tests {
         assert!(parse_http_status("").is_err());
     }
 
+    #[test]
+    fn test_collect_checks_skips_unchecked_backends() {
+        use crate::config::ServerConfig;
+
+        let server = |addr: &str| ServerConfig {
+            address: addr.parse().unwrap(),
+            weight: 1,
+            metadata: Default::default(),
+        };
+        let backends = vec![
+            BackendConfig {
+                name: "checked".to_string(),
+                servers: vec![server("127.0.0.1:9001"), server("127.0.0.1:9002")],
+                health_check: Some(HealthCheckConfig {
+                    interval: Some(Duration::from_secs(2)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            BackendConfig {
+                name: "unchecked".to_string(),
+                servers: vec![server("127.0.0.1:9003")],
+                ..Default::default()
+            },
+        ];
+
+        let checks = collect_checks(&backends, Duration::from_secs(10), |_| None);
+        assert_eq!(checks.len(), 2);
+        assert!(checks.iter().all(|c| c.interval == Duration::from_secs(2)));
+    }
+
     #[tokio::test]
     async fn test_tcp_health_check_success() {
         // Start a test server
@@ -238,7 +456,7 @@ mod tests {
         });
 
         // Health check should pass
-        let result = tcp_health_check(addr, Duration::from_secs(5)).await;
+        let result = tcp_health_check(addr, None, Duration::from_secs(5)).await;
         assert!(result.is_ok());
     }
 
@@ -247,7 +465,7 @@ mod tests {
         // Use a port that's not listening
         let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
 
-        let result = tcp_health_check(addr, Duration::from_secs(1)).await;
+        let result = tcp_health_check(addr, None, Duration::from_secs(1)).await;
         assert!(result.is_err());
     }
 
@@ -256,8 +474,107 @@ mod tests {
         // Use a non-routable address to trigger timeout
         let addr: SocketAddr = "10.255.255.1:12345".parse().unwrap();
 
-        let result = tcp_health_check(addr, Duration::from_millis(100)).await;
+        let result = tcp_health_check(addr, None, Duration::from_millis(100)).await;
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("timeout"));
     }
+
+    #[tokio::test]
+    async fn test_https_health_check() {
+        use crate::config::{BackendProtocol, BackendTlsConfig, Protocol, TlsConfig, TlsVersion};
+        use crate::tls::ServerTls;
+        use std::io::Write;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["api.internal".to_string()]).unwrap();
+        let mut cert_file = tempfile::NamedTempFile::new().unwrap();
+        cert_file.write_all(cert.cert.pem().as_bytes()).unwrap();
+        let mut key_file = tempfile::NamedTempFile::new().unwrap();
+        key_file
+            .write_all(cert.key_pair.serialize_pem().as_bytes())
+            .unwrap();
+
+        let server_tls = ServerTls::new(
+            &TlsConfig {
+                cert: cert_file.path().to_path_buf(),
+                key: key_file.path().to_path_buf(),
+                certificates: Vec::new(),
+                min_version: TlsVersion::Tls12,
+                cipher_suites: Vec::new(),
+                alpn: Vec::new(),
+            },
+            &Protocol::Http,
+        )
+        .unwrap();
+
+        // Serve a single HTTPS response
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = server_tls.accept(stream).await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await;
+            let _ = stream.shutdown().await;
+        });
+
+        let tls = ClientTls::new(
+            &BackendTlsConfig {
+                ca: Some(cert_file.path().to_path_buf()),
+                cert: None,
+                key: None,
+                server_name: Some("api.internal".to_string()),
+                verify: true,
+            },
+            BackendProtocol::Http1,
+        )
+        .unwrap();
+        let result =
+            http_health_check(addr, "/health", 200, Some(&tls), Duration::from_secs(5)).await;
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    /// Serve gRPC health checks answering with `serving_status`.
+    async fn grpc_health_server(serving_status: u8) -> SocketAddr {
+        use bytes::Bytes;
+        use http_body_util::StreamBody;
+        use hyper::body::Frame;
+        use hyper::service::service_fn;
+        use hyper::{HeaderMap, Response};
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                assert_eq!(req.uri().path(), "/grpc.health.v1.Health/Check");
+                let mut trailers = HeaderMap::new();
+                trailers.insert("grpc-status", "0".parse().unwrap());
+                let message = vec![0, 0, 0, 0, 2, 0x08, serving_status];
+                let frames: Vec<Result<_, std::convert::Infallible>> = vec![
+                    Ok(Frame::data(Bytes::from(message))),
+                    Ok(Frame::trailers(trailers)),
+                ];
+                let body = StreamBody::new(futures::stream::iter(frames));
+                async move { Ok::<_, std::convert::Infallible>(Response::new(body)) }
+            });
+            let _ = hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_grpc_health_check() {
+        let addr = grpc_health_server(1).await;
+        let result = grpc_health_check(addr, "", None, Duration::from_secs(5)).await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        // NOT_SERVING
+        let addr = grpc_health_server(2).await;
+        let result = grpc_health_check(addr, "users.Users", None, Duration::from_secs(5)).await;
+        assert!(result.unwrap_err().contains("not serving"));
+    }
 }
@@ -0,0 +1,176 @@
+//! gRPC health check protocol.
+//!
+//! Implements the client side of the standard `grpc.health.v1.Health/Check`
+//! call, encoding the small protobuf messages it uses by hand.
+
+use crate::proxy::GrpcCode;
+use bytes::{BufMut, Bytes, BytesMut};
+use http_body_util::{BodyExt, Full};
+use hyper::header::{CONTENT_TYPE, TE};
+use hyper::{Request, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Path of the health check method.
+const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// `HealthCheckResponse.ServingStatus` value of a healthy service.
+const SERVING: u64 = 1;
+
+/// Send a gRPC health check for `service` on a connected stream.
+///
+/// Succeeds if the call returns `OK` with the `SERVING` status. `tls` only
+/// selects the scheme sent in the request.
+pub(super) async fn grpc_probe<S>(
+    stream: S,
+    server: SocketAddr,
+    service: &str,
+    tls: bool,
+) -> Result<(), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .map_err(|e| format!("HTTP/2 handshake failed: {}", e))?;
+    tokio::spawn(conn);
+
+    let scheme = if tls { "https" } else { "http" };
+    let request = Request::post(format!("{}://{}{}", scheme, server, HEALTH_CHECK_PATH))
+        .header(CONTENT_TYPE, "application/grpc")
+        .header(TE, "trailers")
+        .body(Full::new(encode_request(service)))
+        .map_err(|e| format!("invalid request: {}", e))?;
+
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| format!("request failed: {}", e))?;
+    if response.status() != StatusCode::OK {
+        return Err(format!("unexpected status: {}", response.status()));
+    }
+
+    // The status is in the headers of a Trailers-Only response
+    let (parts, body) = response.into_parts();
+    let body = body
+        .collect()
+        .await
+        .map_err(|e| format!("read failed: {}", e))?;
+    let code = body
+        .trailers()
+        .and_then(GrpcCode::from_headers)
+        .or_else(|| GrpcCode::from_headers(&parts.headers))
+        .unwrap_or(GrpcCode::Unknown);
+    if code != GrpcCode::Ok {
+        return Err(format!("gRPC status {}", code));
+    }
+
+    match parse_serving_status(&body.to_bytes()) {
+        Some(SERVING) => Ok(()),
+        Some(status) => Err(format!("service not serving (status {})", status)),
+        None => Err("invalid health check response".to_string()),
+    }
+}
+
+/// Encode a length-prefixed `HealthCheckRequest { service }` message.
+fn encode_request(service: &str) -> Bytes {
+    let mut message = BytesMut::new();
+    if !service.is_empty() {
+        // Field 1, length-delimited
+        message.put_u8(0x0a);
+        put_varint(&mut message, service.len() as u64);
+        message.put_slice(service.as_bytes());
+    }
+
+    let mut frame = BytesMut::with_capacity(5 + message.len());
+    frame.put_u8(0); // not compressed
+    frame.put_u32(message.len() as u32);
+    frame.put_slice(&message);
+    frame.freeze()
+}
+
+/// Decode the `status` field of a length-prefixed `HealthCheckResponse`.
+///
+/// A message without the field has the default status, 0 (`UNKNOWN`).
+fn parse_serving_status(frame: &[u8]) -> Option<u64> {
+    if frame.len() < 5 || frame[0] != 0 {
+        return None;
+    }
+    let len = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    let mut message = frame.get(5..5 + len)?;
+
+    let mut status = 0;
+    while !message.is_empty() {
+        let key = read_varint(&mut message)?;
+        match key & 0x7 {
+            0 => {
+                let value = read_varint(&mut message)?;
+                if key >> 3 == 1 {
+                    status = value;
+                }
+            }
+            1 => message = message.get(8..)?,
+            2 => {
+                let len = read_varint(&mut message)? as usize;
+                message = message.get(len..)?;
+            }
+            5 => message = message.get(4..)?,
+            _ => return None,
+        }
+    }
+    Some(status)
+}
+
+/// Append a protobuf varint.
+fn put_varint(buf: &mut BytesMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+/// Read a protobuf varint, advancing `buf` past it.
+fn read_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_request() {
+        assert_eq!(&encode_request("")[..], b"\0\0\0\0\0");
+        assert_eq!(
+            &encode_request("users.Users")[..],
+            b"\0\0\0\0\x0d\x0a\x0busers.Users"
+        );
+    }
+
+    #[test]
+    fn test_parse_serving_status() {
+        assert_eq!(parse_serving_status(b"\0\0\0\0\x02\x08\x01"), Some(SERVING));
+        assert_eq!(parse_serving_status(b"\0\0\0\0\x02\x08\x02"), Some(2));
+        // Default status, and unknown fields skipped
+        assert_eq!(parse_serving_status(b"\0\0\0\0\0"), Some(0));
+        assert_eq!(
+            parse_serving_status(b"\0\0\0\0\x05\x12\x01x\x08\x01"),
+            Some(SERVING)
+        );
+
+        assert_eq!(parse_serving_status(b"\0\0\0"), None);
+        assert_eq!(parse_serving_status(b"\0\0\0\0\x02\x08"), None);
+    }
+}
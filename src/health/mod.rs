@@ -1,9 +1,14 @@
 //! Health checking for backend servers.
 
 mod checker;
+mod grpc;
+mod outlier;
 mod passive;
+mod readiness;
 pub mod state;
 
-pub use checker::HealthChecker;
+pub use checker::{check_server, HealthChecker};
+pub use outlier::OutlierDetector;
 pub use passive::PassiveHealthTracker;
-pub use state::{HealthConfig, HealthState};
+pub use readiness::{PoolHealth, Readiness, ReadinessReport};
+pub use state::{HealthConfig, HealthState, ServerStatus};
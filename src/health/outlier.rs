@@ -0,0 +1,476 @@
+//! Outlier detection.
+//!
+//! Periodically compares the request outcomes of every server with the rest
+//! of its pool, and ejects servers whose error rate or p99 latency stands
+//! out. Outcomes come from the request metrics, see
+//! [`MetricsCollector::take_outcomes`].
+
+use crate::backend::BackendRouter;
+use crate::config::OutlierDetectionConfig;
+use crate::health::HealthState;
+use crate::metrics::{MetricsCollector, OutlierReason, ServerOutcomes};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::time::{interval, Interval};
+use tracing::{debug, info, warn};
+
+/// Error rates less than this above the rest of the pool are never outliers.
+const MIN_ERROR_RATE_GAP: f64 = 0.1;
+
+/// Latencies below this multiple of the rest of the pool are never outliers.
+const MIN_LATENCY_RATIO: f64 = 1.5;
+
+/// Tick interval while no backend has outlier detection configured.
+const IDLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A backend pool under outlier detection.
+struct Pool {
+    /// Backend name.
+    name: String,
+    /// Detection settings of the backend.
+    config: OutlierDetectionConfig,
+    /// When the next analysis is due.
+    next_run: Instant,
+}
+
+/// Outlier detector that ejects servers standing out from their pool.
+///
+/// Like the [`HealthChecker`](crate::health::HealthChecker), it follows the
+/// backend pools in the [`BackendRouter`] across hot reloads.
+pub struct OutlierDetector {
+    /// Health state to eject servers in.
+    health_state: Arc<HealthState>,
+    /// Router that owns the current backend pools.
+    router: Arc<BackendRouter>,
+    /// Metrics collector providing the request outcomes.
+    metrics: MetricsCollector,
+    /// Recent ejections per server, worked off one per analysis in which the
+    /// server is neither ejected nor an outlier.
+    ejections: HashMap<SocketAddr, u32>,
+}
+
+impl OutlierDetector {
+    /// Create a new outlier detector.
+    pub fn new(
+        health_state: Arc<HealthState>,
+        router: Arc<BackendRouter>,
+        metrics: MetricsCollector,
+    ) -> Self {
+        Self {
+            health_state,
+            router,
+            metrics,
+            ejections: HashMap::new(),
+        }
+    }
+
+    /// Start the outlier detector background task.
+    pub async fn run(mut self, mut shutdown: broadcast::Receiver<()>) {
+        info!("outlier detector starting");
+
+        let mut changes = self.router.subscribe();
+        let mut pools = self.refresh_pools(Vec::new());
+        let mut tick = tick_interval(&pools);
+
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {
+                    let now = Instant::now();
+                    for pool in &mut pools {
+                        if pool.next_run <= now {
+                            pool.next_run = now + pool.config.interval;
+                            self.analyze(&pool.name, &pool.config);
+                        }
+                    }
+                }
+
+                Ok(()) = changes.changed() => {
+                    info!("backend pools changed, refreshing outlier detection");
+                    pools = self.refresh_pools(pools);
+                    tick = tick_interval(&pools);
+                }
+
+                _ = shutdown.recv() => {
+                    info!("outlier detector shutting down");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Rebuild the list of pools from the router's current backend pools.
+    ///
+    /// Outcomes are tracked for newly configured pools and dropped for pools
+    /// that no longer have outlier detection.
+    fn refresh_pools(&self, previous: Vec<Pool>) -> Vec<Pool> {
+        let mut next_runs: HashMap<String, Instant> = previous
+            .into_iter()
+            .map(|pool| (pool.name, pool.next_run))
+            .collect();
+
+        let pools: Vec<Pool> = self
+            .router
+            .backend_configs()
+            .into_iter()
+            .filter_map(|backend| {
+                let config = backend.outlier_detection?;
+                let next_run = next_runs.remove(&backend.name).unwrap_or_else(|| {
+                    debug!(backend = %backend.name, "starting outlier detection");
+                    self.metrics.track_outcomes(&backend.name);
+                    Instant::now() + config.interval
+                });
+                Some(Pool {
+                    name: backend.name,
+                    config,
+                    next_run,
+                })
+            })
+            .collect();
+
+        for name in next_runs.keys() {
+            debug!(backend = %name, "stopping outlier detection");
+            self.metrics.untrack_outcomes(name);
+        }
+
+        if pools.is_empty() {
+            info!("no outlier detection configured, outlier detector idle");
+        }
+
+        pools
+    }
+
+    /// Analyse the outcomes of a pool since the last analysis and eject its
+    /// outliers.
+    fn analyze(&mut self, backend: &str, config: &OutlierDetectionConfig) {
+        let mut outcomes = self.metrics.take_outcomes(backend);
+        let Some(servers) = self.router.get_servers(backend) else {
+            return;
+        };
+        outcomes.retain(|server, _| servers.contains(server));
+        let outliers = find_outliers(&outcomes, config);
+
+        for server in &servers {
+            if !self.health_state.is_ejected(*server)
+                && !outliers.iter().any(|(outlier, _)| outlier == server)
+                && let Some(count) = self.ejections.get_mut(server)
+            {
+                *count = count.saturating_sub(1);
+            }
+        }
+        self.ejections.retain(|_, count| *count > 0);
+
+        let max_ejected = max_ejected(servers.len(), config.max_ejection_percent);
+        let mut ejected = servers
+            .iter()
+            .filter(|server| self.health_state.is_ejected(**server))
+            .count();
+
+        for (server, reason) in outliers {
+            if self.health_state.is_ejected(server) {
+                continue;
+            }
+            if ejected >= max_ejected {
+                warn!(
+                    backend = backend,
+                    server = %server,
+                    reason = ?reason,
+                    max_ejection_percent = config.max_ejection_percent,
+                    "outlier not ejected, too many servers of the pool are ejected"
+                );
+                continue;
+            }
+
+            let count = self.ejections.entry(server).or_default();
+            *count += 1;
+            let ejection_time = config
+                .base_ejection_time
+                .saturating_mul(*count)
+                .min(config.max_ejection_time);
+            self.health_state.eject(server, ejection_time);
+            self.metrics
+                .record_outlier_ejection(backend, server, reason);
+            ejected += 1;
+
+            warn!(
+                backend = backend,
+                server = %server,
+                reason = ?reason,
+                ejections = *count,
+                ejection_time = ?ejection_time,
+                "server ejected as an outlier"
+            );
+        }
+    }
+}
+
+/// Build the tick interval, using the smallest configured interval.
+fn tick_interval(pools: &[Pool]) -> Interval {
+    let min_interval = pools
+        .iter()
+        .map(|pool| pool.config.interval)
+        .min()
+        .unwrap_or(IDLE_INTERVAL);
+
+    let mut tick = interval(min_interval);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    tick
+}
+
+/// Number of servers of a pool that may be ejected at once.
+///
+/// Any non-zero percentage allows ejecting at least one server, so that small
+/// pools are not exempt from outlier detection.
+fn max_ejected(servers: usize, max_ejection_percent: u32) -> usize {
+    if max_ejection_percent == 0 {
+        return 0;
+    }
+    (servers * max_ejection_percent as usize / 100).max(1)
+}
+
+/// Find the servers whose error rate or p99 latency stands out from the rest
+/// of the pool.
+///
+/// Only servers with `min_requests` outcomes are compared, and only if there
+/// are `min_servers` of them. Error rate outliers come first, then latency
+/// outliers, each worst first.
+fn find_outliers(
+    outcomes: &HashMap<SocketAddr, ServerOutcomes>,
+    config: &OutlierDetectionConfig,
+) -> Vec<(SocketAddr, OutlierReason)> {
+    let mut servers: Vec<(SocketAddr, &ServerOutcomes)> = outcomes
+        .iter()
+        .filter(|(_, outcomes)| outcomes.requests() >= config.min_requests)
+        .map(|(server, outcomes)| (*server, outcomes))
+        .collect();
+    if servers.len() < config.min_servers.max(2) {
+        return Vec::new();
+    }
+    servers.sort_by_key(|(server, _)| *server);
+
+    let error_rates: Vec<f64> = servers.iter().map(|(_, o)| o.error_rate()).collect();
+    let latencies: Vec<f64> = servers
+        .iter()
+        .map(|(_, o)| o.p99_latency().as_secs_f64())
+        .collect();
+
+    let mut error_outliers = Vec::new();
+    let mut latency_outliers = Vec::new();
+    for (index, (server, _)) in servers.iter().enumerate() {
+        if is_outlier(&error_rates, index, config.stdev_factor, |mean| {
+            mean + MIN_ERROR_RATE_GAP
+        }) {
+            error_outliers.push((*server, error_rates[index]));
+        } else if is_outlier(&latencies, index, config.stdev_factor, |mean| {
+            mean * MIN_LATENCY_RATIO
+        }) {
+            latency_outliers.push((*server, latencies[index]));
+        }
+    }
+    error_outliers.sort_by(|a, b| b.1.total_cmp(&a.1));
+    latency_outliers.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    error_outliers
+        .into_iter()
+        .map(|(server, _)| (server, OutlierReason::ErrorRate))
+        .chain(
+            latency_outliers
+                .into_iter()
+                .map(|(server, _)| (server, OutlierReason::Latency)),
+        )
+        .collect()
+}
+
+/// Check whether `values[index]` stands out from the other values: more
+/// than `stdev_factor` standard deviations above their mean, and at least
+/// `floor` of that mean.
+fn is_outlier(values: &[f64], index: usize, stdev_factor: f64, floor: impl Fn(f64) -> f64) -> bool {
+    let rest: Vec<f64> = values
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != index)
+        .map(|(_, value)| *value)
+        .collect();
+    let mean = rest.iter().sum::<f64>() / rest.len() as f64;
+    let variance = rest.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / rest.len() as f64;
+
+    let value = values[index];
+    value > mean + stdev_factor * variance.sqrt() && value >= floor(mean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackendConfig, ServerConfig};
+
+    fn server(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    /// Outcomes of `requests` requests, `errors` of which failed, taking
+    /// `latency_ms` each.
+    fn outcomes(requests: u64, errors: u64, latency_ms: u64) -> ServerOutcomes {
+        let mut outcomes = ServerOutcomes::default();
+        for i in 0..requests {
+            let status = if i < errors { 503 } else { 200 };
+            outcomes.record(status, Duration::from_millis(latency_ms));
+        }
+        outcomes
+    }
+
+    #[test]
+    fn test_error_rate_outlier() {
+        let config = OutlierDetectionConfig::default();
+        let pool = HashMap::from([
+            (server(8001), outcomes(100, 0, 10)),
+            (server(8002), outcomes(100, 1, 10)),
+            (server(8003), outcomes(100, 30, 10)),
+            (server(8004), outcomes(100, 2, 10)),
+        ]);
+        assert_eq!(
+            find_outliers(&pool, &config),
+            vec![(server(8003), OutlierReason::ErrorRate)]
+        );
+    }
+
+    #[test]
+    fn test_latency_outlier() {
+        let config = OutlierDetectionConfig::default();
+        let pool = HashMap::from([
+            (server(8001), outcomes(100, 0, 10)),
+            (server(8002), outcomes(100, 0, 250)),
+            (server(8003), outcomes(100, 0, 12)),
+        ]);
+        assert_eq!(
+            find_outliers(&pool, &config),
+            vec![(server(8002), OutlierReason::Latency)]
+        );
+    }
+
+    #[test]
+    fn test_no_outliers() {
+        let config = OutlierDetectionConfig::default();
+
+        // Small differences are not outliers, even in a very uniform pool
+        let pool = HashMap::from([
+            (server(8001), outcomes(100, 0, 10)),
+            (server(8002), outcomes(100, 0, 10)),
+            (server(8003), outcomes(100, 5, 13)),
+        ]);
+        assert!(find_outliers(&pool, &config).is_empty());
+
+        // Too few requests to judge the failing server
+        let pool = HashMap::from([
+            (server(8001), outcomes(100, 0, 10)),
+            (server(8002), outcomes(100, 0, 10)),
+            (server(8003), outcomes(10, 10, 10)),
+        ]);
+        assert!(find_outliers(&pool, &config).is_empty());
+
+        // Too few servers to compare
+        let pool = HashMap::from([
+            (server(8001), outcomes(100, 0, 10)),
+            (server(8002), outcomes(100, 50, 10)),
+        ]);
+        assert!(find_outliers(&pool, &config).is_empty());
+    }
+
+    #[test]
+    fn test_max_ejected() {
+        assert_eq!(max_ejected(4, 0), 0);
+        assert_eq!(max_ejected(4, 10), 1);
+        assert_eq!(max_ejected(10, 30), 3);
+        assert_eq!(max_ejected(3, 100), 3);
+    }
+
+    fn detector(servers: &[SocketAddr], config: OutlierDetectionConfig) -> OutlierDetector {
+        let backends = vec![BackendConfig {
+            name: "api".to_string(),
+            servers: servers
+                .iter()
+                .map(|address| ServerConfig {
+                    address: *address,
+                    weight: 1,
+                    metadata: Default::default(),
+                })
+                .collect(),
+            outlier_detection: Some(config),
+            ..Default::default()
+        }];
+        let router = Arc::new(BackendRouter::new(&backends, &[]));
+        let metrics = MetricsCollector::new();
+        metrics.track_outcomes("api");
+        OutlierDetector::new(Arc::clone(router.health_state()), router, metrics)
+    }
+
+    fn record(detector: &OutlierDetector, server: SocketAddr, requests: u64, errors: u64) {
+        for i in 0..requests {
+            let status = if i < errors { 502 } else { 200 };
+            detector.metrics.record_request(
+                "web",
+                "api",
+                Some(server),
+                "GET",
+                status,
+                Duration::from_millis(10),
+            );
+        }
+    }
+
+    #[test]
+    fn test_ejection_time_grows() {
+        let servers = [server(8001), server(8002), server(8003)];
+        let config = OutlierDetectionConfig {
+            base_ejection_time: Duration::from_secs(10),
+            max_ejection_percent: 50,
+            ..Default::default()
+        };
+        let mut detector = detector(&servers, config.clone());
+
+        for round in 1..=2 {
+            record(&detector, servers[0], 50, 0);
+            record(&detector, servers[1], 50, 0);
+            record(&detector, servers[2], 50, 40);
+            detector.analyze("api", &config);
+            assert!(detector.health_state.is_ejected(servers[2]));
+            assert_eq!(detector.ejections[&servers[2]], round);
+
+            // End the ejection early to let the server fail again
+            detector.health_state.reset_server(servers[2]);
+        }
+
+        // A clean analysis works off one ejection
+        detector.analyze("api", &config);
+        assert_eq!(detector.ejections[&servers[2]], 1);
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, detector.metrics.registry())
+            .unwrap();
+        assert!(buffer.contains(
+            r#"rustlb_outlier_ejections_total{backend="api",server="127.0.0.1:8003",reason="ErrorRate"} 2"#
+        ));
+    }
+
+    #[test]
+    fn test_max_ejection_percent() {
+        let servers = [server(8001), server(8002), server(8003), server(8004)];
+        let config = OutlierDetectionConfig {
+            stdev_factor: 1.0,
+            max_ejection_percent: 25,
+            ..Default::default()
+        };
+        let mut detector = detector(&servers, config.clone());
+
+        // Two outliers, but only one server of four may be ejected
+        record(&detector, servers[0], 50, 0);
+        record(&detector, servers[1], 50, 0);
+        record(&detector, servers[2], 50, 30);
+        record(&detector, servers[3], 50, 25);
+        detector.analyze("api", &config);
+
+        assert!(detector.health_state.is_ejected(servers[2]));
+        assert!(!detector.health_state.is_ejected(servers[3]));
+    }
+}
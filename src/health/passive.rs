@@ -1,63 +1,121 @@
 //! Passive health tracking.
 //!
-//! Tracks request failures during proxying to detect unhealthy backends.
+//! Tracks the outcome of proxied requests and connections to eject backends
+//! that keep failing.
 
+use crate::config::PassiveHealthConfig;
 use crate::health::HealthState;
+use crate::metrics::{FailureReason, MetricsCollector};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tracing::warn;
 
 /// Tracks request failures for passive health checking.
 ///
 /// This is called by the proxy layer when requests succeed or fail.
-/// It updates the shared health state based on actual traffic.
+/// A server is ejected from selection after its backend's configured number
+/// of consecutive failures, independently of active health checks.
 #[derive(Clone)]
 pub struct PassiveHealthTracker {
     /// Shared health state.
     health_state: Arc<HealthState>,
+    /// Metrics collector for ejections.
+    metrics: MetricsCollector,
 }
 
 impl PassiveHealthTracker {
     /// Create a new passive health tracker.
-    pub fn new(health_state: Arc<HealthState>) -> Self {
-        Self { health_state }
+    pub fn new(health_state: Arc<HealthState>, metrics: MetricsCollector) -> Self {
+        Self {
+            health_state,
+            metrics,
+        }
     }
 
     /// Record a successful request to a server.
     pub fn record_success(&self, server: SocketAddr) {
-        self.health_state.record_success(server);
+        self.health_state.record_passive_success(server);
     }
 
-    /// Record a failed request to a server.
+    /// Record a failed request to a server of `backend`.
     ///
     /// This is called when:
-    /// - Connection to backend fails
-    /// - Backend returns an error response (5xx)
+    /// - Connection to backend fails (TCP connect, TLS or HTTP handshake)
+    /// - Connection fails before the backend responds
     /// - Request times out
-    pub fn record_failure(&self, server: SocketAddr) {
-        self.health_state.record_failure(server);
+    /// - Backend returns one of the configured failure statuses
+    pub fn record_failure(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        config: &PassiveHealthConfig,
+        reason: FailureReason,
+    ) {
+        if !config.enabled {
+            return;
+        }
+
+        if let Some(failures) = self.health_state.record_passive_failure(
+            server,
+            config.unhealthy_threshold,
+            config.ejection_time,
+        ) {
+            warn!(
+                backend = backend,
+                server = %server,
+                reason = ?reason,
+                failures,
+                ejection_time = ?config.ejection_time,
+                "server ejected by passive health checking"
+            );
+            self.metrics
+                .record_passive_ejection(backend, server, reason);
+        }
     }
 
-    /// Check if a server is healthy.
+    /// Record the response status of a proxied request.
+    ///
+    /// Statuses listed in the backend's `failure_statuses` count as failures,
+    /// any other response as a success.
+    pub fn record_status(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        config: &PassiveHealthConfig,
+        status: u16,
+    ) {
+        if config.failure_statuses.contains(&status) {
+            self.record_failure(backend, server, config, FailureReason::Status);
+        } else {
+            self.record_success(server);
+        }
+    }
+
+    /// Check if a server may receive traffic.
     pub fn is_healthy(&self, server: SocketAddr) -> bool {
-        self.health_state.is_healthy(server)
+        self.health_state.is_available(server)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::health::state::HealthConfig;
     use std::time::Duration;
 
+    fn config() -> PassiveHealthConfig {
+        PassiveHealthConfig {
+            unhealthy_threshold: 3,
+            ejection_time: Duration::from_secs(1),
+            failure_statuses: vec![503],
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_passive_tracking() {
-        let config = HealthConfig {
-            unhealthy_threshold: 3,
-            healthy_threshold: 2,
-            cooldown: Duration::from_secs(1),
-        };
-        let health_state = Arc::new(HealthState::with_config(config));
-        let tracker = PassiveHealthTracker::new(Arc::clone(&health_state));
+        let health_state = Arc::new(HealthState::new());
+        let metrics = MetricsCollector::new();
+        let tracker = PassiveHealthTracker::new(Arc::clone(&health_state), metrics.clone());
 
         let server: SocketAddr = "127.0.0.1:8001".parse().unwrap();
         health_state.register_server(server);
@@ -65,37 +123,53 @@ mod tests {
         // Server starts healthy
         assert!(tracker.is_healthy(server));
 
-        // After 3 failures, becomes unhealthy
-        tracker.record_failure(server);
-        tracker.record_failure(server);
-        tracker.record_failure(server);
+        // After 3 failures, it is ejected
+        tracker.record_failure("api", server, &config(), FailureReason::ConnectFailure);
+        tracker.record_failure("api", server, &config(), FailureReason::Reset);
+        tracker.record_status("api", server, &config(), 503);
         assert!(!tracker.is_healthy(server));
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, metrics.registry()).unwrap();
+        assert!(buffer.contains(
+            r#"rustlb_passive_ejections_total{backend="api",server="127.0.0.1:8001",reason="Status"} 1"#
+        ));
     }
 
     #[test]
     fn test_success_resets_failures() {
-        let config = HealthConfig {
-            unhealthy_threshold: 3,
-            healthy_threshold: 2,
-            cooldown: Duration::from_secs(1),
-        };
-        let health_state = Arc::new(HealthState::with_config(config));
-        let tracker = PassiveHealthTracker::new(Arc::clone(&health_state));
+        let health_state = Arc::new(HealthState::new());
+        let tracker = PassiveHealthTracker::new(Arc::clone(&health_state), MetricsCollector::new());
 
         let server: SocketAddr = "127.0.0.1:8001".parse().unwrap();
         health_state.register_server(server);
 
         // Two failures
-        tracker.record_failure(server);
-        tracker.record_failure(server);
+        tracker.record_failure("api", server, &config(), FailureReason::Reset);
+        tracker.record_failure("api", server, &config(), FailureReason::Reset);
         assert!(tracker.is_healthy(server));
 
         // Success resets
-        tracker.record_success(server);
+        tracker.record_status("api", server, &config(), 200);
+
+        // Two more failures don't eject
+        tracker.record_failure("api", server, &config(), FailureReason::Reset);
+        tracker.record_failure("api", server, &config(), FailureReason::Reset);
+        assert!(tracker.is_healthy(server));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let health_state = Arc::new(HealthState::new());
+        let tracker = PassiveHealthTracker::new(Arc::clone(&health_state), MetricsCollector::new());
+        let server: SocketAddr = "127.0.0.1:8001".parse().unwrap();
+        let config = PassiveHealthConfig {
+            enabled: false,
+            unhealthy_threshold: 1,
+            ..Default::default()
+        };
 
-        // Two more failures don't make unhealthy
-        tracker.record_failure(server);
-        tracker.record_failure(server);
+        tracker.record_failure("api", server, &config, FailureReason::ConnectFailure);
         assert!(tracker.is_healthy(server));
     }
 }
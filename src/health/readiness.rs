@@ -0,0 +1,226 @@
+//! Readiness of the load balancer to take traffic.
+//!
+//! rustlb is ready once every configured frontend is listening and the
+//! first round of active health checks finished, for as long as the backends
+//! listed in `global.readiness.require_healthy` have a healthy server and
+//! shutdown has not started.
+
+use crate::backend::BackendRouter;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+
+/// Startup and shutdown progress that readiness depends on.
+#[derive(Debug, Default)]
+pub struct Readiness {
+    /// Configured frontends that are not listening, or `None` until the
+    /// frontends were started.
+    unbound_frontends: Mutex<Option<Vec<String>>>,
+    /// Whether the first round of active health checks finished.
+    initial_checks: AtomicBool,
+    /// Whether shutdown started.
+    stopping: AtomicBool,
+}
+
+/// Readiness with the reasons behind it, as reported by `/readyz`.
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    /// Whether rustlb should receive traffic.
+    pub ready: bool,
+    /// Whether every configured frontend is listening.
+    pub frontends_bound: bool,
+    /// Configured frontends that are not listening.
+    pub unbound_frontends: Vec<String>,
+    /// Whether the first round of active health checks finished.
+    pub initial_health_checks: bool,
+    /// Whether shutdown started.
+    pub stopping: bool,
+    /// Healthy servers of every backend pool.
+    pub backends: Vec<PoolHealth>,
+}
+
+/// Healthy and total servers of a backend pool.
+#[derive(Debug, Serialize)]
+pub struct PoolHealth {
+    /// Backend name.
+    pub name: String,
+    /// Servers the router may select.
+    pub healthy: usize,
+    /// Servers in the pool.
+    pub total: usize,
+    /// Whether the pool needs a healthy server for rustlb to be ready.
+    pub required: bool,
+}
+
+impl Readiness {
+    /// Create readiness for a load balancer that is still starting.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record which configured frontends failed to start listening, after
+    /// startup or a reload.
+    pub fn set_unbound_frontends(&self, unbound: Vec<String>) {
+        *self.unbound_frontends.lock() = Some(unbound);
+    }
+
+    /// Record that the first round of active health checks finished.
+    pub fn initial_checks_finished(&self) {
+        if !self.initial_checks.swap(true, Ordering::AcqRel) {
+            info!("initial health checks finished");
+        }
+    }
+
+    /// Record that shutdown started, so traffic goes elsewhere while
+    /// connections drain.
+    pub fn set_stopping(&self) {
+        self.stopping.store(true, Ordering::Release);
+    }
+
+    /// Report readiness, counting the servers of every pool the router may
+    /// select.
+    ///
+    /// Pools named in `required` must have at least one such server. Servers
+    /// that are unhealthy, ejected by passive health or outlier detection, or
+    /// draining do not count.
+    pub fn report(&self, router: &BackendRouter, required: &[String]) -> ReadinessReport {
+        let mut backends: Vec<PoolHealth> = router
+            .backend_configs()
+            .into_iter()
+            .map(|backend| PoolHealth {
+                healthy: backend
+                    .servers
+                    .iter()
+                    .filter(|s| router.is_available(&backend.name, s.address))
+                    .count(),
+                total: backend.servers.len(),
+                required: required.contains(&backend.name),
+                name: backend.name,
+            })
+            .collect();
+        backends.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let unbound = self.unbound_frontends.lock().clone();
+        let frontends_bound = unbound.as_ref().is_some_and(|unbound| unbound.is_empty());
+        let initial_health_checks = self.initial_checks.load(Ordering::Acquire);
+        let stopping = self.stopping.load(Ordering::Acquire);
+        let pools_healthy = backends.iter().all(|b| !b.required || b.healthy > 0)
+            && required
+                .iter()
+                .all(|name| backends.iter().any(|b| &b.name == name));
+
+        ReadinessReport {
+            ready: frontends_bound && initial_health_checks && !stopping && pools_healthy,
+            frontends_bound,
+            unbound_frontends: unbound.unwrap_or_default(),
+            initial_health_checks,
+            stopping,
+            backends,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::time::Duration;
+
+    fn router() -> BackendRouter {
+        let config: Config = serde_yaml::from_str(
+            r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:8080"
+    backend: api
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+      - address: "127.0.0.1:9002"
+  - name: static
+    servers:
+      - address: "127.0.0.1:9003"
+"#,
+        )
+        .unwrap();
+        BackendRouter::new(&config.backends, &config.frontends)
+    }
+
+    #[test]
+    fn test_ready_after_startup() {
+        let router = router();
+        let readiness = Readiness::new();
+        assert!(!readiness.report(&router, &[]).ready);
+
+        readiness.set_unbound_frontends(Vec::new());
+        assert!(!readiness.report(&router, &[]).ready);
+
+        readiness.initial_checks_finished();
+        let report = readiness.report(&router, &[]);
+        assert!(report.ready);
+        assert_eq!(report.backends[0].name, "api");
+        assert_eq!(report.backends[0].healthy, 2);
+        assert_eq!(report.backends[0].total, 2);
+
+        readiness.set_unbound_frontends(vec!["web".to_string()]);
+        let report = readiness.report(&router, &[]);
+        assert!(!report.ready);
+        assert_eq!(report.unbound_frontends, ["web"]);
+
+        readiness.set_unbound_frontends(Vec::new());
+        readiness.set_stopping();
+        assert!(!readiness.report(&router, &[]).ready);
+    }
+
+    #[test]
+    fn test_required_pools() {
+        let router = router();
+        let readiness = Readiness::new();
+        readiness.set_unbound_frontends(Vec::new());
+        readiness.initial_checks_finished();
+
+        let health = router.health_state();
+        let s3 = "127.0.0.1:9003".parse().unwrap();
+        health.register_server(s3);
+        health.mark_unhealthy(s3);
+
+        // Only required pools need a healthy server
+        let report = readiness.report(&router, &["api".to_string()]);
+        assert!(report.ready);
+        assert_eq!(report.backends[1].healthy, 0);
+        assert!(!report.backends[1].required);
+
+        let report = readiness.report(&router, &["api".to_string(), "static".to_string()]);
+        assert!(!report.ready);
+        assert!(report.backends[1].required);
+    }
+
+    #[test]
+    fn test_ejected_and_draining_servers_are_not_healthy() {
+        let router = router();
+        let readiness = Readiness::new();
+        readiness.set_unbound_frontends(Vec::new());
+        readiness.initial_checks_finished();
+        let required = ["api".to_string()];
+
+        let s1 = "127.0.0.1:9001".parse().unwrap();
+        let s2 = "127.0.0.1:9002".parse().unwrap();
+        let health = router.health_state();
+        health.register_server(s1);
+        health.eject(s1, Duration::from_secs(60));
+
+        let report = readiness.report(&router, &required);
+        assert!(report.ready);
+        assert_eq!(report.backends[0].healthy, 1);
+
+        assert!(router.disable_server("api", s2));
+        let report = readiness.report(&router, &required);
+        assert!(!report.ready);
+        assert_eq!(report.backends[0].healthy, 0);
+
+        assert!(router.enable_server("api", s2));
+        assert!(readiness.report(&router, &required).ready);
+    }
+}
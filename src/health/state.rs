@@ -2,6 +2,7 @@
 //!
 //! Provides shared health tracking for all backend servers.
 
+use crate::config::HealthCheckDefaults;
 use dashmap::DashMap;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
@@ -27,6 +28,16 @@ pub struct HealthConfig {
     pub cooldown: Duration,
 }
 
+impl From<&HealthCheckDefaults> for HealthConfig {
+    fn from(defaults: &HealthCheckDefaults) -> Self {
+        Self {
+            unhealthy_threshold: defaults.unhealthy_threshold,
+            healthy_threshold: defaults.healthy_threshold,
+            cooldown: defaults.cooldown,
+        }
+    }
+}
+
 impl Default for HealthConfig {
     fn default() -> Self {
         Self {
@@ -52,6 +63,30 @@ pub struct ServerHealth {
     unhealthy_since: AtomicU64,
     /// Unix timestamp (seconds) of last health check.
     last_check: AtomicU64,
+    /// Consecutive failures of proxied traffic (passive checking).
+    passive_failures: AtomicU32,
+    /// Unix timestamp (milliseconds) until which the server is ejected by
+    /// passive checking or outlier detection (0 if not ejected).
+    ejected_until: AtomicU64,
+}
+
+/// Snapshot of a server's health, for inspection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerStatus {
+    /// Whether the server is healthy.
+    pub healthy: bool,
+    /// Whether the server is unhealthy and waiting out its cooldown.
+    pub in_cooldown: bool,
+    /// Whether the server is ejected by passive checking or outlier detection.
+    pub ejected: bool,
+    /// Consecutive failed health checks.
+    pub consecutive_failures: u32,
+    /// Consecutive successful health checks.
+    pub consecutive_successes: u32,
+    /// Consecutive failures of proxied traffic.
+    pub passive_failures: u32,
+    /// Unix timestamp (seconds) of the last health check, if any.
+    pub last_check: Option<u64>,
 }
 
 impl Default for ServerHealth {
@@ -63,6 +98,8 @@ impl Default for ServerHealth {
             active_connections: AtomicU32::new(0),
             unhealthy_since: AtomicU64::new(0),
             last_check: AtomicU64::new(0),
+            passive_failures: AtomicU32::new(0),
+            ejected_until: AtomicU64::new(0),
         }
     }
 }
@@ -86,6 +123,11 @@ impl HealthState {
         self.servers.entry(server).or_default();
     }
 
+    /// Stop tracking a server (e.g., after it was removed from every pool).
+    pub fn remove_server(&self, server: SocketAddr) {
+        self.servers.remove(&server);
+    }
+
     /// Check if a server is healthy.
     pub fn is_healthy(&self, server: SocketAddr) -> bool {
         self.servers
@@ -94,6 +136,20 @@ impl HealthState {
             .unwrap_or(true) // Unknown servers are assumed healthy
     }
 
+    /// Check if a server may receive traffic (healthy, not in cooldown and
+    /// not ejected).
+    pub fn is_available(&self, server: SocketAddr) -> bool {
+        self.is_healthy(server) && !self.is_in_cooldown(server) && !self.is_ejected(server)
+    }
+
+    /// Check if a server is ejected by passive health checking or outlier
+    /// detection.
+    pub fn is_ejected(&self, server: SocketAddr) -> bool {
+        self.servers
+            .get(&server)
+            .is_some_and(|s| s.ejected_until.load(Ordering::Acquire) > current_timestamp_ms())
+    }
+
     /// Check if a server is in cooldown period.
     pub fn is_in_cooldown(&self, server: SocketAddr) -> bool {
         if let Some(health) = self.servers.get(&server) {
@@ -146,6 +202,59 @@ impl HealthState {
         }
     }
 
+    /// Record a successful proxied request or connection.
+    pub fn record_passive_success(&self, server: SocketAddr) {
+        if let Some(health) = self.servers.get(&server) {
+            health.passive_failures.store(0, Ordering::Release);
+        }
+    }
+
+    /// Record a failed proxied request or connection.
+    ///
+    /// After `threshold` consecutive failures the server is ejected for
+    /// `ejection_time`. Failures while the server is ejected are ignored.
+    /// Returns the number of failures if this one ejected the server.
+    pub fn record_passive_failure(
+        &self,
+        server: SocketAddr,
+        threshold: u32,
+        ejection_time: Duration,
+    ) -> Option<u32> {
+        let entry = self.servers.entry(server).or_default();
+        let now = current_timestamp_ms();
+        if entry.ejected_until.load(Ordering::Acquire) > now {
+            return None;
+        }
+
+        let failures = entry.passive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+        if failures < threshold {
+            return None;
+        }
+        entry.passive_failures.store(0, Ordering::Release);
+        entry
+            .ejected_until
+            .store(now + ejection_time.as_millis() as u64, Ordering::Release);
+        Some(failures)
+    }
+
+    /// Eject a server for `duration`, such as an outlier of its pool.
+    pub fn eject(&self, server: SocketAddr, duration: Duration) {
+        let entry = self.servers.entry(server).or_default();
+        entry.passive_failures.store(0, Ordering::Release);
+        entry.ejected_until.store(
+            current_timestamp_ms() + duration.as_millis() as u64,
+            Ordering::Release,
+        );
+    }
+
+    /// Get consecutive passive failures for a server.
+    pub fn get_passive_failures(&self, server: SocketAddr) -> u32 {
+        self.servers
+            .get(&server)
+            .map(|s| s.passive_failures.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
     /// Increment active connection count.
     pub fn increment_connections(&self, server: SocketAddr) {
         if let Some(health) = self.servers.get(&server) {
@@ -179,11 +288,29 @@ impl HealthState {
             .unwrap_or(0)
     }
 
+    /// Get a snapshot of a server's health, if it is tracked.
+    pub fn status(&self, server: SocketAddr) -> Option<ServerStatus> {
+        let health = self.servers.get(&server)?;
+        let unhealthy_since = health.unhealthy_since.load(Ordering::Relaxed);
+        let last_check = health.last_check.load(Ordering::Relaxed);
+        Some(ServerStatus {
+            healthy: health.healthy.load(Ordering::Relaxed),
+            in_cooldown: unhealthy_since > 0
+                && Duration::from_secs(current_timestamp().saturating_sub(unhealthy_since))
+                    < self.config.cooldown,
+            ejected: health.ejected_until.load(Ordering::Relaxed) > current_timestamp_ms(),
+            consecutive_failures: health.consecutive_failures.load(Ordering::Relaxed),
+            consecutive_successes: health.consecutive_successes.load(Ordering::Relaxed),
+            passive_failures: health.passive_failures.load(Ordering::Relaxed),
+            last_check: (last_check > 0).then_some(last_check),
+        })
+    }
+
     /// Get all healthy servers from a list.
     pub fn filter_healthy(&self, servers: &[SocketAddr]) -> Vec<SocketAddr> {
         servers
             .iter()
-            .filter(|&&s| self.is_healthy(s) && !self.is_in_cooldown(s))
+            .filter(|&&s| self.is_available(s))
             .copied()
             .collect()
     }
@@ -207,14 +334,14 @@ impl HealthState {
 
     /// Mark a server as explicitly unhealthy (e.g., from passive check).
     pub fn mark_unhealthy(&self, server: SocketAddr) {
-        if let Some(health) = self.servers.get(&server) {
-            if health.healthy.load(Ordering::Acquire) {
-                health.healthy.store(false, Ordering::Release);
-                health.unhealthy_since.store(current_timestamp(), Ordering::Release);
-                health.consecutive_failures.store(0, Ordering::Release);
-                health.consecutive_successes.store(0, Ordering::Release);
-                tracing::warn!(server = %server, "server explicitly marked unhealthy");
-            }
+        if let Some(health) = self.servers.get(&server)
+            && health.healthy.load(Ordering::Acquire)
+        {
+            health.healthy.store(false, Ordering::Release);
+            health.unhealthy_since.store(current_timestamp(), Ordering::Release);
+            health.consecutive_failures.store(0, Ordering::Release);
+            health.consecutive_successes.store(0, Ordering::Release);
+            tracing::warn!(server = %server, "server explicitly marked unhealthy");
         }
     }
 
@@ -225,6 +352,8 @@ impl HealthState {
             health.unhealthy_since.store(0, Ordering::Release);
             health.consecutive_failures.store(0, Ordering::Release);
             health.consecutive_successes.store(0, Ordering::Release);
+            health.passive_failures.store(0, Ordering::Release);
+            health.ejected_until.store(0, Ordering::Release);
         }
     }
 }
@@ -243,6 +372,14 @@ fn current_timestamp() -> u64 {
         .as_secs()
 }
 
+/// Get the current Unix timestamp in milliseconds.
+fn current_timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as u64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -353,6 +490,46 @@ mod tests {
         assert_eq!(state.get_connections(server), 1);
     }
 
+    #[test]
+    fn test_passive_ejection() {
+        let state = HealthState::new();
+        let server: SocketAddr = "127.0.0.1:8001".parse().unwrap();
+        state.register_server(server);
+
+        let ejection_time = Duration::from_millis(50);
+        assert_eq!(state.record_passive_failure(server, 2, ejection_time), None);
+        assert_eq!(
+            state.record_passive_failure(server, 2, ejection_time),
+            Some(2)
+        );
+        assert!(state.is_ejected(server));
+        assert!(!state.is_available(server));
+
+        // Ejection does not touch the active health check state
+        assert!(state.is_healthy(server));
+        assert_eq!(state.get_failures(server), 0);
+
+        // Failures while ejected are ignored
+        assert_eq!(state.record_passive_failure(server, 1, ejection_time), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(state.is_available(server));
+        assert_eq!(state.get_passive_failures(server), 0);
+    }
+
+    #[test]
+    fn test_passive_success_resets_failures() {
+        let state = HealthState::new();
+        let server: SocketAddr = "127.0.0.1:8001".parse().unwrap();
+        state.register_server(server);
+
+        let ejection_time = Duration::from_secs(30);
+        state.record_passive_failure(server, 2, ejection_time);
+        state.record_passive_success(server);
+        assert_eq!(state.record_passive_failure(server, 2, ejection_time), None);
+        assert!(!state.is_ejected(server));
+    }
+
     #[test]
     fn test_filter_healthy() {
         let config = HealthConfig {
@@ -379,4 +556,27 @@ mod tests {
         assert!(healthy.contains(&s3));
         assert!(!healthy.contains(&s2));
     }
+
+    #[test]
+    fn test_status() {
+        let config = HealthConfig {
+            unhealthy_threshold: 1,
+            healthy_threshold: 2,
+            cooldown: Duration::from_secs(60),
+        };
+        let state = HealthState::with_config(config);
+        let server: SocketAddr = "127.0.0.1:8001".parse().unwrap();
+        assert!(state.status(server).is_none());
+
+        state.register_server(server);
+        let status = state.status(server).unwrap();
+        assert!(status.healthy);
+        assert_eq!(status.last_check, None);
+
+        state.record_failure(server);
+        let status = state.status(server).unwrap();
+        assert!(!status.healthy);
+        assert!(status.in_cooldown);
+        assert!(status.last_check.is_some());
+    }
 }
@@ -2,18 +2,28 @@
 //!
 //! This crate provides a production-ready load balancer with support for:
 //! - TCP (Layer 4) and HTTP (Layer 7) protocols
+//! - TLS termination
 //! - Multiple load balancing algorithms
 //! - Active and passive health checking
-//! - Hot configuration reload
-//! - Prometheus metrics
+//! - Backend server discovery through DNS and server list files
+//! - Hot configuration reload and zero-downtime binary upgrades
+//! - Prometheus metrics and an admin API
+//! - systemd socket activation and service notifications
 
+pub mod admin;
 pub mod backend;
 pub mod config;
+pub mod discovery;
 pub mod frontend;
+#[cfg(unix)]
+pub mod handoff;
 pub mod health;
 pub mod metrics;
 pub mod proxy;
 pub mod state;
+#[cfg(unix)]
+pub mod systemd;
+pub mod tls;
 pub mod util;
 
 pub use config::Config;
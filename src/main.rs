@@ -7,18 +7,31 @@
 
 use anyhow::{Context, Result};
 use clap::Parser;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::broadcast;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 use tracing::{error, info, warn};
 
-use rustlb::backend::BackendRouter;
+use rustlb::admin::AdminApi;
+use rustlb::backend::ServerOverlay;
 use rustlb::config::{load_config, Config, ConfigWatcher};
-use rustlb::frontend::FrontendListener;
-use rustlb::health::{HealthChecker, HealthConfig, HealthState};
-use rustlb::metrics::{MetricsCollector, MetricsServer};
+use rustlb::discovery::{DnsDiscovery, FileDiscovery};
+use rustlb::frontend::FrontendManager;
+use rustlb::health::{HealthChecker, OutlierDetector};
+use rustlb::metrics::MetricsServer;
+use rustlb::proxy::ConnectionPool;
+use rustlb::tls::check_backend_tls;
 use rustlb::util::init_logging;
+use rustlb::AppState;
+
+/// Time background tasks get to stop once connections are drained.
+const TASK_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a new process gets to start accepting on a binary upgrade.
+const UPGRADE_READY_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// A high-performance Layer 4/7 load balancer written in Rust.
 #[derive(Parser, Debug)]
@@ -123,56 +136,120 @@ fn run(config: Config, config_path: PathBuf, no_watch: bool) -> Result<()> {
 
 /// Async entry point for the load balancer.
 async fn run_async(config: Config, config_path: PathBuf, no_watch: bool) -> Result<()> {
-    // Create shutdown channel
-    let (shutdown_tx, _) = broadcast::channel::<()>(16);
+    // Backends whose TLS settings fail to load would be left out of the
+    // router, so refuse to start instead
+    check_backend_tls(&config.backends).map_err(anyhow::Error::msg)?;
+
+    // Servers added and removed through the admin API in earlier runs
+    let overlay = match config.global.admin.state_file {
+        Some(ref path) => ServerOverlay::load(path).context("failed to load backend state")?,
+        None => ServerOverlay::new(),
+    };
 
-    // Create metrics collector
-    let metrics = MetricsCollector::new();
+    // Shared state: configuration, health, router, metrics and shutdown
+    let state = AppState::with_overlay(config.clone(), overlay);
+    let shutdown = state.shutdown().clone();
 
-    // Create health state with config defaults
-    let health_config = HealthConfig {
-        unhealthy_threshold: config.health_check_defaults.unhealthy_threshold,
-        healthy_threshold: config.health_check_defaults.healthy_threshold,
-        cooldown: config.health_check_defaults.cooldown,
-    };
-    let health_state = Arc::new(HealthState::with_config(health_config));
+    // Listening sockets passed on by the previous process on binary upgrade
+    #[cfg(unix)]
+    let mut inherited = rustlb::handoff::inherited_sockets();
+    #[cfg(not(unix))]
+    let mut inherited = std::collections::HashMap::new();
 
-    // Create backend router
-    let router = Arc::new(BackendRouter::new(&config.backends, &config.frontends));
+    // Listening sockets passed by systemd socket activation
+    #[cfg(unix)]
+    let activated = rustlb::systemd::activated_sockets();
+    #[cfg(not(unix))]
+    let activated = std::collections::HashMap::new();
 
     // Store handles for all tasks
     let mut handles = Vec::new();
 
-    // Start metrics server (if enabled)
+    // Reloads requested through the admin API are run by the config watcher
+    let (admin_reload_tx, admin_reload_rx) = mpsc::unbounded_channel();
+
+    // Start metrics server and admin API (if enabled)
+    let mut metrics_socket = None;
     if config.global.metrics.enabled {
-        let metrics_server = MetricsServer::new(
-            config.global.metrics.address,
-            config.global.metrics.path.clone(),
-            metrics.clone(),
-        );
-        let shutdown_rx = shutdown_tx.subscribe();
-        let metrics_handle = tokio::spawn(async move {
-            metrics_server.run(shutdown_rx).await;
-        });
-        handles.push(metrics_handle);
+        let address = config.global.metrics.address;
+        let socket = match inherited.remove(&address) {
+            Some(socket) => socket
+                .set_nonblocking(true)
+                .and_then(|()| TcpListener::from_std(socket)),
+            None => TcpListener::bind(address).await,
+        };
+        match socket {
+            Ok(socket) => {
+                let socket = Arc::new(socket);
+                metrics_socket = Some((address, Arc::clone(&socket)));
+                let metrics_server = MetricsServer::new(
+                    address,
+                    config.global.metrics.path.clone(),
+                    state.metrics().clone(),
+                )
+                .with_listener(socket)
+                .with_state(state.clone())
+                .with_admin(AdminApi::new(state.clone()).with_reload_requests(admin_reload_tx));
+                let shutdown_rx = shutdown.subscribe();
+                let metrics_handle = tokio::spawn(async move {
+                    metrics_server.run(shutdown_rx).await;
+                });
+                handles.push(metrics_handle);
+            }
+            Err(e) => error!(error = %e, address = %address, "failed to bind metrics server"),
+        }
     }
 
     // Start health checker
     let health_checker = HealthChecker::new(
-        Arc::clone(&health_state),
-        config.backends.clone(),
+        Arc::clone(state.health()),
+        Arc::clone(state.router()),
         config.health_check_defaults.interval,
         config.health_check_defaults.timeout,
-    );
-    let shutdown_rx = shutdown_tx.subscribe();
+    )
+    .with_readiness(Arc::clone(state.readiness()));
+    let shutdown_rx = shutdown.subscribe();
     let health_handle = tokio::spawn(async move {
         health_checker.run(shutdown_rx).await;
     });
     handles.push(health_handle);
 
+    // Start outlier detector
+    let outlier_detector = OutlierDetector::new(
+        Arc::clone(state.health()),
+        Arc::clone(state.router()),
+        state.metrics().clone(),
+    );
+    let shutdown_rx = shutdown.subscribe();
+    handles.push(tokio::spawn(async move {
+        outlier_detector.run(shutdown_rx).await;
+    }));
+
+    // Start DNS discovery
+    match DnsDiscovery::new(Arc::clone(state.router()), state.metrics().clone()) {
+        Ok(discovery) => {
+            let shutdown_rx = shutdown.subscribe();
+            handles.push(tokio::spawn(async move {
+                discovery.run(shutdown_rx).await;
+            }));
+        }
+        Err(e) => error!(error = %e, "failed to create DNS resolver, DNS discovery disabled"),
+    }
+
+    // Start file discovery
+    let file_discovery = FileDiscovery::new(Arc::clone(state.router()), state.metrics().clone());
+    let shutdown_rx = shutdown.subscribe();
+    handles.push(tokio::spawn(async move {
+        file_discovery.run(shutdown_rx).await;
+    }));
+
+    // Reloaded configurations are applied on this task, since binding new
+    // listeners is async and the watcher callback is not.
+    let (reload_tx, mut reload_rx) = mpsc::unbounded_channel::<Config>();
+
     // Start config watcher (unless disabled)
     if !no_watch {
-        let shutdown_rx = shutdown_tx.subscribe();
+        let shutdown_rx = shutdown.subscribe();
         let watcher = ConfigWatcher::new(
             config_path,
             Box::new(move |new_config| {
@@ -181,42 +258,78 @@ async fn run_async(config: Config, config_path: PathBuf, no_watch: bool) -> Resu
                     backends = new_config.backends.len(),
                     "config reload triggered"
                 );
-                // Note: Full hot reload would require recreating router and listeners
-                // For now, we just log the event. Full implementation would use ArcSwap
-                // in the router to atomically swap the configuration.
-                warn!("hot reload of listeners not yet implemented - restart required for changes");
+                let _ = reload_tx.send(new_config);
             }),
-        );
+        )
+        .watch_files(config.watched_files())
+        .with_reload_requests(admin_reload_rx);
         let watcher_handle = tokio::spawn(async move {
             watcher.run(shutdown_rx).await;
         });
         handles.push(watcher_handle);
     }
 
-    // Start frontend listeners
-    for frontend_config in config.frontends {
-        let router = Arc::clone(&router);
-        let shutdown_rx = shutdown_tx.subscribe();
-        let metrics = metrics.clone();
+    // Start the backend connection pool reaper
+    let pool = ConnectionPool::new(state.metrics().clone());
+    let shutdown_rx = shutdown.subscribe();
+    let pool_reaper = pool.clone();
+    handles.push(tokio::spawn(async move {
+        pool_reaper.run(shutdown_rx).await;
+    }));
 
-        let listener = FrontendListener::bind(frontend_config.clone(), router, metrics)
+    // Start frontend listeners
+    let mut frontends = FrontendManager::new(
+        Arc::clone(state.router()),
+        state.metrics().clone(),
+        pool,
+        state.connections().clone(),
+    )
+    .with_inherited_sockets(inherited)
+    .with_activated_sockets(activated);
+    for frontend_config in config.frontends.clone() {
+        let name = frontend_config.name.clone();
+        let listen = frontend_config.listen;
+
+        frontends
+            .start(frontend_config)
             .await
-            .with_context(|| {
-                format!(
-                    "failed to bind frontend '{}' on {}",
-                    frontend_config.name, frontend_config.listen
-                )
-            })?;
+            .with_context(|| format!("failed to bind frontend '{}' on {}", name, listen))?;
+    }
+    frontends.close_inherited();
+    record_unbound_frontends(&state, &frontends);
 
-        let handle = tokio::spawn(async move {
-            listener.run(shutdown_rx).await;
-        });
+    // Let the previous process, if any, drain and exit
+    #[cfg(unix)]
+    if let Err(e) = rustlb::handoff::notify_ready() {
+        error!(error = %e, "failed to notify the previous process of readiness");
+    }
 
-        handles.push(handle);
+    // Tell systemd startup finished and keep its watchdog fed
+    #[cfg(unix)]
+    {
+        rustlb::systemd::notify(rustlb::systemd::READY);
+        if let Some(interval) = rustlb::systemd::watchdog_interval() {
+            handles.push(tokio::spawn(rustlb::systemd::run_watchdog(
+                interval,
+                shutdown.subscribe(),
+            )));
+        }
     }
 
+    // Binary upgrades are requested with SIGUSR2
+    let (upgrade_tx, mut upgrade_rx) = mpsc::unbounded_channel::<()>();
+    #[cfg(unix)]
+    handles.push(tokio::spawn(forward_upgrade_signals(
+        upgrade_tx,
+        shutdown.subscribe(),
+    )));
+    #[cfg(not(unix))]
+    drop(upgrade_tx);
+
     info!("rustlb is running");
-    info!("press Ctrl+C to stop, send SIGHUP to reload config");
+    info!("press Ctrl+C or send SIGTERM to stop, send SIGHUP to reload config and certificates");
+    #[cfg(unix)]
+    info!("send SIGUSR2 to upgrade to a new binary without dropping connections");
     if config.global.metrics.enabled {
         info!(
             address = %config.global.metrics.address,
@@ -225,23 +338,60 @@ async fn run_async(config: Config, config_path: PathBuf, no_watch: bool) -> Resu
         );
     }
 
-    // Wait for shutdown signal
-    match tokio::signal::ctrl_c().await {
-        Ok(()) => {
-            info!("received shutdown signal");
-        }
-        Err(e) => {
-            error!(error = %e, "failed to listen for shutdown signal");
+    // Apply reloads until a shutdown signal arrives
+    let shutdown_signal = wait_for_shutdown_signal();
+    tokio::pin!(shutdown_signal);
+    let upgraded = loop {
+        tokio::select! {
+            _ = &mut shutdown_signal => break false,
+
+            Some(new_config) = reload_rx.recv() => {
+                apply_config(&state, &mut frontends, new_config).await;
+                #[cfg(unix)]
+                rustlb::systemd::notify(rustlb::systemd::READY);
+            }
+
+            Some(()) = upgrade_rx.recv() => {
+                if upgrade(&frontends, metrics_socket.as_ref()).await {
+                    break true;
+                }
+            }
         }
+    };
+
+    // After an upgrade the service lives on in the new process
+    #[cfg(unix)]
+    if !upgraded {
+        rustlb::systemd::notify(rustlb::systemd::STOPPING);
+    }
+    #[cfg(not(unix))]
+    let _ = upgraded;
+
+    // Stop accepting, then let open connections finish
+    state.readiness().set_stopping();
+    let shutdown_timeout = state.config().global.shutdown_timeout;
+    info!(
+        connections = state.connections().active(),
+        shutdown_timeout = ?shutdown_timeout,
+        "initiating graceful shutdown"
+    );
+    handles.extend(frontends.shutdown());
+    let report = state.connections().drain(shutdown_timeout).await;
+    if report.closed > 0 {
+        warn!(
+            drained = report.drained,
+            closed = report.closed,
+            "shutdown timeout reached, closed remaining connections"
+        );
+    } else {
+        info!(drained = report.drained, "all connections drained");
     }
 
     // Signal all tasks to shut down
-    info!("initiating graceful shutdown");
-    let _ = shutdown_tx.send(());
+    state.trigger_shutdown();
 
     // Wait for all tasks to finish with timeout
-    let shutdown_timeout = Duration::from_secs(30);
-    let shutdown_deadline = tokio::time::sleep(shutdown_timeout);
+    let shutdown_deadline = tokio::time::sleep(TASK_SHUTDOWN_TIMEOUT);
     tokio::pin!(shutdown_deadline);
 
     for (i, handle) in handles.into_iter().enumerate() {
@@ -261,3 +411,117 @@ async fn run_async(config: Config, config_path: PathBuf, no_watch: bool) -> Resu
     info!("rustlb shut down complete");
     Ok(())
 }
+
+/// Wait for Ctrl+C or, on Unix, SIGTERM.
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => match result {
+            Ok(()) => info!(signal = "SIGINT", "received shutdown signal"),
+            Err(e) => error!(error = %e, "failed to listen for shutdown signal"),
+        },
+        _ = terminate => info!(signal = "SIGTERM", "received shutdown signal"),
+    }
+}
+
+/// Forward SIGUSR2 as binary upgrade requests until shutdown.
+#[cfg(unix)]
+async fn forward_upgrade_signals(
+    upgrade_tx: mpsc::UnboundedSender<()>,
+    mut shutdown: tokio::sync::broadcast::Receiver<()>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigusr2 = match signal(SignalKind::user_defined2()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            warn!(error = %e, "failed to setup SIGUSR2 handler, binary upgrades disabled");
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            Some(()) = sigusr2.recv() => {
+                info!("received SIGUSR2, upgrading binary");
+                let _ = upgrade_tx.send(());
+            }
+            _ = shutdown.recv() => break,
+        }
+    }
+}
+
+/// Hand the listening sockets to a new process of the binary.
+///
+/// Returns true once the new process accepts connections, after which this
+/// one should drain and exit. On failure this process keeps serving.
+#[cfg(unix)]
+async fn upgrade(
+    frontends: &FrontendManager,
+    metrics_socket: Option<&(SocketAddr, Arc<TcpListener>)>,
+) -> bool {
+    let mut sockets = frontends.sockets();
+    sockets.extend(metrics_socket.cloned());
+
+    match rustlb::handoff::spawn_successor(&sockets, UPGRADE_READY_TIMEOUT).await {
+        Ok(pid) => {
+            info!(pid = pid, "new process is ready, handing over");
+            rustlb::systemd::notify(&format!("MAINPID={}", pid));
+            true
+        }
+        Err(e) => {
+            error!(error = %e, "binary upgrade failed, continuing to serve");
+            false
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn upgrade(_: &FrontendManager, _: Option<&(SocketAddr, Arc<TcpListener>)>) -> bool {
+    false
+}
+
+/// Apply a reloaded configuration without dropping connections.
+///
+/// Backend pools are swapped atomically in the router (the health checker
+/// follows), then listeners are reconciled. Global settings such as logging,
+/// the metrics address and health check defaults still require a restart.
+async fn apply_config(state: &AppState, frontends: &mut FrontendManager, new_config: Config) {
+    let frontend_configs = new_config.frontends.clone();
+
+    state.swap_config(new_config);
+    let drain_timeout = state.config().global.shutdown_timeout;
+    frontends.apply(&frontend_configs, drain_timeout).await;
+    record_unbound_frontends(state, frontends);
+
+    info!(frontends = frontend_configs.len(), "hot reload applied");
+}
+
+/// Report configured frontends that are not listening to the readiness
+/// probe.
+fn record_unbound_frontends(state: &AppState, frontends: &FrontendManager) {
+    let listening = frontends.names();
+    let unbound = state
+        .config()
+        .frontends
+        .iter()
+        .filter(|f| !listening.contains(&f.name))
+        .map(|f| f.name.clone())
+        .collect();
+    state.readiness().set_unbound_frontends(unbound);
+}
@@ -2,12 +2,15 @@
 //!
 //! Provides metrics for request counts, latency, connections, and backend health.
 
+use crate::metrics::outcomes::ServerOutcomes;
+use dashmap::DashMap;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::Registry;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
@@ -21,6 +24,25 @@ pub struct RequestLabels {
     pub status: String,
 }
 
+/// Labels for gRPC call metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct GrpcRequestLabels {
+    pub frontend: String,
+    pub backend: String,
+    /// Full method path, `/package.Service/Method`.
+    pub method: String,
+    /// Canonical name of the gRPC status code, such as `OK`.
+    pub code: String,
+}
+
+/// Labels for gRPC call duration metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct GrpcMethodLabels {
+    pub frontend: String,
+    pub backend: String,
+    pub method: String,
+}
+
 /// Labels for connection metrics.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct ConnectionLabels {
@@ -73,6 +95,30 @@ struct MetricsCollectorInner {
     connections_total: Family<ConnectionLabels, Counter>,
     /// Health check results counter.
     health_checks_total: Family<HealthCheckLabels, Counter>,
+    /// Idle pooled backend connections gauge.
+    pool_idle_connections: Family<BackendLabels, Gauge>,
+    /// Pooled backend connection checkouts counter.
+    pool_checkouts_total: Family<PoolCheckoutLabels, Counter>,
+    /// TLS handshakes counter.
+    tls_handshakes_total: Family<TlsHandshakeLabels, Counter>,
+    /// gRPC calls counter.
+    grpc_requests_total: Family<GrpcRequestLabels, Counter>,
+    /// gRPC call duration histogram (in seconds).
+    grpc_request_duration_seconds: Family<GrpcMethodLabels, Histogram>,
+    /// Upgraded (e.g. WebSocket) connection duration histogram (in seconds).
+    upgraded_duration_seconds: Family<ConnectionLabels, Histogram>,
+    /// Bytes relayed over upgraded connections counter.
+    upgraded_bytes_total: Family<BytesLabels, Counter>,
+    /// Request and connect retries counter.
+    retries_total: Family<RetryLabels, Counter>,
+    /// Passive health ejections counter.
+    passive_ejections_total: Family<EjectionLabels, Counter>,
+    /// Outlier detection ejections counter.
+    outlier_ejections_total: Family<OutlierEjectionLabels, Counter>,
+    /// Failed server discoveries counter.
+    discovery_errors_total: Family<DiscoveryErrorLabels, Counter>,
+    /// Request outcomes per server of the backends under outlier detection.
+    server_outcomes: DashMap<String, HashMap<SocketAddr, ServerOutcomes>>,
     /// The prometheus registry.
     registry: Registry,
 }
@@ -85,6 +131,102 @@ pub struct HealthCheckLabels {
     pub result: HealthCheckResult,
 }
 
+/// Labels for connection pool checkout metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PoolCheckoutLabels {
+    pub backend: String,
+    pub server: String,
+    pub result: PoolCheckoutResult,
+}
+
+/// Labels for TLS handshake metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct TlsHandshakeLabels {
+    pub frontend: String,
+    /// Configured server name of the certificate served, or "default".
+    pub sni: String,
+    pub result: HandshakeResult,
+}
+
+/// Labels for request retry metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RetryLabels {
+    pub frontend: String,
+    pub backend: String,
+    /// Server whose attempt failed.
+    pub server: String,
+    pub reason: FailureReason,
+}
+
+/// Labels for passive health ejection metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct EjectionLabels {
+    pub backend: String,
+    pub server: String,
+    /// Failure that triggered the ejection.
+    pub reason: FailureReason,
+}
+
+/// Labels for outlier ejection metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct OutlierEjectionLabels {
+    pub backend: String,
+    pub server: String,
+    pub reason: OutlierReason,
+}
+
+/// Labels for server discovery error metrics.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct DiscoveryErrorLabels {
+    pub backend: String,
+    pub source: DiscoverySource,
+}
+
+/// Where discovered servers come from.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum DiscoverySource {
+    /// DNS records.
+    Dns,
+    /// A server list file.
+    File,
+}
+
+/// What made a server an outlier in its pool.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum OutlierReason {
+    /// Its error rate is well above the rest of the pool.
+    ErrorRate,
+    /// Its p99 latency is well above the rest of the pool.
+    Latency,
+}
+
+/// Why a request or connection to a server failed.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum FailureReason {
+    /// Connecting to the server failed.
+    ConnectFailure,
+    /// The connection failed before a response arrived.
+    Reset,
+    /// The attempt exceeded its time limit.
+    Timeout,
+    /// The server answered with a status configured as a failure.
+    Status,
+}
+
+/// Result of a TLS handshake.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum HandshakeResult {
+    Success,
+    Failure,
+}
+
+/// Whether a pooled connection was reused or newly created.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum PoolCheckoutResult {
+    Reused,
+    Created,
+}
+
 /// Result of a health check.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
 pub enum HealthCheckResult {
@@ -110,6 +252,24 @@ impl MetricsCollector {
         let bytes_total = Family::<BytesLabels, Counter>::default();
         let connections_total = Family::<ConnectionLabels, Counter>::default();
         let health_checks_total = Family::<HealthCheckLabels, Counter>::default();
+        let pool_idle_connections = Family::<BackendLabels, Gauge>::default();
+        let pool_checkouts_total = Family::<PoolCheckoutLabels, Counter>::default();
+        let tls_handshakes_total = Family::<TlsHandshakeLabels, Counter>::default();
+        let grpc_requests_total = Family::<GrpcRequestLabels, Counter>::default();
+        let grpc_request_duration_seconds =
+            Family::<GrpcMethodLabels, Histogram>::new_with_constructor(|| {
+                Histogram::new(exponential_buckets(0.001, 2.5, 13))
+            });
+        let upgraded_duration_seconds =
+            Family::<ConnectionLabels, Histogram>::new_with_constructor(|| {
+                // Buckets: 1s, 2s, 4s, ... up to about 68 minutes
+                Histogram::new(exponential_buckets(1.0, 2.0, 13))
+            });
+        let upgraded_bytes_total = Family::<BytesLabels, Counter>::default();
+        let retries_total = Family::<RetryLabels, Counter>::default();
+        let passive_ejections_total = Family::<EjectionLabels, Counter>::default();
+        let outlier_ejections_total = Family::<OutlierEjectionLabels, Counter>::default();
+        let discovery_errors_total = Family::<DiscoveryErrorLabels, Counter>::default();
 
         // Register metrics
         registry.register(
@@ -147,6 +307,61 @@ impl MetricsCollector {
             "Total number of health checks performed",
             health_checks_total.clone(),
         );
+        registry.register(
+            "rustlb_pool_idle_connections",
+            "Number of idle pooled backend connections",
+            pool_idle_connections.clone(),
+        );
+        registry.register(
+            "rustlb_pool_checkouts",
+            "Total backend connection checkouts (reused or created)",
+            pool_checkouts_total.clone(),
+        );
+        registry.register(
+            "rustlb_tls_handshakes",
+            "Total TLS handshakes by certificate server name (success or failure)",
+            tls_handshakes_total.clone(),
+        );
+        registry.register(
+            "rustlb_grpc_requests",
+            "Total gRPC calls by method and status code",
+            grpc_requests_total.clone(),
+        );
+        registry.register(
+            "rustlb_grpc_request_duration_seconds",
+            "gRPC call duration in seconds, until the final status",
+            grpc_request_duration_seconds.clone(),
+        );
+        registry.register(
+            "rustlb_upgraded_duration_seconds",
+            "Duration of upgraded (e.g. WebSocket) connections in seconds",
+            upgraded_duration_seconds.clone(),
+        );
+        registry.register(
+            "rustlb_upgraded_bytes",
+            "Total bytes relayed over upgraded connections",
+            upgraded_bytes_total.clone(),
+        );
+        registry.register(
+            "rustlb_retries",
+            "Total retries of HTTP requests and TCP connects by reason",
+            retries_total.clone(),
+        );
+        registry.register(
+            "rustlb_passive_ejections",
+            "Total servers ejected by passive health checking, by triggering failure",
+            passive_ejections_total.clone(),
+        );
+        registry.register(
+            "rustlb_outlier_ejections",
+            "Total servers ejected by outlier detection, by outlying statistic",
+            outlier_ejections_total.clone(),
+        );
+        registry.register(
+            "rustlb_discovery_errors",
+            "Total failed DNS resolutions and rejected server list files, by backend",
+            discovery_errors_total.clone(),
+        );
 
         Self {
             inner: Arc::new(MetricsCollectorInner {
@@ -157,6 +372,18 @@ impl MetricsCollector {
                 bytes_total,
                 connections_total,
                 health_checks_total,
+                pool_idle_connections,
+                pool_checkouts_total,
+                tls_handshakes_total,
+                grpc_requests_total,
+                grpc_request_duration_seconds,
+                upgraded_duration_seconds,
+                upgraded_bytes_total,
+                retries_total,
+                passive_ejections_total,
+                outlier_ejections_total,
+                discovery_errors_total,
+                server_outcomes: DashMap::new(),
                 registry,
             }),
         }
@@ -168,10 +395,15 @@ impl MetricsCollector {
     }
 
     /// Record a completed request.
+    ///
+    /// `server` is the server that answered, or failed to answer, the
+    /// request. Its outcome is kept for outlier detection if the backend is
+    /// tracked with [`track_outcomes`](Self::track_outcomes).
     pub fn record_request(
         &self,
         frontend: &str,
         backend: &str,
+        server: Option<SocketAddr>,
         method: &str,
         status: u16,
         duration: std::time::Duration,
@@ -192,6 +424,67 @@ impl MetricsCollector {
             .request_duration_seconds
             .get_or_create(&conn_labels)
             .observe(duration.as_secs_f64());
+
+        if let Some(server) = server
+            && let Some(mut servers) = self.inner.server_outcomes.get_mut(backend)
+        {
+            servers.entry(server).or_default().record(status, duration);
+        }
+    }
+
+    /// Start keeping per-server request outcomes of a backend.
+    pub fn track_outcomes(&self, backend: &str) {
+        if !self.inner.server_outcomes.contains_key(backend) {
+            self.inner
+                .server_outcomes
+                .insert(backend.to_string(), HashMap::new());
+        }
+    }
+
+    /// Stop keeping per-server request outcomes of a backend.
+    pub fn untrack_outcomes(&self, backend: &str) {
+        self.inner.server_outcomes.remove(backend);
+    }
+
+    /// Take the per-server request outcomes of a tracked backend recorded
+    /// since the last call, leaving empty ones behind.
+    pub fn take_outcomes(&self, backend: &str) -> HashMap<SocketAddr, ServerOutcomes> {
+        self.inner
+            .server_outcomes
+            .get_mut(backend)
+            .map(|mut servers| std::mem::take(&mut *servers))
+            .unwrap_or_default()
+    }
+
+    /// Record a completed gRPC call.
+    ///
+    /// `method` is the full `/package.Service/Method` path and `code` the
+    /// canonical name of the call's gRPC status.
+    pub fn record_grpc_request(
+        &self,
+        frontend: &str,
+        backend: &str,
+        method: &str,
+        code: &str,
+        duration: std::time::Duration,
+    ) {
+        let labels = GrpcRequestLabels {
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+            method: method.to_string(),
+            code: code.to_string(),
+        };
+        self.inner.grpc_requests_total.get_or_create(&labels).inc();
+
+        let method_labels = GrpcMethodLabels {
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+            method: method.to_string(),
+        };
+        self.inner
+            .grpc_request_duration_seconds
+            .get_or_create(&method_labels)
+            .observe(duration.as_secs_f64());
     }
 
     /// Record a TCP proxy session completion.
@@ -236,6 +529,45 @@ impl MetricsCollector {
             .inc_by(bytes_to_client);
     }
 
+    /// Record the end of an upgraded (e.g. WebSocket) connection.
+    pub fn record_upgraded_session(
+        &self,
+        frontend: &str,
+        backend: &str,
+        bytes_to_backend: u64,
+        bytes_to_client: u64,
+        duration: std::time::Duration,
+    ) {
+        let conn_labels = ConnectionLabels {
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+        };
+        self.inner
+            .upgraded_duration_seconds
+            .get_or_create(&conn_labels)
+            .observe(duration.as_secs_f64());
+
+        let inbound_labels = BytesLabels {
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+            direction: Direction::Inbound,
+        };
+        self.inner
+            .upgraded_bytes_total
+            .get_or_create(&inbound_labels)
+            .inc_by(bytes_to_backend);
+
+        let outbound_labels = BytesLabels {
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+            direction: Direction::Outbound,
+        };
+        self.inner
+            .upgraded_bytes_total
+            .get_or_create(&outbound_labels)
+            .inc_by(bytes_to_client);
+    }
+
     /// Increment active connections.
     pub fn connection_opened(&self, frontend: &str, backend: &str) {
         let labels = ConnectionLabels {
@@ -255,6 +587,16 @@ impl MetricsCollector {
         self.inner.active_connections.get_or_create(&labels).dec();
     }
 
+    /// Count a connection as active until the returned guard is dropped.
+    pub fn track_connection(&self, frontend: &str, backend: &str) -> ActiveConnection {
+        self.connection_opened(frontend, backend);
+        ActiveConnection {
+            collector: self.clone(),
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+        }
+    }
+
     /// Update backend health status.
     pub fn set_backend_health(&self, backend: &str, server: SocketAddr, healthy: bool) {
         let labels = BackendLabels {
@@ -281,6 +623,116 @@ impl MetricsCollector {
         self.inner.health_checks_total.get_or_create(&labels).inc();
     }
 
+    /// Record a backend connection checkout from the pool.
+    pub fn record_pool_checkout(&self, backend: &str, server: SocketAddr, reused: bool) {
+        let labels = PoolCheckoutLabels {
+            backend: backend.to_string(),
+            server: server.to_string(),
+            result: if reused {
+                PoolCheckoutResult::Reused
+            } else {
+                PoolCheckoutResult::Created
+            },
+        };
+        self.inner.pool_checkouts_total.get_or_create(&labels).inc();
+    }
+
+    /// Record a TLS handshake on a frontend.
+    ///
+    /// `sni` should be the configured name of the certificate that served
+    /// the handshake rather than the client's raw SNI value, which is
+    /// unbounded.
+    pub fn record_tls_handshake(&self, frontend: &str, sni: &str, success: bool) {
+        let labels = TlsHandshakeLabels {
+            frontend: frontend.to_string(),
+            sni: sni.to_string(),
+            result: if success {
+                HandshakeResult::Success
+            } else {
+                HandshakeResult::Failure
+            },
+        };
+        self.inner.tls_handshakes_total.get_or_create(&labels).inc();
+    }
+
+    /// Record a request or TCP connect being retried after a failed attempt
+    /// on `server`.
+    pub fn record_retry(
+        &self,
+        frontend: &str,
+        backend: &str,
+        server: SocketAddr,
+        reason: FailureReason,
+    ) {
+        let labels = RetryLabels {
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+            server: server.to_string(),
+            reason,
+        };
+        self.inner.retries_total.get_or_create(&labels).inc();
+    }
+
+    /// Record a server being ejected by passive health checking.
+    pub fn record_passive_ejection(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        reason: FailureReason,
+    ) {
+        let labels = EjectionLabels {
+            backend: backend.to_string(),
+            server: server.to_string(),
+            reason,
+        };
+        self.inner
+            .passive_ejections_total
+            .get_or_create(&labels)
+            .inc();
+    }
+
+    /// Record a server being ejected by outlier detection.
+    pub fn record_outlier_ejection(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        reason: OutlierReason,
+    ) {
+        let labels = OutlierEjectionLabels {
+            backend: backend.to_string(),
+            server: server.to_string(),
+            reason,
+        };
+        self.inner
+            .outlier_ejections_total
+            .get_or_create(&labels)
+            .inc();
+    }
+
+    /// Record a failed attempt to discover the servers of a backend.
+    pub fn record_discovery_error(&self, backend: &str, source: DiscoverySource) {
+        let labels = DiscoveryErrorLabels {
+            backend: backend.to_string(),
+            source,
+        };
+        self.inner
+            .discovery_errors_total
+            .get_or_create(&labels)
+            .inc();
+    }
+
+    /// Update the number of idle pooled connections for a server.
+    pub fn set_pool_idle(&self, backend: &str, server: SocketAddr, idle: usize) {
+        let labels = BackendLabels {
+            backend: backend.to_string(),
+            server: server.to_string(),
+        };
+        self.inner
+            .pool_idle_connections
+            .get_or_create(&labels)
+            .set(idle as i64);
+    }
+
     /// Start timing a request. Returns a guard that records duration on drop.
     pub fn start_request_timer(&self, frontend: &str, backend: &str) -> RequestTimer {
         RequestTimer {
@@ -298,6 +750,19 @@ impl Default for MetricsCollector {
     }
 }
 
+/// Guard that counts a connection as active until dropped.
+pub struct ActiveConnection {
+    collector: MetricsCollector,
+    frontend: String,
+    backend: String,
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.collector.connection_closed(&self.frontend, &self.backend);
+    }
+}
+
 /// Timer guard that records request duration on drop.
 pub struct RequestTimer {
     collector: MetricsCollector,
@@ -318,6 +783,7 @@ impl RequestTimer {
         self.collector.record_request(
             &self.frontend,
             &self.backend,
+            None,
             method,
             status,
             duration,
@@ -349,6 +815,7 @@ mod tests {
         collector.record_request(
             "web",
             "api-servers",
+            None,
             "GET",
             200,
             std::time::Duration::from_millis(50),
@@ -356,6 +823,32 @@ mod tests {
         // Metrics should be recorded without panic
     }
 
+    #[test]
+    fn test_server_outcomes() {
+        let collector = MetricsCollector::new();
+        let server: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let duration = std::time::Duration::from_millis(5);
+
+        // Outcomes are only kept for tracked backends
+        collector.record_request("web", "api", Some(server), "GET", 200, duration);
+        assert!(collector.take_outcomes("api").is_empty());
+
+        collector.track_outcomes("api");
+        collector.record_request("web", "api", Some(server), "GET", 200, duration);
+        collector.record_request("web", "api", Some(server), "GET", 502, duration);
+        collector.record_request("web", "api", None, "GET", 503, duration);
+        let outcomes = collector.take_outcomes("api");
+        assert_eq!(outcomes[&server].requests(), 2);
+        assert_eq!(outcomes[&server].error_rate(), 0.5);
+
+        // Taking the outcomes starts a new window
+        assert!(collector.take_outcomes("api").is_empty());
+
+        collector.untrack_outcomes("api");
+        collector.record_request("web", "api", Some(server), "GET", 200, duration);
+        assert!(collector.take_outcomes("api").is_empty());
+    }
+
     #[test]
     fn test_connection_tracking() {
         let collector = MetricsCollector::new();
@@ -398,6 +891,114 @@ mod tests {
         // Session should be recorded without panic
     }
 
+    #[test]
+    fn test_pool_metrics() {
+        let collector = MetricsCollector::new();
+        let server: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+
+        collector.record_pool_checkout("api-servers", server, false);
+        collector.record_pool_checkout("api-servers", server, true);
+        collector.set_pool_idle("api-servers", server, 1);
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, collector.registry()).unwrap();
+        assert!(buffer.contains("rustlb_pool_checkouts_total"));
+        assert!(buffer.contains("rustlb_pool_idle_connections"));
+    }
+
+    #[test]
+    fn test_tls_handshake_metrics() {
+        let collector = MetricsCollector::new();
+
+        collector.record_tls_handshake("https", "*.example.com", true);
+        collector.record_tls_handshake("https", "default", false);
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, collector.registry()).unwrap();
+        assert!(buffer.contains(
+            r#"rustlb_tls_handshakes_total{frontend="https",sni="*.example.com",result="Success"} 1"#
+        ));
+        assert!(buffer.contains(
+            r#"rustlb_tls_handshakes_total{frontend="https",sni="default",result="Failure"} 1"#
+        ));
+    }
+
+    #[test]
+    fn test_grpc_metrics() {
+        let collector = MetricsCollector::new();
+
+        collector.record_grpc_request(
+            "grpc",
+            "users",
+            "/users.Users/Get",
+            "OK",
+            std::time::Duration::from_millis(5),
+        );
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, collector.registry()).unwrap();
+        assert!(buffer.contains(
+            r#"rustlb_grpc_requests_total{frontend="grpc",backend="users",method="/users.Users/Get",code="OK"} 1"#
+        ));
+        assert!(buffer.contains("rustlb_grpc_request_duration_seconds_count"));
+    }
+
+    #[test]
+    fn test_upgraded_session_metrics() {
+        let collector = MetricsCollector::new();
+        collector.record_upgraded_session(
+            "web",
+            "chat",
+            100,
+            2048,
+            std::time::Duration::from_secs(30),
+        );
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, collector.registry()).unwrap();
+        assert!(buffer.contains(
+            r#"rustlb_upgraded_bytes_total{frontend="web",backend="chat",direction="Outbound"} 2048"#
+        ));
+        assert!(buffer.contains("rustlb_upgraded_duration_seconds_count"));
+    }
+
+    #[test]
+    fn test_retry_metrics() {
+        let collector = MetricsCollector::new();
+        let server: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        collector.record_retry("web", "api", server, FailureReason::ConnectFailure);
+        collector.record_retry("web", "api", server, FailureReason::ConnectFailure);
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, collector.registry()).unwrap();
+        assert!(buffer.contains(
+            r#"rustlb_retries_total{frontend="web",backend="api",server="10.0.0.1:8080",reason="ConnectFailure"} 2"#
+        ));
+    }
+
+    #[test]
+    fn test_passive_ejection_metrics() {
+        let collector = MetricsCollector::new();
+        let server: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        collector.record_passive_ejection("api", server, FailureReason::Status);
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, collector.registry()).unwrap();
+        assert!(buffer.contains(
+            r#"rustlb_passive_ejections_total{backend="api",server="10.0.0.1:8080",reason="Status"} 1"#
+        ));
+    }
+
+    #[test]
+    fn test_discovery_error_metrics() {
+        let collector = MetricsCollector::new();
+        collector.record_discovery_error("api", DiscoverySource::File);
+
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, collector.registry()).unwrap();
+        assert!(buffer.contains(r#"rustlb_discovery_errors_total{backend="api",source="File"} 1"#));
+    }
+
     #[test]
     fn test_health_check_recording() {
         let collector = MetricsCollector::new();
@@ -1,7 +1,12 @@
 //! Metrics collection and exposition.
 
 mod collector;
+mod outcomes;
 mod server;
 
-pub use collector::{MetricsCollector, RequestTimer};
+pub use collector::{
+    ActiveConnection, DiscoverySource, FailureReason, MetricsCollector, OutlierReason,
+    RequestTimer,
+};
+pub use outcomes::ServerOutcomes;
 pub use server::MetricsServer;
@@ -0,0 +1,97 @@
+//! Per-server request outcomes.
+//!
+//! Collected alongside the request metrics for backends under outlier
+//! detection, which periodically takes and analyses them.
+
+use std::time::Duration;
+
+/// Latency samples kept per server, the most recent ones winning.
+const MAX_LATENCY_SAMPLES: usize = 1024;
+
+/// Outcomes of the requests to one server since they were last taken.
+#[derive(Debug, Default, Clone)]
+pub struct ServerOutcomes {
+    /// Requests answered, by the server or on its behalf.
+    requests: u64,
+    /// Requests answered with a 5xx status.
+    errors: u64,
+    /// Recent request latencies, used as a ring buffer once full.
+    latencies: Vec<Duration>,
+}
+
+impl ServerOutcomes {
+    /// Record the outcome of a request.
+    pub fn record(&mut self, status: u16, duration: Duration) {
+        if self.latencies.len() < MAX_LATENCY_SAMPLES {
+            self.latencies.push(duration);
+        } else {
+            let index = (self.requests % MAX_LATENCY_SAMPLES as u64) as usize;
+            self.latencies[index] = duration;
+        }
+        self.requests += 1;
+        if status >= 500 {
+            self.errors += 1;
+        }
+    }
+
+    /// Number of requests recorded.
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Share of the requests that failed, between 0 and 1.
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+
+    /// 99th percentile of the recorded latencies.
+    pub fn p99_latency(&self) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        let rank = (latencies.len() * 99).div_ceil(100);
+        latencies[rank.saturating_sub(1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_rate() {
+        let mut outcomes = ServerOutcomes::default();
+        assert_eq!(outcomes.error_rate(), 0.0);
+
+        outcomes.record(200, Duration::from_millis(5));
+        outcomes.record(404, Duration::from_millis(5));
+        outcomes.record(502, Duration::from_millis(5));
+        outcomes.record(503, Duration::from_millis(5));
+        assert_eq!(outcomes.requests(), 4);
+        assert_eq!(outcomes.error_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_p99_latency() {
+        let mut outcomes = ServerOutcomes::default();
+        assert_eq!(outcomes.p99_latency(), Duration::ZERO);
+
+        for ms in 1..=100 {
+            outcomes.record(200, Duration::from_millis(ms));
+        }
+        assert_eq!(outcomes.p99_latency(), Duration::from_millis(99));
+
+        // Only the most recent samples are kept
+        for _ in 0..MAX_LATENCY_SAMPLES {
+            outcomes.record(200, Duration::from_millis(1));
+        }
+        assert_eq!(outcomes.p99_latency(), Duration::from_millis(1));
+        assert_eq!(outcomes.requests(), 100 + MAX_LATENCY_SAMPLES as u64);
+    }
+}
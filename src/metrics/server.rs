@@ -1,8 +1,11 @@
 //! Prometheus metrics HTTP server.
 //!
-//! Serves metrics on a configurable HTTP endpoint.
+//! Serves metrics on a configurable HTTP endpoint, liveness and readiness
+//! probes, and the admin API under `/admin/` when it is enabled.
 
+use crate::admin::AdminApi;
 use crate::metrics::MetricsCollector;
+use crate::AppState;
 use bytes::Bytes;
 use http_body_util::Full;
 use hyper::server::conn::http1;
@@ -25,6 +28,12 @@ pub struct MetricsServer {
     path: String,
     /// Metrics collector.
     collector: MetricsCollector,
+    /// Socket to serve on instead of binding `address`.
+    listener: Option<Arc<TcpListener>>,
+    /// Admin API served under `/admin/`.
+    admin: Option<Arc<AdminApi>>,
+    /// State the readiness probe reports on.
+    state: Option<AppState>,
 }
 
 impl MetricsServer {
@@ -34,23 +43,50 @@ impl MetricsServer {
             address,
             path,
             collector,
+            listener: None,
+            admin: None,
+            state: None,
         }
     }
 
+    /// Serve the readiness probe on `/readyz`, reporting on `state`.
+    pub fn with_state(mut self, state: AppState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Serve the admin API under `/admin/` while it is enabled.
+    pub fn with_admin(mut self, admin: AdminApi) -> Self {
+        self.admin = Some(Arc::new(admin));
+        self
+    }
+
+    /// Serve on an already bound socket, such as one inherited from the
+    /// previous process on a binary upgrade.
+    pub fn with_listener(mut self, listener: Arc<TcpListener>) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
     /// Run the metrics server.
     pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
-        let listener = match TcpListener::bind(self.address).await {
-            Ok(l) => l,
-            Err(e) => {
-                error!(error = %e, address = %self.address, "failed to bind metrics server");
-                return;
-            }
+        let listener = match self.listener {
+            Some(listener) => listener,
+            None => match TcpListener::bind(self.address).await {
+                Ok(l) => Arc::new(l),
+                Err(e) => {
+                    error!(error = %e, address = %self.address, "failed to bind metrics server");
+                    return;
+                }
+            },
         };
 
         info!(address = %self.address, path = %self.path, "metrics server started");
 
         let collector = Arc::new(self.collector);
         let path = Arc::new(self.path);
+        let admin = self.admin;
+        let state = self.state.map(Arc::new);
 
         loop {
             tokio::select! {
@@ -59,14 +95,25 @@ impl MetricsServer {
                         Ok((stream, _addr)) => {
                             let collector = Arc::clone(&collector);
                             let path = Arc::clone(&path);
+                            let admin = admin.clone();
+                            let state = state.clone();
 
                             tokio::spawn(async move {
                                 let io = TokioIo::new(stream);
                                 let service = service_fn(move |req| {
                                     let collector = Arc::clone(&collector);
                                     let path = Arc::clone(&path);
+                                    let admin = admin.clone();
+                                    let state = state.clone();
                                     async move {
-                                        handle_request(req, &collector, &path).await
+                                        handle_request(
+                                            req,
+                                            &collector,
+                                            &path,
+                                            admin.as_deref(),
+                                            state.as_deref(),
+                                        )
+                                        .await
                                     }
                                 });
 
@@ -98,12 +145,20 @@ async fn handle_request(
     req: Request<hyper::body::Incoming>,
     collector: &MetricsCollector,
     metrics_path: &str,
+    admin: Option<&AdminApi>,
+    state: Option<&AppState>,
 ) -> Result<Response<Full<Bytes>>, Infallible> {
     let path = req.uri().path();
     let method = req.method();
 
     debug!(path = %path, method = %method, "metrics request");
 
+    if let Some(admin) = admin
+        && (path == "/admin" || path.starts_with("/admin/"))
+    {
+        return Ok(admin.handle(req).await);
+    }
+
     // Only handle GET requests to the metrics path
     if method != Method::GET {
         return Ok(Response::builder()
@@ -128,18 +183,43 @@ async fn handle_request(
             .header("content-type", "text/plain; version=0.0.4; charset=utf-8")
             .body(Full::new(Bytes::from(buffer)))
             .unwrap())
-    } else if path == "/health" || path == "/healthz" {
-        // Health check endpoint
+    } else if path == "/livez" || path == "/health" || path == "/healthz" {
+        // Liveness: answering at all means the process is alive
         Ok(Response::builder()
             .status(StatusCode::OK)
             .body(Full::new(Bytes::from("OK\n")))
             .unwrap())
+    } else if let Some(state) = state
+        && path == "/readyz"
+    {
+        let config = state.config();
+        let report = state
+            .readiness()
+            .report(state.router(), &config.global.readiness.require_healthy);
+        let status = if report.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let mut body = serde_json::to_vec_pretty(&report).unwrap_or_default();
+        body.push(b'\n');
+        Ok(Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .unwrap())
     } else if path == "/" {
         // Root path - show simple info
-        let body = format!(
-            "rustlb metrics server\n\nEndpoints:\n  {} - Prometheus metrics\n  /health - Health check\n",
+        let mut body = format!(
+            "rustlb metrics server\n\nEndpoints:\n  {} - Prometheus metrics\n  /livez - Liveness probe\n",
             metrics_path
         );
+        if state.is_some() {
+            body.push_str("  /readyz - Readiness probe\n");
+        }
+        if admin.is_some_and(|admin| admin.enabled()) {
+            body.push_str("  /admin/ - Admin API\n");
+        }
         Ok(Response::builder()
             .status(StatusCode::OK)
             .header("content-type", "text/plain")
@@ -174,7 +254,7 @@ mod tests {
         let collector = MetricsCollector::new();
 
         // Record some metrics
-        collector.record_request("web", "api", "GET", 200, std::time::Duration::from_millis(10));
+        collector.record_request("web", "api", None, "GET", 200, std::time::Duration::from_millis(10));
         collector.connection_opened("web", "api");
 
         // Encode metrics
@@ -0,0 +1,93 @@
+//! Response body wrappers.
+
+use hyper::body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+pin_project! {
+    /// Body that holds a guard until the body has been fully streamed.
+    ///
+    /// Used to keep per-request state (such as an active backend connection
+    /// count) alive for as long as the response is being sent to the client.
+    pub struct GuardedBody<B, G> {
+        #[pin]
+        inner: B,
+        guard: Option<G>,
+    }
+}
+
+impl<B, G> GuardedBody<B, G> {
+    /// Wrap a body, dropping `guard` when the body ends or is dropped.
+    pub fn new(inner: B, guard: G) -> Self {
+        Self {
+            inner,
+            guard: Some(guard),
+        }
+    }
+}
+
+impl<B: Body, G> Body for GuardedBody<B, G> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let result = this.inner.poll_frame(cx);
+        if let Poll::Ready(None) | Poll::Ready(Some(Err(_))) = result {
+            this.guard.take();
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct Flag(Arc<AtomicBool>);
+
+    impl Drop for Flag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_guard_released_at_end_of_body() {
+        let released = Arc::new(AtomicBool::new(false));
+        let mut body =
+            GuardedBody::new(Full::new(Bytes::from("hello")), Flag(Arc::clone(&released)));
+
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), Bytes::from("hello"));
+        assert!(!released.load(Ordering::SeqCst));
+
+        assert!(body.frame().await.is_none());
+        assert!(released.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_guard_released_on_drop() {
+        let released = Arc::new(AtomicBool::new(false));
+        let body = GuardedBody::new(Full::new(Bytes::from("hello")), Flag(Arc::clone(&released)));
+
+        drop(body);
+        assert!(released.load(Ordering::SeqCst));
+    }
+}
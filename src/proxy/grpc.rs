@@ -0,0 +1,378 @@
+//! gRPC support for HTTP frontends in gRPC mode.
+//!
+//! gRPC calls are recognised by their `application/grpc` content type. Calls
+//! that cannot be proxied are answered with a gRPC status instead of an HTTP
+//! error page, and every call is recorded in per-method metrics once its
+//! final status is known.
+
+use crate::metrics::MetricsCollector;
+use bytes::Bytes;
+use http_body_util::{combinators::BoxBody, BodyExt, Empty};
+use hyper::body::{Body, Frame, SizeHint};
+use hyper::header::CONTENT_TYPE;
+use hyper::{HeaderMap, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Header carrying the status code of a gRPC call.
+const GRPC_STATUS: &str = "grpc-status";
+
+/// Header carrying the error message of a gRPC call.
+const GRPC_MESSAGE: &str = "grpc-message";
+
+/// gRPC status code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrpcCode {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+impl GrpcCode {
+    /// Look up a status code by number; unknown numbers map to `Unknown`.
+    pub fn from_u32(code: u32) -> Self {
+        match code {
+            0 => Self::Ok,
+            1 => Self::Cancelled,
+            3 => Self::InvalidArgument,
+            4 => Self::DeadlineExceeded,
+            5 => Self::NotFound,
+            6 => Self::AlreadyExists,
+            7 => Self::PermissionDenied,
+            8 => Self::ResourceExhausted,
+            9 => Self::FailedPrecondition,
+            10 => Self::Aborted,
+            11 => Self::OutOfRange,
+            12 => Self::Unimplemented,
+            13 => Self::Internal,
+            14 => Self::Unavailable,
+            15 => Self::DataLoss,
+            16 => Self::Unauthenticated,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Read the `grpc-status` header of a response or its trailers.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let status = headers.get(GRPC_STATUS)?.to_str().ok()?;
+        Some(status.parse().map_or(Self::Unknown, Self::from_u32))
+    }
+
+    /// Status code for an HTTP error, as specified by the gRPC HTTP/2
+    /// protocol for responses that carry no `grpc-status`.
+    pub fn from_http_status(status: StatusCode) -> Self {
+        match status.as_u16() {
+            400 => Self::Internal,
+            401 => Self::Unauthenticated,
+            403 => Self::PermissionDenied,
+            404 => Self::Unimplemented,
+            429 | 502 | 503 | 504 => Self::Unavailable,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Canonical name of the status code, such as `UNAVAILABLE`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Cancelled => "CANCELLED",
+            Self::Unknown => "UNKNOWN",
+            Self::InvalidArgument => "INVALID_ARGUMENT",
+            Self::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            Self::NotFound => "NOT_FOUND",
+            Self::AlreadyExists => "ALREADY_EXISTS",
+            Self::PermissionDenied => "PERMISSION_DENIED",
+            Self::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Self::FailedPrecondition => "FAILED_PRECONDITION",
+            Self::Aborted => "ABORTED",
+            Self::OutOfRange => "OUT_OF_RANGE",
+            Self::Unimplemented => "UNIMPLEMENTED",
+            Self::Internal => "INTERNAL",
+            Self::Unavailable => "UNAVAILABLE",
+            Self::DataLoss => "DATA_LOSS",
+            Self::Unauthenticated => "UNAUTHENTICATED",
+        }
+    }
+}
+
+impl fmt::Display for GrpcCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.as_str(), *self as u32)
+    }
+}
+
+/// The `/package.Service/Method` path of a gRPC call.
+///
+/// Returns `None` for requests that are not gRPC calls.
+pub fn grpc_method<B>(req: &Request<B>) -> Option<String> {
+    let content_type = req.headers().get(CONTENT_TYPE)?.to_str().ok()?;
+    if content_type != "application/grpc" && !content_type.starts_with("application/grpc+") {
+        return None;
+    }
+
+    let path = req.uri().path();
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    if service.is_empty() || method.is_empty() || method.contains('/') {
+        return None;
+    }
+    Some(path.to_string())
+}
+
+/// Create a Trailers-Only gRPC error response.
+///
+/// The status is sent in the response headers of an otherwise empty
+/// response, which gRPC clients treat as the call's trailers.
+pub fn grpc_error_response(
+    code: GrpcCode,
+    message: &str,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let body = Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/grpc")
+        .header(GRPC_STATUS, code as u32)
+        .header(GRPC_MESSAGE, encode_message(message))
+        .body(body)
+        .unwrap()
+}
+
+/// Percent-encode a `grpc-message` value: everything outside printable
+/// ASCII, and `%` itself.
+fn encode_message(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (0x20..=0x7e).contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// A proxied gRPC call whose outcome is recorded once.
+///
+/// A call that is dropped before its status is known was abandoned by the
+/// client and is recorded as `CANCELLED`.
+pub struct GrpcCall {
+    metrics: MetricsCollector,
+    frontend: String,
+    backend: String,
+    method: String,
+    start: Instant,
+    done: bool,
+}
+
+impl GrpcCall {
+    /// Start tracking a call; `start` is when the request arrived.
+    pub fn new(
+        metrics: MetricsCollector,
+        frontend: &str,
+        backend: &str,
+        method: &str,
+        start: Instant,
+    ) -> Self {
+        Self {
+            metrics,
+            frontend: frontend.to_string(),
+            backend: backend.to_string(),
+            method: method.to_string(),
+            start,
+            done: false,
+        }
+    }
+
+    /// Record the call's final status, unless it has been recorded already.
+    pub fn finish(&mut self, code: GrpcCode) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        self.metrics.record_grpc_request(
+            &self.frontend,
+            &self.backend,
+            &self.method,
+            code.as_str(),
+            self.start.elapsed(),
+        );
+    }
+}
+
+impl Drop for GrpcCall {
+    fn drop(&mut self) {
+        self.finish(GrpcCode::Cancelled);
+    }
+}
+
+pin_project! {
+    /// Response body that records the gRPC status found in its trailers.
+    ///
+    /// A body that ends without a `grpc-status` trailer, or with an error,
+    /// is recorded as `UNKNOWN`.
+    pub struct GrpcBody<B> {
+        #[pin]
+        inner: B,
+        call: GrpcCall,
+    }
+}
+
+impl<B> GrpcBody<B> {
+    /// Wrap the body of a gRPC response.
+    pub fn new(inner: B, call: GrpcCall) -> Self {
+        Self { inner, call }
+    }
+}
+
+impl<B: Body> Body for GrpcBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let result = this.inner.poll_frame(cx);
+        match result {
+            Poll::Ready(Some(Ok(ref frame))) => {
+                if let Some(trailers) = frame.trailers_ref() {
+                    let code = GrpcCode::from_headers(trailers).unwrap_or(GrpcCode::Unknown);
+                    this.call.finish(code);
+                }
+            }
+            Poll::Ready(None) | Poll::Ready(Some(Err(_))) => this.call.finish(GrpcCode::Unknown),
+            Poll::Pending => {}
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::StreamBody;
+
+    fn grpc_request(path: &str, content_type: &str) -> Request<()> {
+        Request::builder()
+            .method("POST")
+            .uri(path)
+            .header(CONTENT_TYPE, content_type)
+            .body(())
+            .unwrap()
+    }
+
+    fn encoded_metrics(metrics: &MetricsCollector) -> String {
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, metrics.registry()).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_grpc_method() {
+        let req = grpc_request("/helloworld.Greeter/SayHello", "application/grpc");
+        assert_eq!(
+            grpc_method(&req).as_deref(),
+            Some("/helloworld.Greeter/SayHello")
+        );
+
+        let req = grpc_request("/helloworld.Greeter/SayHello", "application/grpc+proto");
+        assert!(grpc_method(&req).is_some());
+
+        assert!(grpc_method(&grpc_request("/helloworld.Greeter/SayHello", "text/html")).is_none());
+        assert!(grpc_method(&grpc_request("/index.html", "application/grpc")).is_none());
+        assert!(grpc_method(&grpc_request("/a/b/c", "application/grpc")).is_none());
+    }
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(
+            GrpcCode::from_http_status(StatusCode::BAD_GATEWAY),
+            GrpcCode::Unavailable
+        );
+        assert_eq!(
+            GrpcCode::from_http_status(StatusCode::NOT_FOUND),
+            GrpcCode::Unimplemented
+        );
+        assert_eq!(GrpcCode::from_u32(99), GrpcCode::Unknown);
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(GrpcCode::from_headers(&headers), None);
+        headers.insert(GRPC_STATUS, "5".parse().unwrap());
+        assert_eq!(GrpcCode::from_headers(&headers), Some(GrpcCode::NotFound));
+    }
+
+    #[test]
+    fn test_grpc_error_response() {
+        let resp = grpc_error_response(GrpcCode::Unavailable, "no backend: 100% down");
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[CONTENT_TYPE], "application/grpc");
+        assert_eq!(resp.headers()[GRPC_STATUS], "14");
+        assert_eq!(resp.headers()[GRPC_MESSAGE], "no backend: 100%25 down");
+        assert!(resp.body().is_end_stream());
+    }
+
+    #[tokio::test]
+    async fn test_grpc_body_records_trailer_status() {
+        let metrics = MetricsCollector::new();
+        let mut trailers = HeaderMap::new();
+        trailers.insert(GRPC_STATUS, "5".parse().unwrap());
+        let frames: Vec<Result<Frame<Bytes>, hyper::Error>> = vec![
+            Ok(Frame::data(Bytes::from_static(b"\0\0\0\0\0"))),
+            Ok(Frame::trailers(trailers)),
+        ];
+        let call = GrpcCall::new(
+            metrics.clone(),
+            "grpc",
+            "users",
+            "/users.Users/Get",
+            Instant::now(),
+        );
+        let body = GrpcBody::new(StreamBody::new(futures::stream::iter(frames)), call);
+
+        body.collect().await.unwrap();
+        assert!(encoded_metrics(&metrics).contains(
+            r#"rustlb_grpc_requests_total{frontend="grpc",backend="users",method="/users.Users/Get",code="NOT_FOUND"} 1"#
+        ));
+    }
+
+    #[test]
+    fn test_abandoned_call_is_cancelled() {
+        let metrics = MetricsCollector::new();
+        drop(GrpcCall::new(
+            metrics.clone(),
+            "grpc",
+            "users",
+            "/users.Users/Get",
+            Instant::now(),
+        ));
+        assert!(encoded_metrics(&metrics).contains(r#"code="CANCELLED"} 1"#));
+    }
+}
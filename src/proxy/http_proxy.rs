@@ -1,17 +1,38 @@
 //! HTTP proxy implementation.
 //!
-//! Provides HTTP/1.1 proxying with header manipulation.
+//! Provides HTTP proxying with header manipulation, to plaintext or TLS
+//! backends. Requests are translated between HTTP/1.1 and HTTP/2 when the
+//! client and backend speak different versions, and failed requests can be
+//! retried on other servers of the backend. In gRPC mode, calls get
+//! gRPC status codes and per-method metrics. Upgrade requests (such as
+//! WebSockets) are tunnelled to the backend once it switches protocols.
 
-use crate::metrics::MetricsCollector;
+use crate::backend::{BackendRouter, ConnectionGuard, DrainSignal};
+use crate::config::{
+    BackendProtocol, ConnectionPoolConfig, PassiveHealthConfig, RetryCondition, RetryConfig,
+};
+use crate::health::PassiveHealthTracker;
+use crate::metrics::{FailureReason, MetricsCollector};
+use crate::proxy::body::GuardedBody;
+use crate::proxy::grpc::{grpc_error_response, grpc_method, GrpcBody, GrpcCall, GrpcCode};
+use crate::proxy::pool::{ConnectionPool, PooledConnection, RequestBody};
+use crate::proxy::upgrade::tunnel;
+use crate::tls::ClientTls;
+use crate::util::ConnectionTracker;
 use bytes::Bytes;
 use http_body_util::{combinators::BoxBody, BodyExt, Full};
-use hyper::body::Incoming;
-use hyper::{Request, Response, StatusCode};
-use hyper_util::rt::TokioIo;
+use hyper::body::{Body, Incoming};
+use hyper::header::HOST;
+use hyper::http::request;
+use hyper::http::uri::{Authority, PathAndQuery, Scheme};
+use hyper::{Method, Request, Response, StatusCode, Uri, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 use tracing::{debug, error, info, instrument, warn};
 
@@ -24,6 +45,10 @@ pub struct HttpProxyConfig {
     pub response_headers: HashMap<String, String>,
     /// Connect timeout for backend.
     pub connect_timeout: Duration,
+    /// Idle timeout of upgraded connections.
+    pub upgrade_idle_timeout: Duration,
+    /// Retry policy for failed requests, if any.
+    pub retry: Option<RetryConfig>,
 }
 
 impl Default for HttpProxyConfig {
@@ -32,6 +57,8 @@ impl Default for HttpProxyConfig {
             request_headers: HashMap::new(),
             response_headers: HashMap::new(),
             connect_timeout: Duration::from_secs(10),
+            upgrade_idle_timeout: Duration::from_secs(300),
+            retry: None,
         }
     }
 }
@@ -53,6 +80,22 @@ pub struct ProxyContext {
     pub metrics: MetricsCollector,
     /// Connection-level request ID.
     pub connection_request_id: String,
+    /// Keep-alive pool of backend connections.
+    pub pool: ConnectionPool,
+    /// Pool settings for the backend.
+    pub pool_config: ConnectionPoolConfig,
+    /// TLS settings for connecting to the backend, if it speaks HTTPS.
+    pub tls: Option<Arc<ClientTls>>,
+    /// HTTP version spoken to the backend.
+    pub protocol: BackendProtocol,
+    /// Whether the frontend is in gRPC mode.
+    pub grpc: bool,
+    /// Passive health tracking of backend servers.
+    pub passive_health: PassiveHealthTracker,
+    /// Passive health settings for the backend.
+    pub passive_health_config: PassiveHealthConfig,
+    /// Client connections, which upgraded connections join.
+    pub connections: ConnectionTracker,
 }
 
 /// HTTP proxy error.
@@ -69,6 +112,12 @@ pub enum HttpProxyError {
 }
 
 /// Proxy a single HTTP request to the backend.
+///
+/// `guard` tracks the request on the selected server, `ctx.backend_addr`.
+/// With a retry policy, failed attempts are retried on other servers of the
+/// backend picked by `router`; the guard moves to each new server. Bodies of
+/// requests that may be retried after being sent are buffered if small
+/// enough, otherwise only connection failures are retried.
 #[instrument(skip_all, fields(
     method = %req.method(),
     uri = %req.uri(),
@@ -76,151 +125,700 @@ pub enum HttpProxyError {
     backend = %ctx.backend_addr
 ))]
 pub async fn proxy_request(
-    mut req: Request<Incoming>,
-    ctx: ProxyContext,
+    req: Request<Incoming>,
+    mut ctx: ProxyContext,
+    mut guard: ConnectionGuard,
+    router: &Arc<BackendRouter>,
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Infallible> {
     let start_time = Instant::now();
     let method = req.method().to_string();
     let uri = req.uri().to_string();
+    let client_version = req.version();
+    let grpc_method = if ctx.grpc { grpc_method(&req) } else { None };
 
     debug!(
         connection_id = %ctx.connection_request_id,
         "proxying HTTP request"
     );
 
-    // Add request headers
-    add_request_headers(&mut req, &ctx);
+    let policy = ctx.config.retry.clone();
+    let (head, body) = req.into_parts();
 
-    // Connect to backend
-    let backend_stream = match TcpStream::connect(ctx.backend_addr).await {
-        Ok(stream) => {
-            let _ = stream.set_nodelay(true);
-            stream
-        }
+    // Keep the body if it may have to be sent to another server
+    let replay = policy.as_ref().is_some_and(|policy| {
+        policy.max_attempts > 1 && (policy.retry_non_idempotent || head.method.is_idempotent())
+    });
+    let mut body = match ReplayBody::new(body, replay).await {
+        Ok(body) => body,
         Err(e) => {
-            error!(
+            warn!(
                 connection_id = %ctx.connection_request_id,
                 error = %e,
-                "failed to connect to backend"
+                "failed to read request body"
             );
-            let duration = start_time.elapsed();
-            ctx.metrics.record_request(
-                &ctx.frontend_name,
-                &ctx.backend_name,
+            return Ok(reject_request(
+                &ctx,
+                None,
                 &method,
-                502,
-                duration,
-            );
-            return Ok(error_response(
-                StatusCode::BAD_GATEWAY,
-                "Failed to connect to backend",
+                grpc_method.as_deref(),
+                StatusCode::BAD_REQUEST,
+                "Failed to read request body",
+                start_time.elapsed(),
             ));
         }
     };
 
-    let io = TokioIo::new(backend_stream);
+    let deadline = policy
+        .as_ref()
+        .and_then(|policy| policy.budget)
+        .map(|budget| start_time + budget);
+    let mut tried = Vec::new();
+    let mut attempt = 1;
+
+    let backend_response = loop {
+        let result = match attempt_limit(policy.as_ref(), deadline) {
+            Some(limit) => tokio::time::timeout(
+                limit,
+                send_attempt(&ctx, &head, &mut body, guard.drain_signal()),
+            )
+            .await
+            .unwrap_or(Err(AttemptError::Timeout)),
+            None => send_attempt(&ctx, &head, &mut body, guard.drain_signal()).await,
+        };
+        match result {
+            Ok(ref resp) => ctx.passive_health.record_status(
+                &ctx.backend_name,
+                ctx.backend_addr,
+                &ctx.passive_health_config,
+                resp.status().as_u16(),
+            ),
+            Err(ref e) => ctx.passive_health.record_failure(
+                &ctx.backend_name,
+                ctx.backend_addr,
+                &ctx.passive_health_config,
+                e.reason(),
+            ),
+        }
 
-    // Create HTTP client connection
-    let (mut sender, conn) = match hyper::client::conn::http1::handshake(io).await {
-        Ok(result) => result,
+        let reason = match result {
+            Ok(ref resp)
+                if policy.as_ref().is_some_and(|policy| {
+                    policy.retry_on_status.contains(&resp.status().as_u16())
+                }) =>
+            {
+                FailureReason::Status
+            }
+            Ok(_) => break result,
+            Err(ref e) => e.reason(),
+        };
+
+        let retryable = policy.as_ref().is_some_and(|policy| {
+            attempt < policy.max_attempts
+                && deadline.is_none_or(|deadline| Instant::now() < deadline)
+                && body.is_replayable()
+                && is_retryable(policy, reason, &head.method)
+        });
+        let next = if retryable {
+            tried.push(ctx.backend_addr);
+            router.select_excluding(&ctx.backend_name, Some(ctx.client_addr), &tried)
+        } else {
+            None
+        };
+        let Some(next) = next else {
+            break result;
+        };
+
+        warn!(
+            connection_id = %ctx.connection_request_id,
+            server = %ctx.backend_addr,
+            next = %next,
+            attempt,
+            reason = ?reason,
+            "retrying request on another server"
+        );
+        ctx.metrics.record_retry(
+            &ctx.frontend_name,
+            &ctx.backend_name,
+            ctx.backend_addr,
+            reason,
+        );
+        guard = router.track(&ctx.backend_name, next);
+        ctx.backend_addr = next;
+        attempt += 1;
+    };
+
+    let backend_response = match backend_response {
+        Ok(resp) => resp,
         Err(e) => {
-            error!(
+            let (status, message) = e.response();
+            return Ok(reject_request(
+                &ctx,
+                Some(ctx.backend_addr),
+                &method,
+                grpc_method.as_deref(),
+                status,
+                message,
+                start_time.elapsed(),
+            ));
+        }
+    };
+
+    // Convert the response, answering in the client's HTTP version
+    let (mut parts, body) = backend_response.into_parts();
+    let status_code = parts.status.as_u16();
+    parts.version = client_version;
+
+    // A gRPC call's status is in the headers of a Trailers-Only response,
+    // otherwise in the trailers at the end of the body
+    let mut call = grpc_method.map(|grpc_method| {
+        GrpcCall::new(
+            ctx.metrics.clone(),
+            &ctx.frontend_name,
+            &ctx.backend_name,
+            &grpc_method,
+            start_time,
+        )
+    });
+    if let Some(ref mut grpc_call) = call {
+        if let Some(code) = GrpcCode::from_headers(&parts.headers) {
+            grpc_call.finish(code);
+        } else if parts.status != StatusCode::OK {
+            // Not a gRPC response, such as an error page from a proxy
+            warn!(
                 connection_id = %ctx.connection_request_id,
-                error = %e,
-                "backend handshake failed"
+                status = status_code,
+                "backend answered gRPC call without a gRPC status"
             );
-            let duration = start_time.elapsed();
+            let code = GrpcCode::from_http_status(parts.status);
+            grpc_call.finish(code);
             ctx.metrics.record_request(
                 &ctx.frontend_name,
                 &ctx.backend_name,
+                Some(ctx.backend_addr),
                 &method,
-                502,
-                duration,
+                status_code,
+                start_time.elapsed(),
             );
-            return Ok(error_response(
-                StatusCode::BAD_GATEWAY,
-                "Backend handshake failed",
+            return Ok(grpc_error_response(
+                code,
+                &format!("backend responded with HTTP status {}", status_code),
             ));
         }
+    }
+
+    // Add response headers
+    add_response_headers(&mut parts.headers, &ctx);
+
+    // Build the response with boxed body, counting the request as active on
+    // the server until the body is done
+    let boxed_body = match call {
+        Some(grpc_call) => GrpcBody::new(body, grpc_call).boxed(),
+        None => body.map_err(|e| e).boxed(),
     };
+    let response = Response::from_parts(parts, GuardedBody::new(boxed_body, guard).boxed());
 
-    // Spawn connection driver
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            warn!(error = %e, "backend connection error");
+    // Record metrics
+    let duration = start_time.elapsed();
+    ctx.metrics.record_request(
+        &ctx.frontend_name,
+        &ctx.backend_name,
+        Some(ctx.backend_addr),
+        &method,
+        status_code,
+        duration,
+    );
+
+    info!(
+        connection_id = %ctx.connection_request_id,
+        method = %method,
+        uri = %uri,
+        status = status_code,
+        attempts = attempt,
+        duration_ms = duration.as_millis(),
+        "proxied request completed"
+    );
+
+    Ok(response)
+}
+
+/// Largest request body that is buffered so it can be sent again on retry.
+const MAX_REPLAY_BODY: u64 = 64 * 1024;
+
+/// Request body, kept so that it can be sent to another server.
+enum ReplayBody {
+    /// Fully read body, sent again on every attempt.
+    Buffered(Bytes),
+    /// Streamed body, which can only be sent once.
+    Streaming(Option<Incoming>),
+}
+
+impl ReplayBody {
+    /// Read the body into memory if it may be replayed and is small enough.
+    async fn new(body: Incoming, replay: bool) -> Result<Self, hyper::Error> {
+        if body.is_end_stream() {
+            return Ok(ReplayBody::Buffered(Bytes::new()));
         }
-    });
+        let small = body
+            .size_hint()
+            .upper()
+            .is_some_and(|len| len <= MAX_REPLAY_BODY);
+        if replay && small {
+            Ok(ReplayBody::Buffered(body.collect().await?.to_bytes()))
+        } else {
+            Ok(ReplayBody::Streaming(Some(body)))
+        }
+    }
 
-    // Modify the request URI to be relative (required for proxying)
-    let req_uri = req.uri().clone();
-    let path_and_query = req_uri
-        .path_and_query()
-        .map(|pq| pq.as_str())
-        .unwrap_or("/");
+    /// Body for the next attempt, or None if the streamed body was sent.
+    fn take(&mut self) -> Option<RequestBody> {
+        match self {
+            ReplayBody::Buffered(bytes) => Some(
+                Full::new(bytes.clone())
+                    .map_err(|never| match never {})
+                    .boxed(),
+            ),
+            ReplayBody::Streaming(body) => body.take().map(|body| body.boxed()),
+        }
+    }
+
+    /// Whether the body can still be sent.
+    fn is_replayable(&self) -> bool {
+        !matches!(self, ReplayBody::Streaming(None))
+    }
+}
 
-    *req.uri_mut() = path_and_query.parse().unwrap_or_else(|_| "/".parse().unwrap());
+/// A failed attempt to proxy a request.
+#[derive(Debug)]
+enum AttemptError {
+    /// Connecting to the server failed; carries the client-facing message.
+    Connect(&'static str),
+    /// The connection failed before a response arrived.
+    Reset,
+    /// The attempt exceeded its time limit.
+    Timeout,
+}
 
-    // Send request to backend
-    let backend_response = match sender.send_request(req).await {
-        Ok(resp) => resp,
+impl AttemptError {
+    fn reason(&self) -> FailureReason {
+        match self {
+            AttemptError::Connect(_) => FailureReason::ConnectFailure,
+            AttemptError::Reset => FailureReason::Reset,
+            AttemptError::Timeout => FailureReason::Timeout,
+        }
+    }
+
+    /// Status and message to answer the client with.
+    fn response(&self) -> (StatusCode, &'static str) {
+        match self {
+            AttemptError::Connect(message) => (StatusCode::BAD_GATEWAY, message),
+            AttemptError::Reset => (StatusCode::BAD_GATEWAY, "Failed to send request to backend"),
+            AttemptError::Timeout => (StatusCode::GATEWAY_TIMEOUT, "Timed out waiting for backend"),
+        }
+    }
+}
+
+/// Send one attempt of a request to `ctx.backend_addr`.
+///
+/// The body is only taken once the connection is ready, so it is untouched
+/// if connecting fails. A new connection is closed once `drain` fires.
+async fn send_attempt(
+    ctx: &ProxyContext,
+    head: &request::Parts,
+    body: &mut ReplayBody,
+    drain: DrainSignal,
+) -> Result<Response<Incoming>, AttemptError> {
+    // Reuse a pooled connection or open a new one
+    let mut conn = match ctx.pool.checkout(
+        &ctx.backend_name,
+        ctx.backend_addr,
+        ctx.protocol,
+        ctx.tls.as_deref(),
+    ) {
+        Some(conn) => conn,
+        None => match connect_backend(ctx, drain).await {
+            Ok(conn) => {
+                // Let concurrent requests share a new HTTP/2 connection
+                if let Some(shared) = conn.share() {
+                    ctx.pool.checkin(
+                        &ctx.backend_name,
+                        ctx.backend_addr,
+                        shared,
+                        &ctx.pool_config,
+                    );
+                }
+                conn
+            }
+            Err((e, message)) => {
+                error!(
+                    connection_id = %ctx.connection_request_id,
+                    server = %ctx.backend_addr,
+                    error = %e,
+                    reason = message,
+                    "failed to open backend connection"
+                );
+                return Err(AttemptError::Connect(message));
+            }
+        },
+    };
+
+    // Only bodies that can still be sent are retried
+    let Some(body) = body.take() else {
+        return Err(AttemptError::Reset);
+    };
+    let mut req = Request::new(body);
+    *req.method_mut() = head.method.clone();
+    *req.uri_mut() = head.uri.clone();
+    *req.version_mut() = head.version;
+    *req.headers_mut() = head.headers.clone();
+    add_request_headers(req.headers_mut(), ctx);
+
+    // Rewrite the request target for the backend's HTTP version
+    set_request_target(&mut req, ctx.protocol, ctx.tls.is_some());
+
+    match conn.send_request(req).await {
+        Ok(resp) => {
+            // Hand the connection back once the response body is consumed
+            ctx.pool
+                .release(&ctx.backend_name, ctx.backend_addr, conn, &ctx.pool_config);
+            Ok(resp)
+        }
         Err(e) => {
             error!(
                 connection_id = %ctx.connection_request_id,
+                server = %ctx.backend_addr,
                 error = %e,
                 "failed to send request to backend"
             );
-            let duration = start_time.elapsed();
-            ctx.metrics.record_request(
-                &ctx.frontend_name,
+            Err(AttemptError::Reset)
+        }
+    }
+}
+
+/// Time limit of the next attempt: the per-try timeout, capped by what is
+/// left of the overall budget.
+fn attempt_limit(policy: Option<&RetryConfig>, deadline: Option<Instant>) -> Option<Duration> {
+    let per_try = policy.and_then(|policy| policy.per_try_timeout);
+    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    match (per_try, remaining) {
+        (Some(per_try), Some(remaining)) => Some(per_try.min(remaining)),
+        (per_try, remaining) => per_try.or(remaining),
+    }
+}
+
+/// Check whether the policy retries a failure of the given kind.
+///
+/// Requests that never reached a server can always be sent again; others
+/// only if their method is idempotent or the policy allows any method.
+fn is_retryable(policy: &RetryConfig, reason: FailureReason, method: &Method) -> bool {
+    let condition = match reason {
+        FailureReason::ConnectFailure => RetryCondition::ConnectFailure,
+        FailureReason::Reset => RetryCondition::Reset,
+        FailureReason::Timeout => RetryCondition::Timeout,
+        // Only statuses listed in the policy are reported
+        FailureReason::Status => return policy.retry_non_idempotent || method.is_idempotent(),
+    };
+    policy.retry_on.contains(&condition)
+        && (reason == FailureReason::ConnectFailure
+            || policy.retry_non_idempotent
+            || method.is_idempotent())
+}
+
+/// Proxy an HTTP/1.1 Upgrade request, such as a WebSocket handshake.
+///
+/// The request goes to the backend over a dedicated connection. If the
+/// backend switches protocols, the `101` response is returned to the client
+/// and both upgraded connections are tunnelled in the background; `guard`
+/// is held until the tunnel closes, which happens at the latest when the
+/// server finishes draining or shutdown closes the remaining connections.
+/// Any other response is passed through.
+#[instrument(skip_all, fields(
+    method = %req.method(),
+    uri = %req.uri(),
+    client = %ctx.client_addr,
+    backend = %ctx.backend_addr
+))]
+pub async fn proxy_upgrade(
+    mut req: Request<Incoming>,
+    ctx: ProxyContext,
+    guard: ConnectionGuard,
+) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Infallible> {
+    let start_time = Instant::now();
+    let method = req.method().to_string();
+    let uri = req.uri().to_string();
+
+    add_request_headers(req.headers_mut(), &ctx);
+    let client_upgrade = hyper::upgrade::on(&mut req);
+
+    // Upgraded connections cannot be reused, so never take one from the pool
+    let mut conn = match connect_backend(&ctx, guard.drain_signal()).await {
+        Ok(conn) => conn,
+        Err((e, message)) => {
+            error!(
+                connection_id = %ctx.connection_request_id,
+                error = %e,
+                reason = message,
+                "failed to open backend connection"
+            );
+            ctx.passive_health.record_failure(
                 &ctx.backend_name,
+                ctx.backend_addr,
+                &ctx.passive_health_config,
+                FailureReason::ConnectFailure,
+            );
+            return Ok(reject_request(
+                &ctx,
+                Some(ctx.backend_addr),
                 &method,
-                502,
-                duration,
+                None,
+                StatusCode::BAD_GATEWAY,
+                message,
+                start_time.elapsed(),
+            ));
+        }
+    };
+
+    set_request_target(&mut req, BackendProtocol::Http1, ctx.tls.is_some());
+    let mut backend_response = match conn.send_request(req.map(|body| body.boxed())).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            error!(
+                connection_id = %ctx.connection_request_id,
+                error = %e,
+                "failed to send request to backend"
+            );
+            ctx.passive_health.record_failure(
+                &ctx.backend_name,
+                ctx.backend_addr,
+                &ctx.passive_health_config,
+                FailureReason::Reset,
             );
-            return Ok(error_response(
+            return Ok(reject_request(
+                &ctx,
+                Some(ctx.backend_addr),
+                &method,
+                None,
                 StatusCode::BAD_GATEWAY,
                 "Failed to send request to backend",
+                start_time.elapsed(),
             ));
         }
     };
 
-    // Convert the response
-    let (mut parts, body) = backend_response.into_parts();
-    let status_code = parts.status.as_u16();
-
-    // Add response headers
-    add_response_headers(&mut parts.headers, &ctx);
-
-    // Build the response with boxed body
-    let boxed_body = body.map_err(|e| e).boxed();
-    let response = Response::from_parts(parts, boxed_body);
-
-    // Record metrics
-    let duration = start_time.elapsed();
+    let status_code = backend_response.status().as_u16();
+    ctx.passive_health.record_status(
+        &ctx.backend_name,
+        ctx.backend_addr,
+        &ctx.passive_health_config,
+        status_code,
+    );
     ctx.metrics.record_request(
         &ctx.frontend_name,
         &ctx.backend_name,
+        Some(ctx.backend_addr),
         &method,
         status_code,
-        duration,
+        start_time.elapsed(),
     );
 
+    if backend_response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        let backend_upgrade = hyper::upgrade::on(&mut backend_response);
+        let ctx = ctx.clone();
+        // The tunnel outlives the client connection it came from
+        let token = ctx.connections.track();
+        tokio::spawn(async move {
+            let (client, backend) = match tokio::try_join!(client_upgrade, backend_upgrade) {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    warn!(
+                        connection_id = %ctx.connection_request_id,
+                        error = %e,
+                        "connection upgrade failed"
+                    );
+                    return;
+                }
+            };
+
+            let start = Instant::now();
+            let result = tunnel(
+                TokioIo::new(client),
+                TokioIo::new(backend),
+                ctx.config.upgrade_idle_timeout,
+                async {
+                    tokio::select! {
+                        _ = guard.drain_signal().expired() => {}
+                        _ = ctx.connections.closed() => {}
+                    }
+                },
+            )
+            .await;
+            drop(guard);
+            drop(token);
+            let duration = start.elapsed();
+            ctx.metrics.record_upgraded_session(
+                &ctx.frontend_name,
+                &ctx.backend_name,
+                result.bytes_to_backend,
+                result.bytes_to_client,
+                duration,
+            );
+
+            info!(
+                connection_id = %ctx.connection_request_id,
+                backend = %ctx.backend_addr,
+                bytes_to_backend = result.bytes_to_backend,
+                bytes_to_client = result.bytes_to_client,
+                duration_ms = duration.as_millis(),
+                "upgraded connection closed"
+            );
+        });
+    }
+
+    let (mut parts, body) = backend_response.into_parts();
+    parts.version = Version::HTTP_11;
+    add_response_headers(&mut parts.headers, &ctx);
+
     info!(
         connection_id = %ctx.connection_request_id,
         method = %method,
         uri = %uri,
         status = status_code,
-        duration_ms = duration.as_millis(),
-        "proxied request completed"
+        "proxied upgrade request"
     );
 
-    Ok(response)
+    Ok(Response::from_parts(parts, body.boxed()))
 }
 
-/// Add headers to the request being sent to the backend.
-fn add_request_headers(req: &mut Request<Incoming>, ctx: &ProxyContext) {
-    let headers = req.headers_mut();
+/// Open a new connection to the backend, over TLS if configured.
+///
+/// The connection is closed once `drain` fires. Returns the error along with
+/// a client-facing message on failure.
+async fn connect_backend(
+    ctx: &ProxyContext,
+    drain: DrainSignal,
+) -> Result<PooledConnection, (Box<dyn std::error::Error + Send + Sync>, &'static str)> {
+    let backend_stream =
+        match tokio::time::timeout(ctx.config.connect_timeout, TcpStream::connect(ctx.backend_addr))
+            .await
+        {
+            Ok(Ok(stream)) => {
+                let _ = stream.set_nodelay(true);
+                stream
+            }
+            Ok(Err(e)) => return Err((Box::new(e), "Failed to connect to backend")),
+            Err(e) => return Err((Box::new(e), "Timed out connecting to backend")),
+        };
+
+    let conn = match ctx.tls {
+        Some(ref tls) => {
+            let tls_stream = match tokio::time::timeout(
+                ctx.config.connect_timeout,
+                tls.connect(ctx.backend_addr, backend_stream),
+            )
+            .await
+            {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => return Err((Box::new(e), "Backend TLS handshake failed")),
+                Err(e) => return Err((Box::new(e), "Timed out connecting to backend")),
+            };
+            handshake(tls_stream, ctx.protocol, drain).await
+        }
+        None => handshake(backend_stream, ctx.protocol, drain).await,
+    }
+    .map_err(|e| (Box::new(e) as _, "Backend handshake failed"))?;
+
+    ctx.pool.record_created(&ctx.backend_name, ctx.backend_addr);
+    Ok(conn.with_tls(ctx.tls.as_deref()))
+}
+
+/// Perform the HTTP client handshake and spawn the connection driver.
+///
+/// The driver drops the connection once `drain` fires, failing any request
+/// still in flight on it.
+async fn handshake<IO>(
+    io: IO,
+    protocol: BackendProtocol,
+    drain: DrainSignal,
+) -> Result<PooledConnection, hyper::Error>
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let io = TokioIo::new(io);
+    match protocol {
+        BackendProtocol::Http1 => {
+            let (sender, conn) = hyper::client::conn::http1::handshake(io).await?;
+            tokio::spawn(async move {
+                tokio::select! {
+                    result = conn.with_upgrades() => if let Err(e) = result {
+                        warn!(error = %e, "backend connection error");
+                    },
+                    _ = drain.expired() => debug!("server drained, backend connection closed"),
+                }
+            });
+            Ok(PooledConnection::new(sender))
+        }
+        BackendProtocol::Http2 => {
+            let (sender, conn) =
+                hyper::client::conn::http2::handshake(TokioExecutor::new(), io).await?;
+            tokio::spawn(async move {
+                tokio::select! {
+                    result = conn => if let Err(e) = result {
+                        warn!(error = %e, "backend connection error");
+                    },
+                    _ = drain.expired() => debug!("server drained, backend connection closed"),
+                }
+            });
+            Ok(PooledConnection::new_http2(sender))
+        }
+    }
+}
+
+/// Rewrite the request URI and version for the backend's HTTP version.
+///
+/// HTTP/1.1 backends get an origin-form URI, with a Host header built from
+/// the authority of HTTP/2 requests. HTTP/2 backends get an absolute URI,
+/// whose authority is sent as `:authority` in place of the Host header.
+fn set_request_target<B>(req: &mut Request<B>, protocol: BackendProtocol, tls: bool) {
+    let authority = req.uri().authority().cloned().or_else(|| {
+        req.headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .and_then(|host| host.parse::<Authority>().ok())
+    });
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .cloned()
+        .unwrap_or_else(|| PathAndQuery::from_static("/"));
+
+    if protocol == BackendProtocol::Http2
+        && let Some(ref authority) = authority
+    {
+        let scheme = if tls { Scheme::HTTPS } else { Scheme::HTTP };
+        if let Ok(uri) = Uri::builder()
+            .scheme(scheme)
+            .authority(authority.clone())
+            .path_and_query(path_and_query.clone())
+            .build()
+        {
+            *req.uri_mut() = uri;
+            *req.version_mut() = Version::HTTP_2;
+            req.headers_mut().remove(HOST);
+            return;
+        }
+    }
+
+    if !req.headers().contains_key(HOST)
+        && let Some(authority) = authority
+        && let Ok(host) = authority.as_str().parse()
+    {
+        req.headers_mut().insert(HOST, host);
+    }
+    *req.uri_mut() = Uri::from(path_and_query);
+    *req.version_mut() = Version::HTTP_11;
+}
 
+/// Add headers to the request being sent to the backend.
+fn add_request_headers(headers: &mut hyper::HeaderMap, ctx: &ProxyContext) {
     // Add X-Forwarded-For
     let forwarded_for = ctx.client_addr.ip().to_string();
     if let Ok(value) = forwarded_for.parse() {
@@ -276,8 +874,47 @@ fn substitute_variables(value: &str, ctx: &ProxyContext) -> String {
         .replace("$backend_addr", &ctx.backend_addr.to_string())
 }
 
+/// Answer a request that could not be proxied and record it in the metrics.
+///
+/// `server` is the server the request failed on, if it reached one.
+/// gRPC calls (`grpc_method` set) get a gRPC status matching `status`, while
+/// other requests get a plain-text error response.
+pub fn reject_request(
+    ctx: &ProxyContext,
+    server: Option<SocketAddr>,
+    method: &str,
+    grpc_method: Option<&str>,
+    status: StatusCode,
+    message: &str,
+    duration: Duration,
+) -> Response<BoxBody<Bytes, hyper::Error>> {
+    ctx.metrics.record_request(
+        &ctx.frontend_name,
+        &ctx.backend_name,
+        server,
+        method,
+        status.as_u16(),
+        duration,
+    );
+
+    match grpc_method {
+        Some(grpc_method) => {
+            let code = GrpcCode::from_http_status(status);
+            ctx.metrics.record_grpc_request(
+                &ctx.frontend_name,
+                &ctx.backend_name,
+                grpc_method,
+                code.as_str(),
+                duration,
+            );
+            grpc_error_response(code, message)
+        }
+        None => error_response(status, message),
+    }
+}
+
 /// Create an error response.
-fn error_response(status: StatusCode, message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+pub fn error_response(status: StatusCode, message: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
     let body = Full::new(Bytes::from(format!("{}: {}\n", status, message)))
         .map_err(|never| match never {})
         .boxed();
@@ -305,6 +942,17 @@ mod tests {
             config: HttpProxyConfig::default(),
             metrics: MetricsCollector::new(),
             connection_request_id: "test-request-123".to_string(),
+            pool: ConnectionPool::new(MetricsCollector::new()),
+            pool_config: ConnectionPoolConfig::default(),
+            tls: None,
+            protocol: BackendProtocol::Http1,
+            grpc: false,
+            passive_health: PassiveHealthTracker::new(
+                Arc::new(crate::health::HealthState::new()),
+                MetricsCollector::new(),
+            ),
+            passive_health_config: PassiveHealthConfig::default(),
+            connections: ConnectionTracker::new(),
         }
     }
 
@@ -331,4 +979,284 @@ mod tests {
         let resp = error_response(StatusCode::BAD_GATEWAY, "test error");
         assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
     }
+
+    #[test]
+    fn test_reject_grpc_request() {
+        let mut ctx = test_context();
+        ctx.grpc = true;
+
+        let resp = reject_request(
+            &ctx,
+            None,
+            "POST",
+            Some("/users.Users/Get"),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No healthy backend servers available",
+            Duration::ZERO,
+        );
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["grpc-status"], "14");
+
+        // Requests that are not gRPC calls get a plain HTTP error
+        let resp = reject_request(
+            &ctx,
+            None,
+            "GET",
+            None,
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No healthy backend servers available",
+            Duration::ZERO,
+        );
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let policy = RetryConfig {
+            retry_on: vec![RetryCondition::ConnectFailure, RetryCondition::Reset],
+            ..Default::default()
+        };
+        assert!(is_retryable(&policy, FailureReason::Reset, &Method::GET));
+        assert!(!is_retryable(&policy, FailureReason::Timeout, &Method::GET));
+
+        // POST requests that reached a server are not sent again by default
+        assert!(is_retryable(
+            &policy,
+            FailureReason::ConnectFailure,
+            &Method::POST
+        ));
+        assert!(!is_retryable(&policy, FailureReason::Reset, &Method::POST));
+        assert!(!is_retryable(&policy, FailureReason::Status, &Method::POST));
+
+        let policy = RetryConfig {
+            retry_non_idempotent: true,
+            ..policy
+        };
+        assert!(is_retryable(&policy, FailureReason::Reset, &Method::POST));
+    }
+
+    #[test]
+    fn test_attempt_limit() {
+        let policy = RetryConfig {
+            per_try_timeout: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+        assert_eq!(attempt_limit(None, None), None);
+        assert_eq!(
+            attempt_limit(Some(&policy), None),
+            Some(Duration::from_secs(2))
+        );
+
+        // The overall budget caps the last attempts
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let limit = attempt_limit(Some(&policy), Some(deadline)).unwrap();
+        assert!(limit <= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_request_retries_other_server() {
+        use crate::config::{Algorithm, BackendConfig, FrontendConfig, Protocol, ServerConfig};
+        use hyper::service::service_fn;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // One server is down, the other echoes the request body
+        let dead_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = backend.accept().await {
+                tokio::spawn(async move {
+                    let service = service_fn(|req: Request<Incoming>| async move {
+                        let body = req.into_body().collect().await?.to_bytes();
+                        Ok::<_, hyper::Error>(Response::new(Full::new(body)))
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let backends = vec![BackendConfig {
+            name: "web-servers".to_string(),
+            servers: [dead_addr, backend_addr]
+                .into_iter()
+                .map(|address| ServerConfig {
+                    address,
+                    weight: 1,
+                    metadata: Default::default(),
+                })
+                .collect(),
+            ..Default::default()
+        }];
+        let frontends = vec![FrontendConfig {
+            name: "test-frontend".to_string(),
+            listen: "127.0.0.1:0".parse().unwrap(),
+            protocol: Protocol::Http,
+            backend: "web-servers".to_string(),
+            routes: Vec::new(),
+            algorithm: Algorithm::RoundRobin,
+            http: None,
+            tcp: None,
+            tls: None,
+        }];
+        let router = Arc::new(BackendRouter::new(&backends, &frontends));
+
+        let frontend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let frontend_addr = frontend.local_addr().unwrap();
+        let mut ctx = test_context();
+        ctx.backend_addr = dead_addr;
+        ctx.config.retry = Some(RetryConfig::default());
+        let metrics = ctx.metrics.clone();
+        tokio::spawn(async move {
+            let (stream, _) = frontend.accept().await.unwrap();
+            let service = service_fn(move |req| {
+                let ctx = ctx.clone();
+                let router = Arc::clone(&router);
+                async move {
+                    let guard = router.track(&ctx.backend_name, ctx.backend_addr);
+                    proxy_request(req, ctx, guard, &router).await
+                }
+            });
+            let _ = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        // Connection failures are retried even for POST requests
+        let mut client = TcpStream::connect(frontend_addr).await.unwrap();
+        client
+            .write_all(b"POST /items HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains(&format!("x-served-by: web-servers:{}", backend_addr)));
+        assert!(response.ends_with("hello"));
+
+        let mut encoded = String::new();
+        prometheus_client::encoding::text::encode(&mut encoded, metrics.registry()).unwrap();
+        assert!(encoded.contains(&format!(
+            r#"rustlb_retries_total{{frontend="test-frontend",backend="web-servers",server="{}",reason="ConnectFailure"}} 1"#,
+            dead_addr
+        )));
+    }
+
+    #[test]
+    fn test_set_request_target_for_http1() {
+        // HTTP/2 requests carry the authority in the URI, not a Host header
+        let mut req = Request::builder()
+            .version(Version::HTTP_2)
+            .uri("https://api.example.com/items?page=2")
+            .body(())
+            .unwrap();
+        set_request_target(&mut req, BackendProtocol::Http1, false);
+
+        assert_eq!(req.uri(), "/items?page=2");
+        assert_eq!(req.version(), Version::HTTP_11);
+        assert_eq!(req.headers()[HOST], "api.example.com");
+    }
+
+    #[test]
+    fn test_set_request_target_for_http2() {
+        let mut req = Request::builder()
+            .uri("/items")
+            .header(HOST, "api.example.com:8080")
+            .body(())
+            .unwrap();
+        set_request_target(&mut req, BackendProtocol::Http2, true);
+
+        assert_eq!(req.uri(), "https://api.example.com:8080/items");
+        assert_eq!(req.version(), Version::HTTP_2);
+        assert!(req.headers().get(HOST).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_upgrade() {
+        use hyper::service::service_fn;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        async fn read_head(stream: &mut TcpStream) -> String {
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+            while !head.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).await.unwrap();
+                head.push(byte[0]);
+            }
+            String::from_utf8(head).unwrap()
+        }
+
+        // Backend that switches to an echo protocol
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backend_addr = backend.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.unwrap();
+            let head = read_head(&mut stream).await;
+            assert!(head.to_ascii_lowercase().contains("upgrade: echo"));
+            stream
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\n")
+                .await
+                .unwrap();
+            let mut buf = [0u8; 64];
+            loop {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => stream.write_all(&buf[..n]).await.unwrap(),
+                }
+            }
+        });
+
+        // Frontend that proxies every request as an upgrade
+        let frontend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let frontend_addr = frontend.local_addr().unwrap();
+        let mut ctx = test_context();
+        ctx.backend_addr = backend_addr;
+        let metrics = ctx.metrics.clone();
+        let router = Arc::new(BackendRouter::new(&[], &[]));
+        tokio::spawn(async move {
+            let (stream, _) = frontend.accept().await.unwrap();
+            let service = service_fn(move |req| {
+                let guard = router.track(&ctx.backend_name, ctx.backend_addr);
+                proxy_upgrade(req, ctx.clone(), guard)
+            });
+            let _ = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await;
+        });
+
+        let mut client = TcpStream::connect(frontend_addr).await.unwrap();
+        client
+            .write_all(b"GET /chat HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade\r\nUpgrade: echo\r\n\r\n")
+            .await
+            .unwrap();
+        let head = read_head(&mut client).await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{}", head);
+
+        client.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 5];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        drop(client);
+        let mut encoded = String::new();
+        for _ in 0..50 {
+            encoded.clear();
+            prometheus_client::encoding::text::encode(&mut encoded, metrics.registry()).unwrap();
+            if encoded.contains("rustlb_upgraded_bytes_total") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(encoded.contains(
+            r#"rustlb_upgraded_bytes_total{frontend="test-frontend",backend="web-servers",direction="Inbound"} 5"#
+        ));
+    }
 }
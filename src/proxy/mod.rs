@@ -1,9 +1,20 @@
 //! Proxy implementations for TCP and HTTP.
 
+mod body;
+mod grpc;
 mod http_proxy;
+mod pool;
+mod rewind;
 mod tcp_proxy;
+mod upgrade;
 
-pub use http_proxy::{proxy_request, HttpProxy, HttpProxyConfig, HttpProxyError, ProxyContext};
+pub use body::GuardedBody;
+pub use grpc::{grpc_error_response, grpc_method, GrpcBody, GrpcCall, GrpcCode};
+pub use http_proxy::{error_response, proxy_request, proxy_upgrade, reject_request, HttpProxy, HttpProxyConfig, HttpProxyError, ProxyContext};
+pub use pool::{ConnectionPool, PooledConnection};
+pub use rewind::Rewind;
+pub use upgrade::{is_upgrade_request, tunnel};
 pub use tcp_proxy::{
-    connect_to_backend, handle_tcp_proxy, proxy_bidirectional, ProxyResult, TcpProxyError,
+    connect_backend, connect_tls, connect_to_backend, handle_tcp_proxy, proxy_bidirectional, BackendStream,
+    ProxyResult, TcpProxyError,
 };
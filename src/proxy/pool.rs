@@ -0,0 +1,568 @@
+//! Keep-alive connection pool for HTTP backends.
+//!
+//! Reuses connections to backend servers instead of paying a TCP connect and
+//! handshake for every proxied request. HTTP/1.1 connections are checked out
+//! for one request at a time; HTTP/2 connections stay in the pool and are
+//! shared by concurrent requests.
+
+use crate::config::{BackendProtocol, ConnectionPoolConfig};
+use crate::metrics::MetricsCollector;
+use crate::tls::ClientTls;
+use bytes::Bytes;
+use dashmap::DashMap;
+use http_body_util::combinators::BoxBody;
+use hyper::body::Incoming;
+use hyper::client::conn::{http1, http2};
+use hyper::{Request, Response};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::time::interval;
+use tracing::{debug, info};
+
+/// How often idle connections are swept for expiry.
+const REAP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Pool key: backend name and server address.
+type PoolKey = (String, SocketAddr);
+
+/// Body of requests sent to backends: the client's streamed body, or a
+/// buffered copy that can be sent again when a request is retried.
+pub type RequestBody = BoxBody<Bytes, hyper::Error>;
+
+/// Request sender of an HTTP/1.1 or HTTP/2 backend connection.
+enum Sender {
+    Http1(http1::SendRequest<RequestBody>),
+    Http2(http2::SendRequest<RequestBody>),
+}
+
+impl Sender {
+    fn protocol(&self) -> BackendProtocol {
+        match self {
+            Sender::Http1(_) => BackendProtocol::Http1,
+            Sender::Http2(_) => BackendProtocol::Http2,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        match self {
+            Sender::Http1(sender) => sender.is_closed(),
+            Sender::Http2(sender) => sender.is_closed(),
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        match self {
+            Sender::Http1(sender) => sender.is_ready(),
+            Sender::Http2(sender) => sender.is_ready(),
+        }
+    }
+
+    async fn ready(&mut self) -> hyper::Result<()> {
+        match self {
+            Sender::Http1(sender) => sender.ready().await,
+            Sender::Http2(sender) => sender.ready().await,
+        }
+    }
+}
+
+/// A backend connection that can be returned to the pool.
+pub struct PooledConnection {
+    /// Request sender for the connection.
+    sender: Sender,
+    /// When the connection was established.
+    created: Instant,
+    /// Number of requests sent over this connection, shared by every handle
+    /// to an HTTP/2 connection.
+    requests: Arc<AtomicU32>,
+    /// Generation of the TLS settings the connection was made with, if it
+    /// uses TLS.
+    tls: Option<u64>,
+}
+
+impl PooledConnection {
+    /// Wrap a freshly established HTTP/1.1 connection.
+    pub fn new(sender: http1::SendRequest<RequestBody>) -> Self {
+        Self::with_sender(Sender::Http1(sender))
+    }
+
+    /// Wrap a freshly established HTTP/2 connection.
+    pub fn new_http2(sender: http2::SendRequest<RequestBody>) -> Self {
+        Self::with_sender(Sender::Http2(sender))
+    }
+
+    fn with_sender(sender: Sender) -> Self {
+        Self {
+            sender,
+            created: Instant::now(),
+            requests: Arc::new(AtomicU32::new(0)),
+            tls: None,
+        }
+    }
+
+    /// Record the TLS settings the connection was made with.
+    pub fn with_tls(mut self, tls: Option<&ClientTls>) -> Self {
+        self.tls = tls.map(ClientTls::generation);
+        self
+    }
+
+    /// Whether the connection carries concurrent requests (HTTP/2).
+    pub fn is_multiplexed(&self) -> bool {
+        matches!(self.sender, Sender::Http2(_))
+    }
+
+    /// Another handle to the same connection, if it is multiplexed.
+    pub fn share(&self) -> Option<PooledConnection> {
+        match self.sender {
+            Sender::Http1(_) => None,
+            Sender::Http2(ref sender) => Some(Self {
+                sender: Sender::Http2(sender.clone()),
+                created: self.created,
+                requests: Arc::clone(&self.requests),
+                tls: self.tls,
+            }),
+        }
+    }
+
+    /// Send a request over the connection.
+    pub async fn send_request(
+        &mut self,
+        req: Request<RequestBody>,
+    ) -> hyper::Result<Response<Incoming>> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        match self.sender {
+            Sender::Http1(ref mut sender) => sender.send_request(req).await,
+            Sender::Http2(ref mut sender) => sender.send_request(req).await,
+        }
+    }
+
+    /// Check whether the connection may serve another request.
+    fn is_reusable(&self, config: &ConnectionPoolConfig) -> bool {
+        if self.sender.is_closed() {
+            return false;
+        }
+        if let Some(max_lifetime) = config.max_lifetime
+            && self.created.elapsed() >= max_lifetime
+        {
+            return false;
+        }
+        if let Some(max_requests) = config.max_requests
+            && self.requests.load(Ordering::Relaxed) >= max_requests
+        {
+            return false;
+        }
+        true
+    }
+
+    /// Point after which the connection must not be reused if it stays idle.
+    fn expiry(&self, config: &ConnectionPoolConfig, now: Instant) -> Instant {
+        let expires_at = now + config.idle_timeout;
+        match config.max_lifetime {
+            Some(max_lifetime) => expires_at.min(self.created + max_lifetime),
+            None => expires_at,
+        }
+    }
+}
+
+/// An idle connection waiting in the pool.
+///
+/// HTTP/2 connections stay here while in use, so "idle" means no request
+/// has been started on them for `idle_timeout`.
+struct IdleConnection {
+    conn: PooledConnection,
+    /// Point after which the connection must not be reused.
+    expires_at: Instant,
+    /// Pool settings the connection was checked in with.
+    config: ConnectionPoolConfig,
+}
+
+impl IdleConnection {
+    fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at || self.conn.sender.is_closed()
+    }
+}
+
+/// Per-server pool of keep-alive backend connections.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    /// Idle connections per backend server, most recently used last.
+    idle: DashMap<PoolKey, Vec<IdleConnection>>,
+    /// Metrics collector for pool size and reuse counters.
+    metrics: MetricsCollector,
+}
+
+impl ConnectionPool {
+    /// Create an empty connection pool.
+    pub fn new(metrics: MetricsCollector) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                idle: DashMap::new(),
+                metrics,
+            }),
+        }
+    }
+
+    /// Take an idle connection to a server speaking `protocol` and made with
+    /// the TLS settings `tls`, if one is available.
+    ///
+    /// HTTP/2 connections are shared rather than taken: a new handle is
+    /// returned and the connection stays pooled for concurrent requests.
+    /// Expired or closed connections found along the way are dropped, and so
+    /// are connections whose protocol or TLS settings a reload replaced.
+    pub fn checkout(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        protocol: BackendProtocol,
+        tls: Option<&ClientTls>,
+    ) -> Option<PooledConnection> {
+        let mut entry = self.inner.idle.get_mut(&(backend.to_string(), server))?;
+        let now = Instant::now();
+        let generation = tls.map(ClientTls::generation);
+        entry.retain(|idle| idle.conn.sender.protocol() == protocol && idle.conn.tls == generation);
+
+        let mut found = None;
+        while let Some(idle) = entry.last_mut() {
+            if idle.is_expired(now) || !idle.conn.sender.is_ready() {
+                entry.pop();
+                continue;
+            }
+            if let Some(shared) = idle.conn.share() {
+                if !idle.conn.is_reusable(&idle.config) {
+                    entry.pop();
+                    continue;
+                }
+                idle.expires_at = idle.conn.expiry(&idle.config, now);
+                found = Some(shared);
+            } else {
+                found = entry.pop().map(|idle| idle.conn);
+            }
+            break;
+        }
+
+        let remaining = entry.len();
+        drop(entry);
+        self.inner.metrics.set_pool_idle(backend, server, remaining);
+
+        if found.is_some() {
+            debug!(backend = backend, server = %server, "reusing pooled backend connection");
+            self.inner
+                .metrics
+                .record_pool_checkout(backend, server, true);
+        }
+        found
+    }
+
+    /// Return a connection to the pool.
+    ///
+    /// The connection is closed instead if it has reached its lifetime or
+    /// request limit, or if the server already has `max_idle` connections.
+    pub fn checkin(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        conn: PooledConnection,
+        config: &ConnectionPoolConfig,
+    ) {
+        if config.max_idle == 0 || !conn.is_reusable(config) {
+            return;
+        }
+
+        let expires_at = conn.expiry(config, Instant::now());
+
+        let mut entry = self
+            .inner
+            .idle
+            .entry((backend.to_string(), server))
+            .or_default();
+        if entry.len() >= config.max_idle {
+            return;
+        }
+        entry.push(IdleConnection {
+            conn,
+            expires_at,
+            config: config.clone(),
+        });
+
+        let idle = entry.len();
+        drop(entry);
+        self.inner.metrics.set_pool_idle(backend, server, idle);
+    }
+
+    /// Return a connection to the pool once its current response completes.
+    ///
+    /// HTTP/1.1 connections can only carry one request at a time, so this
+    /// waits in the background until the response body has been consumed.
+    /// HTTP/2 connections never left the pool, so their handle is dropped.
+    pub fn release(
+        &self,
+        backend: &str,
+        server: SocketAddr,
+        mut conn: PooledConnection,
+        config: &ConnectionPoolConfig,
+    ) {
+        if config.max_idle == 0 || conn.is_multiplexed() {
+            return;
+        }
+
+        let pool = self.clone();
+        let backend = backend.to_string();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if conn.sender.ready().await.is_ok() {
+                pool.checkin(&backend, server, conn, &config);
+            }
+        });
+    }
+
+    /// Record that a new backend connection had to be created.
+    pub fn record_created(&self, backend: &str, server: SocketAddr) {
+        self.inner
+            .metrics
+            .record_pool_checkout(backend, server, false);
+    }
+
+    /// Number of idle connections held for a server.
+    pub fn idle_count(&self, backend: &str, server: SocketAddr) -> usize {
+        self.inner
+            .idle
+            .get(&(backend.to_string(), server))
+            .map(|e| e.len())
+            .unwrap_or(0)
+    }
+
+    /// Drop all expired idle connections.
+    pub fn reap(&self) {
+        let now = Instant::now();
+        for mut entry in self.inner.idle.iter_mut() {
+            let before = entry.len();
+            entry.retain(|idle| !idle.is_expired(now));
+            if entry.len() != before {
+                let ((backend, server), idle) = entry.pair();
+                self.inner
+                    .metrics
+                    .set_pool_idle(backend, *server, idle.len());
+            }
+        }
+        self.inner.idle.retain(|_, idle| !idle.is_empty());
+    }
+
+    /// Periodically close idle connections that outlived their timeout.
+    pub async fn run(self, mut shutdown: broadcast::Receiver<()>) {
+        let mut reap_interval = interval(REAP_INTERVAL);
+        reap_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        loop {
+            tokio::select! {
+                _ = reap_interval.tick() => self.reap(),
+
+                _ = shutdown.recv() => {
+                    info!("connection pool shutting down");
+                    self.inner.idle.clear();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::server::conn::http1 as server_http1;
+    use hyper::service::service_fn;
+    use hyper_util::rt::{TokioExecutor, TokioIo};
+    use std::convert::Infallible;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Start a keep-alive HTTP server and return its address.
+    async fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let service = service_fn(|_req| async {
+                        Ok::<_, Infallible>(Response::new(http_body_util::Empty::<Bytes>::new()))
+                    });
+                    let _ = server_http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    async fn connect(addr: SocketAddr) -> PooledConnection {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        sender.ready().await.unwrap();
+        PooledConnection::new(sender)
+    }
+
+    #[tokio::test]
+    async fn test_checkin_and_checkout() {
+        let addr = start_server().await;
+        let pool = ConnectionPool::new(MetricsCollector::new());
+        let config = ConnectionPoolConfig::default();
+
+        assert!(
+            pool.checkout("web", addr, BackendProtocol::Http1, None)
+                .is_none()
+        );
+
+        pool.checkin("web", addr, connect(addr).await, &config);
+        assert_eq!(pool.idle_count("web", addr), 1);
+
+        assert!(
+            pool.checkout("web", addr, BackendProtocol::Http1, None)
+                .is_some()
+        );
+        assert_eq!(pool.idle_count("web", addr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_max_idle_limit() {
+        let addr = start_server().await;
+        let pool = ConnectionPool::new(MetricsCollector::new());
+        let config = ConnectionPoolConfig {
+            max_idle: 1,
+            ..Default::default()
+        };
+
+        pool.checkin("web", addr, connect(addr).await, &config);
+        pool.checkin("web", addr, connect(addr).await, &config);
+        assert_eq!(pool.idle_count("web", addr), 1);
+    }
+
+    #[tokio::test]
+    async fn test_max_requests_retires_connection() {
+        let addr = start_server().await;
+        let pool = ConnectionPool::new(MetricsCollector::new());
+        let config = ConnectionPoolConfig {
+            max_requests: Some(1),
+            ..Default::default()
+        };
+
+        let conn = connect(addr).await;
+        conn.requests.store(1, Ordering::Relaxed);
+        pool.checkin("web", addr, conn, &config);
+        assert_eq!(pool.idle_count("web", addr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_expires() {
+        let addr = start_server().await;
+        let pool = ConnectionPool::new(MetricsCollector::new());
+        let config = ConnectionPoolConfig {
+            idle_timeout: Duration::from_millis(10),
+            ..Default::default()
+        };
+
+        pool.checkin("web", addr, connect(addr).await, &config);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        pool.reap();
+        assert_eq!(pool.idle_count("web", addr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_http2_connection_is_shared() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|_req| async {
+                Ok::<_, Infallible>(Response::new(http_body_util::Empty::<Bytes>::new()))
+            });
+            let _ = hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (sender, conn) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+
+        let pool = ConnectionPool::new(MetricsCollector::new());
+        let config = ConnectionPoolConfig::default();
+        pool.checkin("grpc", addr, PooledConnection::new_http2(sender), &config);
+
+        // Every checkout shares the same pooled connection
+        let first = pool
+            .checkout("grpc", addr, BackendProtocol::Http2, None)
+            .unwrap();
+        let second = pool
+            .checkout("grpc", addr, BackendProtocol::Http2, None)
+            .unwrap();
+        assert!(first.is_multiplexed());
+        assert!(Arc::ptr_eq(&first.requests, &second.requests));
+        assert_eq!(pool.idle_count("grpc", addr), 1);
+
+        pool.release("grpc", addr, first, &config);
+        assert_eq!(pool.idle_count("grpc", addr), 1);
+
+        // After a reload switched the backend to HTTP/1.1, it is closed
+        assert!(
+            pool.checkout("grpc", addr, BackendProtocol::Http1, None)
+                .is_none()
+        );
+        assert_eq!(pool.idle_count("grpc", addr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_replaced_tls_settings_not_reused() {
+        use crate::config::BackendTlsConfig;
+
+        let addr = start_server().await;
+        let pool = ConnectionPool::new(MetricsCollector::new());
+        let config = ConnectionPoolConfig::default();
+        let tls_config = BackendTlsConfig {
+            ca: None,
+            cert: None,
+            key: None,
+            server_name: None,
+            verify: false,
+        };
+        let old = ClientTls::new(&tls_config, BackendProtocol::Http1).unwrap();
+        let new = ClientTls::new(&tls_config, BackendProtocol::Http1).unwrap();
+
+        let conn = connect(addr).await.with_tls(Some(&old));
+        pool.checkin("web", addr, conn, &config);
+        assert!(
+            pool.checkout("web", addr, BackendProtocol::Http1, Some(&old.clone()))
+                .is_some()
+        );
+
+        // After a reload replaced the settings, older connections are closed
+        let conn = connect(addr).await.with_tls(Some(&old));
+        pool.checkin("web", addr, conn, &config);
+        assert!(
+            pool.checkout("web", addr, BackendProtocol::Http1, Some(&new))
+                .is_none()
+        );
+        assert_eq!(pool.idle_count("web", addr), 0);
+
+        // Switching between TLS and plaintext also needs new connections
+        pool.checkin("web", addr, connect(addr).await, &config);
+        assert!(
+            pool.checkout("web", addr, BackendProtocol::Http1, Some(&new))
+                .is_none()
+        );
+    }
+}
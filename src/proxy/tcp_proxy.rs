@@ -2,12 +2,16 @@
 //!
 //! Provides bidirectional data transfer between client and backend.
 
+use crate::tls::ClientTls;
 use std::io;
 use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::Duration;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
 use tokio::time::timeout;
+use tokio_rustls::client::TlsStream;
 use tracing::{debug, error, info, instrument, warn};
 
 /// Result of a proxy operation.
@@ -28,8 +32,17 @@ pub enum TcpProxyError {
     #[error("connection timeout to backend {0}")]
     BackendTimeout(SocketAddr),
 
+    #[error("TLS handshake with backend {0} failed: {1}")]
+    BackendTlsError(SocketAddr, io::Error),
+
     #[error("proxy error: {0}")]
     ProxyError(#[from] io::Error),
+
+    #[error("session with draining backend {0} closed at drain timeout")]
+    Drained(SocketAddr),
+
+    #[error("no backend available")]
+    NoBackendAvailable,
 }
 
 /// Connect to a backend server with timeout.
@@ -60,10 +73,102 @@ pub async fn connect_to_backend(
     }
 }
 
+/// Perform a TLS handshake on a backend connection, within `handshake_timeout`.
+#[instrument(skip_all, fields(backend = %addr))]
+pub async fn connect_tls(
+    tls: &ClientTls,
+    addr: SocketAddr,
+    stream: TcpStream,
+    handshake_timeout: Duration,
+) -> Result<TlsStream<TcpStream>, TcpProxyError> {
+    match timeout(handshake_timeout, tls.connect(addr, stream)).await {
+        Ok(Ok(stream)) => {
+            debug!("TLS handshake with backend completed");
+            Ok(stream)
+        }
+        Ok(Err(e)) => {
+            error!(error = %e, "TLS handshake with backend failed");
+            Err(TcpProxyError::BackendTlsError(addr, e))
+        }
+        Err(_) => {
+            error!("TLS handshake timeout");
+            Err(TcpProxyError::BackendTimeout(addr))
+        }
+    }
+}
+
+/// Connection to a backend server of a TCP proxy session.
+pub enum BackendStream {
+    /// Plaintext connection.
+    Plain(TcpStream),
+    /// Connection wrapped in TLS.
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for BackendStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            BackendStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for BackendStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            BackendStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            BackendStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            BackendStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            BackendStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Connect to a backend server, over TLS if `tls` is given.
+///
+/// `connect_timeout` applies to the TCP connect and the TLS handshake each.
+pub async fn connect_backend(
+    addr: SocketAddr,
+    connect_timeout: Duration,
+    tls: Option<&ClientTls>,
+) -> Result<BackendStream, TcpProxyError> {
+    let stream = connect_to_backend(addr, connect_timeout).await?;
+    match tls {
+        Some(tls) => {
+            let stream = connect_tls(tls, addr, stream, connect_timeout).await?;
+            Ok(BackendStream::Tls(Box::new(stream)))
+        }
+        None => Ok(BackendStream::Plain(stream)),
+    }
+}
+
 /// Proxy data bidirectionally between two streams.
 ///
 /// This function copies data in both directions simultaneously until
-/// one side closes the connection or an error occurs.
+/// one side closes the connection or an error occurs. When one side finishes
+/// sending, the write half towards the other side is shut down so that the
+/// close propagates.
 #[instrument(skip_all)]
 pub async fn proxy_bidirectional<C, B>(
     client: C,
@@ -77,8 +182,16 @@ where
     let (mut backend_read, mut backend_write) = tokio::io::split(backend);
 
     // Copy in both directions simultaneously
-    let client_to_backend = tokio::io::copy(&mut client_read, &mut backend_write);
-    let backend_to_client = tokio::io::copy(&mut backend_read, &mut client_write);
+    let client_to_backend = async {
+        let result = tokio::io::copy(&mut client_read, &mut backend_write).await;
+        let _ = backend_write.shutdown().await;
+        result
+    };
+    let backend_to_client = async {
+        let result = tokio::io::copy(&mut backend_read, &mut client_write).await;
+        let _ = client_write.shutdown().await;
+        result
+    };
 
     // Wait for both directions to complete
     let (c2b_result, b2c_result) = tokio::join!(client_to_backend, backend_to_client);
@@ -98,22 +211,21 @@ where
     })
 }
 
-/// Handle a complete TCP proxy session.
+/// Handle a TCP proxy session over an established backend connection.
 ///
-/// Connects to the backend and proxies data bidirectionally.
+/// Proxies data bidirectionally until both sides are done.
 #[instrument(skip_all, fields(client = %client_addr, backend = %backend_addr))]
-pub async fn handle_tcp_proxy(
-    client_stream: TcpStream,
+pub async fn handle_tcp_proxy<C>(
+    client_stream: C,
     client_addr: SocketAddr,
     backend_addr: SocketAddr,
-    connect_timeout: Duration,
-) -> Result<ProxyResult, TcpProxyError> {
+    backend_stream: BackendStream,
+) -> Result<ProxyResult, TcpProxyError>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
     info!("starting TCP proxy session");
 
-    // Connect to backend
-    let backend_stream = connect_to_backend(backend_addr, connect_timeout).await?;
-
-    // Proxy data
     let result = proxy_bidirectional(client_stream, backend_stream).await?;
 
     info!(
@@ -0,0 +1,259 @@
+//! HTTP Upgrade (such as WebSocket) tunnelling.
+//!
+//! Once the backend answers an upgrade request with `101 Switching
+//! Protocols`, the client and backend connections are spliced together with
+//! [`proxy_bidirectional`] until either side closes or the tunnel goes idle.
+
+use crate::proxy::tcp_proxy::{proxy_bidirectional, ProxyResult};
+use hyper::header::{CONNECTION, UPGRADE};
+use hyper::{Request, Version};
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::debug;
+
+/// Check whether a request asks to upgrade the connection.
+///
+/// Only HTTP/1.1 requests can upgrade: they need an `Upgrade` header and the
+/// `upgrade` token in `Connection`.
+pub fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    req.version() == Version::HTTP_11
+        && req.headers().contains_key(UPGRADE)
+        && req
+            .headers()
+            .get_all(CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+}
+
+/// Byte counts and last activity of a tunnel, shared by both of its ends.
+struct Activity {
+    start: Instant,
+    /// Milliseconds since `start` of the last read on either end.
+    last_read_ms: AtomicU64,
+    to_backend: AtomicU64,
+    to_client: AtomicU64,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_read_ms: AtomicU64::new(0),
+            to_backend: AtomicU64::new(0),
+            to_client: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, bytes: usize, to_backend: bool) {
+        let counter = if to_backend {
+            &self.to_backend
+        } else {
+            &self.to_client
+        };
+        counter.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.last_read_ms
+            .store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn last_read(&self) -> Instant {
+        self.start + Duration::from_millis(self.last_read_ms.load(Ordering::Relaxed))
+    }
+}
+
+pin_project! {
+    /// Stream that records the bytes read from it in an [`Activity`].
+    struct Metered<S> {
+        #[pin]
+        inner: S,
+        activity: Arc<Activity>,
+        // Whether bytes read from this stream are headed to the backend
+        to_backend: bool,
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Metered<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let before = buf.filled().len();
+        let result = this.inner.poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        if read > 0 {
+            this.activity.record(read, *this.to_backend);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for Metered<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// Splice an upgraded client connection to its backend connection.
+///
+/// Runs until both directions are closed, until no data has flowed in
+/// either direction for `idle_timeout`, or until `closed` completes, such as
+/// when the backend server finishes draining. In the latter two cases both
+/// connections are dropped. Returns the bytes relayed each way either way.
+pub async fn tunnel<C, B>(
+    client: C,
+    backend: B,
+    idle_timeout: Duration,
+    closed: impl Future<Output = ()>,
+) -> ProxyResult
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let activity = Arc::new(Activity::new());
+    let client = Metered {
+        inner: client,
+        activity: Arc::clone(&activity),
+        to_backend: true,
+    };
+    let backend = Metered {
+        inner: backend,
+        activity: Arc::clone(&activity),
+        to_backend: false,
+    };
+
+    let idle = async {
+        loop {
+            let deadline = activity.last_read() + idle_timeout;
+            tokio::time::sleep_until(deadline.into()).await;
+            if activity.last_read().elapsed() >= idle_timeout {
+                break;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = proxy_bidirectional(client, backend) => {}
+        _ = idle => debug!(idle_timeout = ?idle_timeout, "upgraded connection idle, closing"),
+        _ = closed => debug!("upgraded connection closed by the proxy"),
+    }
+
+    ProxyResult {
+        bytes_to_backend: activity.to_backend.load(Ordering::Relaxed),
+        bytes_to_client: activity.to_client.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_is_upgrade_request() {
+        let req = Request::builder()
+            .header(CONNECTION, "keep-alive, Upgrade")
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(is_upgrade_request(&req));
+
+        // Upgrade offered without the Connection token
+        let req = Request::builder()
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(!is_upgrade_request(&req));
+
+        let req = Request::builder()
+            .version(Version::HTTP_2)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(!is_upgrade_request(&req));
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_relays_until_closed() {
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let (backend, mut backend_peer) = tokio::io::duplex(1024);
+        let session = tokio::spawn(tunnel(
+            client,
+            backend,
+            Duration::from_secs(5),
+            std::future::pending(),
+        ));
+
+        client_peer.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        backend_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        backend_peer.write_all(b"pong!").await.unwrap();
+        let mut buf = [0u8; 5];
+        client_peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong!");
+
+        drop(client_peer);
+        drop(backend_peer);
+        let result = session.await.unwrap();
+        assert_eq!(result.bytes_to_backend, 4);
+        assert_eq!(result.bytes_to_client, 5);
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_idle_timeout() {
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let (backend, _backend_peer) = tokio::io::duplex(1024);
+        let result = tunnel(
+            client,
+            backend,
+            Duration::from_millis(50),
+            std::future::pending(),
+        )
+        .await;
+        assert_eq!(result.bytes_to_backend, 0);
+
+        // The client side is closed once the tunnel gives up
+        let mut buf = [0u8; 1];
+        assert_eq!(client_peer.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_closed() {
+        let (client, mut client_peer) = tokio::io::duplex(1024);
+        let (backend, _backend_peer) = tokio::io::duplex(1024);
+        let closed = tokio::time::sleep(Duration::from_millis(50));
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            tunnel(client, backend, Duration::from_secs(5), closed),
+        )
+        .await
+        .expect("tunnel was not closed");
+        assert_eq!(result.bytes_to_client, 0);
+
+        let mut buf = [0u8; 1];
+        assert_eq!(client_peer.read(&mut buf).await.unwrap(), 0);
+    }
+}
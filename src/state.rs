@@ -1,8 +1,10 @@
 //! Shared application state.
 
+use crate::backend::{BackendRouter, ServerOverlay};
 use crate::config::Config;
-use crate::health::HealthState;
-use crate::util::ShutdownSignal;
+use crate::health::{HealthConfig, HealthState, Readiness};
+use crate::metrics::MetricsCollector;
+use crate::util::{ConnectionTracker, ShutdownSignal};
 use arc_swap::ArcSwap;
 use std::sync::Arc;
 
@@ -15,17 +17,49 @@ pub struct AppState {
     /// Health state for all backends.
     health: Arc<HealthState>,
 
+    /// Backend router shared by all frontends.
+    router: Arc<BackendRouter>,
+
+    /// Metrics collector.
+    metrics: MetricsCollector,
+
     /// Shutdown signal.
     shutdown: ShutdownSignal,
+
+    /// Client connections, drained on shutdown.
+    connections: ConnectionTracker,
+
+    /// Startup and shutdown progress, reported by the readiness probe.
+    readiness: Arc<Readiness>,
 }
 
 impl AppState {
     /// Create new application state.
     pub fn new(config: Config) -> Self {
+        Self::with_overlay(config, ServerOverlay::new())
+    }
+
+    /// Create new application state whose backend pools include the runtime
+    /// server changes recorded in `overlay`.
+    pub fn with_overlay(config: Config, overlay: ServerOverlay) -> Self {
+        let health = Arc::new(HealthState::with_config(HealthConfig::from(
+            &config.health_check_defaults,
+        )));
+        let router = Arc::new(BackendRouter::with_overlay(
+            &config.backends,
+            &config.frontends,
+            Arc::clone(&health),
+            overlay,
+        ));
+
         Self {
             config: Arc::new(ArcSwap::from_pointee(config)),
-            health: Arc::new(HealthState::new()),
+            health,
+            router,
+            metrics: MetricsCollector::new(),
             shutdown: ShutdownSignal::new(),
+            connections: ConnectionTracker::new(),
+            readiness: Arc::new(Readiness::new()),
         }
     }
 
@@ -35,7 +69,12 @@ impl AppState {
     }
 
     /// Swap the configuration atomically (for hot reload).
+    ///
+    /// The backend router is reloaded from the new configuration so new
+    /// selections use the new pools immediately. Frontend listeners are
+    /// managed separately by [`crate::frontend::FrontendManager`].
     pub fn swap_config(&self, new_config: Config) {
+        self.router.reload(&new_config.backends, &new_config.frontends);
         self.config.store(Arc::new(new_config));
     }
 
@@ -44,11 +83,31 @@ impl AppState {
         &self.health
     }
 
+    /// Get the backend router.
+    pub fn router(&self) -> &Arc<BackendRouter> {
+        &self.router
+    }
+
+    /// Get the metrics collector.
+    pub fn metrics(&self) -> &MetricsCollector {
+        &self.metrics
+    }
+
     /// Get the shutdown signal.
     pub fn shutdown(&self) -> &ShutdownSignal {
         &self.shutdown
     }
 
+    /// Get the client connection tracker.
+    pub fn connections(&self) -> &ConnectionTracker {
+        &self.connections
+    }
+
+    /// Get the readiness state.
+    pub fn readiness(&self) -> &Arc<Readiness> {
+        &self.readiness
+    }
+
     /// Trigger shutdown.
     pub fn trigger_shutdown(&self) {
         self.shutdown.shutdown();
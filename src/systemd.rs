@@ -0,0 +1,212 @@
+//! systemd integration: socket activation and service notifications.
+//!
+//! Sockets passed by a socket unit (`LISTEN_FDS`) are matched to frontends by
+//! their `FileDescriptorName=`, which must equal the frontend name. Service
+//! state is reported over `NOTIFY_SOCKET` so `Type=notify` units know when
+//! rustlb is ready, reloading or stopping, and `WATCHDOG=1` keepalives are
+//! sent when the unit sets `WatchdogSec=`.
+//!
+//! Everything here does nothing when rustlb was not started by systemd.
+
+use socket2::{Domain, SockRef, Type};
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::net::TcpListener as StdTcpListener;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+/// First file descriptor passed by socket activation.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Service startup is finished.
+pub const READY: &str = "READY=1";
+
+/// Service is shutting down.
+pub const STOPPING: &str = "STOPPING=1";
+
+/// Watchdog keepalive.
+pub const WATCHDOG: &str = "WATCHDOG=1";
+
+/// Take the listening sockets passed by socket activation, keyed by their
+/// file descriptor name.
+///
+/// Returns no sockets if the process was not socket activated.
+pub fn activated_sockets() -> HashMap<String, StdTcpListener> {
+    let (Ok(pid), Ok(count)) = (env::var("LISTEN_PID"), env::var("LISTEN_FDS")) else {
+        return HashMap::new();
+    };
+    // The variables are inherited by child processes, so check they are ours
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return HashMap::new();
+    }
+    let Ok(count) = count.parse::<RawFd>() else {
+        warn!(count = %count, "ignoring invalid LISTEN_FDS");
+        return HashMap::new();
+    };
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    parse_activated(LISTEN_FDS_START, count, &names)
+}
+
+/// Adopt `count` sockets starting at file descriptor `start`, named by the
+/// colon separated `names`.
+fn parse_activated(start: RawFd, count: RawFd, names: &str) -> HashMap<String, StdTcpListener> {
+    let mut names = names.split(':');
+    let mut sockets = HashMap::new();
+    for fd in start..start + count {
+        let name = names
+            .next()
+            .filter(|name| !name.is_empty())
+            .unwrap_or("unknown");
+
+        // SAFETY: systemd passed this descriptor for our exclusive use, and
+        // nothing else in this process refers to it.
+        let listener = unsafe { StdTcpListener::from_raw_fd(fd) };
+        let socket = SockRef::from(&listener);
+        let is_tcp = matches!(socket.domain(), Ok(Domain::IPV4 | Domain::IPV6))
+            && matches!(socket.r#type(), Ok(Type::STREAM));
+        if !is_tcp {
+            warn!(
+                name = name,
+                fd = fd,
+                "ignoring activated socket that is not TCP"
+            );
+            continue;
+        }
+        // Do not leak the socket into processes started later on
+        if let Err(e) = socket.set_cloexec(true) {
+            warn!(name = name, error = %e, "failed to set close-on-exec on activated socket");
+        }
+        if sockets.contains_key(name) {
+            warn!(
+                name = name,
+                fd = fd,
+                "ignoring activated socket with duplicate name"
+            );
+            continue;
+        }
+        info!(name = name, address = ?listener.local_addr().ok(), "activated listening socket");
+        sockets.insert(name.to_string(), listener);
+    }
+    sockets
+}
+
+/// Send a state notification to systemd.
+///
+/// Does nothing if the service manager does not expect notifications.
+/// Failures are logged, since they must not affect the proxy.
+pub fn notify(state: &str) {
+    let Ok(socket) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    match send(&socket, state) {
+        Ok(()) => debug!(state = state, "notified systemd"),
+        Err(e) => warn!(state = state, error = %e, "failed to notify systemd"),
+    }
+}
+
+/// Tell systemd a reload started.
+///
+/// Must be followed by [`READY`] once the reload finished or failed.
+pub fn notify_reloading() {
+    notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()));
+}
+
+/// Send `state` to the notification socket at `path`, which starts with `@`
+/// for an abstract socket.
+fn send(path: &str, state: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract notification socket",
+            ));
+        }
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Current `CLOCK_MONOTONIC` time in microseconds, as systemd expects it.
+fn monotonic_usec() -> u64 {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid timespec to write to.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    now.tv_sec as u64 * 1_000_000 + now.tv_nsec as u64 / 1_000
+}
+
+/// Interval systemd expects watchdog keepalives in, if the watchdog is
+/// enabled for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID")
+        && pid.parse::<u32>().ok() != Some(std::process::id())
+    {
+        return None;
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Send watchdog keepalives at half the watchdog interval until shutdown.
+pub async fn run_watchdog(interval: Duration, mut shutdown: broadcast::Receiver<()>) {
+    info!(interval = ?interval, "systemd watchdog enabled");
+    let mut ticker = tokio::time::interval(interval / 2);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => notify(WATCHDOG),
+            _ = shutdown.recv() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::fd::AsRawFd;
+
+    #[test]
+    fn test_parse_activated() {
+        // Place the sockets at consecutive descriptors like systemd does
+        let web = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let web_addr = web.local_addr().unwrap();
+        let udp = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let start = 900;
+        unsafe {
+            assert_eq!(libc::dup2(web.as_raw_fd(), start), start);
+            assert_eq!(libc::dup2(udp.as_raw_fd(), start + 1), start + 1);
+        }
+
+        // Datagram sockets are skipped
+        let sockets = parse_activated(start, 2, "web:dns");
+        assert_eq!(sockets.len(), 1);
+        assert_eq!(sockets["web"].local_addr().unwrap(), web_addr);
+    }
+
+    #[test]
+    fn test_notify_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send(path.to_str().unwrap(), READY).unwrap();
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+}
@@ -0,0 +1,104 @@
+//! PEM certificate and key loading.
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Errors that can occur while setting up TLS.
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("no certificates found in {0}")]
+    NoCertificates(PathBuf),
+
+    #[error("no private key found in {0}")]
+    NoPrivateKey(PathBuf),
+
+    #[error("unknown cipher suite '{0}'")]
+    UnknownCipherSuite(String),
+
+    #[error("invalid server name '{0}'")]
+    InvalidServerName(String),
+
+    #[error("TLS configuration error: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Load a PEM-encoded certificate chain.
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let read_error = |source| TlsError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let file = File::open(path).map_err(read_error)?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error)?;
+
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(path.to_path_buf()));
+    }
+    Ok(certs)
+}
+
+/// Load a PEM-encoded private key (PKCS#8, PKCS#1 or SEC1).
+pub fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let read_error = |source| TlsError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let file = File::open(path).map_err(read_error)?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(read_error)?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_load_certs_and_key() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+
+        let mut cert_file = NamedTempFile::new().unwrap();
+        cert_file.write_all(cert.cert.pem().as_bytes()).unwrap();
+        let mut key_file = NamedTempFile::new().unwrap();
+        key_file
+            .write_all(cert.key_pair.serialize_pem().as_bytes())
+            .unwrap();
+
+        assert_eq!(load_certs(cert_file.path()).unwrap().len(), 1);
+        assert!(load_private_key(key_file.path()).is_ok());
+    }
+
+    #[test]
+    fn test_missing_pem_items() {
+        let mut empty = NamedTempFile::new().unwrap();
+        empty.write_all(b"not a pem file\n").unwrap();
+
+        assert!(matches!(
+            load_certs(empty.path()),
+            Err(TlsError::NoCertificates(_))
+        ));
+        assert!(matches!(
+            load_private_key(empty.path()),
+            Err(TlsError::NoPrivateKey(_))
+        ));
+        assert!(matches!(
+            load_certs(Path::new("/nonexistent/cert.pem")),
+            Err(TlsError::Read { .. })
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+//! Client-side TLS for connections to backend servers.
+
+use crate::config::{BackendConfig, BackendProtocol, BackendTlsConfig};
+use crate::tls::server::crypto_provider;
+use crate::tls::{load_certs, load_private_key, TlsError};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// Source of [`ClientTls::generation`].
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// TLS settings for connecting to the servers of a backend pool.
+///
+/// Certificates are read once when the settings are built; reloading means
+/// building a new `ClientTls`.
+#[derive(Clone)]
+pub struct ClientTls {
+    connector: TlsConnector,
+    /// Name sent as SNI and verified; the server's IP address if unset.
+    server_name: Option<ServerName<'static>>,
+    /// Identifies these settings among every `ClientTls` built.
+    generation: u64,
+}
+
+impl ClientTls {
+    /// Load the CA bundle and client certificate of a backend.
+    ///
+    /// HTTP/2 backends are offered `h2` by ALPN; otherwise no ALPN protocol
+    /// is sent, since TCP frontends proxy arbitrary protocols.
+    pub fn new(config: &BackendTlsConfig, protocol: BackendProtocol) -> Result<Self, TlsError> {
+        let provider = Arc::new(crypto_provider(&[])?);
+        let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+
+        let builder = if config.verify {
+            let mut roots = RootCertStore::empty();
+            if let Some(ref ca) = config.ca {
+                for cert in load_certs(ca)? {
+                    roots.add(cert)?;
+                }
+            }
+            builder.with_root_certificates(roots)
+        } else {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+        };
+
+        let mut client_config = match (&config.cert, &config.key) {
+            (Some(cert), Some(key)) => {
+                builder.with_client_auth_cert(load_certs(cert)?, load_private_key(key)?)?
+            }
+            _ => builder.with_no_client_auth(),
+        };
+        if protocol == BackendProtocol::Http2 {
+            client_config.alpn_protocols = vec![b"h2".to_vec()];
+        }
+
+        let server_name = config
+            .server_name
+            .as_deref()
+            .map(|name| {
+                ServerName::try_from(name.to_string())
+                    .map_err(|_| TlsError::InvalidServerName(name.to_string()))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            connector: TlsConnector::from(Arc::new(client_config)),
+            server_name,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+
+    /// Identifier of these settings.
+    ///
+    /// Every load gets a new one, even of unchanged files, so connections
+    /// made with different settings can be told apart.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Perform the client side of a TLS handshake with a backend server.
+    pub async fn connect<IO>(&self, server: SocketAddr, stream: IO) -> io::Result<TlsStream<IO>>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let server_name = self
+            .server_name
+            .clone()
+            .unwrap_or_else(|| ServerName::IpAddress(server.ip().into()));
+        self.connector.connect(server_name, stream).await
+    }
+}
+
+/// Check that the TLS settings of every backend load.
+///
+/// A backend whose settings fail to load is left out of the router, so
+/// configurations with one are refused at startup and on reload.
+pub fn check_backend_tls(backends: &[BackendConfig]) -> Result<(), String> {
+    for backend in backends {
+        if let Some(ref tls) = backend.tls {
+            ClientTls::new(tls, backend.protocol).map_err(|e| {
+                format!(
+                    "failed to load TLS settings of backend '{}': {}",
+                    backend.name, e
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Certificate verifier that accepts any server certificate.
+///
+/// Handshake signatures are still checked, so the connection is encrypted
+/// with the key of the certificate presented, whoever it belongs to.
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Protocol, TlsConfig, TlsVersion};
+    use crate::tls::{write_cert, ServerTls};
+    use tempfile::NamedTempFile;
+
+    fn client_tls(ca: Option<&NamedTempFile>, server_name: Option<&str>) -> ClientTls {
+        let config = BackendTlsConfig {
+            ca: ca.map(|f| f.path().to_path_buf()),
+            cert: None,
+            key: None,
+            server_name: server_name.map(str::to_string),
+            verify: ca.is_some(),
+        };
+        ClientTls::new(&config, BackendProtocol::Http1).unwrap()
+    }
+
+    /// Connect `client` to a server presenting `cert` over an in-memory pipe.
+    async fn connect(client: ClientTls, cert: &NamedTempFile, key: &NamedTempFile) -> bool {
+        let server_tls = ServerTls::new(
+            &TlsConfig {
+                cert: cert.path().to_path_buf(),
+                key: key.path().to_path_buf(),
+                certificates: Vec::new(),
+                min_version: TlsVersion::Tls12,
+                cipher_suites: Vec::new(),
+                alpn: Vec::new(),
+            },
+            &Protocol::Tcp,
+        )
+        .unwrap();
+
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let server = tokio::spawn(async move { server_tls.accept(server_io).await.is_ok() });
+        let addr: SocketAddr = "127.0.0.1:443".parse().unwrap();
+
+        // Keep the client side open until the server has finished too
+        let client = client.connect(addr, client_io).await;
+        let server_ok = server.await.unwrap();
+        client.is_ok() && server_ok
+    }
+
+    #[tokio::test]
+    async fn test_verified_connection() {
+        let (cert, key) = write_cert("api.internal");
+
+        let tls = client_tls(Some(&cert), Some("api.internal"));
+        assert!(connect(tls, &cert, &key).await);
+
+        // The certificate is not valid for the server's IP address
+        let tls = client_tls(Some(&cert), None);
+        assert!(!connect(tls, &cert, &key).await);
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_certificate() {
+        let (cert, key) = write_cert("api.internal");
+        let (other, _) = write_cert("api.internal");
+
+        let tls = client_tls(Some(&other), Some("api.internal"));
+        assert!(!connect(tls, &cert, &key).await);
+
+        // Any certificate is accepted when verification is off
+        let tls = client_tls(None, None);
+        assert!(connect(tls, &cert, &key).await);
+    }
+}
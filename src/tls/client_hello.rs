@@ -0,0 +1,281 @@
+//! TLS ClientHello inspection for passthrough routing.
+//!
+//! Reads just enough of a connection to extract the SNI server name from the
+//! ClientHello, without terminating TLS. The bytes read are handed back to
+//! the caller so they can be replayed to the backend unchanged.
+
+use std::io;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// TLS record header length: type (1), version (2), length (2).
+const RECORD_HEADER_LEN: usize = 5;
+
+/// Handshake message header length: type (1), length (3).
+const HANDSHAKE_HEADER_LEN: usize = 4;
+
+/// TLS record content type for handshake messages.
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+
+/// Handshake message type of a ClientHello.
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+
+/// Extension type of server_name (RFC 6066).
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+
+/// Name type of a DNS host name in the server_name extension.
+const NAME_TYPE_HOST_NAME: u8 = 0x00;
+
+/// Largest ClientHello accepted; real ones are a few kilobytes at most.
+const MAX_CLIENT_HELLO_LEN: usize = 16 * 1024;
+
+/// Errors that can occur while inspecting a ClientHello.
+#[derive(Debug, Error)]
+pub enum ClientHelloError {
+    #[error("connection does not start with a TLS handshake")]
+    NotTls,
+
+    #[error("malformed ClientHello: {0}")]
+    Malformed(&'static str),
+
+    #[error("ClientHello larger than {MAX_CLIENT_HELLO_LEN} bytes")]
+    TooLarge,
+
+    #[error("failed to read ClientHello: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Read a connection's ClientHello and return its SNI server name.
+///
+/// Every byte read from `stream` is appended to `buf`, including when an
+/// error is returned, so the caller can forward it untouched. Returns
+/// `Ok(None)` for a valid ClientHello without SNI.
+pub async fn read_client_hello<S>(
+    stream: &mut S,
+    buf: &mut Vec<u8>,
+) -> Result<Option<String>, ClientHelloError>
+where
+    S: AsyncRead + Unpin,
+{
+    loop {
+        if let Some(server_name) = parse_client_hello(buf)? {
+            return Ok(server_name);
+        }
+
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Parse the SNI server name from the start of a TLS connection.
+///
+/// Returns `Ok(None)` if more bytes are needed, and `Ok(Some(sni))` once the
+/// whole ClientHello has been seen. The ClientHello may be split across
+/// several records.
+pub fn parse_client_hello(buf: &[u8]) -> Result<Option<Option<String>>, ClientHelloError> {
+    let mut handshake = Vec::new();
+    let mut rest = buf;
+
+    loop {
+        if rest.len() < RECORD_HEADER_LEN {
+            check_record_prefix(rest)?;
+            return Ok(None);
+        }
+        check_record_prefix(rest)?;
+
+        let record_len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        if record_len == 0 {
+            return Err(ClientHelloError::Malformed("empty handshake record"));
+        }
+        let Some(fragment) = rest.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + record_len) else {
+            return Ok(None);
+        };
+        handshake.extend_from_slice(fragment);
+        rest = &rest[RECORD_HEADER_LEN + record_len..];
+
+        if handshake.len() < HANDSHAKE_HEADER_LEN {
+            continue;
+        }
+        if handshake[0] != HANDSHAKE_CLIENT_HELLO {
+            return Err(ClientHelloError::Malformed(
+                "first handshake message is not a ClientHello",
+            ));
+        }
+        let message_len =
+            u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+        if message_len > MAX_CLIENT_HELLO_LEN {
+            return Err(ClientHelloError::TooLarge);
+        }
+        if let Some(body) = handshake.get(HANDSHAKE_HEADER_LEN..HANDSHAKE_HEADER_LEN + message_len)
+        {
+            return parse_server_name(body).map(Some);
+        }
+    }
+}
+
+/// Check that the bytes seen so far can start a TLS handshake record.
+fn check_record_prefix(record: &[u8]) -> Result<(), ClientHelloError> {
+    match record {
+        [] => Ok(()),
+        [content_type, ..] if *content_type != CONTENT_TYPE_HANDSHAKE => {
+            Err(ClientHelloError::NotTls)
+        }
+        [_, major, ..] if *major != 3 => Err(ClientHelloError::NotTls),
+        _ => Ok(()),
+    }
+}
+
+/// Extract the host name from the extensions of a ClientHello body.
+fn parse_server_name(body: &[u8]) -> Result<Option<String>, ClientHelloError> {
+    let mut reader = Reader(body);
+    reader.skip(2)?; // legacy_version
+    reader.skip(32)?; // random
+    let session_id_len = reader.u8()? as usize;
+    reader.skip(session_id_len)?;
+    let cipher_suites_len = reader.u16()? as usize;
+    reader.skip(cipher_suites_len)?;
+    let compression_len = reader.u8()? as usize;
+    reader.skip(compression_len)?;
+
+    // Extensions are optional in old clients
+    if reader.0.is_empty() {
+        return Ok(None);
+    }
+
+    let extensions_len = reader.u16()? as usize;
+    let mut extensions = Reader(reader.take(extensions_len)?);
+    while !extensions.0.is_empty() {
+        let extension_type = extensions.u16()?;
+        let extension_len = extensions.u16()? as usize;
+        let data = extensions.take(extension_len)?;
+        if extension_type != EXTENSION_SERVER_NAME {
+            continue;
+        }
+
+        let mut data = Reader(data);
+        let list_len = data.u16()? as usize;
+        let mut names = Reader(data.take(list_len)?);
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name_len = names.u16()? as usize;
+            let name = names.take(name_len)?;
+            if name_type == NAME_TYPE_HOST_NAME {
+                let name = std::str::from_utf8(name)
+                    .map_err(|_| ClientHelloError::Malformed("server name is not valid UTF-8"))?;
+                return Ok(Some(name.to_ascii_lowercase()));
+            }
+        }
+        return Ok(None);
+    }
+
+    Ok(None)
+}
+
+/// Bounds-checked cursor over handshake bytes.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ClientHelloError> {
+        if self.0.len() < len {
+            return Err(ClientHelloError::Malformed("truncated ClientHello"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), ClientHelloError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, ClientHelloError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ClientHelloError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Capture the first flight a rustls client sends for `server_name`.
+    fn client_hello(server_name: &str) -> Vec<u8> {
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        let name = rustls::pki_types::ServerName::try_from(server_name.to_string()).unwrap();
+        let mut conn = rustls::ClientConnection::new(Arc::new(config), name).unwrap();
+
+        let mut out = Vec::new();
+        conn.write_tls(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_sni() {
+        let hello = client_hello("API.example.com");
+        assert_eq!(
+            parse_client_hello(&hello).unwrap(),
+            Some(Some("api.example.com".to_string()))
+        );
+
+        // IP addresses are never sent as SNI
+        let hello = client_hello("127.0.0.1");
+        assert_eq!(parse_client_hello(&hello).unwrap(), Some(None));
+    }
+
+    #[test]
+    fn test_parse_incomplete_and_fragmented() {
+        let hello = client_hello("example.com");
+        for len in [0, 3, RECORD_HEADER_LEN, hello.len() - 1] {
+            assert_eq!(parse_client_hello(&hello[..len]).unwrap(), None);
+        }
+
+        // Split the handshake message across two records
+        let fragment = &hello[RECORD_HEADER_LEN..];
+        let (first, second) = fragment.split_at(10);
+        let mut split = Vec::new();
+        for part in [first, second] {
+            split.extend_from_slice(&hello[..3]);
+            split.extend_from_slice(&(part.len() as u16).to_be_bytes());
+            split.extend_from_slice(part);
+        }
+        assert_eq!(
+            parse_client_hello(&split).unwrap(),
+            Some(Some("example.com".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_not_tls() {
+        assert!(matches!(
+            parse_client_hello(b"GET / HTTP/1.1\r\n"),
+            Err(ClientHelloError::NotTls)
+        ));
+        assert!(matches!(
+            parse_client_hello(&[0x16, 0x03, 0x01, 0x00, 0x04, 0x02, 0x00, 0x00, 0x00]),
+            Err(ClientHelloError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_client_hello_keeps_bytes() {
+        let hello = client_hello("db.example.com");
+        let mut stream: &[u8] = &hello;
+
+        let mut buf = Vec::new();
+        let sni = read_client_hello(&mut stream, &mut buf).await.unwrap();
+
+        assert_eq!(sni.as_deref(), Some("db.example.com"));
+        assert_eq!(buf, hello);
+    }
+}
@@ -0,0 +1,35 @@
+//! TLS support.
+//!
+//! Loads PEM certificates and keys and builds rustls configurations for
+//! terminating TLS on frontends, choosing certificates by SNI server name,
+//! and for connecting to backends over TLS.
+//! Also inspects ClientHellos for routing TLS connections without
+//! terminating them.
+
+mod certs;
+mod client;
+mod client_hello;
+mod server;
+mod sni;
+
+pub use certs::{load_certs, load_private_key, TlsError};
+pub use client::{check_backend_tls, ClientTls};
+pub use client_hello::{parse_client_hello, read_client_hello, ClientHelloError};
+pub use server::{HandshakeError, ServerTls};
+pub use sni::DEFAULT_CERTIFICATE;
+
+/// Write a self-signed certificate for `name` and its private key to
+/// temporary PEM files.
+#[cfg(test)]
+pub(crate) fn write_cert(name: &str) -> (tempfile::NamedTempFile, tempfile::NamedTempFile) {
+    use std::io::Write;
+
+    let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+    let mut cert_file = tempfile::NamedTempFile::new().unwrap();
+    cert_file.write_all(cert.cert.pem().as_bytes()).unwrap();
+    let mut key_file = tempfile::NamedTempFile::new().unwrap();
+    key_file
+        .write_all(cert.key_pair.serialize_pem().as_bytes())
+        .unwrap();
+    (cert_file, key_file)
+}
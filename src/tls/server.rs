@@ -0,0 +1,243 @@
+//! Server-side TLS for frontends.
+
+use crate::config::{Protocol, TlsConfig, TlsVersion};
+use crate::tls::sni::SniResolver;
+use crate::tls::TlsError;
+use rustls::crypto::CryptoProvider;
+use rustls::server::Acceptor;
+use rustls::ServerConfig;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::LazyConfigAcceptor;
+
+/// A TLS handshake that failed.
+#[derive(Debug, thiserror::Error)]
+#[error("{source}")]
+pub struct HandshakeError {
+    /// SNI server name sent by the client, if the ClientHello was received.
+    pub server_name: Option<String>,
+    /// The underlying error.
+    #[source]
+    pub source: io::Error,
+}
+
+/// TLS termination settings for a frontend listener.
+///
+/// Certificates are read once when the settings are built; reloading means
+/// building a new `ServerTls` and swapping it into the listener.
+#[derive(Clone)]
+pub struct ServerTls {
+    config: Arc<ServerConfig>,
+    resolver: Arc<SniResolver>,
+}
+
+impl ServerTls {
+    /// Load certificates and build the TLS configuration for a frontend.
+    pub fn new(config: &TlsConfig, protocol: &Protocol) -> Result<Self, TlsError> {
+        let provider = Arc::new(crypto_provider(&config.cipher_suites)?);
+        let resolver = Arc::new(SniResolver::new(config, &provider)?);
+        let server_config = build_server_config(config, protocol, provider, &resolver)?;
+        Ok(Self {
+            config: Arc::new(server_config),
+            resolver,
+        })
+    }
+
+    /// Perform the server side of a TLS handshake.
+    ///
+    /// The certificate is chosen from the client's SNI server name; see
+    /// [`certificate_name`](Self::certificate_name).
+    pub async fn accept<IO>(&self, stream: IO) -> Result<TlsStream<IO>, HandshakeError>
+    where
+        IO: AsyncRead + AsyncWrite + Unpin,
+    {
+        let start = LazyConfigAcceptor::new(Acceptor::default(), stream)
+            .await
+            .map_err(|source| HandshakeError {
+                server_name: None,
+                source,
+            })?;
+        let server_name = start.client_hello().server_name().map(str::to_string);
+
+        start
+            .into_stream(Arc::clone(&self.config))
+            .await
+            .map_err(|source| HandshakeError {
+                server_name,
+                source,
+            })
+    }
+
+    /// Configured server name of the certificate served for an SNI name, or
+    /// `"default"` if the default certificate is used.
+    pub fn certificate_name(&self, server_name: Option<&str>) -> &str {
+        self.resolver.certificate_name(server_name)
+    }
+}
+
+/// Build a rustls server configuration from frontend TLS settings.
+fn build_server_config(
+    config: &TlsConfig,
+    protocol: &Protocol,
+    provider: Arc<CryptoProvider>,
+    resolver: &Arc<SniResolver>,
+) -> Result<ServerConfig, TlsError> {
+    let versions: &[&'static rustls::SupportedProtocolVersion] = match config.min_version {
+        TlsVersion::Tls12 => &[&rustls::version::TLS13, &rustls::version::TLS12],
+        TlsVersion::Tls13 => &[&rustls::version::TLS13],
+    };
+
+    let mut server_config = ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(versions)?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::clone(resolver) as Arc<_>);
+
+    server_config.alpn_protocols = alpn_protocols(&config.alpn, protocol);
+    Ok(server_config)
+}
+
+/// Crypto provider restricted to the configured cipher suites.
+pub(crate) fn crypto_provider(cipher_suites: &[String]) -> Result<CryptoProvider, TlsError> {
+    let mut provider = rustls::crypto::ring::default_provider();
+    if cipher_suites.is_empty() {
+        return Ok(provider);
+    }
+
+    let mut selected = Vec::with_capacity(cipher_suites.len());
+    for name in cipher_suites {
+        let suite = provider
+            .cipher_suites
+            .iter()
+            .find(|s| {
+                s.suite()
+                    .as_str()
+                    .is_some_and(|n| n.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| TlsError::UnknownCipherSuite(name.clone()))?;
+        selected.push(*suite);
+    }
+    provider.cipher_suites = selected;
+    Ok(provider)
+}
+
+/// ALPN protocols to offer; HTTP frontends default to HTTP/2 and HTTP/1.1.
+fn alpn_protocols(configured: &[String], protocol: &Protocol) -> Vec<Vec<u8>> {
+    if !configured.is_empty() {
+        return configured.iter().map(|p| p.as_bytes().to_vec()).collect();
+    }
+    match protocol {
+        Protocol::Http => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        Protocol::Tcp => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::{load_certs, write_cert};
+    use tempfile::NamedTempFile;
+
+    fn tls_config(cert: &NamedTempFile, key: &NamedTempFile) -> TlsConfig {
+        TlsConfig {
+            cert: cert.path().to_path_buf(),
+            key: key.path().to_path_buf(),
+            certificates: Vec::new(),
+            min_version: TlsVersion::Tls12,
+            cipher_suites: Vec::new(),
+            alpn: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_alpn_protocols() {
+        assert_eq!(
+            alpn_protocols(&[], &Protocol::Http),
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+        assert!(alpn_protocols(&[], &Protocol::Tcp).is_empty());
+        assert_eq!(
+            alpn_protocols(&["h2".to_string()], &Protocol::Http),
+            vec![b"h2".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_cipher_suite_selection() {
+        let provider = crypto_provider(&["TLS13_AES_256_GCM_SHA384".to_string()]).unwrap();
+        assert_eq!(provider.cipher_suites.len(), 1);
+
+        assert!(matches!(
+            crypto_provider(&["TLS_NOT_A_SUITE".to_string()]),
+            Err(TlsError::UnknownCipherSuite(_))
+        ));
+    }
+
+    /// Connect to `tls` over an in-memory pipe, trusting only `trusted`.
+    async fn handshake(
+        tls: ServerTls,
+        trusted: &NamedTempFile,
+        server_name: &'static str,
+    ) -> (
+        std::io::Result<tokio_rustls::client::TlsStream<tokio::io::DuplexStream>>,
+        Result<Option<String>, HandshakeError>,
+    ) {
+        let (client, server) = tokio::io::duplex(16 * 1024);
+        let server = tokio::spawn(async move {
+            tls.accept(server)
+                .await
+                .map(|s| s.get_ref().1.server_name().map(str::to_string))
+        });
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(load_certs(trusted.path()).unwrap().remove(0))
+            .unwrap();
+        let mut client_config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let name = rustls::pki_types::ServerName::try_from(server_name).unwrap();
+        let client = connector.connect(name, client).await;
+        (client, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        let (cert, key) = write_cert("localhost");
+        let tls = ServerTls::new(&tls_config(&cert, &key), &Protocol::Http).unwrap();
+
+        let (client, server) = handshake(tls, &cert, "localhost").await;
+        let stream = client.unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+        assert_eq!(server.unwrap().as_deref(), Some("localhost"));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_selects_sni_certificate() {
+        let (default_cert, default_key) = write_cert("default.test");
+        let (api_cert, api_key) = write_cert("api.example.com");
+        let mut config = tls_config(&default_cert, &default_key);
+        config.certificates = vec![crate::config::SniCertificate {
+            server_names: vec!["api.example.com".to_string()],
+            cert: api_cert.path().to_path_buf(),
+            key: api_key.path().to_path_buf(),
+        }];
+        let tls = ServerTls::new(&config, &Protocol::Http).unwrap();
+
+        // The per-name certificate is served when the client asks for it
+        let (client, server) = handshake(tls.clone(), &api_cert, "api.example.com").await;
+        assert!(client.is_ok());
+        assert!(server.is_ok());
+
+        // Other names get the default certificate, which this client rejects
+        let (client, server) = handshake(tls.clone(), &api_cert, "www.example.com").await;
+        assert!(client.is_err());
+        let err = server.unwrap_err();
+        assert_eq!(err.server_name.as_deref(), Some("www.example.com"));
+        assert_eq!(tls.certificate_name(err.server_name.as_deref()), "default");
+    }
+}
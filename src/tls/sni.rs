@@ -0,0 +1,154 @@
+//! Certificate selection by SNI server name.
+
+use crate::config::TlsConfig;
+use crate::tls::{load_certs, load_private_key, TlsError};
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Name reported for handshakes served with the default certificate.
+pub const DEFAULT_CERTIFICATE: &str = "default";
+
+/// Picks the certificate for a handshake from the client's SNI server name.
+///
+/// Exact names take precedence over wildcards, and a wildcard only covers a
+/// single label (`*.example.com` matches `api.example.com` but not
+/// `a.b.example.com`), as clients verify it. Handshakes without SNI, or for a
+/// name no certificate covers, get the default certificate.
+#[derive(Debug)]
+pub struct SniResolver {
+    /// Certificates keyed by lowercase server name or wildcard pattern.
+    names: HashMap<String, Arc<CertifiedKey>>,
+    /// Certificate for every other handshake.
+    default: Arc<CertifiedKey>,
+}
+
+impl SniResolver {
+    /// Load the default and per-name certificates of a frontend.
+    pub fn new(config: &TlsConfig, provider: &CryptoProvider) -> Result<Self, TlsError> {
+        let default = load_certified_key(&config.cert, &config.key, provider)?;
+
+        let mut names = HashMap::new();
+        for cert in &config.certificates {
+            let key = load_certified_key(&cert.cert, &cert.key, provider)?;
+            for name in &cert.server_names {
+                names.insert(name.to_ascii_lowercase(), Arc::clone(&key));
+            }
+        }
+
+        Ok(Self { names, default })
+    }
+
+    /// Configured server name (or wildcard) whose certificate serves
+    /// `server_name`, or [`DEFAULT_CERTIFICATE`].
+    ///
+    /// Unlike the raw SNI value this is bounded by the configuration, so it
+    /// is safe to use as a metrics label.
+    pub fn certificate_name(&self, server_name: Option<&str>) -> &str {
+        server_name
+            .and_then(|name| self.lookup(name))
+            .map(|(pattern, _)| pattern)
+            .unwrap_or(DEFAULT_CERTIFICATE)
+    }
+
+    /// Find the certificate configured for a server name.
+    fn lookup(&self, server_name: &str) -> Option<(&str, &Arc<CertifiedKey>)> {
+        let name = server_name.to_ascii_lowercase();
+        if let Some((pattern, key)) = self.names.get_key_value(&name) {
+            return Some((pattern, key));
+        }
+
+        let (_, parent) = name.split_once('.')?;
+        self.names
+            .get_key_value(&format!("*.{}", parent))
+            .map(|(pattern, key)| (pattern.as_str(), key))
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let key = client_hello
+            .server_name()
+            .and_then(|name| self.lookup(name))
+            .map(|(_, key)| key)
+            .unwrap_or(&self.default);
+        Some(Arc::clone(key))
+    }
+}
+
+/// Load a certificate chain and check that the private key belongs to it.
+fn load_certified_key(
+    cert: &Path,
+    key: &Path,
+    provider: &CryptoProvider,
+) -> Result<Arc<CertifiedKey>, TlsError> {
+    let certified = CertifiedKey::from_der(load_certs(cert)?, load_private_key(key)?, provider)?;
+    Ok(Arc::new(certified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SniCertificate, TlsVersion};
+    use crate::tls::write_cert;
+
+    #[test]
+    fn test_certificate_selection() {
+        let default = write_cert("default.test");
+        let api = write_cert("api.example.com");
+        let wildcard = write_cert("*.example.com");
+
+        let config = TlsConfig {
+            cert: default.0.path().to_path_buf(),
+            key: default.1.path().to_path_buf(),
+            certificates: vec![
+                SniCertificate {
+                    server_names: vec!["*.example.com".to_string()],
+                    cert: wildcard.0.path().to_path_buf(),
+                    key: wildcard.1.path().to_path_buf(),
+                },
+                SniCertificate {
+                    server_names: vec!["API.example.com".to_string()],
+                    cert: api.0.path().to_path_buf(),
+                    key: api.1.path().to_path_buf(),
+                },
+            ],
+            min_version: TlsVersion::Tls12,
+            cipher_suites: Vec::new(),
+            alpn: Vec::new(),
+        };
+        let resolver =
+            SniResolver::new(&config, &rustls::crypto::ring::default_provider()).unwrap();
+
+        assert_eq!(
+            resolver.certificate_name(Some("api.example.com")),
+            "api.example.com"
+        );
+        assert_eq!(
+            resolver.certificate_name(Some("Shop.Example.com")),
+            "*.example.com"
+        );
+        assert_eq!(
+            resolver.certificate_name(Some("a.b.example.com")),
+            "default"
+        );
+        assert_eq!(resolver.certificate_name(Some("example.com")), "default");
+        assert_eq!(resolver.certificate_name(None), "default");
+    }
+
+    #[test]
+    fn test_mismatched_key_rejected() {
+        let (cert, _) = write_cert("a.test");
+        let (_, key) = write_cert("b.test");
+
+        let result = load_certified_key(
+            cert.path(),
+            key.path(),
+            &rustls::crypto::ring::default_provider(),
+        );
+        assert!(matches!(result, Err(TlsError::Rustls(_))));
+    }
+}
@@ -6,4 +6,4 @@ mod shutdown;
 
 pub use logging::init_logging;
 pub use request_id::{generate_request_id, generate_short_request_id, RequestId};
-pub use shutdown::ShutdownSignal;
+pub use shutdown::{ConnectionToken, ConnectionTracker, DrainReport, ShutdownSignal};
@@ -1,6 +1,9 @@
 //! Graceful shutdown handling.
 
-use tokio::sync::broadcast;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch, Notify};
 
 /// Manages graceful shutdown signals.
 #[derive(Clone)]
@@ -31,3 +34,236 @@ impl Default for ShutdownSignal {
         Self::new()
     }
 }
+
+/// Shutdown phase of the tracked connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Running,
+    Draining,
+    Closed,
+}
+
+struct TrackerInner {
+    active: AtomicUsize,
+    idle: Notify,
+    phase: watch::Sender<Phase>,
+    /// Tracker that also counts these connections and can drain them.
+    parent: Option<Arc<TrackerInner>>,
+}
+
+impl TrackerInner {
+    /// This tracker and the trackers above it.
+    fn chain(self: &Arc<Self>) -> impl Iterator<Item = &Arc<TrackerInner>> {
+        std::iter::successors(Some(self), |inner| inner.parent.as_ref())
+    }
+}
+
+/// Tracks client connections so shutdown can drain them.
+///
+/// Once draining starts, connections are asked to finish: HTTP connections
+/// stop keep-alive after their current requests, while TCP sessions and
+/// upgraded connections carry on. Once the drain timeout expires, whatever
+/// is still open is closed.
+#[derive(Clone)]
+pub struct ConnectionTracker {
+    inner: Arc<TrackerInner>,
+}
+
+/// Outcome of draining the tracked connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrainReport {
+    /// Connections that finished on their own while draining.
+    pub drained: usize,
+    /// Connections still open at the drain timeout, which were closed.
+    pub closed: usize,
+}
+
+impl ConnectionTracker {
+    /// Create a tracker with no connections.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(TrackerInner {
+                active: AtomicUsize::new(0),
+                idle: Notify::new(),
+                phase: watch::Sender::new(Phase::Running),
+                parent: None,
+            }),
+        }
+    }
+
+    /// Create a tracker for a subset of the connections, such as those of a
+    /// single listener.
+    ///
+    /// Its connections also count in this tracker, and draining either
+    /// tracker drains them.
+    pub fn child(&self) -> Self {
+        Self {
+            inner: Arc::new(TrackerInner {
+                active: AtomicUsize::new(0),
+                idle: Notify::new(),
+                phase: watch::Sender::new(Phase::Running),
+                parent: Some(Arc::clone(&self.inner)),
+            }),
+        }
+    }
+
+    /// Track a connection until the returned token is dropped.
+    pub fn track(&self) -> ConnectionToken {
+        for inner in self.inner.chain() {
+            inner.active.fetch_add(1, Ordering::AcqRel);
+        }
+        ConnectionToken {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Number of open connections.
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::Acquire)
+    }
+
+    /// Wait until connections are asked to finish.
+    pub async fn draining(&self) {
+        self.wait_for(Phase::Draining).await
+    }
+
+    /// Wait until the remaining connections must be closed.
+    pub async fn closed(&self) {
+        self.wait_for(Phase::Closed).await
+    }
+
+    async fn wait_for(&self, phase: Phase) {
+        let mut receivers: Vec<_> = self
+            .inner
+            .chain()
+            .map(|inner| inner.phase.subscribe())
+            .collect();
+        // The senders live as long as `self`, so this cannot fail
+        let reached = receivers
+            .iter_mut()
+            .map(|rx| Box::pin(rx.wait_for(|current| *current >= phase)));
+        let _ = futures::future::select_all(reached).await;
+    }
+
+    /// Drain the tracked connections, closing those still open after
+    /// `timeout`.
+    pub async fn drain(&self, timeout: Duration) -> DrainReport {
+        let open = self.active();
+        self.inner.phase.send_replace(Phase::Draining);
+
+        let closed = match tokio::time::timeout(timeout, self.idle()).await {
+            Ok(()) => 0,
+            Err(_) => {
+                let closed = self.active();
+                self.inner.phase.send_replace(Phase::Closed);
+                closed
+            }
+        };
+
+        DrainReport {
+            drained: open.saturating_sub(closed),
+            closed,
+        }
+    }
+
+    /// Wait until no connection is open.
+    async fn idle(&self) {
+        loop {
+            let notified = self.inner.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.active() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Default for ConnectionTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A connection counted by a [`ConnectionTracker`].
+pub struct ConnectionToken {
+    inner: Arc<TrackerInner>,
+}
+
+impl Drop for ConnectionToken {
+    fn drop(&mut self) {
+        for inner in self.inner.chain() {
+            if inner.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+                inner.idle.notify_waiters();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_connections() {
+        let tracker = ConnectionTracker::new();
+        let token = tracker.track();
+        assert_eq!(tracker.active(), 1);
+
+        // The connection finishes once asked to
+        let connection = tracker.clone();
+        tokio::spawn(async move {
+            connection.draining().await;
+            drop(token);
+        });
+
+        let report = tracker.drain(Duration::from_secs(5)).await;
+        assert_eq!(report.drained, 1);
+        assert_eq!(report.closed, 0);
+        assert_eq!(tracker.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_closes_after_timeout() {
+        let tracker = ConnectionTracker::new();
+        let token = tracker.track();
+
+        let report = tracker.drain(Duration::from_millis(50)).await;
+        assert_eq!(report.drained, 0);
+        assert_eq!(report.closed, 1);
+
+        // Connections still open are told to close
+        tokio::time::timeout(Duration::from_secs(1), tracker.closed())
+            .await
+            .expect("closed did not fire");
+        drop(token);
+    }
+
+    #[tokio::test]
+    async fn test_child_tracker() {
+        let tracker = ConnectionTracker::new();
+        let child = tracker.child();
+        let token = child.track();
+        assert_eq!(tracker.active(), 1);
+        assert_eq!(child.active(), 1);
+
+        // Draining the parent reaches connections of the child
+        let parent = tracker.clone();
+        let drain = tokio::spawn(async move { parent.drain(Duration::from_secs(5)).await });
+        tokio::time::timeout(Duration::from_secs(1), child.draining())
+            .await
+            .expect("draining did not fire");
+        drop(token);
+        assert_eq!(drain.await.unwrap().drained, 1);
+
+        // Draining a child leaves the parent running
+        let tracker = ConnectionTracker::new();
+        let child = tracker.child();
+        let _token = child.track();
+        let report = child.drain(Duration::from_millis(50)).await;
+        assert_eq!(report.closed, 1);
+        assert_eq!(*tracker.inner.phase.borrow(), Phase::Running);
+        assert_eq!(tracker.active(), 1);
+    }
+}
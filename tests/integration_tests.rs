@@ -7,6 +7,7 @@ use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 /// Helper to create a simple TCP echo server.
 fn start_echo_server(addr: &str) -> (SocketAddr, Arc<AtomicU32>) {
@@ -96,6 +97,7 @@ fn test_config_parsing() {
     let config_content = r#"
 global:
   log_level: info
+  shutdown_timeout: 10s
 
 frontends:
   - name: test
@@ -119,6 +121,7 @@ backends:
     assert_eq!(config.frontends[0].name, "test");
     assert_eq!(config.backends.len(), 1);
     assert_eq!(config.backends[0].servers.len(), 1);
+    assert_eq!(config.global.shutdown_timeout, Duration::from_secs(10));
 }
 
 #[test]
@@ -147,6 +150,551 @@ backends: []
     assert!(config.is_err());
 }
 
+#[test]
+fn test_config_parsing_routes() {
+    use rustlb::config::{load_config, PathMatch};
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: web-servers
+    routes:
+      - host: "*.example.com"
+        path:
+          prefix: /api/
+        methods: [GET]
+        backend: api-servers
+      - path:
+          regex: "^/users/[0-9]+$"
+        headers:
+          - name: X-Canary
+        backend: api-servers
+
+backends:
+  - name: web-servers
+    servers:
+      - address: "127.0.0.1:9000"
+  - name: api-servers
+    servers:
+      - address: "127.0.0.1:9001"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let routes = &config.frontends[0].routes;
+
+    assert_eq!(routes.len(), 2);
+    assert_eq!(routes[0].host.as_deref(), Some("*.example.com"));
+    assert!(matches!(routes[0].path, Some(PathMatch::Prefix(ref p)) if p == "/api/"));
+    assert!(matches!(routes[1].path, Some(PathMatch::Regex(ref r)) if r.is_match("/users/7")));
+    assert_eq!(routes[1].headers[0].name, "X-Canary");
+}
+
+#[test]
+fn test_config_parsing_invalid_route_regex() {
+    use rustlb::config::load_config;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: web-servers
+    routes:
+      - path:
+          regex: "^/users/([0-9]+$"
+        backend: web-servers
+
+backends:
+  - name: web-servers
+    servers:
+      - address: "127.0.0.1:9000"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    assert!(load_config(temp_file.path()).is_err());
+}
+
+#[test]
+fn test_config_parsing_sni() {
+    use rustlb::config::load_config;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: tls-tcp
+    listen: "127.0.0.1:0"
+    protocol: tcp
+    backend: default-servers
+    tls:
+      cert: /certs/default.pem
+      key: /certs/default.key
+      certificates:
+        - server_names: ["*.example.com", example.com]
+          cert: /certs/example.pem
+          key: /certs/example.key
+    routes:
+      - sni: "*.example.com"
+        backend: tenant-servers
+
+backends:
+  - name: default-servers
+    servers:
+      - address: "127.0.0.1:9000"
+  - name: tenant-servers
+    servers:
+      - address: "127.0.0.1:9001"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let tls = config.frontends[0].tls.as_ref().unwrap();
+
+    assert_eq!(tls.certificates[0].server_names, vec!["*.example.com", "example.com"]);
+    assert_eq!(config.frontends[0].routes[0].sni.as_deref(), Some("*.example.com"));
+    assert_eq!(config.watched_files().len(), 4);
+}
+
+#[test]
+fn test_config_parsing_backend_tls() {
+    use rustlb::config::load_config;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: secure-servers
+
+backends:
+  - name: secure-servers
+    servers:
+      - address: "127.0.0.1:9443"
+    tls:
+      ca: /certs/ca.pem
+      cert: /certs/client.pem
+      key: /certs/client.key
+      server_name: api.internal
+  - name: unverified-servers
+    servers:
+      - address: "127.0.0.1:9444"
+    tls:
+      verify: false
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let tls = config.backends[0].tls.as_ref().unwrap();
+
+    assert!(tls.verify);
+    assert_eq!(tls.server_name.as_deref(), Some("api.internal"));
+    assert!(!config.backends[1].tls.as_ref().unwrap().verify);
+    assert_eq!(config.watched_files().len(), 3);
+}
+
+#[test]
+fn test_config_parsing_backend_protocol() {
+    use rustlb::config::{load_config, BackendProtocol};
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: grpc
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: grpc-servers
+
+backends:
+  - name: grpc-servers
+    protocol: http2
+    servers:
+      - address: "127.0.0.1:50051"
+  - name: web-servers
+    servers:
+      - address: "127.0.0.1:8080"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    assert_eq!(config.backends[0].protocol, BackendProtocol::Http2);
+    assert_eq!(config.backends[1].protocol, BackendProtocol::Http1);
+}
+
+#[test]
+fn test_config_parsing_grpc() {
+    use rustlb::config::{load_config, HealthCheckType};
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: grpc
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: users
+    http:
+      grpc: true
+
+backends:
+  - name: users
+    protocol: http2
+    servers:
+      - address: "127.0.0.1:50051"
+    health_check:
+      type: grpc
+      service: users.Users
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    assert!(config.frontends[0].http.as_ref().unwrap().grpc);
+    let health_check = config.backends[0].health_check.as_ref().unwrap();
+    assert_eq!(health_check.check_type, HealthCheckType::Grpc);
+    assert_eq!(health_check.service.as_deref(), Some("users.Users"));
+}
+
+#[test]
+fn test_config_parsing_retry() {
+    use rustlb::config::{load_config, RetryCondition};
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: api
+    http:
+      retry:
+        max_attempts: 2
+        retry_on: [connect_failure]
+        retry_on_status: [503]
+        per_try_timeout: 2s
+        budget: 5s
+
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:8001"
+      - address: "127.0.0.1:8002"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let retry = config.frontends[0].http.as_ref().unwrap().retry.as_ref().unwrap();
+    assert_eq!(retry.max_attempts, 2);
+    assert_eq!(retry.retry_on, vec![RetryCondition::ConnectFailure]);
+    assert_eq!(retry.retry_on_status, vec![503]);
+    assert!(!retry.retry_non_idempotent);
+    assert_eq!(retry.per_try_timeout, Some(Duration::from_secs(2)));
+    assert_eq!(retry.budget, Some(Duration::from_secs(5)));
+}
+
+#[test]
+fn test_config_parsing_connect_retry() {
+    use rustlb::config::load_config;
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: db
+    listen: "127.0.0.1:0"
+    protocol: tcp
+    backend: postgres
+    tcp:
+      connect_timeout: 2s
+      connect_retry:
+        max_attempts: 2
+        backoff: 100ms
+
+backends:
+  - name: postgres
+    servers:
+      - address: "127.0.0.1:5432"
+      - address: "127.0.0.1:5433"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let tcp = config.frontends[0].tcp.as_ref().unwrap();
+    let retry = tcp.connect_retry.as_ref().unwrap();
+    assert_eq!(retry.max_attempts, 2);
+    assert_eq!(retry.backoff, Duration::from_millis(100));
+    assert_eq!(retry.max_backoff, Duration::from_secs(1));
+}
+
+#[test]
+fn test_config_parsing_passive_health() {
+    use rustlb::config::load_config;
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: api
+
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+    passive_health:
+      unhealthy_threshold: 3
+      ejection_time: 10s
+      failure_statuses: [502, 503]
+  - name: static
+    servers:
+      - address: "127.0.0.1:9002"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let passive = &config.backends[0].passive_health;
+    assert!(passive.enabled);
+    assert_eq!(passive.unhealthy_threshold, 3);
+    assert_eq!(passive.ejection_time, Duration::from_secs(10));
+    assert_eq!(passive.failure_statuses, vec![502, 503]);
+
+    // Passive checks are on by default
+    let passive = &config.backends[1].passive_health;
+    assert!(passive.enabled);
+    assert_eq!(passive.unhealthy_threshold, 5);
+    assert!(passive.failure_statuses.is_empty());
+}
+
+#[test]
+fn test_config_parsing_outlier_detection() {
+    use rustlb::config::load_config;
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: http
+    backend: api
+
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+      - address: "127.0.0.1:9002"
+      - address: "127.0.0.1:9003"
+    outlier_detection:
+      interval: 5s
+      stdev_factor: 1.5
+      max_ejection_time: 2m
+      max_ejection_percent: 34
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let outlier = config.backends[0].outlier_detection.as_ref().unwrap();
+    assert_eq!(outlier.interval, Duration::from_secs(5));
+    assert_eq!(outlier.stdev_factor, 1.5);
+    assert_eq!(outlier.max_ejection_time, Duration::from_secs(120));
+    assert_eq!(outlier.max_ejection_percent, 34);
+    assert_eq!(outlier.min_requests, 20);
+    assert_eq!(outlier.base_ejection_time, Duration::from_secs(30));
+}
+
+#[test]
+fn test_config_parsing_drain_timeout() {
+    use rustlb::config::load_config;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: tcp
+    backend: api
+
+backends:
+  - name: api
+    drain_timeout: 2m
+    servers:
+      - address: "127.0.0.1:9001"
+  - name: default
+    servers:
+      - address: "127.0.0.1:9002"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    assert_eq!(config.backends[0].drain_timeout, Duration::from_secs(120));
+    assert_eq!(config.backends[1].drain_timeout, Duration::from_secs(30));
+}
+
+#[test]
+fn test_config_parsing_dns_discovery() {
+    use rustlb::config::load_config;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: tcp
+    backend: api
+
+backends:
+  - name: api
+    discovery:
+      dns:
+        - address: "api.internal:8080"
+          weight: 3
+        - srv: "_http._tcp.api.internal"
+      dns_refresh: 10s
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    let discovery = config.backends[0].discovery.as_ref().unwrap();
+    assert!(config.backends[0].servers.is_empty());
+    assert_eq!(discovery.dns[0].host_port(), Some(("api.internal", 8080)));
+    assert_eq!(discovery.dns[0].weight, 3);
+    assert_eq!(discovery.dns[1].srv.as_deref(), Some("_http._tcp.api.internal"));
+    assert_eq!(discovery.dns_refresh, Duration::from_secs(10));
+
+    // An address needs a port
+    let invalid = config_content.replace("api.internal:8080", "api.internal");
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(invalid.as_bytes()).expect("failed to write config");
+    assert!(load_config(temp_file.path()).is_err());
+}
+
+#[test]
+fn test_config_parsing_file_discovery() {
+    use rustlb::config::{load_config, validate_config};
+    use rustlb::discovery::load_server_list;
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let mut servers_file = tempfile::Builder::new()
+        .suffix(".json")
+        .tempfile()
+        .expect("failed to create temp file");
+    servers_file
+        .write_all(br#"[{"address": "127.0.0.1:9001", "metadata": {"version": "1.4.2"}}]"#)
+        .expect("failed to write server list");
+
+    let config_content = format!(
+        r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: tcp
+    backend: api
+
+backends:
+  - name: api
+    discovery:
+      file: "{}"
+"#,
+        servers_file.path().display()
+    );
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    assert!(validate_config(&config).is_ok());
+    let file = config.backends[0].discovery.as_ref().unwrap().file.as_ref().unwrap();
+    let servers = load_server_list(file).expect("failed to load server list");
+    assert_eq!(servers[0].address, "127.0.0.1:9001".parse().unwrap());
+    assert_eq!(servers[0].metadata["version"], "1.4.2");
+}
+
+#[test]
+fn test_config_parsing_admin() {
+    use rustlb::config::{load_config, validate_config};
+    use tempfile::NamedTempFile;
+    use std::io::Write as IoWrite;
+
+    let config_content = r#"
+global:
+  admin:
+    enabled: true
+    token: "s3cret"
+    state_file: /var/lib/rustlb/servers.json
+
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: tcp
+    backend: api
+
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+"#;
+
+    let mut temp_file = NamedTempFile::new().expect("failed to create temp file");
+    temp_file.write_all(config_content.as_bytes()).expect("failed to write config");
+
+    let config = load_config(temp_file.path()).expect("failed to load config");
+    assert!(config.global.admin.enabled);
+    assert_eq!(config.global.admin.token.as_deref(), Some("s3cret"));
+    assert_eq!(
+        config.global.admin.state_file.as_deref(),
+        Some(std::path::Path::new("/var/lib/rustlb/servers.json"))
+    );
+    assert!(validate_config(&config).is_ok());
+
+    // The admin API is off by default
+    let config: rustlb::Config = serde_yaml::from_str("frontends: []\nbackends: []\n").unwrap();
+    assert!(!config.global.admin.enabled);
+    assert!(config.global.admin.token.is_none());
+    assert!(config.global.admin.state_file.is_none());
+}
+
 #[test]
 fn test_backend_router_round_robin() {
     use rustlb::backend::BackendRouter;
@@ -158,13 +706,15 @@ fn test_backend_router_round_robin() {
             ServerConfig {
                 address: "127.0.0.1:9001".parse().unwrap(),
                 weight: 1,
+                metadata: Default::default(),
             },
             ServerConfig {
                 address: "127.0.0.1:9002".parse().unwrap(),
                 weight: 1,
+                metadata: Default::default(),
             },
         ],
-        health_check: None,
+        ..Default::default()
     }];
 
     let frontends = vec![FrontendConfig {
@@ -172,9 +722,11 @@ fn test_backend_router_round_robin() {
         listen: "127.0.0.1:0".parse().unwrap(),
         protocol: Protocol::Http,
         backend: "test".to_string(),
+        routes: Vec::new(),
         algorithm: Algorithm::RoundRobin,
         http: None,
         tcp: None,
+        tls: None,
     }];
 
     let router = BackendRouter::new(&backends, &frontends);
@@ -200,13 +752,15 @@ fn test_backend_router_weighted() {
             ServerConfig {
                 address: "127.0.0.1:9001".parse().unwrap(),
                 weight: 3,
+                metadata: Default::default(),
             },
             ServerConfig {
                 address: "127.0.0.1:9002".parse().unwrap(),
                 weight: 1,
+                metadata: Default::default(),
             },
         ],
-        health_check: None,
+        ..Default::default()
     }];
 
     let frontends = vec![FrontendConfig {
@@ -214,9 +768,11 @@ fn test_backend_router_weighted() {
         listen: "127.0.0.1:0".parse().unwrap(),
         protocol: Protocol::Http,
         backend: "test".to_string(),
+        routes: Vec::new(),
         algorithm: Algorithm::Weighted,
         http: None,
         tcp: None,
+        tls: None,
     }];
 
     let router = BackendRouter::new(&backends, &frontends);
@@ -249,13 +805,15 @@ fn test_backend_router_ip_hash() {
             ServerConfig {
                 address: "127.0.0.1:9001".parse().unwrap(),
                 weight: 1,
+                metadata: Default::default(),
             },
             ServerConfig {
                 address: "127.0.0.1:9002".parse().unwrap(),
                 weight: 1,
+                metadata: Default::default(),
             },
         ],
-        health_check: None,
+        ..Default::default()
     }];
 
     let frontends = vec![FrontendConfig {
@@ -263,9 +821,11 @@ fn test_backend_router_ip_hash() {
         listen: "127.0.0.1:0".parse().unwrap(),
         protocol: Protocol::Http,
         backend: "test".to_string(),
+        routes: Vec::new(),
         algorithm: Algorithm::IpHash,
         http: None,
         tcp: None,
+        tls: None,
     }];
 
     let router = BackendRouter::new(&backends, &frontends);
@@ -281,6 +841,64 @@ fn test_backend_router_ip_hash() {
     assert_eq!(addr2, addr3);
 }
 
+#[test]
+fn test_backend_router_state_file() {
+    use rustlb::backend::{BackendRouter, ServerOverlay};
+    use rustlb::config::ServerConfig;
+    use rustlb::health::HealthState;
+    use std::sync::Arc;
+
+    let config: rustlb::Config = serde_yaml::from_str(
+        r#"
+frontends:
+  - name: web
+    listen: "127.0.0.1:0"
+    protocol: tcp
+    backend: api
+backends:
+  - name: api
+    servers:
+      - address: "127.0.0.1:9001"
+      - address: "127.0.0.1:9002"
+"#,
+    )
+    .unwrap();
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("state.json");
+    let start = || {
+        BackendRouter::with_overlay(
+            &config.backends,
+            &config.frontends,
+            Arc::new(HealthState::new()),
+            ServerOverlay::load(&path).expect("failed to load state file"),
+        )
+    };
+
+    let router = start();
+    router
+        .add_server(
+            "api",
+            ServerConfig {
+                address: "127.0.0.1:9003".parse().unwrap(),
+                weight: 1,
+                metadata: Default::default(),
+            },
+        )
+        .expect("failed to add server");
+    router
+        .remove_server("api", "127.0.0.1:9001".parse().unwrap())
+        .expect("failed to remove server");
+    drop(router);
+
+    // A restarted router picks the changes up from the state file
+    let router = start();
+    let servers: Vec<SocketAddr> = vec![
+        "127.0.0.1:9002".parse().unwrap(),
+        "127.0.0.1:9003".parse().unwrap(),
+    ];
+    assert_eq!(router.get_servers("api").unwrap(), servers);
+}
+
 #[test]
 fn test_health_state() {
     use rustlb::health::{HealthConfig, HealthState};
@@ -327,9 +945,9 @@ fn test_metrics_collector() {
     let collector = MetricsCollector::new();
 
     // Record various metrics
-    collector.record_request("web", "api", "GET", 200, Duration::from_millis(10));
-    collector.record_request("web", "api", "POST", 201, Duration::from_millis(20));
-    collector.record_request("web", "api", "GET", 500, Duration::from_millis(100));
+    collector.record_request("web", "api", None, "GET", 200, Duration::from_millis(10));
+    collector.record_request("web", "api", None, "POST", 201, Duration::from_millis(20));
+    collector.record_request("web", "api", None, "GET", 500, Duration::from_millis(100));
 
     collector.connection_opened("web", "api");
     collector.connection_opened("web", "api");